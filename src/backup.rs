@@ -6,7 +6,7 @@ use anyhow::Result;
 use chrono::{DateTime, TimeZone, Utc};
 use rocksdb::backup::BackupEngineInfo;
 
-use crate::Store;
+use crate::{Actor, AuditAction, AuditEvent, Store, tables};
 
 #[allow(clippy::module_name_repetitions)]
 pub struct BackupInfo {
@@ -44,6 +44,39 @@ pub fn create(store: &Arc<RwLock<Store>>, flush: bool, backups_to_keep: u32) ->
     store.backup(flush, backups_to_keep)
 }
 
+/// Creates a new database backup as [`create`] does, and records it in the
+/// audit trail as taken by `actor`. The new backup does not contain the record.
+///
+/// # Errors
+///
+/// Returns an error if backup fails or recording it fails.
+///
+/// # Panics
+///
+/// Panics if the lock is poisoned, which should never happen as the backup
+/// operation does not panic.
+pub fn create_as(
+    store: &Arc<RwLock<Store>>,
+    flush: bool,
+    backups_to_keep: u32,
+    actor: &Actor,
+) -> Result<()> {
+    let mut store = store
+        .write()
+        .expect("write lock should not be poisoned as backup does not panic");
+    store.backup(flush, backups_to_keep)?;
+    let target = store
+        .get_backup_info()?
+        .last()
+        .map(|backup| backup.backup_id.to_string())
+        .unwrap_or_default();
+    store.audit_log().record(&AuditEvent::new(
+        actor.clone(),
+        AuditAction::BackupCreated,
+        target,
+    ))
+}
+
 /// Lists the backup information of the database.
 ///
 /// # Errors
@@ -68,7 +101,9 @@ pub fn list(store: &Arc<RwLock<Store>>) -> Result<Vec<BackupInfo>> {
         .collect())
 }
 
-/// Restores the database from a backup with the specified ID.
+/// Restores the database from a backup with the specified ID. The change log
+/// is not rolled back, so the sequence numbers of its records are never
+/// reused.
 ///
 /// # Errors
 ///
@@ -83,10 +118,45 @@ pub fn restore(store: &Arc<RwLock<Store>>, backup_id: Option<u32>) -> Result<()>
     let mut store = store
         .write()
         .expect("write lock should not be poisoned as restore does not panic");
-    match &backup_id {
-        Some(id) => store.restore_from_backup(*id),
-        None => store.restore_from_latest_backup(),
+    restore_keeping(&mut store, backup_id, &[tables::CHANGE_LOG])
+}
+
+/// Restores the database from a backup as [`restore`] does, and records it in
+/// the audit trail as taken by `actor`. Neither the audit trail nor the change
+/// log is rolled back: the entries written after the backup was created are
+/// written back once the rest of the database is restored.
+///
+/// # Errors
+///
+/// Returns an error if the restore operation fails or recording it fails.
+///
+/// # Panics
+///
+/// Panics if the lock is poisoned, which should never happen as the restore
+/// operation does not panic.
+pub fn restore_as(store: &Arc<RwLock<Store>>, backup_id: Option<u32>, actor: &Actor) -> Result<()> {
+    let mut store = store
+        .write()
+        .expect("write lock should not be poisoned as restore does not panic");
+    restore_keeping(&mut store, backup_id, &[tables::AUDIT, tables::CHANGE_LOG])?;
+    let target = backup_id.map_or_else(|| "latest".to_string(), |id| id.to_string());
+    store.audit_log().record(&AuditEvent::new(
+        actor.clone(),
+        AuditAction::BackupRestored,
+        target,
+    ))
+}
+
+/// Restores the backup with `backup_id`, or the latest backup, keeping the
+/// column families `kept`, which include the change log, as they are. The
+/// change log stays enabled or disabled as it was before the restore.
+fn restore_keeping(store: &mut Store, backup_id: Option<u32>, kept: &[&str]) -> Result<()> {
+    let logging = store.change_log().is_enabled()?;
+    store.restore_keeping(backup_id, kept)?;
+    if !logging {
+        store.change_log().disable()?;
     }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -206,6 +276,65 @@ mod tests {
         assert_eq!(backup_list[2].id, 3);
     }
 
+    #[test]
+    fn audited_backup_and_restore() {
+        use std::sync::RwLock;
+
+        use anyhow::Result;
+
+        use crate::backup::{create_as, restore_as};
+        use crate::{Actor, AuditAction, AuditFilter};
+
+        let _permit = acquire_db_permit();
+        let db_dir = tempfile::tempdir().unwrap();
+        let backup_dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(RwLock::new(
+            Store::new(db_dir.path(), backup_dir.path(), None).unwrap(),
+        ));
+        let actor = Actor {
+            username: "admin".to_string(),
+            source_ip: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+        };
+
+        store
+            .read()
+            .expect("test holds no other locks")
+            .change_log()
+            .enable()
+            .unwrap();
+        create_as(&store, true, 3, &actor).unwrap();
+        restore_as(&store, Some(1), &actor).unwrap();
+
+        let store = store.read().expect("test holds no other locks");
+        let events = store
+            .audit_log()
+            .events(&AuditFilter::default())
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        // The record of creating the backup was made after the backup, but
+        // survives restoring it.
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].action, AuditAction::BackupCreated);
+        assert_eq!(events[0].target, "1");
+        assert_eq!(events[1].action, AuditAction::BackupRestored);
+        assert_eq!(events[1].target, "1");
+        assert!(events.iter().all(|event| event.actor == actor));
+
+        // Neither is the change log, so the record of the restore does not
+        // reuse the sequence number of the record of creating the backup.
+        let records = store
+            .change_log()
+            .since(0)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            records.iter().map(|r| r.sequence).collect::<Vec<_>>(),
+            [1, 2]
+        );
+        assert!(records.iter().all(|r| r.actor.as_ref() == Some(&actor)));
+    }
+
     #[test]
     fn test_backup_info_timestamp_conversion() {
         use chrono::{DateTime, Datelike};
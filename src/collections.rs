@@ -8,7 +8,11 @@ use anyhow::{Context, Result, bail};
 use bincode::Options;
 use serde::{Deserialize, Serialize};
 
-pub use self::{indexed_map::IndexedMap, indexed_set::IndexedSet, map::Map};
+pub use self::{
+    indexed_map::IndexedMap,
+    indexed_set::{IndexedSet, SetView},
+    map::Map,
+};
 use super::types::FromKeyValue;
 use crate::EXCLUSIVE;
 
@@ -179,11 +183,43 @@ where
     fn make_indexed_key(key: Cow<[u8]>, index: u32) -> Cow<[u8]>;
     fn value(&self) -> Vec<u8>;
     fn set_index(&mut self, index: u32);
+
+    /// Checks that the record can be stored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the record is invalid.
+    fn validate(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
 pub trait Indexed {
     fn db(&self) -> &rocksdb::OptimisticTransactionDB;
     fn cf(&self) -> &rocksdb::ColumnFamily;
+    fn name(&self) -> &'static str;
+
+    /// Appends a record of writing `new_value` under `key`, or deleting the
+    /// entry if `new_value` is `None`, to the change log, unless it is
+    /// disabled, and updates the tag index if the entries have tags. The index
+    /// under the empty key is not recorded.
+    ///
+    /// It must be called before the write, while `txn` still reads the old
+    /// value.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an entry with tags cannot be decoded or the
+    /// database operation fails.
+    fn record_change(
+        &self,
+        txn: &rocksdb::Transaction<rocksdb::OptimisticTransactionDB>,
+        key: &[u8],
+        new_value: Option<&[u8]>,
+    ) -> Result<()> {
+        crate::tables::record_change(self.db(), txn, self.cf(), self.name(), key, new_value)?;
+        crate::tables::record_tag_change(self.db(), txn, self.cf(), self.name(), key, new_value)
+    }
 
     /// Returns the index.
     ///
@@ -248,6 +284,7 @@ pub trait Indexed {
                     .context("failed to serialize index")?,
             )
             .context("failed to update database index")?;
+            self.record_change(&txn, &key, None)?;
             txn.delete_cf(self.cf(), &key)
                 .context("failed to remove entry")?;
             match txn.commit() {
@@ -365,7 +402,9 @@ pub trait Indexed {
                     .expect("serializable"),
             )
             .context("failed to update database index")?;
-            txn.put_cf(self.cf(), entry.indexed_key(), entry.value())
+            let value = entry.value();
+            self.record_change(&txn, &entry.indexed_key(), Some(&value))?;
+            txn.put_cf(self.cf(), entry.indexed_key(), value)
                 .context("failed to write new entry")?;
             match txn.commit() {
                 Ok(()) => break,
@@ -410,7 +449,9 @@ pub trait Indexed {
                 .expect("serializable"),
         )
         .context("failed to update database index")?;
-        txn.put_cf(self.cf(), entry.indexed_key(), entry.value())
+        let value = entry.value();
+        self.record_change(txn, &entry.indexed_key(), Some(&value))?;
+        txn.put_cf(self.cf(), entry.indexed_key(), value)
             .context("failed to write new entry")?;
         Ok(i)
     }
@@ -440,6 +481,7 @@ pub trait Indexed {
                     .context("failed to serialize index")?,
             )
             .context("failed to update database index")?;
+            self.record_change(&txn, &indexed_key, None)?;
             txn.delete_cf(self.cf(), indexed_key)
                 .context("failed to remove entry")?;
             match txn.commit() {
@@ -480,6 +522,7 @@ pub trait Indexed {
                 .context("failed to serialize index")?,
         )
         .context("failed to update database index")?;
+        self.record_change(txn, &indexed_key, None)?;
         txn.delete_cf(self.cf(), indexed_key)
             .context("failed to remove entry")?;
         Ok(key)
@@ -533,6 +576,7 @@ pub trait Indexed {
                 let new_key = V::Entry::make_indexed_key(new_key, id);
 
                 if new_key != key {
+                    self.record_change(&txn, &key, None)?;
                     txn.delete_cf(self.cf(), &key)
                         .context("failed to delete old entry")?;
                     if txn
@@ -548,13 +592,10 @@ pub trait Indexed {
                 key
             };
 
-            let new_entry = new.apply(entry.into());
-            txn.put_cf(
-                self.cf(),
-                new_key,
-                new_entry.context("invalid update")?.value(),
-            )
-            .context("failed to write updated entry")?;
+            let new_value = new.apply(entry.into()).context("invalid update")?.value();
+            self.record_change(&txn, &new_key, Some(&new_value))?;
+            txn.put_cf(self.cf(), new_key, new_value)
+                .context("failed to write updated entry")?;
             txn.put_cf(
                 self.cf(),
                 [],
@@ -627,6 +668,7 @@ pub trait Indexed {
             let new_key = V::Entry::make_indexed_key(new_key, id);
 
             if new_key != key {
+                self.record_change(txn, &key, None)?;
                 txn.delete_cf(self.cf(), &key)
                     .context("failed to delete old entry")?;
                 if txn
@@ -642,13 +684,10 @@ pub trait Indexed {
             key
         };
 
-        let new_entry = new.apply(entry.into());
-        txn.put_cf(
-            self.cf(),
-            new_key,
-            new_entry.context("invalid update")?.value(),
-        )
-        .context("failed to write updated entry")?;
+        let new_value = new.apply(entry.into()).context("invalid update")?.value();
+        self.record_change(txn, &new_key, Some(&new_value))?;
+        txn.put_cf(self.cf(), new_key, new_value)
+            .context("failed to write updated entry")?;
         txn.put_cf(
             self.cf(),
             [],
@@ -9,6 +9,7 @@ use super::Indexed;
 pub struct IndexedMap<'a> {
     db: &'a rocksdb::OptimisticTransactionDB,
     cf: &'a rocksdb::ColumnFamily,
+    name: &'static str,
 }
 
 impl Indexed for IndexedMap<'_> {
@@ -19,6 +20,10 @@ impl Indexed for IndexedMap<'_> {
     fn cf(&self) -> &rocksdb::ColumnFamily {
         self.cf
     }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
 }
 
 impl<'a> IndexedMap<'a> {
@@ -27,9 +32,9 @@ impl<'a> IndexedMap<'a> {
     /// # Errors
     ///
     /// Returns an error if the column family cannot be found.
-    pub fn new(db: &'a rocksdb::OptimisticTransactionDB, name: &str) -> Result<Self> {
+    pub fn new(db: &'a rocksdb::OptimisticTransactionDB, name: &'static str) -> Result<Self> {
         db.cf_handle(name)
-            .map(|cf| Self { db, cf })
+            .map(|cf| Self { db, cf, name })
             .ok_or_else(|| anyhow!("database error: cannot find column family \"{name}\""))
     }
 
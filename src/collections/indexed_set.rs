@@ -1,201 +1,404 @@
-use anyhow::{Context, Result, anyhow};
-use bincode::Options;
+use anyhow::{Context, Result, anyhow, bail};
 
-use super::KeyIndex;
-use crate::EXCLUSIVE;
+use crate::{EXCLUSIVE, tables::record_change};
 
+/// A set of unique keys, each with an associated numerical ID.
+///
+/// Every entry is stored as two RocksDB keys under the set's name: one maps
+/// the key to its ID, and the other maps the ID back to the key. An entry may
+/// also have a value, stored under a third key for its ID. The next ID to
+/// assign is stored under the name of the set itself. IDs are never reused, so
+/// a reference to a removed entry never points to a new one.
 pub struct IndexedSet<'a> {
     db: &'a rocksdb::OptimisticTransactionDB,
     cf: &'a rocksdb::ColumnFamily,
-    key: &'static [u8],
+    cf_name: &'static str,
+    name: &'static [u8],
 }
 
+/// The entries of a set as a transaction writing to the set reads them, for
+/// the checks the write depends on.
+///
+/// A write running such checks updates the next ID of the set in the same
+/// transaction, so that of two of them changing the set at the same time, the
+/// one committing later fails to commit, and runs its checks again.
+pub struct SetView<'t> {
+    txn: &'t rocksdb::Transaction<'t, rocksdb::OptimisticTransactionDB>,
+    cf: &'t rocksdb::ColumnFamily,
+    name: &'static [u8],
+}
+
+impl SetView<'_> {
+    /// Returns the key with the given ID, or `None` if there is no such ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn get(&self, id: u32) -> Result<Option<Vec<u8>>> {
+        self.txn
+            .get_cf(self.cf, id_key(self.name, id))
+            .context("database error")
+    }
+
+    /// Returns the IDs and values of the entries with a value, in the order of
+    /// their IDs.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails or an entry is
+    /// invalid.
+    pub fn values(&self) -> Result<Vec<(u32, Vec<u8>)>> {
+        let prefix = entry_prefix(self.name, VALUE_TAG);
+        let iter = self.txn.iterator_cf(
+            self.cf,
+            rocksdb::IteratorMode::From(&prefix, rocksdb::Direction::Forward),
+        );
+        entries_with_prefix(iter, &prefix, |key, value| {
+            Ok((decode_id(key)?, value.to_vec()))
+        })
+    }
+
+    /// Returns the IDs and keys of the entries whose keys start with `prefix`,
+    /// in the order of their keys.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails or an entry is
+    /// invalid.
+    pub fn entries_starting_with(&self, prefix: &[u8]) -> Result<Vec<(u32, Vec<u8>)>> {
+        let full = key_key(self.name, prefix);
+        let iter = self.txn.iterator_cf(
+            self.cf,
+            rocksdb::IteratorMode::From(&full, rocksdb::Direction::Forward),
+        );
+        entries_with_prefix(iter, &full, |rest, value| {
+            Ok((decode_id(value)?, [prefix, rest].concat()))
+        })
+    }
+}
+
+/// The tag following the name of a set in the keys mapping an ID to a key.
+const ID_TAG: u8 = b'i';
+
+/// The tag following the name of a set in the keys mapping a key to an ID.
+const KEY_TAG: u8 = b'k';
+
+/// The tag following the name of a set in the keys mapping an ID to a value.
+const VALUE_TAG: u8 = b'v';
+
 impl<'a> IndexedSet<'a> {
     pub(crate) fn new(
         db: &'a rocksdb::OptimisticTransactionDB,
-        name: &str,
-        key: &'static [u8],
+        cf_name: &'static str,
+        name: &'static [u8],
     ) -> Result<Self> {
-        db.cf_handle(name)
-            .map(|cf| Self { db, cf, key })
-            .ok_or_else(|| anyhow!("database error: cannot find column family \"{name}\""))
+        db.cf_handle(cf_name)
+            .map(|cf| Self {
+                db,
+                cf,
+                cf_name,
+                name,
+            })
+            .ok_or_else(|| anyhow!("database error: cannot find column family \"{cf_name}\""))
+    }
+
+    /// Returns the key with the given ID, or `None` if there is no such ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn get(&self, id: u32) -> Result<Option<Vec<u8>>> {
+        self.db
+            .get_cf(self.cf, id_key(self.name, id))
+            .context("database error")
     }
 
-    /// Returns the index of the set.
+    /// Returns the ID of the given key, or `None` if the key is not in the set.
     ///
     /// # Errors
     ///
-    /// Returns an error if the index is invalid or cannot be read.
-    pub fn index(&self) -> Result<KeyIndex> {
-        let Some(value) = self
-            .db
-            .get_cf(self.cf, self.key)
+    /// Returns an error if the database operation fails or the stored ID is
+    /// invalid.
+    pub fn id(&self, key: &[u8]) -> Result<Option<u32>> {
+        self.db
+            .get_cf(self.cf, key_key(self.name, key))
             .context("database error")?
-        else {
-            return Ok(KeyIndex::default());
-        };
-        KeyIndex::from_bytes(value).context("invalid index in database")
+            .map(|id| decode_id(&id))
+            .transpose()
     }
 
-    /// Deactivates an entry with the given ID.
+    /// Returns the value of the entry with the given ID, or `None` if there is
+    /// no such entry or it has no value.
     ///
     /// # Errors
     ///
-    /// Returns an error if the index is invalid or any database operation fails.
-    pub fn deactivate(&self, id: u32) -> Result<Vec<u8>> {
-        let mut key;
-        loop {
-            let txn = self.db.transaction();
-            let mut index = self
-                .index_in_transaction(&txn)
-                .context("cannot read index")?;
-            key = index.deactivate(id).context("cannot deactivate key")?;
-            txn.put_cf(
-                self.cf,
-                self.key,
-                bincode::DefaultOptions::new()
-                    .serialize(&index)
-                    .context("failed to serialize index")?,
-            )
-            .context("failed to update database index")?;
-            match txn.commit() {
-                Ok(()) => break,
-                Err(e) => {
-                    if !e.as_ref().starts_with("Resource busy:") {
-                        return Err(e).context("failed to remove entry");
-                    }
-                }
-            }
-        }
-        Ok(key)
+    /// Returns an error if the database operation fails.
+    pub fn value(&self, id: u32) -> Result<Option<Vec<u8>>> {
+        self.db
+            .get_cf(self.cf, value_key(self.name, id))
+            .context("database error")
+    }
+
+    /// Returns the IDs and values of the entries with a value, in the order of
+    /// their IDs.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails or an entry is
+    /// invalid.
+    pub fn values(&self) -> Result<Vec<(u32, Vec<u8>)>> {
+        let prefix = entry_prefix(self.name, VALUE_TAG);
+        self.entries_with_prefix(&prefix, |key, value| Ok((decode_id(key)?, value.to_vec())))
+    }
+
+    /// Returns the IDs and keys of the entries, in the order of their IDs.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails or an entry is
+    /// invalid.
+    pub fn entries(&self) -> Result<Vec<(u32, Vec<u8>)>> {
+        let prefix = entry_prefix(self.name, ID_TAG);
+        self.entries_with_prefix(&prefix, |key, value| Ok((decode_id(key)?, value.to_vec())))
+    }
+
+    /// Returns the IDs and keys of the entries whose keys start with `prefix`,
+    /// in the order of their keys.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails or an entry is
+    /// invalid.
+    pub fn entries_starting_with(&self, prefix: &[u8]) -> Result<Vec<(u32, Vec<u8>)>> {
+        self.entries_with_prefix(&key_key(self.name, prefix), |rest, value| {
+            Ok((decode_id(value)?, [prefix, rest].concat()))
+        })
+    }
+
+    /// Inserts a key, returning its ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key is already in the set, no more IDs are
+    /// available, or any database operation fails.
+    pub fn insert<T: AsRef<[u8]>>(&self, entry: T) -> Result<u32> {
+        self.insert_entry(entry.as_ref(), None, |_| Ok(()))
     }
 
-    /// Makes deactivated indices available.
+    /// Inserts a key with a value, returning its ID, once `check` accepts the
+    /// set as the insertion reads it.
     ///
     /// # Errors
     ///
-    /// Returns an error if the index is invalid or any database operation fails.
-    pub fn clear_inactive(&self) -> Result<()> {
+    /// Returns an error if `check` does, the key is already in the set, no
+    /// more IDs are available, or any database operation fails.
+    pub fn insert_with_value<F>(&self, entry: &[u8], value: &[u8], check: F) -> Result<u32>
+    where
+        F: FnMut(&SetView) -> Result<()>,
+    {
+        self.insert_entry(entry, Some(value), check)
+    }
+
+    fn insert_entry<F>(&self, entry: &[u8], value: Option<&[u8]>, mut check: F) -> Result<u32>
+    where
+        F: FnMut(&SetView) -> Result<()>,
+    {
         loop {
             let txn = self.db.transaction();
-            let mut index = self
-                .index_in_transaction(&txn)
-                .context("cannot read index")?;
-            index.clear_inactive()?;
-            txn.put_cf(
-                self.cf,
-                [],
-                bincode::DefaultOptions::new()
-                    .serialize(&index)
-                    .context("failed to serialize index")?,
-            )
-            .context("failed to update database index")?;
+            let id = match txn
+                .get_for_update_cf(self.cf, self.name, EXCLUSIVE)
+                .context("database error")?
+            {
+                Some(next) => decode_id(&next)?,
+                None => 0,
+            };
+            check(&self.view(&txn))?;
+            if txn
+                .get_for_update_cf(self.cf, key_key(self.name, entry), EXCLUSIVE)
+                .context("database error")?
+                .is_some()
+            {
+                bail!("key already exists");
+            }
+            let next = id.checked_add(1).context("set is full")?;
+            self.put(&txn, self.name, &next.to_be_bytes())
+                .context("failed to update the next ID")?;
+            self.put(&txn, &id_key(self.name, id), entry)
+                .context("failed to store new entry")?;
+            self.put(&txn, &key_key(self.name, entry), &id.to_be_bytes())
+                .context("failed to store new entry")?;
+            if let Some(value) = value {
+                self.put(&txn, &value_key(self.name, id), value)
+                    .context("failed to store new entry")?;
+            }
             match txn.commit() {
-                Ok(()) => break,
+                Ok(()) => return Ok(id),
                 Err(e) => {
                     if !e.as_ref().starts_with("Resource busy:") {
-                        return Err(e).context("failed to remove entry");
+                        return Err(e).context("failed to store new entry");
                     }
                 }
             }
         }
-        Ok(())
     }
 
-    /// Inserts an entry.
+    /// Removes an entry for the given ID, returning the removed key.
     ///
     /// # Errors
     ///
-    /// Returns an error if the index is invalid or any database operation fails.
-    pub fn insert<T: AsRef<[u8]>>(&self, entry: T) -> Result<u32> {
-        let mut i;
+    /// Returns an error if there is no such ID or any database operation
+    /// fails.
+    pub fn remove(&self, id: u32) -> Result<Vec<u8>> {
+        let mut keys = self.remove_all(|_| Ok(vec![id]))?;
+        keys.pop().context("no such ID")
+    }
+
+    /// Removes at once the entries for the IDs `select` returns from the set
+    /// as the removal reads it, returning the removed keys in the same order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `select` does, any of the IDs does not exist, in
+    /// which case none is removed, or any database operation fails.
+    pub fn remove_all<F>(&self, select: F) -> Result<Vec<Vec<u8>>>
+    where
+        F: FnMut(&SetView) -> Result<Vec<u32>>,
+    {
+        self.remove_all_with(select, |_, _| Ok(()))
+    }
+
+    /// Removes the entries like [`remove_all`](Self::remove_all), and runs
+    /// `write` with the removed IDs in the transaction of the removal, so that
+    /// what `write` does, such as removing the references to the entries, is
+    /// committed together with the removal or not at all.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `select` or `write` does, any of the IDs does not
+    /// exist, in which case none is removed, or any database operation fails.
+    pub(crate) fn remove_all_with<F, W>(&self, mut select: F, mut write: W) -> Result<Vec<Vec<u8>>>
+    where
+        F: FnMut(&SetView) -> Result<Vec<u32>>,
+        W: FnMut(&rocksdb::Transaction<rocksdb::OptimisticTransactionDB>, &[u32]) -> Result<()>,
+    {
         loop {
             let txn = self.db.transaction();
-            let mut index = self.index_in_transaction(&txn)?;
-            i = index.insert(entry.as_ref()).context("cannot insert key")?;
-            txn.put_cf(
-                self.cf,
-                self.key,
-                bincode::DefaultOptions::new()
-                    .serialize(&index)
-                    .context("failed to serialize index")?,
-            )
-            .context("failed to update database index")?;
+            self.lock(&txn)?;
+            let ids = select(&self.view(&txn))?;
+            let mut keys = Vec::with_capacity(ids.len());
+            for &id in &ids {
+                let Some(key) = txn
+                    .get_for_update_cf(self.cf, id_key(self.name, id), EXCLUSIVE)
+                    .context("database error")?
+                else {
+                    bail!("no such ID");
+                };
+                self.delete(&txn, &id_key(self.name, id))
+                    .context("failed to remove entry")?;
+                self.delete(&txn, &key_key(self.name, &key))
+                    .context("failed to remove entry")?;
+                self.delete(&txn, &value_key(self.name, id))
+                    .context("failed to remove entry")?;
+                keys.push(key);
+            }
+            write(&txn, &ids)?;
             match txn.commit() {
-                Ok(()) => break,
+                Ok(()) => return Ok(keys),
                 Err(e) => {
                     if !e.as_ref().starts_with("Resource busy:") {
-                        return Err(e).context("failed to store new entry");
+                        return Err(e).context("failed to remove entry");
                     }
                 }
             }
         }
-        Ok(i)
     }
 
-    /// Removes an entry for the given ID, returning the removed entry.
+    /// Updates an old key to a new one for the given ID.
+    ///
+    /// It returns `true` if the key was updated, and `false` if the key was
+    /// different or not found.
     ///
     /// # Errors
     ///
-    /// Returns an error if the index is invalid or any database operation fails.
-    pub fn remove(&self, id: u32) -> Result<Vec<u8>> {
-        let mut key;
+    /// Returns an error if another entry has the new key or any database
+    /// operation fails.
+    pub fn update(&self, id: u32, old: &[u8], new: &[u8]) -> Result<bool> {
         loop {
             let txn = self.db.transaction();
-            let mut index = self.index_in_transaction(&txn)?;
-            key = index.remove(id).context("cannot remove key")?;
-            txn.put_cf(
-                self.cf,
-                self.key,
-                bincode::DefaultOptions::new()
-                    .serialize(&index)
-                    .context("failed to serialize index")?,
-            )
-            .context("failed to update database index")?;
+            let current = txn
+                .get_for_update_cf(self.cf, id_key(self.name, id), EXCLUSIVE)
+                .context("database error")?;
+            if current.as_deref() != Some(old) {
+                return Ok(false);
+            }
+            if old == new {
+                return Ok(true);
+            }
+            if txn
+                .get_for_update_cf(self.cf, key_key(self.name, new), EXCLUSIVE)
+                .context("database error")?
+                .is_some()
+            {
+                bail!("key already exists");
+            }
+            self.delete(&txn, &key_key(self.name, old))
+                .context("failed to update entry")?;
+            self.put(&txn, &key_key(self.name, new), &id.to_be_bytes())
+                .context("failed to update entry")?;
+            self.put(&txn, &id_key(self.name, id), new)
+                .context("failed to update entry")?;
             match txn.commit() {
-                Ok(()) => break,
+                Ok(()) => return Ok(true),
                 Err(e) => {
                     if !e.as_ref().starts_with("Resource busy:") {
-                        return Err(e).context("failed to remove entry");
+                        return Err(e).context("failed to update entry");
                     }
                 }
             }
         }
-        Ok(key)
     }
 
-    /// Updates an old entry to a new one for the given ID.
+    /// Updates the value of the entry with the given ID from `old` to `new`,
+    /// where `None` stands for no value, once `check` accepts the set as the
+    /// update reads it.
     ///
-    /// It returns `true` if the entry was updated, and `false` if the entry was
-    /// different or not found.
+    /// It returns `true` if the value was updated, and `false` if there is no
+    /// such entry or its value was different from `old`.
     ///
     /// # Errors
     ///
-    /// Returns an error if the index is invalid or any database operation fails.
-    pub fn update(&self, id: u32, old: &[u8], new: &[u8]) -> Result<bool> {
+    /// Returns an error if `check` does or any database operation fails.
+    pub fn update_value<F>(
+        &self,
+        id: u32,
+        old: Option<&[u8]>,
+        new: &[u8],
+        mut check: F,
+    ) -> Result<bool>
+    where
+        F: FnMut(&SetView) -> Result<()>,
+    {
         loop {
             let txn = self.db.transaction();
-            let mut index = self.index_in_transaction(&txn)?;
-            if let Some(v) = index.get(id).context("invalid ID")? {
-                if v != old {
-                    return Ok(false);
-                }
-            } else {
+            self.lock(&txn)?;
+            if txn
+                .get_for_update_cf(self.cf, id_key(self.name, id), EXCLUSIVE)
+                .context("database error")?
+                .is_none()
+            {
+                return Ok(false);
+            }
+            let current = txn
+                .get_for_update_cf(self.cf, value_key(self.name, id), EXCLUSIVE)
+                .context("database error")?;
+            if current.as_deref() != old {
                 return Ok(false);
             }
-            index
-                .update(id, new.as_ref())
-                .context("cannot update index")?;
-            txn.put_cf(
-                self.cf,
-                self.key,
-                bincode::DefaultOptions::new()
-                    .serialize(&index)
-                    .context("failed to serialize index")?,
-            )
-            .context("failed to update database index")?;
+            check(&self.view(&txn))?;
+            self.put(&txn, &value_key(self.name, id), new)
+                .context("failed to update entry")?;
             match txn.commit() {
-                Ok(()) => break,
+                Ok(()) => return Ok(true),
                 Err(e) => {
                     if !e.as_ref().starts_with("Resource busy:") {
                         return Err(e).context("failed to update entry");
@@ -203,43 +406,168 @@ impl<'a> IndexedSet<'a> {
                 }
             }
         }
-        Ok(true)
     }
 
-    fn index_in_transaction(
+    /// Adds to `batch` the writes storing `entries` as set `name` in `cf`,
+    /// with `next` as the next ID to assign.
+    ///
+    /// It is for migrating entries stored in another format; the keys must be
+    /// unique, and `next` greater than any of the IDs.
+    pub(crate) fn put_in_batch(
+        batch: &mut rocksdb::WriteBatchWithTransaction<true>,
+        cf: &rocksdb::ColumnFamily,
+        name: &[u8],
+        entries: &[(u32, Vec<u8>)],
+        next: u32,
+    ) {
+        batch.put_cf(cf, name, next.to_be_bytes());
+        for (id, key) in entries {
+            batch.put_cf(cf, id_key(name, *id), key);
+            batch.put_cf(cf, key_key(name, key), id.to_be_bytes());
+        }
+    }
+
+    /// Reads the entries under `prefix`, passing the rest of each RocksDB key
+    /// and its value to `f`.
+    fn entries_with_prefix<F>(&self, prefix: &[u8], f: F) -> Result<Vec<(u32, Vec<u8>)>>
+    where
+        F: Fn(&[u8], &[u8]) -> Result<(u32, Vec<u8>)>,
+    {
+        let iter = self.db.iterator_cf(
+            self.cf,
+            rocksdb::IteratorMode::From(prefix, rocksdb::Direction::Forward),
+        );
+        entries_with_prefix(iter, prefix, f)
+    }
+
+    /// Updates the next ID to assign in `txn` without changing it, so that
+    /// `txn` fails to commit if another write running checks against the set
+    /// commits first. As nothing changes, nothing is added to the change log.
+    fn lock(&self, txn: &rocksdb::Transaction<rocksdb::OptimisticTransactionDB>) -> Result<()> {
+        if let Some(next) = txn
+            .get_for_update_cf(self.cf, self.name, EXCLUSIVE)
+            .context("database error")?
+        {
+            txn.put_cf(self.cf, self.name, next)
+                .context("failed to lock the set")?;
+        }
+        Ok(())
+    }
+
+    /// Writes `value` under the RocksDB key `key` in `txn`, recording the
+    /// change in the change log.
+    fn put(
         &self,
         txn: &rocksdb::Transaction<rocksdb::OptimisticTransactionDB>,
-    ) -> Result<KeyIndex> {
-        let Some(value) = txn
-            .get_for_update_cf(self.cf, self.key, EXCLUSIVE)
-            .context("database error")?
-        else {
-            return Ok(KeyIndex::default());
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<()> {
+        record_change(self.db, txn, self.cf, self.cf_name, key, Some(value))?;
+        txn.put_cf(self.cf, key, value)?;
+        Ok(())
+    }
+
+    /// Deletes the RocksDB key `key` in `txn`, recording the change in the
+    /// change log.
+    fn delete(
+        &self,
+        txn: &rocksdb::Transaction<rocksdb::OptimisticTransactionDB>,
+        key: &[u8],
+    ) -> Result<()> {
+        record_change(self.db, txn, self.cf, self.cf_name, key, None)?;
+        txn.delete_cf(self.cf, key)?;
+        Ok(())
+    }
+
+    fn view<'t>(
+        &'t self,
+        txn: &'t rocksdb::Transaction<'t, rocksdb::OptimisticTransactionDB>,
+    ) -> SetView<'t> {
+        SetView {
+            txn,
+            cf: self.cf,
+            name: self.name,
+        }
+    }
+}
+
+/// Reads the entries `iter` yields while their RocksDB keys start with
+/// `prefix`, passing the rest of each key and its value to `f`.
+fn entries_with_prefix<I, F>(iter: I, prefix: &[u8], f: F) -> Result<Vec<(u32, Vec<u8>)>>
+where
+    I: Iterator<Item = Result<(Box<[u8]>, Box<[u8]>), rocksdb::Error>>,
+    F: Fn(&[u8], &[u8]) -> Result<(u32, Vec<u8>)>,
+{
+    let mut entries = Vec::new();
+    for item in iter {
+        let (key, value) = item.context("database error")?;
+        let Some(rest) = key.strip_prefix(prefix) else {
+            break;
         };
-        KeyIndex::from_bytes(value).context("invalid index in database")
+        entries.push(f(rest, &value).context("invalid entry in database")?);
     }
+    Ok(entries)
+}
+
+/// Returns the prefix of the RocksDB keys of the given kind in set `name`.
+fn entry_prefix(name: &[u8], tag: u8) -> Vec<u8> {
+    let mut prefix = Vec::with_capacity(name.len() + 2);
+    prefix.extend_from_slice(name);
+    prefix.push(0);
+    prefix.push(tag);
+    prefix
+}
+
+/// Returns the RocksDB key mapping `id` to its key in set `name`.
+fn id_key(name: &[u8], id: u32) -> Vec<u8> {
+    let mut key = entry_prefix(name, ID_TAG);
+    key.extend_from_slice(&id.to_be_bytes());
+    key
+}
+
+/// Returns the RocksDB key mapping `key` to its ID in set `name`.
+fn key_key(name: &[u8], key: &[u8]) -> Vec<u8> {
+    let mut full = entry_prefix(name, KEY_TAG);
+    full.extend_from_slice(key);
+    full
+}
+
+/// Returns the RocksDB key mapping `id` to its value in set `name`.
+fn value_key(name: &[u8], id: u32) -> Vec<u8> {
+    let mut key = entry_prefix(name, VALUE_TAG);
+    key.extend_from_slice(&id.to_be_bytes());
+    key
+}
+
+fn decode_id(bytes: &[u8]) -> Result<u32> {
+    Ok(u32::from_be_bytes(
+        bytes.try_into().context("invalid ID in database")?,
+    ))
 }
 
 #[cfg(test)]
 mod tests {
+    use anyhow::anyhow;
+
     use crate::test;
 
     #[test]
-    fn deactivate() {
+    fn insert_rejects_duplicates() {
         let db = test::Store::new();
         let set = db.indexed_set();
         let id_a = set.insert(b"a").unwrap();
         assert_eq!(id_a, 0);
         let id_b = set.insert(b"b").unwrap();
         assert_eq!(id_b, 1);
+        assert!(set.insert(b"a").is_err());
 
-        let key = set.deactivate(id_b).unwrap();
-        assert_eq!(key, b"b");
-
-        let index = set.index().unwrap();
-        let mut iter = index.iter();
-        assert!(iter.next().is_some());
-        assert!(iter.next().is_none());
+        assert_eq!(set.id(b"a").unwrap(), Some(id_a));
+        assert_eq!(set.id(b"c").unwrap(), None);
+        assert_eq!(set.get(id_b).unwrap().as_deref(), Some(&b"b"[..]));
+        assert_eq!(
+            set.entries().unwrap(),
+            vec![(id_a, b"a".to_vec()), (id_b, b"b".to_vec())]
+        );
     }
 
     #[test]
@@ -251,10 +579,68 @@ mod tests {
 
         let key = set.remove(id_b).unwrap();
         assert_eq!(key, b"b");
+        assert!(set.remove(id_b).is_err());
+        assert_eq!(set.id(b"b").unwrap(), None);
+        assert_eq!(set.entries().unwrap().len(), 1);
+
+        // A removed ID is never assigned again.
+        let id_b = set.insert(b"b").unwrap();
+        assert_eq!(id_b, 2);
+    }
+
+    #[test]
+    fn update() {
+        let db = test::Store::new();
+        let set = db.indexed_set();
+        let id_a = set.insert(b"a").unwrap();
+        let id_b = set.insert(b"b").unwrap();
+
+        assert!(set.update(id_a, b"b", b"c").is_ok_and(|updated| !updated));
+        assert!(set.update(id_a, b"a", b"b").is_err());
+        assert_eq!(set.get(id_a).unwrap().as_deref(), Some(&b"a"[..]));
+
+        assert!(set.update(id_a, b"a", b"c").unwrap());
+        assert_eq!(set.id(b"a").unwrap(), None);
+        assert_eq!(set.id(b"c").unwrap(), Some(id_a));
+        assert_eq!(set.id(b"b").unwrap(), Some(id_b));
+
+        set.insert(b"ca").unwrap();
+        let entries = set.entries_starting_with(b"c").unwrap();
+        assert_eq!(entries, vec![(id_a, b"c".to_vec()), (3, b"ca".to_vec())]);
+    }
+
+    #[test]
+    fn values() {
+        let db = test::Store::new();
+        let set = db.indexed_set();
+        let id_a = set.insert_with_value(b"a", b"1", |_| Ok(())).unwrap();
+        let id_b = set.insert(b"b").unwrap();
+        assert_eq!(set.value(id_a).unwrap().as_deref(), Some(&b"1"[..]));
+        assert_eq!(set.value(id_b).unwrap(), None);
+
+        assert!(
+            !set.update_value(id_b, Some(b"1"), b"2", |_| Ok(()))
+                .unwrap()
+        );
+        assert!(set.update_value(id_b, None, b"2", |_| Ok(())).unwrap());
+        assert!(!set.update_value(5, None, b"2", |_| Ok(())).unwrap());
+        assert!(
+            set.update_value(id_a, Some(b"1"), b"3", |_| Err(anyhow!("refused")))
+                .is_err()
+        );
+        assert_eq!(set.value(id_a).unwrap().as_deref(), Some(&b"1"[..]));
+        assert_eq!(
+            set.values().unwrap(),
+            vec![(id_a, b"1".to_vec()), (id_b, b"2".to_vec())]
+        );
 
-        let index = set.index().unwrap();
-        let mut iter = index.iter();
-        assert!(iter.next().is_some());
-        assert!(iter.next().is_none());
+        assert!(set.remove_all(|_| Ok(vec![id_a, 5])).is_err());
+        assert_eq!(set.entries().unwrap().len(), 2);
+        // The IDs are selected from the set as the removal reads it.
+        let removed = set
+            .remove_all(|view| Ok(view.values()?.into_iter().rev().map(|(id, _)| id).collect()))
+            .unwrap();
+        assert_eq!(removed, vec![b"b".to_vec(), b"a".to_vec()]);
+        assert!(set.values().unwrap().is_empty());
     }
 }
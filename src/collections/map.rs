@@ -1,17 +1,21 @@
 use anyhow::{Context, Result, anyhow, bail};
 use rocksdb::IteratorMode;
 
-use crate::EXCLUSIVE;
+use crate::{EXCLUSIVE, tables::record_change};
 
 #[derive(Clone)]
 pub struct Map<'a> {
     pub(crate) db: &'a rocksdb::OptimisticTransactionDB,
     pub(crate) cf: &'a rocksdb::ColumnFamily,
+    name: &'static str,
 }
 
 impl<'a> Map<'a> {
-    pub(crate) fn open(db: &'a rocksdb::OptimisticTransactionDB, name: &str) -> Option<Self> {
-        db.cf_handle(name).map(|cf| Self { db, cf })
+    pub(crate) fn open(
+        db: &'a rocksdb::OptimisticTransactionDB,
+        name: &'static str,
+    ) -> Option<Self> {
+        db.cf_handle(name).map(|cf| Self { db, cf, name })
     }
 
     /// Deletes a key-value pair with the given key.
@@ -20,9 +24,19 @@ impl<'a> Map<'a> {
     ///
     /// Returns an error if the key does not exist or the database operation fails.
     pub fn delete(&self, key: &[u8]) -> Result<(), anyhow::Error> {
-        self.db
-            .delete_cf(self.cf, key)
-            .map_err(|e| anyhow!("database error: {e}"))
+        loop {
+            let txn = self.db.transaction();
+            self.delete_with_transaction(key, &txn)?;
+            match txn.commit() {
+                Ok(()) => break,
+                Err(e) => {
+                    if !e.as_ref().starts_with("Resource busy:") {
+                        return Err(e).context("failed to delete entry");
+                    }
+                }
+            }
+        }
+        Ok(())
     }
 
     /// Deletes a key-value pair with the given key within a transaction.
@@ -35,6 +49,7 @@ impl<'a> Map<'a> {
         key: &[u8],
         txn: &rocksdb::Transaction<rocksdb::OptimisticTransactionDB>,
     ) -> Result<()> {
+        self.record(txn, key, None)?;
         txn.delete_cf(self.cf, key)
             .map_err(|e| anyhow!("database error: {e}"))
     }
@@ -56,9 +71,19 @@ impl<'a> Map<'a> {
     ///
     /// Returns an error if the database operation fails.
     pub fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
-        self.db
-            .put_cf(self.cf, key, value)
-            .map_err(|e| anyhow!("database error: {e}"))
+        loop {
+            let txn = self.db.transaction();
+            self.put_with_transaction(key, value, &txn)?;
+            match txn.commit() {
+                Ok(()) => break,
+                Err(e) => {
+                    if !e.as_ref().starts_with("Resource busy:") {
+                        return Err(e).context("failed to write entry");
+                    }
+                }
+            }
+        }
+        Ok(())
     }
 
     /// Puts a key-value pair within a transaction, overwriting any existing value for the key.
@@ -72,6 +97,7 @@ impl<'a> Map<'a> {
         value: &[u8],
         txn: &rocksdb::Transaction<rocksdb::OptimisticTransactionDB>,
     ) -> Result<()> {
+        self.record(txn, key, Some(value))?;
         txn.put_cf(self.cf, key, value)
             .context("failed to write entry")
     }
@@ -82,27 +108,22 @@ impl<'a> Map<'a> {
     ///
     /// Returns an error if the key already exists or the database operation fails.
     pub fn insert(&self, key: &[u8], value: &[u8]) -> Result<()> {
-        let txn = self.db.transaction();
-        if txn
-            .get_for_update_cf(self.cf, key, EXCLUSIVE)
-            .context("database read error")?
-            .is_some()
-        {
-            bail!("key already exists");
-        }
-        txn.put_cf(self.cf, key, value)
-            .context("failed to write new entry")?;
-
-        match txn.commit() {
-            Ok(()) => Ok(()),
-            Err(e) => {
-                if e.as_ref().starts_with("Resource busy:") {
-                    Err(anyhow!("already exists"))
-                } else {
-                    Err(e).context("failed to insert entry")
+        loop {
+            let txn = self.db.transaction();
+            self.insert_with_transaction(key, value, &txn)?;
+            match txn.commit() {
+                Ok(()) => break,
+                Err(e) => {
+                    // The conflict may be over the next sequence number of
+                    // the change log rather than over `key`, which trying
+                    // again tells apart.
+                    if !e.as_ref().starts_with("Resource busy:") {
+                        return Err(e).context("failed to insert entry");
+                    }
                 }
             }
         }
+        Ok(())
     }
 
     /// Inserts a new key-value pair within a transaction.
@@ -123,6 +144,7 @@ impl<'a> Map<'a> {
         {
             bail!("key already exists");
         }
+        self.record(txn, key, Some(value))?;
         txn.put_cf(self.cf, key, value)
             .context("failed to write new entry")
     }
@@ -136,7 +158,13 @@ impl<'a> Map<'a> {
         loop {
             let txn = self.db.transaction();
 
+            for (key, value) in new {
+                self.record(&txn, key, Some(value))?;
+            }
             for (old_key, _) in self.inner_iterator(IteratorMode::Start) {
+                if !new.iter().any(|(key, _)| **key == *old_key) {
+                    self.record(&txn, &old_key, None)?;
+                }
                 txn.delete_cf(self.cf, old_key)
                     .context("failed to delete entries")?;
             }
@@ -180,6 +208,7 @@ impl<'a> Map<'a> {
             }
 
             if old.0 != new.0 {
+                self.record(&txn, old.0, None)?;
                 txn.delete_cf(self.cf, old.0)
                     .context("failed to delete old entry")?;
                 if txn
@@ -190,6 +219,7 @@ impl<'a> Map<'a> {
                     bail!("new key already exists");
                 }
             }
+            self.record(&txn, new.0, Some(new.1))?;
             txn.put_cf(self.cf, new.0, new.1)
                 .context("failed to write new entry")?;
 
@@ -229,6 +259,7 @@ impl<'a> Map<'a> {
         }
 
         if old.0 != new.0 {
+            self.record(txn, old.0, None)?;
             txn.delete_cf(self.cf, old.0)
                 .context("failed to delete old entry")?;
             if txn
@@ -239,6 +270,7 @@ impl<'a> Map<'a> {
                 bail!("new key already exists");
             }
         }
+        self.record(txn, new.0, Some(new.1))?;
         txn.put_cf(self.cf, new.0, new.1)
             .context("failed to write new entry")
     }
@@ -252,23 +284,7 @@ impl<'a> Map<'a> {
     pub fn update_compare_multi(&self, updates: &[(&[u8], &[u8], &[u8])]) -> Result<()> {
         loop {
             let txn = self.db.transaction();
-
-            for (key, old_val, new_val) in updates {
-                if let Some(current_val) = txn
-                    .get_for_update_cf(self.cf, key, EXCLUSIVE)
-                    .context("cannot read old entry")?
-                {
-                    if current_val.as_slice() != *old_val {
-                        bail!("old value mismatch");
-                    }
-                } else {
-                    bail!("no such entry");
-                }
-
-                txn.put_cf(self.cf, key, new_val)
-                    .context("failed to write new entry")?;
-            }
-
+            self.update_compare_multi_with_transaction(updates, &txn)?;
             match txn.commit() {
                 Ok(()) => break,
                 Err(e) => {
@@ -281,6 +297,37 @@ impl<'a> Map<'a> {
         Ok(())
     }
 
+    /// Updates multiple key-value pairs with compare-and-swap semantics within
+    /// a transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any old value does not match, any key does not exist,
+    /// or database operation fails.
+    pub fn update_compare_multi_with_transaction(
+        &self,
+        updates: &[(&[u8], &[u8], &[u8])],
+        txn: &rocksdb::Transaction<rocksdb::OptimisticTransactionDB>,
+    ) -> Result<()> {
+        for (key, old_val, new_val) in updates {
+            if let Some(current_val) = txn
+                .get_for_update_cf(self.cf, key, EXCLUSIVE)
+                .context("cannot read old entry")?
+            {
+                if current_val.as_slice() != *old_val {
+                    bail!("old value mismatch");
+                }
+            } else {
+                bail!("no such entry");
+            }
+
+            self.record(txn, key, Some(new_val))?;
+            txn.put_cf(self.cf, key, new_val)
+                .context("failed to write new entry")?;
+        }
+        Ok(())
+    }
+
     /// Inserts multiple key-value pairs atomically.
     ///
     /// # Errors
@@ -299,6 +346,7 @@ impl<'a> Map<'a> {
                     bail!("key already exists: {:?}", String::from_utf8_lossy(key));
                 }
 
+                self.record(&txn, key, Some(val))?;
                 txn.put_cf(self.cf, key, val)
                     .context("failed to write new entry")?;
             }
@@ -315,6 +363,15 @@ impl<'a> Map<'a> {
         Ok(())
     }
 
+    fn record(
+        &self,
+        txn: &rocksdb::Transaction<rocksdb::OptimisticTransactionDB>,
+        key: &[u8],
+        new_value: Option<&[u8]>,
+    ) -> Result<()> {
+        record_change(self.db, txn, self.cf, self.name, key, new_value)
+    }
+
     fn inner_iterator(&self, mode: IteratorMode) -> MapIterator<'_> {
         let iter = self.db.iterator_cf(self.cf, mode);
 
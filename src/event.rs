@@ -1,29 +1,41 @@
 #![allow(clippy::too_many_lines)]
+mod aggregate;
 mod bootp;
 mod common;
 mod conn;
 mod dcerpc;
 mod dhcp;
 mod dns;
+mod explain;
 mod ftp;
 mod http;
+mod index;
+mod jsonl;
 mod kerberos;
 mod ldap;
 mod log;
 mod malformed_dns;
+mod mapping;
 mod mqtt;
 mod network;
 mod nfs;
 mod ntlm;
+mod page;
+mod preview;
 mod radius;
 mod rdp;
+mod rollup;
+mod siem;
 mod smb;
 mod smtp;
 mod ssh;
+mod stix;
+mod subscribe;
 mod sysmon;
 pub(crate) mod timestamp;
 mod tls;
 mod tor;
+mod triage;
 mod unusual_destination_pattern;
 
 #[cfg(test)]
@@ -33,6 +45,7 @@ use std::{
     collections::{HashMap, HashSet},
     convert::TryInto,
     fmt::{self},
+    io::{BufRead, Write},
     net::IpAddr,
 };
 
@@ -51,6 +64,56 @@ use tracing::warn;
 #[cfg(test)]
 pub(crate) use self::common::tests::stored_event_samples_v0_46;
 pub(crate) use self::conn::{BlocklistConnFieldsStored, MultiHostPortScanFieldsStored};
+pub(crate) use self::subscribe::Subscribers;
+pub use self::{
+    aggregate::{Aggregation, Dimension, Granularity},
+    bootp::{BlocklistBootp, BlocklistBootpFields},
+    common::TriageScore,
+    conn::{
+        BlocklistConn, BlocklistConnFields, ExternalDdos, ExternalDdosFields, MultiHostPortScan,
+        MultiHostPortScanFields, PortScan, PortScanFields,
+    },
+    dcerpc::{BlocklistDceRpc, BlocklistDceRpcFields, DceRpcContext},
+    dhcp::{BlocklistDhcp, BlocklistDhcpFields},
+    dns::{
+        BlocklistDns, BlocklistDnsFields, CryptocurrencyMiningPool, CryptocurrencyMiningPoolFields,
+        DnsCovertChannel, DnsEventFields, LockyRansomware,
+    },
+    explain::{ConfidenceContribution, RuleContribution, TriageExplanation},
+    ftp::{
+        BlocklistFtp, FtpBruteForce, FtpBruteForceFields, FtpCommand, FtpEventFields, FtpPlainText,
+    },
+    http::{
+        BlocklistHttp, BlocklistHttpFields, DgaFields, DomainGenerationAlgorithm, HttpEventFields,
+        HttpThreat, HttpThreatFields, NonBrowser, RepeatedHttpSessions, RepeatedHttpSessionsFields,
+    },
+    jsonl::JSONL_SCHEMA_VERSION,
+    kerberos::{BlocklistKerberos, BlocklistKerberosFields},
+    ldap::{BlocklistLdap, LdapBruteForce, LdapBruteForceFields, LdapEventFields, LdapPlainText},
+    log::{ExtraThreat, ExtraThreatFields},
+    malformed_dns::{BlocklistMalformedDns, BlocklistMalformedDnsFields},
+    mapping::OcsfClass,
+    mqtt::{BlocklistMqtt, BlocklistMqttFields},
+    network::{NetworkThreat, NetworkThreatFields},
+    nfs::{BlocklistNfs, BlocklistNfsFields},
+    ntlm::{BlocklistNtlm, BlocklistNtlmFields},
+    page::{Cursor, Page, PageRequest},
+    preview::{PolicyImpact, PolicyImpactPreview, SCORE_BUCKET_WIDTH, ScoreDistribution},
+    radius::{BlocklistRadius, BlocklistRadiusFields},
+    rdp::{BlocklistRdp, BlocklistRdpFields, RdpBruteForce, RdpBruteForceFields},
+    rollup::Rollup,
+    siem::{Device, SiemFormat},
+    smb::{BlocklistSmb, BlocklistSmbFields},
+    smtp::{BlocklistSmtp, BlocklistSmtpFields},
+    ssh::{BlocklistSsh, BlocklistSshFields},
+    stix::StixBundle,
+    subscribe::{EventNotice, Subscription},
+    sysmon::{WindowsThreat, WindowsThreatFields},
+    tls::{BlocklistTls, BlocklistTlsFields, SuspiciousTlsTraffic},
+    tor::{TorConnection, TorConnectionConn},
+    triage::CompiledTriagePolicy,
+    unusual_destination_pattern::{UnusualDestinationPattern, UnusualDestinationPatternFields},
+};
 use self::{
     bootp::BlocklistBootpFieldsStored,
     common::Match,
@@ -61,6 +124,7 @@ use self::{
         BlocklistHttpFieldsStored, DgaFieldsStored, HttpEventFieldsStored,
         RepeatedHttpSessionsFieldsStored,
     },
+    index::Index,
     kerberos::BlocklistKerberosFieldsStored,
     ldap::{LdapBruteForceFieldsStored, LdapEventFieldsStored},
     log::ExtraThreatFieldsStored,
@@ -112,46 +176,13 @@ pub(crate) use self::{
     tls::BlocklistTlsFieldsStoredV0_46,
     unusual_destination_pattern::UnusualDestinationPatternFieldsStoredV0_46,
 };
-pub use self::{
-    bootp::{BlocklistBootp, BlocklistBootpFields},
-    common::TriageScore,
-    conn::{
-        BlocklistConn, BlocklistConnFields, ExternalDdos, ExternalDdosFields, MultiHostPortScan,
-        MultiHostPortScanFields, PortScan, PortScanFields,
-    },
-    dcerpc::{BlocklistDceRpc, BlocklistDceRpcFields, DceRpcContext},
-    dhcp::{BlocklistDhcp, BlocklistDhcpFields},
-    dns::{
-        BlocklistDns, BlocklistDnsFields, CryptocurrencyMiningPool, CryptocurrencyMiningPoolFields,
-        DnsCovertChannel, DnsEventFields, LockyRansomware,
-    },
-    ftp::{
-        BlocklistFtp, FtpBruteForce, FtpBruteForceFields, FtpCommand, FtpEventFields, FtpPlainText,
-    },
-    http::{
-        BlocklistHttp, BlocklistHttpFields, DgaFields, DomainGenerationAlgorithm, HttpEventFields,
-        HttpThreat, HttpThreatFields, NonBrowser, RepeatedHttpSessions, RepeatedHttpSessionsFields,
-    },
-    kerberos::{BlocklistKerberos, BlocklistKerberosFields},
-    ldap::{BlocklistLdap, LdapBruteForce, LdapBruteForceFields, LdapEventFields, LdapPlainText},
-    log::{ExtraThreat, ExtraThreatFields},
-    malformed_dns::{BlocklistMalformedDns, BlocklistMalformedDnsFields},
-    mqtt::{BlocklistMqtt, BlocklistMqttFields},
-    network::{NetworkThreat, NetworkThreatFields},
-    nfs::{BlocklistNfs, BlocklistNfsFields},
-    ntlm::{BlocklistNtlm, BlocklistNtlmFields},
-    radius::{BlocklistRadius, BlocklistRadiusFields},
-    rdp::{BlocklistRdp, BlocklistRdpFields, RdpBruteForce, RdpBruteForceFields},
-    smb::{BlocklistSmb, BlocklistSmbFields},
-    smtp::{BlocklistSmtp, BlocklistSmtpFields},
-    ssh::{BlocklistSsh, BlocklistSshFields},
-    sysmon::{WindowsThreat, WindowsThreatFields},
-    tls::{BlocklistTls, BlocklistTlsFields, SuspiciousTlsTraffic},
-    tor::{TorConnection, TorConnectionConn},
-    unusual_destination_pattern::{UnusualDestinationPattern, UnusualDestinationPatternFields},
-};
 use super::{
-    Customer, EventCategory, Network, TriageExclusion, TriagePolicyInput,
+    Customer, EventCategory, Network, ResponseAction, ResponseActionKind, ResponseKind,
+    ResponseTargets, TriageExclusion, TriagePolicyInput,
+    tables::{
+        EVENT_CONSUMER_OFFSETS, EVENT_INDEXES, EVENT_ROLLUP_STATE, EVENT_ROLLUPS,
+        EVENT_TRIAGE_RESULTS, META,
+    },
     types::{Endpoint, HostNetworkGroup},
 };
 
@@ -631,11 +662,14 @@ impl Event {
 
     /// Returns whether any of `exclusions` matches this event.
     ///
-    /// Dispatches through each event variant's `score_by_triage_exclusion`
-    /// override so per-event semantics (DNS domain/hostname, HTTP URI/host,
-    /// Tor domain, etc.) are preserved. The default implementation handles
-    /// the `IpAddress` variant only; richer exclusion shapes for events
-    /// without an override are not currently matched.
+    /// Domain, hostname, URI, user-agent, and JA3 exclusions are compared with
+    /// the names each variant exposes through `exclusion_targets`: the query
+    /// of a DNS event, with its first label as the hostname, the host, URI,
+    /// and user agent of an HTTP event, and the server name and JA3
+    /// fingerprint of a TLS event. The other variants are
+    /// matched by the `IpAddress` exclusions only. A scoped exclusion matches
+    /// only an event from within its validity period, of the kind and from
+    /// the sensor its scope names, if any.
     #[must_use]
     pub fn matches_exclusion(&self, exclusions: &[TriageExclusion]) -> bool {
         match self {
@@ -690,7 +724,7 @@ impl Event {
     /// Computes inline triage scores for this event against each of `policies`.
     ///
     /// Each policy contributes a `TriageScore` only when
-    /// `score_by_attr + score_by_confidence` reaches at least one of the
+    /// `score_by_rules + score_by_confidence` reaches at least one of the
     /// policy's `response.minimum_score` thresholds; policies whose
     /// `response` is empty contribute nothing. Each policy's
     /// `triage_exclusion` is treated as already applied by the caller and
@@ -750,486 +784,286 @@ impl Event {
         }
     }
 
+    /// Explains the score of this event against each of `policies`, in order.
+    ///
+    /// The scores are the ones filtering by the policies gives, each broken
+    /// down into the exclusions, rules, and confidence entries of the policy
+    /// that contributed to it. Unlike filtering, every policy is explained,
+    /// whether or not the score reaches a response threshold.
+    #[must_use]
+    pub fn explain_triage(&self, policies: &[TriagePolicyInput]) -> Vec<TriageExplanation> {
+        let event = self.as_match();
+        policies
+            .iter()
+            .map(|policy| event.explain_policy(policy))
+            .collect()
+    }
+
+    /// Returns the actions the responses of `policies` call for, where the
+    /// score of this event, stored under `key`, reaches their
+    /// `minimum_score`.
+    ///
+    /// A `Blacklist` or a `Whitelist` response calls for adding each
+    /// originator of the event to the network `targets` names for it, and
+    /// calls for nothing if `targets` names none. A `Manual` response calls
+    /// for tagging the event with the tags of `targets`.
+    #[must_use]
+    pub fn response_actions(
+        &self,
+        key: i128,
+        policies: &[TriagePolicyInput],
+        targets: &ResponseTargets,
+    ) -> Vec<ResponseAction> {
+        let event = self.as_match();
+        let mut actions = Vec::new();
+        for (policy, explanation) in policies.iter().zip(self.explain_triage(policies)) {
+            if !explanation.scored {
+                continue;
+            }
+            for response in &policy.response {
+                if explanation.score < response.minimum_score {
+                    continue;
+                }
+                let kinds: Vec<_> = match response.kind {
+                    ResponseKind::Blacklist => {
+                        targets
+                            .block_network_id
+                            .map_or_else(Vec::new, |network_id| {
+                                event
+                                    .orig_addrs()
+                                    .iter()
+                                    .map(|&addr| ResponseActionKind::Block { network_id, addr })
+                                    .collect()
+                            })
+                    }
+                    ResponseKind::Whitelist => {
+                        targets
+                            .allow_network_id
+                            .map_or_else(Vec::new, |network_id| {
+                                event
+                                    .orig_addrs()
+                                    .iter()
+                                    .map(|&addr| ResponseActionKind::Allow { network_id, addr })
+                                    .collect()
+                            })
+                    }
+                    ResponseKind::Manual => vec![ResponseActionKind::Tag {
+                        sensor: event.sensor().to_string(),
+                        time: timestamp::event_key_time(self.time()),
+                        tag_ids: targets.tag_ids.clone(),
+                    }],
+                };
+                actions.extend(
+                    kinds
+                        .into_iter()
+                        .map(|kind| ResponseAction::new(kind, policy.id, key, explanation.score)),
+                );
+            }
+        }
+        actions
+    }
+
+    /// Returns the event as the `Match` implementation of its variant.
+    ///
+    /// Accessors every variant shares, such as the addresses and the sensor,
+    /// can then be read without matching on each variant in turn.
+    fn as_match(&self) -> &dyn Match {
+        match self {
+            Event::DnsCovertChannel(event) => event,
+            Event::HttpThreat(event) => event,
+            Event::RdpBruteForce(event) => event,
+            Event::RepeatedHttpSessions(event) => event,
+            Event::TorConnection(event) => event,
+            Event::TorConnectionConn(event) => event,
+            Event::DomainGenerationAlgorithm(event) => event,
+            Event::FtpBruteForce(event) => event,
+            Event::FtpPlainText(event) => event,
+            Event::PortScan(event) => event,
+            Event::MultiHostPortScan(event) => event,
+            Event::ExternalDdos(event) => event,
+            Event::NonBrowser(event) => event,
+            Event::LdapBruteForce(event) => event,
+            Event::LdapPlainText(event) => event,
+            Event::CryptocurrencyMiningPool(event) => event,
+            Event::Blocklist(record_type) => match record_type {
+                RecordType::Bootp(event) => event,
+                RecordType::Conn(event) => event,
+                RecordType::DceRpc(event) => event,
+                RecordType::Dhcp(event) => event,
+                RecordType::Dns(event) => event,
+                RecordType::Ftp(event) => event,
+                RecordType::Http(event) => event,
+                RecordType::Kerberos(event) => event,
+                RecordType::Ldap(event) => event,
+                RecordType::MalformedDns(event) => event,
+                RecordType::Mqtt(event) => event,
+                RecordType::Nfs(event) => event,
+                RecordType::Ntlm(event) => event,
+                RecordType::Radius(event) => event,
+                RecordType::Rdp(event) => event,
+                RecordType::Smb(event) => event,
+                RecordType::Smtp(event) => event,
+                RecordType::Ssh(event) => event,
+                RecordType::Tls(event) => event,
+                RecordType::UnusualDestinationPattern(event) => event,
+            },
+            Event::WindowsThreat(event) => event,
+            Event::NetworkThreat(event) => event,
+            Event::ExtraThreat(event) => event,
+            Event::LockyRansomware(event) => event,
+            Event::SuspiciousTlsTraffic(event) => event,
+        }
+    }
+
     fn address_pair(&self, filter: &EventFilter) -> Result<(Option<IpAddr>, Option<IpAddr>)> {
-        let mut addr_pair = (None, None);
+        let addr_pair = self.addresses();
+        if addr_pair == (None, None) || !self.matches(filter)?.0 {
+            return Ok((None, None));
+        }
+        Ok(addr_pair)
+    }
+
+    /// Returns the originator and responder addresses the per-address counters
+    /// use for the event, regardless of any filter.
+    fn addresses(&self) -> (Option<IpAddr>, Option<IpAddr>) {
         match self {
-            Event::DnsCovertChannel(event) => {
-                if event.matches(filter)?.0 {
-                    addr_pair = (Some(event.orig_addr), Some(event.resp_addr));
-                }
-            }
-            Event::HttpThreat(event) => {
-                if event.matches(filter)?.0 {
-                    addr_pair = (Some(event.orig_addr), Some(event.resp_addr));
-                }
-            }
-            Event::RdpBruteForce(event) => {
-                if event.matches(filter)?.0 {
-                    addr_pair = (Some(event.orig_addr), None);
-                }
-            }
-            Event::RepeatedHttpSessions(event) => {
-                if event.matches(filter)?.0 {
-                    addr_pair = (Some(event.orig_addr), Some(event.resp_addr));
-                }
-            }
-            Event::TorConnection(event) => {
-                if event.matches(filter)?.0 {
-                    addr_pair = (Some(event.orig_addr), Some(event.resp_addr));
-                }
-            }
-            Event::TorConnectionConn(event) => {
-                if event.matches(filter)?.0 {
-                    addr_pair = (Some(event.orig_addr), Some(event.resp_addr));
-                }
-            }
+            Event::DnsCovertChannel(event) => (Some(event.orig_addr), Some(event.resp_addr)),
+            Event::HttpThreat(event) => (Some(event.orig_addr), Some(event.resp_addr)),
+            Event::RdpBruteForce(event) => (Some(event.orig_addr), None),
+            Event::RepeatedHttpSessions(event) => (Some(event.orig_addr), Some(event.resp_addr)),
+            Event::TorConnection(event) => (Some(event.orig_addr), Some(event.resp_addr)),
+            Event::TorConnectionConn(event) => (Some(event.orig_addr), Some(event.resp_addr)),
             Event::DomainGenerationAlgorithm(event) => {
-                if event.matches(filter)?.0 {
-                    addr_pair = (Some(event.orig_addr), Some(event.resp_addr));
-                }
-            }
-            Event::FtpBruteForce(event) => {
-                if event.matches(filter)?.0 {
-                    addr_pair = (Some(event.orig_addr), Some(event.resp_addr));
-                }
-            }
-            Event::FtpPlainText(event) => {
-                if event.matches(filter)?.0 {
-                    addr_pair = (Some(event.orig_addr), Some(event.resp_addr));
-                }
-            }
-            Event::PortScan(event) => {
-                if event.matches(filter)?.0 {
-                    addr_pair = (Some(event.orig_addr), Some(event.resp_addr));
-                }
-            }
-            Event::MultiHostPortScan(event) => {
-                if event.matches(filter)?.0 {
-                    addr_pair = (Some(event.orig_addr), None);
-                }
-            }
-            Event::ExternalDdos(event) => {
-                if event.matches(filter)?.0 {
-                    addr_pair = (None, Some(event.resp_addr));
-                }
-            }
-            Event::NonBrowser(event) => {
-                if event.matches(filter)?.0 {
-                    addr_pair = (Some(event.orig_addr), Some(event.resp_addr));
-                }
-            }
-            Event::LdapBruteForce(event) => {
-                if event.matches(filter)?.0 {
-                    addr_pair = (Some(event.orig_addr), Some(event.resp_addr));
-                }
-            }
-            Event::LdapPlainText(event) => {
-                if event.matches(filter)?.0 {
-                    addr_pair = (Some(event.orig_addr), Some(event.resp_addr));
-                }
-            }
+                (Some(event.orig_addr), Some(event.resp_addr))
+            }
+            Event::FtpBruteForce(event) => (Some(event.orig_addr), Some(event.resp_addr)),
+            Event::FtpPlainText(event) => (Some(event.orig_addr), Some(event.resp_addr)),
+            Event::PortScan(event) => (Some(event.orig_addr), Some(event.resp_addr)),
+            Event::MultiHostPortScan(event) => (Some(event.orig_addr), None),
+            Event::ExternalDdos(event) => (None, Some(event.resp_addr)),
+            Event::NonBrowser(event) => (Some(event.orig_addr), Some(event.resp_addr)),
+            Event::LdapBruteForce(event) => (Some(event.orig_addr), Some(event.resp_addr)),
+            Event::LdapPlainText(event) => (Some(event.orig_addr), Some(event.resp_addr)),
             Event::CryptocurrencyMiningPool(event) => {
-                if event.matches(filter)?.0 {
-                    addr_pair = (Some(event.orig_addr), Some(event.resp_addr));
-                }
+                (Some(event.orig_addr), Some(event.resp_addr))
             }
             Event::Blocklist(record_type) => match record_type {
                 RecordType::Bootp(bootp_event) => {
-                    if bootp_event.matches(filter)?.0 {
-                        addr_pair = (Some(bootp_event.orig_addr), Some(bootp_event.resp_addr));
-                    }
+                    (Some(bootp_event.orig_addr), Some(bootp_event.resp_addr))
                 }
                 RecordType::Conn(conn_event) => {
-                    if conn_event.matches(filter)?.0 {
-                        addr_pair = (Some(conn_event.orig_addr), Some(conn_event.resp_addr));
-                    }
+                    (Some(conn_event.orig_addr), Some(conn_event.resp_addr))
                 }
                 RecordType::DceRpc(dcerpc_event) => {
-                    if dcerpc_event.matches(filter)?.0 {
-                        addr_pair = (Some(dcerpc_event.orig_addr), Some(dcerpc_event.resp_addr));
-                    }
+                    (Some(dcerpc_event.orig_addr), Some(dcerpc_event.resp_addr))
                 }
                 RecordType::Dhcp(dhcp_event) => {
-                    if dhcp_event.matches(filter)?.0 {
-                        addr_pair = (Some(dhcp_event.orig_addr), Some(dhcp_event.resp_addr));
-                    }
+                    (Some(dhcp_event.orig_addr), Some(dhcp_event.resp_addr))
                 }
                 RecordType::Dns(dns_event) => {
-                    if dns_event.matches(filter)?.0 {
-                        addr_pair = (Some(dns_event.orig_addr), Some(dns_event.resp_addr));
-                    }
+                    (Some(dns_event.orig_addr), Some(dns_event.resp_addr))
                 }
                 RecordType::Ftp(ftp_event) => {
-                    if ftp_event.matches(filter)?.0 {
-                        addr_pair = (Some(ftp_event.orig_addr), Some(ftp_event.resp_addr));
-                    }
+                    (Some(ftp_event.orig_addr), Some(ftp_event.resp_addr))
                 }
                 RecordType::Http(http_event) => {
-                    if http_event.matches(filter)?.0 {
-                        addr_pair = (Some(http_event.orig_addr), Some(http_event.resp_addr));
-                    }
-                }
-                RecordType::Kerberos(kerberos_event) => {
-                    if kerberos_event.matches(filter)?.0 {
-                        addr_pair = (
-                            Some(kerberos_event.orig_addr),
-                            Some(kerberos_event.resp_addr),
-                        );
-                    }
+                    (Some(http_event.orig_addr), Some(http_event.resp_addr))
                 }
+                RecordType::Kerberos(kerberos_event) => (
+                    Some(kerberos_event.orig_addr),
+                    Some(kerberos_event.resp_addr),
+                ),
                 RecordType::Ldap(ldap_event) => {
-                    if ldap_event.matches(filter)?.0 {
-                        addr_pair = (Some(ldap_event.orig_addr), Some(ldap_event.resp_addr));
-                    }
-                }
-                RecordType::MalformedDns(malformed_dns_event) => {
-                    if malformed_dns_event.matches(filter)?.0 {
-                        addr_pair = (
-                            Some(malformed_dns_event.orig_addr),
-                            Some(malformed_dns_event.resp_addr),
-                        );
-                    }
+                    (Some(ldap_event.orig_addr), Some(ldap_event.resp_addr))
                 }
+                RecordType::MalformedDns(malformed_dns_event) => (
+                    Some(malformed_dns_event.orig_addr),
+                    Some(malformed_dns_event.resp_addr),
+                ),
                 RecordType::Mqtt(mqtt_event) => {
-                    if mqtt_event.matches(filter)?.0 {
-                        addr_pair = (Some(mqtt_event.orig_addr), Some(mqtt_event.resp_addr));
-                    }
+                    (Some(mqtt_event.orig_addr), Some(mqtt_event.resp_addr))
                 }
                 RecordType::Nfs(nfs_event) => {
-                    if nfs_event.matches(filter)?.0 {
-                        addr_pair = (Some(nfs_event.orig_addr), Some(nfs_event.resp_addr));
-                    }
+                    (Some(nfs_event.orig_addr), Some(nfs_event.resp_addr))
                 }
                 RecordType::Ntlm(ntlm_event) => {
-                    if ntlm_event.matches(filter)?.0 {
-                        addr_pair = (Some(ntlm_event.orig_addr), Some(ntlm_event.resp_addr));
-                    }
+                    (Some(ntlm_event.orig_addr), Some(ntlm_event.resp_addr))
                 }
                 RecordType::Radius(radius_event) => {
-                    if radius_event.matches(filter)?.0 {
-                        addr_pair = (Some(radius_event.orig_addr), Some(radius_event.resp_addr));
-                    }
+                    (Some(radius_event.orig_addr), Some(radius_event.resp_addr))
                 }
                 RecordType::Rdp(rdp_event) => {
-                    if rdp_event.matches(filter)?.0 {
-                        addr_pair = (Some(rdp_event.orig_addr), Some(rdp_event.resp_addr));
-                    }
+                    (Some(rdp_event.orig_addr), Some(rdp_event.resp_addr))
                 }
                 RecordType::Smb(smb_event) => {
-                    if smb_event.matches(filter)?.0 {
-                        addr_pair = (Some(smb_event.orig_addr), Some(smb_event.resp_addr));
-                    }
+                    (Some(smb_event.orig_addr), Some(smb_event.resp_addr))
                 }
                 RecordType::Smtp(smtp_event) => {
-                    if smtp_event.matches(filter)?.0 {
-                        addr_pair = (Some(smtp_event.orig_addr), Some(smtp_event.resp_addr));
-                    }
+                    (Some(smtp_event.orig_addr), Some(smtp_event.resp_addr))
                 }
                 RecordType::Ssh(ssh_event) => {
-                    if ssh_event.matches(filter)?.0 {
-                        addr_pair = (Some(ssh_event.orig_addr), Some(ssh_event.resp_addr));
-                    }
+                    (Some(ssh_event.orig_addr), Some(ssh_event.resp_addr))
                 }
                 RecordType::Tls(tls_event) => {
-                    if tls_event.matches(filter)?.0 {
-                        addr_pair = (Some(tls_event.orig_addr), Some(tls_event.resp_addr));
-                    }
+                    (Some(tls_event.orig_addr), Some(tls_event.resp_addr))
                 }
+                // UnusualDestinationPattern has multiple responder IPs but no originator.
+                // Use the first responder IP if available.
                 RecordType::UnusualDestinationPattern(event) => {
-                    if event.matches(filter)?.0 {
-                        // UnusualDestinationPattern has multiple responder IPs but no originator.
-                        // Use the first responder IP if available.
-                        addr_pair = (None, event.destination_ips.first().copied());
-                    }
+                    (None, event.destination_ips.first().copied())
                 }
             },
-            Event::WindowsThreat(_event) => {}
-            Event::NetworkThreat(event) => {
-                if event.matches(filter)?.0 {
-                    addr_pair = (Some(event.orig_addr), Some(event.resp_addr));
-                }
-            }
-            Event::ExtraThreat(_event) => {}
-            Event::LockyRansomware(event) => {
-                if event.matches(filter)?.0 {
-                    addr_pair = (Some(event.orig_addr), Some(event.resp_addr));
-                }
-            }
-            Event::SuspiciousTlsTraffic(event) => {
-                if event.matches(filter)?.0 {
-                    addr_pair = (Some(event.orig_addr), Some(event.resp_addr));
-                }
-            }
+            Event::WindowsThreat(_) | Event::ExtraThreat(_) => (None, None),
+            Event::NetworkThreat(event) => (Some(event.orig_addr), Some(event.resp_addr)),
+            Event::LockyRansomware(event) => (Some(event.orig_addr), Some(event.resp_addr)),
+            Event::SuspiciousTlsTraffic(event) => (Some(event.orig_addr), Some(event.resp_addr)),
         }
-        Ok(addr_pair)
     }
 
     fn kind(&self, filter: &EventFilter) -> Result<Option<&'static str>> {
-        let mut kind = None;
+        Ok(self.matches(filter)?.0.then(|| self.kind_name()))
+    }
+
+    /// Returns the name the per-kind counters use for the event, regardless of
+    /// any filter.
+    fn kind_name(&self) -> &'static str {
         match self {
-            Event::DnsCovertChannel(event) => {
-                if event.matches(filter)?.0 {
-                    kind = Some(DNS_COVERT_CHANNEL);
-                }
-            }
-            Event::HttpThreat(event) => {
-                if event.matches(filter)?.0 {
-                    kind = Some(HTTP_THREAT);
-                }
-            }
-            Event::RdpBruteForce(event) => {
-                if event.matches(filter)?.0 {
-                    kind = Some(RDP_BRUTE_FORCE);
-                }
-            }
-            Event::RepeatedHttpSessions(event) => {
-                if event.matches(filter)?.0 {
-                    kind = Some(REPEATED_HTTP_SESSIONS);
-                }
-            }
-            Event::TorConnection(event) => {
-                if event.matches(filter)?.0 {
-                    kind = Some(TOR_CONNECTION);
-                }
-            }
-            Event::TorConnectionConn(event) => {
-                if event.matches(filter)?.0 {
-                    kind = Some(TOR_CONNECTION_CONN);
-                }
-            }
-            Event::DomainGenerationAlgorithm(event) => {
-                if event.matches(filter)?.0 {
-                    kind = Some(DOMAIN_GENERATION_ALGORITHM);
-                }
-            }
-            Event::FtpBruteForce(event) => {
-                if event.matches(filter)?.0 {
-                    kind = Some(FTP_BRUTE_FORCE);
-                }
-            }
-            Event::FtpPlainText(event) => {
-                if event.matches(filter)?.0 {
-                    kind = Some(FTP_PLAIN_TEXT);
-                }
-            }
-            Event::PortScan(event) => {
-                if event.matches(filter)?.0 {
-                    kind = Some(PORT_SCAN);
-                }
-            }
-            Event::MultiHostPortScan(event) => {
-                if event.matches(filter)?.0 {
-                    kind = Some(MULTI_HOST_PORT_SCAN);
-                }
-            }
-            Event::ExternalDdos(event) => {
-                if event.matches(filter)?.0 {
-                    kind = Some(EXTERNAL_DDOS);
-                }
-            }
-            Event::NonBrowser(event) => {
-                if event.matches(filter)?.0 {
-                    kind = Some(NON_BROWSER);
-                }
-            }
-            Event::LdapBruteForce(event) => {
-                if event.matches(filter)?.0 {
-                    kind = Some(LDAP_BRUTE_FORCE);
-                }
-            }
-            Event::LdapPlainText(event) => {
-                if event.matches(filter)?.0 {
-                    kind = Some(LDAP_PLAIN_TEXT);
-                }
-            }
-            Event::CryptocurrencyMiningPool(event) => {
-                if event.matches(filter)?.0 {
-                    kind = Some(CRYPTOCURRENCY_MINING_POOL);
-                }
-            }
-            Event::Blocklist(record_type) => match record_type {
-                RecordType::Bootp(bootp_event) => {
-                    if bootp_event.matches(filter)?.0 {
-                        kind = Some(BLOCKLIST);
-                    }
-                }
-                RecordType::Conn(conn_event) => {
-                    if conn_event.matches(filter)?.0 {
-                        kind = Some(BLOCKLIST);
-                    }
-                }
-                RecordType::DceRpc(dcerpc_event) => {
-                    if dcerpc_event.matches(filter)?.0 {
-                        kind = Some(BLOCKLIST);
-                    }
-                }
-                RecordType::Dhcp(dhcp_event) => {
-                    if dhcp_event.matches(filter)?.0 {
-                        kind = Some(BLOCKLIST);
-                    }
-                }
-                RecordType::Dns(dns_event) => {
-                    if dns_event.matches(filter)?.0 {
-                        kind = Some(BLOCKLIST);
-                    }
-                }
-                RecordType::Ftp(ftp_event) => {
-                    if ftp_event.matches(filter)?.0 {
-                        kind = Some(BLOCKLIST);
-                    }
-                }
-                RecordType::Http(http_event) => {
-                    if http_event.matches(filter)?.0 {
-                        kind = Some(BLOCKLIST);
-                    }
-                }
-                RecordType::Kerberos(kerberos_event) => {
-                    if kerberos_event.matches(filter)?.0 {
-                        kind = Some(BLOCKLIST);
-                    }
-                }
-                RecordType::Ldap(ldap_event) => {
-                    if ldap_event.matches(filter)?.0 {
-                        kind = Some(BLOCKLIST);
-                    }
-                }
-                RecordType::MalformedDns(malformed_dns_event) => {
-                    if malformed_dns_event.matches(filter)?.0 {
-                        kind = Some(BLOCKLIST);
-                    }
-                }
-                RecordType::Mqtt(mqtt_event) => {
-                    if mqtt_event.matches(filter)?.0 {
-                        kind = Some(BLOCKLIST);
-                    }
-                }
-                RecordType::Nfs(nfs_event) => {
-                    if nfs_event.matches(filter)?.0 {
-                        kind = Some(BLOCKLIST);
-                    }
-                }
-                RecordType::Ntlm(ntlm_event) => {
-                    if ntlm_event.matches(filter)?.0 {
-                        kind = Some(BLOCKLIST);
-                    }
-                }
-                RecordType::Radius(radius_event) => {
-                    if radius_event.matches(filter)?.0 {
-                        kind = Some(BLOCKLIST);
-                    }
-                }
-                RecordType::Rdp(rdp_event) => {
-                    if rdp_event.matches(filter)?.0 {
-                        kind = Some(BLOCKLIST);
-                    }
-                }
-                RecordType::Smb(smb_event) => {
-                    if smb_event.matches(filter)?.0 {
-                        kind = Some(BLOCKLIST);
-                    }
-                }
-                RecordType::Smtp(smtp_event) => {
-                    if smtp_event.matches(filter)?.0 {
-                        kind = Some(BLOCKLIST);
-                    }
-                }
-                RecordType::Ssh(ssh_event) => {
-                    if ssh_event.matches(filter)?.0 {
-                        kind = Some(BLOCKLIST);
-                    }
-                }
-                RecordType::Tls(tls_event) => {
-                    if tls_event.matches(filter)?.0 {
-                        kind = Some(BLOCKLIST);
-                    }
-                }
-                RecordType::UnusualDestinationPattern(event) => {
-                    if event.matches(filter)?.0 {
-                        kind = Some(UNUSUAL_DESTINATION_PATTERN);
-                    }
-                }
-            },
-            Event::WindowsThreat(event) => {
-                if event.matches(filter)?.0 {
-                    kind = Some(WINDOWS_THREAT_EVENT);
-                }
-            }
-            Event::NetworkThreat(event) => {
-                if event.matches(filter)?.0 {
-                    kind = Some(NETWORK_THREAT_EVENT);
-                }
-            }
-            Event::ExtraThreat(event) => {
-                if event.matches(filter)?.0 {
-                    kind = Some(MISC_LOG_THREAT);
-                }
-            }
-            Event::LockyRansomware(event) => {
-                if event.matches(filter)?.0 {
-                    kind = Some(LOCKY_RANSOMWARE);
-                }
-            }
-            Event::SuspiciousTlsTraffic(event) => {
-                if event.matches(filter)?.0 {
-                    kind = Some(SUSPICIOUS_TLS_TRAFFIC);
-                }
-            }
+            Event::DnsCovertChannel(_) => DNS_COVERT_CHANNEL,
+            Event::HttpThreat(_) => HTTP_THREAT,
+            Event::RdpBruteForce(_) => RDP_BRUTE_FORCE,
+            Event::RepeatedHttpSessions(_) => REPEATED_HTTP_SESSIONS,
+            Event::TorConnection(_) => TOR_CONNECTION,
+            Event::TorConnectionConn(_) => TOR_CONNECTION_CONN,
+            Event::DomainGenerationAlgorithm(_) => DOMAIN_GENERATION_ALGORITHM,
+            Event::FtpBruteForce(_) => FTP_BRUTE_FORCE,
+            Event::FtpPlainText(_) => FTP_PLAIN_TEXT,
+            Event::PortScan(_) => PORT_SCAN,
+            Event::MultiHostPortScan(_) => MULTI_HOST_PORT_SCAN,
+            Event::ExternalDdos(_) => EXTERNAL_DDOS,
+            Event::NonBrowser(_) => NON_BROWSER,
+            Event::LdapBruteForce(_) => LDAP_BRUTE_FORCE,
+            Event::LdapPlainText(_) => LDAP_PLAIN_TEXT,
+            Event::CryptocurrencyMiningPool(_) => CRYPTOCURRENCY_MINING_POOL,
+            Event::Blocklist(RecordType::UnusualDestinationPattern(_)) => {
+                UNUSUAL_DESTINATION_PATTERN
+            }
+            Event::Blocklist(_) => BLOCKLIST,
+            Event::WindowsThreat(_) => WINDOWS_THREAT_EVENT,
+            Event::NetworkThreat(_) => NETWORK_THREAT_EVENT,
+            Event::ExtraThreat(_) => MISC_LOG_THREAT,
+            Event::LockyRansomware(_) => LOCKY_RANSOMWARE,
+            Event::SuspiciousTlsTraffic(_) => SUSPICIOUS_TLS_TRAFFIC,
         }
-        Ok(kind)
+    }
+
+    /// Returns the time the event was recorded at.
+    fn time(&self) -> Timestamp {
+        self.as_match().time()
     }
 
     fn kind_and_category(&self) -> (EventKind, Option<EventCategory>) {
-        match self {
-            Event::DnsCovertChannel(e) => (EventKind::DnsCovertChannel, e.category()),
-            Event::HttpThreat(e) => (EventKind::HttpThreat, e.category()),
-            Event::RdpBruteForce(e) => (EventKind::RdpBruteForce, e.category()),
-            Event::RepeatedHttpSessions(e) => (EventKind::RepeatedHttpSessions, e.category()),
-            Event::TorConnection(e) => (EventKind::TorConnection, e.category()),
-            Event::TorConnectionConn(e) => (EventKind::TorConnectionConn, e.category()),
-            Event::DomainGenerationAlgorithm(e) => {
-                (EventKind::DomainGenerationAlgorithm, e.category())
-            }
-            Event::FtpBruteForce(e) => (EventKind::FtpBruteForce, e.category()),
-            Event::FtpPlainText(e) => (EventKind::FtpPlainText, e.category()),
-            Event::PortScan(e) => (EventKind::PortScan, e.category()),
-            Event::MultiHostPortScan(e) => (EventKind::MultiHostPortScan, e.category()),
-            Event::ExternalDdos(e) => (EventKind::ExternalDdos, e.category()),
-            Event::NonBrowser(e) => (EventKind::NonBrowser, e.category()),
-            Event::LdapBruteForce(e) => (EventKind::LdapBruteForce, e.category()),
-            Event::LdapPlainText(e) => (EventKind::LdapPlainText, e.category()),
-            Event::CryptocurrencyMiningPool(e) => {
-                (EventKind::CryptocurrencyMiningPool, e.category())
-            }
-            Event::Blocklist(record_type) => match record_type {
-                RecordType::Bootp(e) => (EventKind::BlocklistBootp, e.category()),
-                RecordType::Conn(e) => (EventKind::BlocklistConn, e.category()),
-                RecordType::DceRpc(e) => (EventKind::BlocklistDceRpc, e.category()),
-                RecordType::Dhcp(e) => (EventKind::BlocklistDhcp, e.category()),
-                RecordType::Dns(e) => (EventKind::BlocklistDns, e.category()),
-                RecordType::Ftp(e) => (EventKind::BlocklistFtp, e.category()),
-                RecordType::Http(e) => (EventKind::BlocklistHttp, e.category()),
-                RecordType::Kerberos(e) => (EventKind::BlocklistKerberos, e.category()),
-                RecordType::Ldap(e) => (EventKind::BlocklistLdap, e.category()),
-                RecordType::MalformedDns(e) => (EventKind::BlocklistMalformedDns, e.category()),
-                RecordType::Mqtt(e) => (EventKind::BlocklistMqtt, e.category()),
-                RecordType::Nfs(e) => (EventKind::BlocklistNfs, e.category()),
-                RecordType::Ntlm(e) => (EventKind::BlocklistNtlm, e.category()),
-                RecordType::Radius(e) => (EventKind::BlocklistRadius, e.category()),
-                RecordType::Rdp(e) => (EventKind::BlocklistRdp, e.category()),
-                RecordType::Smb(e) => (EventKind::BlocklistSmb, e.category()),
-                RecordType::Smtp(e) => (EventKind::BlocklistSmtp, e.category()),
-                RecordType::Ssh(e) => (EventKind::BlocklistSsh, e.category()),
-                RecordType::Tls(e) => (EventKind::BlocklistTls, e.category()),
-                RecordType::UnusualDestinationPattern(e) => {
-                    (EventKind::UnusualDestinationPattern, e.category())
-                }
-            },
-            Event::WindowsThreat(e) => (EventKind::WindowsThreat, e.category()),
-            Event::NetworkThreat(e) => (EventKind::NetworkThreat, e.category()),
-            Event::ExtraThreat(e) => (EventKind::ExtraThreat, e.category()),
-            Event::LockyRansomware(e) => (EventKind::LockyRansomware, e.category()),
-            Event::SuspiciousTlsTraffic(e) => (EventKind::SuspiciousTlsTraffic, e.category()),
-        }
+        let event = self.as_match();
+        (event.event_kind(), event.category())
     }
 
     /// Returns all MITRE ATT&CK categories that this event can match based on its kind.
@@ -1446,6 +1280,18 @@ impl Event {
         filter: &EventFilter,
     ) -> Result<()> {
         let addr_pair = self.address_pair(filter)?;
+        self.tally_country(counter, addr_pair);
+
+        Ok(())
+    }
+
+    /// Counts the countries of `addr_pair`, the addresses of the event that
+    /// passed the filter.
+    fn tally_country(
+        &self,
+        counter: &mut HashMap<String, usize>,
+        addr_pair: (Option<IpAddr>, Option<IpAddr>),
+    ) {
         let (orig_code, resp_code) = self.stored_country_code_pair();
 
         if addr_pair.1.is_some() {
@@ -1461,8 +1307,6 @@ impl Event {
             let orig_country = crate::util::country_code_as_str(&orig_code);
             Self::increment_country_count(counter, orig_country);
         }
-
-        Ok(())
     }
 
     /// Counts the number of events per category.
@@ -2245,7 +2089,9 @@ fn find_network(ip: IpAddr, networks: &[Network]) -> Option<u32> {
     None
 }
 
-#[derive(Serialize, Clone, Copy, Debug, Deserialize, Eq, FromPrimitive, PartialEq, ToPrimitive)]
+#[derive(
+    Serialize, Clone, Copy, Debug, Deserialize, Eq, FromPrimitive, Hash, PartialEq, ToPrimitive,
+)]
 #[repr(u32)]
 #[non_exhaustive]
 #[allow(clippy::module_name_repetitions)]
@@ -2989,6 +2835,86 @@ fn convert_for_storage(
     }
 }
 
+/// Converts producer-facing `*Fields` bytes of the given [`EventKind`] into a
+/// JSON object with a member per field.
+///
+/// The syntax UUIDs of a DCE/RPC context are given in their canonical text
+/// form, since a JSON number cannot hold a `u128`.
+fn fields_to_json(
+    kind: EventKind,
+    bytes: &[u8],
+) -> Result<serde_json::Map<String, serde_json::Value>> {
+    fn to_object<T>(bytes: &[u8]) -> Result<serde_json::Map<String, serde_json::Value>>
+    where
+        T: for<'de> Deserialize<'de> + Serialize,
+    {
+        let fields: T = bincode::deserialize(bytes)
+            .context("failed to deserialize event fields as the producer-facing schema")?;
+        match serde_json::to_value(fields).context("failed to convert event fields to JSON")? {
+            serde_json::Value::Object(object) => Ok(object),
+            _ => bail!("event fields are not a struct"),
+        }
+    }
+
+    match kind {
+        EventKind::BlocklistBootp => to_object::<BlocklistBootpFields>(bytes),
+        EventKind::BlocklistConn | EventKind::TorConnectionConn => {
+            to_object::<BlocklistConnFields>(bytes)
+        }
+        EventKind::BlocklistDceRpc => {
+            let mut fields: BlocklistDceRpcFields = bincode::deserialize(bytes)
+                .context("failed to deserialize event fields as the producer-facing schema")?;
+            let context = std::mem::take(&mut fields.context);
+            let serde_json::Value::Object(mut object) =
+                serde_json::to_value(fields).context("failed to convert event fields to JSON")?
+            else {
+                bail!("event fields are not a struct");
+            };
+            object.insert(
+                "context".to_string(),
+                context.iter().map(DceRpcContext::to_json).collect(),
+            );
+            Ok(object)
+        }
+        EventKind::BlocklistDhcp => to_object::<BlocklistDhcpFields>(bytes),
+        EventKind::BlocklistDns => to_object::<BlocklistDnsFields>(bytes),
+        EventKind::BlocklistFtp | EventKind::FtpPlainText => to_object::<FtpEventFields>(bytes),
+        EventKind::BlocklistHttp => to_object::<BlocklistHttpFields>(bytes),
+        EventKind::BlocklistKerberos => to_object::<BlocklistKerberosFields>(bytes),
+        EventKind::BlocklistLdap | EventKind::LdapPlainText => to_object::<LdapEventFields>(bytes),
+        EventKind::BlocklistMalformedDns => to_object::<BlocklistMalformedDnsFields>(bytes),
+        EventKind::BlocklistMqtt => to_object::<BlocklistMqttFields>(bytes),
+        EventKind::BlocklistNfs => to_object::<BlocklistNfsFields>(bytes),
+        EventKind::BlocklistNtlm => to_object::<BlocklistNtlmFields>(bytes),
+        EventKind::BlocklistRadius => to_object::<BlocklistRadiusFields>(bytes),
+        EventKind::BlocklistRdp => to_object::<BlocklistRdpFields>(bytes),
+        EventKind::BlocklistSmb => to_object::<BlocklistSmbFields>(bytes),
+        EventKind::BlocklistSmtp => to_object::<BlocklistSmtpFields>(bytes),
+        EventKind::BlocklistSsh => to_object::<BlocklistSshFields>(bytes),
+        EventKind::BlocklistTls | EventKind::SuspiciousTlsTraffic => {
+            to_object::<BlocklistTlsFields>(bytes)
+        }
+        EventKind::CryptocurrencyMiningPool => to_object::<CryptocurrencyMiningPoolFields>(bytes),
+        EventKind::DnsCovertChannel | EventKind::LockyRansomware => {
+            to_object::<DnsEventFields>(bytes)
+        }
+        EventKind::DomainGenerationAlgorithm => to_object::<DgaFields>(bytes),
+        EventKind::ExternalDdos => to_object::<ExternalDdosFields>(bytes),
+        EventKind::FtpBruteForce => to_object::<FtpBruteForceFields>(bytes),
+        EventKind::HttpThreat => to_object::<HttpThreatFields>(bytes),
+        EventKind::LdapBruteForce => to_object::<LdapBruteForceFields>(bytes),
+        EventKind::MultiHostPortScan => to_object::<MultiHostPortScanFields>(bytes),
+        EventKind::NonBrowser | EventKind::TorConnection => to_object::<HttpEventFields>(bytes),
+        EventKind::PortScan => to_object::<PortScanFields>(bytes),
+        EventKind::RdpBruteForce => to_object::<RdpBruteForceFields>(bytes),
+        EventKind::RepeatedHttpSessions => to_object::<RepeatedHttpSessionsFields>(bytes),
+        EventKind::UnusualDestinationPattern => to_object::<UnusualDestinationPatternFields>(bytes),
+        EventKind::ExtraThreat => to_object::<ExtraThreatFields>(bytes),
+        EventKind::NetworkThreat => to_object::<NetworkThreatFields>(bytes),
+        EventKind::WindowsThreat => to_object::<WindowsThreatFields>(bytes),
+    }
+}
+
 /// Resolves endpoint country codes on already-serialized stored event fields.
 ///
 /// Retained for migration paths where records are already in the on-disk
@@ -3159,6 +3085,7 @@ fn event_sensor_matches(kind: EventKind, value: &[u8], sensors: &HashSet<&str>)
 pub struct EventDb<'a> {
     inner: &'a rocksdb::OptimisticTransactionDB,
     country_lookup: Option<crate::geo::SharedCountryLookup>,
+    subscribers: Option<&'a Subscribers>,
 }
 
 impl<'a> EventDb<'a> {
@@ -3167,6 +3094,7 @@ impl<'a> EventDb<'a> {
         Self {
             inner,
             country_lookup: None,
+            subscribers: None,
         }
     }
 
@@ -3178,104 +3106,584 @@ impl<'a> EventDb<'a> {
         Self {
             inner,
             country_lookup,
+            subscribers: None,
         }
     }
 
+    /// Publishes the events this `EventDb` stores to the subscriptions in
+    /// `subscribers`.
+    #[must_use]
+    pub(crate) fn with_subscribers(mut self, subscribers: &'a Subscribers) -> EventDb<'a> {
+        self.subscribers = Some(subscribers);
+        self
+    }
+
     /// Creates an iterator over key-value pairs, starting from `key`.
     #[must_use]
     pub fn iter_from(&self, key: i128, direction: Direction) -> EventIterator<'_> {
         let iter = self
             .inner
             .iterator(IteratorMode::From(&key.to_be_bytes(), direction));
-        EventIterator { inner: iter }
+        EventIterator {
+            inner: iter,
+            kinds: None,
+        }
     }
 
     /// Creates an iterator over key-value pairs for the entire events.
     #[must_use]
     pub fn iter_forward(&self) -> EventIterator<'_> {
         let iter = self.inner.iterator(IteratorMode::Start);
-        EventIterator { inner: iter }
+        EventIterator {
+            inner: iter,
+            kinds: None,
+        }
     }
 
-    #[cfg(test)]
+    /// Creates an iterator over the events whose timestamp is in the
+    /// half-open range `[start, end)`, optionally restricted to `kinds`.
+    ///
+    /// The timestamp occupies the upper 64 bits of an event key, so the range
+    /// is handed to RocksDB as iterator bounds and entries outside it are
+    /// never read. The kind sits below the timestamp and cannot narrow the
+    /// scan further; entries of other kinds are skipped by their key alone,
+    /// without deserializing their values.
+    ///
+    /// Timestamps before the Unix epoch are not supported as bounds, since
+    /// their keys sort after every key from the epoch onward.
     #[must_use]
-    pub(crate) fn raw_iter(&self) -> RawEventIterator<'_> {
-        let iter = self.inner.iterator(IteratorMode::Start);
-        RawEventIterator { inner: iter }
+    pub fn range(
+        &self,
+        start: Timestamp,
+        end: Timestamp,
+        kinds: Option<&[EventKind]>,
+    ) -> EventIterator<'_> {
+        let lower = i128::from(timestamp::event_key_nanos(start)) << 64;
+        let upper = i128::from(timestamp::event_key_nanos(end)) << 64;
+        let mut opts = rocksdb::ReadOptions::default();
+        opts.set_iterate_lower_bound(lower.to_be_bytes());
+        opts.set_iterate_upper_bound(upper.to_be_bytes());
+        let iter = self.inner.iterator_opt(IteratorMode::Start, opts);
+        EventIterator {
+            inner: iter,
+            kinds: kinds.map(<[EventKind]>::to_vec),
+        }
     }
 
-    /// Stores a new event into the database.
+    /// Counts the events in the half-open time range `[start, end)` that match
+    /// `filter` along every one of `dimensions`, reading each event once.
     ///
-    /// Converts the producer-facing `*Fields` bytes into the repository-local
-    /// `*FieldsStored` representation so that the on-disk schema can evolve
-    /// independently of the ingestion schema.
+    /// Events that cannot be decoded are skipped.
     ///
     /// # Errors
     ///
-    /// Returns an error if the fields cannot be deserialized as the
-    /// producer-facing schema or if a database operation fails.
-    pub fn put(&self, event: &EventMessage) -> Result<i128> {
-        use anyhow::anyhow;
-        let stored_fields =
-            convert_for_storage(event.kind, &event.fields, self.country_lookup.as_deref())?;
-        let mut key = (i128::from(timestamp::event_key_nanos(event.time)) << 64)
-            | (event
-                .kind
-                .to_i128()
-                .ok_or(anyhow!("`EventKind` exceeds i128::MAX"))?
-                << 32);
-        loop {
-            let txn = self.inner.transaction();
-            if txn
-                .get_for_update(key.to_be_bytes(), super::EXCLUSIVE)
-                .context("cannot read from event database")?
-                .is_some()
-            {
-                let start = i128::from(rng().next_u32());
-                key |= start;
-                #[allow(clippy::cast_possible_wrap)] // bit pattern
-                while txn
-                    .get_for_update(key.to_be_bytes(), super::EXCLUSIVE)
-                    .context("cannot read from event database")?
-                    .is_some()
-                {
-                    let next = (key + 1) & 0xffff_ffff;
-                    if next == start {
-                        bail!("too many events with the same timestamp");
-                    }
-                    key = key & 0xffff_ffff_ffff_ffff_ffff_ffff_0000_0000_u128 as i128 | next;
-                }
-            }
-            txn.put(key.to_be_bytes(), stored_fields.as_slice())
-                .context("cannot write event")?;
-            match txn.commit() {
-                Ok(()) => break,
-                Err(e) => {
-                    if !e.as_ref().starts_with("Resource busy:") {
-                        return Err(e).context("failed to store event");
-                    }
-                }
+    /// Returns an error if a dimension is requested more than once, or if
+    /// triage-policy scoring fails while matching an event.
+    pub fn aggregate(
+        &self,
+        start: Timestamp,
+        end: Timestamp,
+        filter: &EventFilter,
+        dimensions: &[Dimension<'_>],
+    ) -> Result<Aggregation> {
+        let mut aggregation = Aggregation::new(dimensions)?;
+        for item in self.range(start, end, None) {
+            let Ok((key, event)) = item else {
+                warn!("Skipped an event that cannot be decoded");
+                continue;
+            };
+            if !event.matches(filter)?.0 {
+                continue;
             }
+            aggregation.add(dimensions, key, &event);
         }
-        Ok(key)
+        Ok(aggregation)
     }
 
-    /// Updates an old key-value pair to a new one.
+    /// Returns one page of the events matching `filter`, with the triage
+    /// scores [`Event::matches`] computed for each.
+    ///
+    /// The page holds at most `page.size` events, starting right after
+    /// `page.cursor` in `page.direction`. Its cursors continue the sequence in
+    /// either direction, and `has_more` tells whether another matching event
+    /// follows the last one. Events that cannot be decoded are skipped.
     ///
     /// # Errors
     ///
-    /// Returns an error if the old value does not match the value in the database, the old key does
-    /// not exist, or the database operation fails.
-    pub fn update(&self, old: (&[u8], &[u8]), new: (&[u8], &[u8])) -> Result<()> {
-        loop {
-            let txn = self.inner.transaction();
-            if let Some(old_value) = txn
-                .get_for_update(old.0, super::EXCLUSIVE)
-                .context("cannot read old entry")?
-            {
-                if old.1 != old_value.as_slice() {
-                    bail!("old value mismatch");
-                }
+    /// Returns an error if `page.size` is zero, or if triage-policy scoring
+    /// fails while evaluating a matching event.
+    #[allow(clippy::type_complexity)]
+    pub fn query(
+        &self,
+        filter: &EventFilter,
+        page: PageRequest,
+    ) -> Result<Page<(i128, Event, Option<Vec<TriageScore>>)>> {
+        if page.size == 0 {
+            bail!("page size must be greater than zero");
+        }
+        let iter = match (page.cursor, page.direction) {
+            (Some(cursor), direction) => self.iter_from(cursor.key(), direction),
+            (None, Direction::Forward) => self.iter_forward(),
+            (None, Direction::Reverse) => EventIterator {
+                inner: self.inner.iterator(IteratorMode::End),
+                kinds: None,
+            },
+        };
+
+        let mut items = Vec::with_capacity(page.size);
+        let mut has_more = false;
+        for item in iter {
+            let Ok((key, event)) = item else {
+                warn!("Skipped an event that cannot be decoded");
+                continue;
+            };
+            if page.cursor.is_some_and(|cursor| cursor.key() == key) {
+                continue;
+            }
+            let (matched, triage_scores) = event.matches(filter)?;
+            if !matched {
+                continue;
+            }
+            if items.len() == page.size {
+                has_more = true;
+                break;
+            }
+            items.push((key, event, triage_scores));
+        }
+
+        Ok(Page {
+            start_cursor: items.first().map(|(key, _, _)| Cursor::new(*key)),
+            end_cursor: items.last().map(|(key, _, _)| Cursor::new(*key)),
+            has_more,
+            items,
+        })
+    }
+
+    /// Writes the events matching `filter`, or every event if `filter` is
+    /// `None`, to `writer` as JSON Lines, oldest first, and returns the number
+    /// of events written.
+    ///
+    /// Each line is a JSON object with the `schema_version`
+    /// ([`JSONL_SCHEMA_VERSION`]), `kind`, and RFC 3339 `time` of an event,
+    /// its `fields`, and, when `filter` scored the event, its
+    /// `triage_scores`. Events that cannot be decoded are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be read, if triage-policy
+    /// scoring fails while matching an event, or if writing to `writer` fails.
+    pub fn export_jsonl<W: Write>(
+        &self,
+        mut writer: W,
+        filter: Option<&EventFilter>,
+    ) -> Result<u64> {
+        let mut count = 0;
+        for item in self.inner.iterator(IteratorMode::Start) {
+            let (key, value) = item.context("cannot read from event database")?;
+            let Ok((number, event)) = decode_entry(&key, &value) else {
+                warn!("Skipped an event that cannot be decoded");
+                continue;
+            };
+            let triage_scores = match filter {
+                Some(filter) => {
+                    let (matched, triage_scores) = event.matches(filter)?;
+                    if !matched {
+                        continue;
+                    }
+                    triage_scores
+                }
+                None => None,
+            };
+            jsonl::write_line(
+                &mut writer,
+                stored_kind(&key)?,
+                number,
+                &value,
+                triage_scores.as_deref(),
+            )?;
+            count += 1;
+        }
+        writer.flush().context("cannot write event")?;
+        Ok(count)
+    }
+
+    /// Stores the events read from `reader` as JSON Lines, in the format
+    /// [`EventDb::export_jsonl`] writes, and returns the number of events
+    /// stored.
+    ///
+    /// Each event is validated and stored through [`EventDb::put`], so it gets
+    /// a new key and its country codes are resolved again. Triage scores in
+    /// the input are ignored. Blank lines are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the line if a line cannot be read or parsed, if
+    /// its schema version is not [`JSONL_SCHEMA_VERSION`], or if its event
+    /// cannot be stored. The events on the lines before it remain stored.
+    pub fn import_jsonl<R: BufRead>(&self, reader: R) -> Result<u64> {
+        let mut count = 0;
+        for (i, line) in reader.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.with_context(|| format!("cannot read line {line_number}"))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event = jsonl::parse_line(&line).with_context(|| format!("line {line_number}"))?;
+            self.put(&event)
+                .with_context(|| format!("cannot store the event on line {line_number}"))?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Scores the events in the half-open time range `[start, end)` against
+    /// `policies`, oldest first, and returns the key and scores of each event
+    /// that reaches a response threshold of at least one policy. Events that
+    /// cannot be decoded are skipped.
+    #[must_use]
+    pub fn score_range(
+        &self,
+        policies: &[CompiledTriagePolicy],
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Vec<(i128, Vec<TriageScore>)> {
+        self.range(start, end, None)
+            .filter_map(|item| {
+                let Ok((key, event)) = item else {
+                    warn!("Skipped an event that cannot be decoded");
+                    return None;
+                };
+                let scores = event.score_against_compiled_policies(policies);
+                (!scores.is_empty()).then_some((key, scores))
+            })
+            .collect()
+    }
+
+    /// Previews what `candidate` would do to the events in the half-open time
+    /// range `[start, end)` that match `filter`, compared with `current`, the
+    /// stored version of the policy, if given.
+    ///
+    /// Each policy is compiled into a [`CompiledTriagePolicy`], so its
+    /// `triage_exclusion` applies, and an event counts as scored by it if its
+    /// score reaches at least one of its response thresholds. The sample of
+    /// each policy holds the keys of up to `sample_size` of the events it
+    /// scores. Events that cannot be decoded are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a policy cannot be compiled, or if triage-policy
+    /// scoring fails while matching an event against `filter`.
+    pub fn preview_triage_policy(
+        &self,
+        start: Timestamp,
+        end: Timestamp,
+        filter: &EventFilter,
+        candidate: &TriagePolicyInput,
+        current: Option<&TriagePolicyInput>,
+        sample_size: usize,
+    ) -> Result<PolicyImpactPreview> {
+        let mut preview = preview::Preview::new(candidate, current, sample_size)?;
+        for item in self.range(start, end, None) {
+            let Ok((key, event)) = item else {
+                warn!("Skipped an event that cannot be decoded");
+                continue;
+            };
+            if !event.matches(filter)?.0 {
+                continue;
+            }
+            preview.add(key, &event);
+        }
+        Ok(preview.finish())
+    }
+
+    /// Explains the scores of the events in the half-open time range
+    /// `[start, end)` against `policies` and stores, for each event, the
+    /// explanations of the policies whose score reaches a response threshold.
+    /// The results stored for an event earlier are replaced, and removed if no
+    /// policy scores it. Returns the number of events with results stored.
+    /// Events that cannot be decoded are skipped.
+    ///
+    /// The results are not updated when a policy changes; store them again to
+    /// reflect the change.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the results cannot be encoded or a database
+    /// operation fails.
+    pub fn store_triage_results(
+        &self,
+        start: Timestamp,
+        end: Timestamp,
+        policies: &[TriagePolicyInput],
+    ) -> Result<u64> {
+        let cf = self.triage_result_cf()?;
+        let mut batch = rocksdb::WriteBatchWithTransaction::<true>::default();
+        let mut stored = 0;
+        for item in self.range(start, end, None) {
+            let Ok((key, event)) = item else {
+                warn!("Skipped an event that cannot be decoded");
+                continue;
+            };
+            let results: Vec<_> = event
+                .explain_triage(policies)
+                .into_iter()
+                .filter(|explanation| explanation.scored)
+                .collect();
+            if results.is_empty() {
+                batch.delete_cf(cf, key.to_be_bytes());
+            } else {
+                let value = bincode::serialize(&results).context("cannot encode triage results")?;
+                batch.put_cf(cf, key.to_be_bytes(), value);
+                stored += 1;
+            }
+            if batch.len() >= EVENT_DELETION_BATCH_SIZE {
+                self.inner
+                    .write(std::mem::take(&mut batch))
+                    .context("cannot write triage results")?;
+            }
+        }
+        self.inner
+            .write(batch)
+            .context("cannot write triage results")?;
+        Ok(stored)
+    }
+
+    /// Returns the triage results stored for the event under `key` by
+    /// [`store_triage_results`](Self::store_triage_results), or `None` if
+    /// there are none.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be read or the stored results
+    /// are malformed.
+    pub fn triage_results(&self, key: i128) -> Result<Option<Vec<TriageExplanation>>> {
+        self.inner
+            .get_pinned_cf(self.triage_result_cf()?, key.to_be_bytes())
+            .context("cannot read triage results")?
+            .map(|value| bincode::deserialize(&value).context("invalid triage results"))
+            .transpose()
+    }
+
+    /// Adds the stored events matching `filter`, or all of them if `filter` is
+    /// `None`, to `bundle`, oldest first, and returns the number of events
+    /// added. Events that cannot be decoded are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be read, if triage-policy
+    /// scoring fails while matching an event, or if an event cannot be
+    /// converted to STIX.
+    pub fn export_stix(
+        &self,
+        bundle: &mut StixBundle,
+        filter: Option<&EventFilter>,
+    ) -> Result<u64> {
+        let mut count = 0;
+        for item in self.inner.iterator(IteratorMode::Start) {
+            let (key, value) = item.context("cannot read from event database")?;
+            let Ok((number, event)) = decode_entry(&key, &value) else {
+                warn!("Skipped an event that cannot be decoded");
+                continue;
+            };
+            if let Some(filter) = filter
+                && !event.matches(filter)?.0
+            {
+                continue;
+            }
+            bundle.add_event(number, &event)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Subscribes to the events stored from now on, buffering up to
+    /// `capacity` notices. See [`Subscription`] for what happens to the
+    /// notices that do not fit.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `capacity` is zero, or if this `EventDb` was not
+    /// obtained from [`Store::events`](crate::Store::events), which is what
+    /// publishes the notices.
+    pub fn subscribe(&self, capacity: usize) -> Result<Subscription> {
+        if capacity == 0 {
+            bail!("a subscription needs room for at least one notice");
+        }
+        let Some(subscribers) = self.subscribers else {
+            bail!("events are published only through `Store::events`");
+        };
+        Ok(subscribers.subscribe(capacity))
+    }
+
+    fn publish(&self, key: i128, kind: EventKind) {
+        if let Some(subscribers) = self.subscribers {
+            subscribers.publish(EventNotice { key, kind });
+        }
+    }
+
+    /// Returns the key of the last event `consumer` recorded as processed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be read or the stored offset
+    /// is malformed.
+    pub fn consumer_offset(&self, consumer: &str) -> Result<Option<i128>> {
+        self.inner
+            .get_pinned_cf(self.consumer_offset_cf()?, consumer)
+            .context("cannot read consumer offset")?
+            .map(|value| {
+                value
+                    .as_ref()
+                    .try_into()
+                    .map(i128::from_be_bytes)
+                    .map_err(|_| anyhow::anyhow!("invalid consumer offset"))
+            })
+            .transpose()
+    }
+
+    /// Records `key` as the key of the last event `consumer` processed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be written.
+    pub fn set_consumer_offset(&self, consumer: &str, key: i128) -> Result<()> {
+        self.inner
+            .put_cf(self.consumer_offset_cf()?, consumer, key.to_be_bytes())
+            .context("cannot write consumer offset")
+    }
+
+    /// Removes the offset of `consumer`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be written.
+    pub fn remove_consumer_offset(&self, consumer: &str) -> Result<()> {
+        self.inner
+            .delete_cf(self.consumer_offset_cf()?, consumer)
+            .context("cannot remove consumer offset")
+    }
+
+    /// Creates an iterator over the events after the offset of `consumer`, or
+    /// over every event if it has none, so that a consumer resumes where it
+    /// left off.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the offset cannot be read.
+    pub fn iter_after_consumer_offset(&self, consumer: &str) -> Result<EventIterator<'_>> {
+        Ok(match self.consumer_offset(consumer)? {
+            Some(offset) => self.iter_from(offset.saturating_add(1), Direction::Forward),
+            None => self.iter_forward(),
+        })
+    }
+
+    #[cfg(test)]
+    #[must_use]
+    pub(crate) fn raw_iter(&self) -> RawEventIterator<'_> {
+        let iter = self.inner.iterator(IteratorMode::Start);
+        RawEventIterator { inner: iter }
+    }
+
+    /// Stores a new event into the database.
+    ///
+    /// Converts the producer-facing `*Fields` bytes into the repository-local
+    /// `*FieldsStored` representation so that the on-disk schema can evolve
+    /// independently of the ingestion schema.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the fields cannot be deserialized as the
+    /// producer-facing schema or if a database operation fails.
+    pub fn put(&self, event: &EventMessage) -> Result<i128> {
+        use anyhow::anyhow;
+        let stored_fields =
+            convert_for_storage(event.kind, &event.fields, self.country_lookup.as_deref())?;
+        let mut key = (i128::from(timestamp::event_key_nanos(event.time)) << 64)
+            | (event
+                .kind
+                .to_i128()
+                .ok_or(anyhow!("`EventKind` exceeds i128::MAX"))?
+                << 32);
+        let meta = self.meta_cf()?;
+        let mut stored = None;
+        loop {
+            let txn = self.inner.transaction();
+            let indexed = txn
+                .get_for_update_cf(meta, EVENT_INDEXES, super::EXCLUSIVE)
+                .context("cannot read from database")?
+                .is_some();
+            let rollups = self.rollup_state(&txn)?;
+            if txn
+                .get_for_update(key.to_be_bytes(), super::EXCLUSIVE)
+                .context("cannot read from event database")?
+                .is_some()
+            {
+                let start = i128::from(rng().next_u32());
+                key |= start;
+                #[allow(clippy::cast_possible_wrap)] // bit pattern
+                while txn
+                    .get_for_update(key.to_be_bytes(), super::EXCLUSIVE)
+                    .context("cannot read from event database")?
+                    .is_some()
+                {
+                    let next = (key + 1) & 0xffff_ffff;
+                    if next == start {
+                        bail!("too many events with the same timestamp");
+                    }
+                    key = key & 0xffff_ffff_ffff_ffff_ffff_ffff_0000_0000_u128 as i128 | next;
+                }
+            }
+            txn.put(key.to_be_bytes(), stored_fields.as_slice())
+                .context("cannot write event")?;
+            if (indexed || rollups.covers(key)) && stored.is_none() {
+                stored = Some(
+                    decode_stored(event.kind, event.time, &stored_fields)
+                        .context("cannot decode the stored event")?,
+                );
+            }
+            let mut delta = rollup::Delta::default();
+            if let Some(stored) = &stored {
+                if indexed {
+                    self.put_index_entries(&txn, key, stored)?;
+                }
+                if rollups.covers(key) {
+                    delta.add(key, stored);
+                }
+            }
+            self.write_rollups(&txn, &rollups, delta)?;
+            match txn.commit() {
+                Ok(()) => break,
+                Err(e) => {
+                    if !e.as_ref().starts_with("Resource busy:") {
+                        return Err(e).context("failed to store event");
+                    }
+                }
+            }
+        }
+        self.publish(key, event.kind);
+        Ok(key)
+    }
+
+    /// Updates an old key-value pair to a new one.
+    ///
+    /// The triage results stored for the old entry are removed, as they may no
+    /// longer describe the event.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the old value does not match the value in the database, the old key does
+    /// not exist, or the database operation fails.
+    pub fn update(&self, old: (&[u8], &[u8]), new: (&[u8], &[u8])) -> Result<()> {
+        let meta = self.meta_cf()?;
+        loop {
+            let txn = self.inner.transaction();
+            if let Some(old_value) = txn
+                .get_for_update(old.0, super::EXCLUSIVE)
+                .context("cannot read old entry")?
+            {
+                if old.1 != old_value.as_slice() {
+                    bail!("old value mismatch");
+                }
             } else {
                 bail!("no such entry");
             }
@@ -3284,6 +3692,29 @@ impl<'a> EventDb<'a> {
             if old.0 != new.0 {
                 txn.delete(old.0).context("failed to delete old entry")?;
             }
+            txn.delete_cf(self.triage_result_cf()?, old.0)
+                .context("failed to delete triage results")?;
+            let indexed = txn
+                .get_for_update_cf(meta, EVENT_INDEXES, super::EXCLUSIVE)
+                .context("cannot read from database")?
+                .is_some();
+            let rollups = self.rollup_state(&txn)?;
+            let mut delta = rollup::Delta::default();
+            if indexed || rollups != rollup::State::Disabled {
+                let (old_key, old_event) = decode_entry(old.0, old.1)?;
+                let (new_key, new_event) = decode_entry(new.0, new.1)?;
+                if indexed {
+                    self.delete_index_entries(&txn, old_key, &old_event)?;
+                    self.put_index_entries(&txn, new_key, &new_event)?;
+                }
+                if rollups.covers(old_key) {
+                    delta.remove(old_key, &old_event);
+                }
+                if rollups.covers(new_key) {
+                    delta.add(new_key, &new_event);
+                }
+            }
+            self.write_rollups(&txn, &rollups, delta)?;
 
             match txn.commit() {
                 Ok(()) => break,
@@ -3294,6 +3725,9 @@ impl<'a> EventDb<'a> {
                 }
             }
         }
+        if let (Ok(key), Ok(kind)) = (<[u8; 16]>::try_from(new.0), stored_kind(new.0)) {
+            self.publish(i128::from_be_bytes(key), kind);
+        }
         Ok(())
     }
 
@@ -3301,8 +3735,14 @@ impl<'a> EventDb<'a> {
     ///
     /// Events are stored with an i128 key whose upper 64 bits encode the
     /// timestamp in nanoseconds. This method iterates from the beginning
-    /// of the event database and deletes every entry whose timestamp is
-    /// earlier than `before`, using batched writes for efficiency.
+    /// of the event database and removes the events earlier than `before`, a
+    /// chunk of events per transaction. The index entries, triage results,
+    /// and rollup counts of a removed event are removed in the same
+    /// transaction, which also reads whether the indexes and rollups are
+    /// maintained, so that enabling them at the same time cannot leave
+    /// entries or counts of removed events behind. An event that cannot be
+    /// decoded is removed without its index entries and rollup counts, with
+    /// a warning.
     ///
     /// Returns the number of events deleted.
     ///
@@ -3325,11 +3765,10 @@ impl<'a> EventDb<'a> {
 
         loop {
             let iter = self.inner.iterator(IteratorMode::Start);
-            let mut batch = rocksdb::WriteBatchWithTransaction::<true>::default();
-            let mut batch_count = 0;
+            let mut keys = Vec::new();
 
             for item in iter {
-                let (k, _v) = item.context("cannot read from event database")?;
+                let (k, _) = item.context("cannot read from event database")?;
                 let key_bytes: [u8; 16] = match k.as_ref().try_into() {
                     Ok(b) => b,
                     Err(_) => continue,
@@ -3341,22 +3780,16 @@ impl<'a> EventDb<'a> {
                     break;
                 }
 
-                batch.delete(&k);
-                batch_count += 1;
-
-                if batch_count >= EVENT_DELETION_BATCH_SIZE {
+                keys.push(key_bytes);
+                if keys.len() >= EVENT_DELETION_BATCH_SIZE {
                     break;
                 }
             }
 
-            if batch_count == 0 {
+            if keys.is_empty() {
                 break;
             }
-
-            self.inner
-                .write(batch)
-                .context("failed to delete expired events")?;
-            deleted += batch_count as u64;
+            deleted += self.remove_keys(&keys)?;
         }
 
         Ok(deleted)
@@ -3364,62 +3797,577 @@ impl<'a> EventDb<'a> {
 
     /// Removes all events whose sensor exactly matches one of `sensors`.
     ///
+    /// While the secondary indexes are enabled, the events are found through
+    /// the sensor index instead of a scan of every event. The events are
+    /// removed a chunk per transaction, together with their index entries,
+    /// their triage results, and their counts in the rollups. Sensor index
+    /// entries left behind by events that are already gone are removed as
+    /// well.
+    ///
     /// # Errors
     ///
-    /// Returns an error if an event cannot be read or decoded, or if a
-    /// database operation fails.
+    /// Returns an error if an event cannot be read or decoded while scanning
+    /// for the events of `sensors`, or if a database operation fails.
     pub fn remove_by_sensors(&self, sensors: &[String]) -> Result<()> {
         if sensors.is_empty() {
             return Ok(());
         }
 
         let sensors: HashSet<&str> = sensors.iter().map(String::as_str).collect();
-        let iter = self.inner.iterator(IteratorMode::Start);
-        let mut batch = rocksdb::WriteBatchWithTransaction::<true>::default();
-        let mut batch_count = 0;
-
-        for item in iter {
-            let (key, value) = item.context("cannot read from event database")?;
-            let key_bytes: [u8; 16] = key
-                .as_ref()
-                .try_into()
-                .map_err(|_| anyhow::anyhow!("event key must be 16 bytes, got {}", key.len()))?;
-            let key_number = i128::from_be_bytes(key_bytes);
-            let kind_number = (key_number & 0xffff_ffff_0000_0000) >> 32;
-            let kind = EventKind::from_i128(kind_number)
-                .ok_or_else(|| anyhow::anyhow!("unknown event kind: {kind_number}"))?;
-
-            if event_sensor_matches(kind, &value, &sensors)? {
-                batch.delete(&key);
-                batch_count += 1;
+        if self.indexes_enabled()? {
+            for sensor in &sensors {
+                let prefix = index::sensor_prefix(sensor)?;
+                let keys: Vec<_> = self
+                    .index_keys(Index::Sensor, &prefix)?
+                    .into_iter()
+                    .map(i128::to_be_bytes)
+                    .collect();
+                for chunk in keys.chunks(EVENT_DELETION_BATCH_SIZE) {
+                    self.remove_keys(chunk)?;
+                    self.remove_dangling_sensor_entries(&prefix, chunk)?;
+                }
             }
+            return Ok(());
+        }
 
-            if batch_count >= EVENT_DELETION_BATCH_SIZE {
-                self.inner
-                    .write(std::mem::take(&mut batch))
-                    .context("failed to delete events by sensor")?;
-                batch_count = 0;
+        let mut keys = Vec::new();
+        for item in self.inner.iterator(IteratorMode::Start) {
+            let (key, value) = item.context("cannot read from event database")?;
+            if event_sensor_matches(stored_kind(&key)?, &value, &sensors)? {
+                keys.push(key.as_ref().try_into().expect("16-byte event key"));
             }
         }
-
-        if batch_count > 0 {
-            self.inner
-                .write(batch)
-                .context("failed to delete events by sensor")?;
+        for chunk in keys.chunks(EVENT_DELETION_BATCH_SIZE) {
+            self.remove_keys(chunk)?;
         }
-
         Ok(())
     }
 
-    /// Inserts a raw key-value pair into the event database.
-    #[cfg(test)]
-    fn put_raw(&self, key: &[u8], value: &[u8]) {
-        self.inner.put(key, value).expect("put_raw should succeed");
+    /// Removes the events stored under `keys` in one transaction, together
+    /// with their index entries, their triage results, and their counts in
+    /// the rollups, and returns how many were there to remove.
+    fn remove_keys(&self, keys: &[[u8; 16]]) -> Result<u64> {
+        let meta = self.meta_cf()?;
+        let results = self.triage_result_cf()?;
+        loop {
+            let txn = self.inner.transaction();
+            let indexed = txn
+                .get_for_update_cf(meta, EVENT_INDEXES, super::EXCLUSIVE)
+                .context("cannot read from database")?
+                .is_some();
+            let rollups = self.rollup_state(&txn)?;
+            let mut delta = rollup::Delta::default();
+            let mut removed = 0;
+            for key in keys {
+                let Some(value) = txn
+                    .get_for_update(key, super::EXCLUSIVE)
+                    .context("cannot read from event database")?
+                else {
+                    continue;
+                };
+                txn.delete(key).context("cannot delete event")?;
+                txn.delete_cf(results, key)
+                    .context("cannot delete triage results")?;
+                removed += 1;
+                if !indexed && !rollups.covers(i128::from_be_bytes(*key)) {
+                    continue;
+                }
+                match decode_entry(key, &value) {
+                    Ok((key, event)) => {
+                        if indexed {
+                            self.delete_index_entries(&txn, key, &event)?;
+                        }
+                        if rollups.covers(key) {
+                            delta.remove(key, &event);
+                        }
+                    }
+                    Err(e) => warn!("Event removed without its index entries and rollups: {e:#}"),
+                }
+            }
+            self.write_rollups(&txn, &rollups, delta)?;
+            match txn.commit() {
+                Ok(()) => return Ok(removed),
+                Err(e) => {
+                    if !e.as_ref().starts_with("Resource busy:") {
+                        return Err(e).context("failed to delete events");
+                    }
+                }
+            }
+        }
     }
 
-    /// Returns the first event's on-disk key bytes in forward iteration order.
-    #[cfg(test)]
-    fn first_raw_event_key(&self) -> Result<Option<[u8; 16]>> {
+    /// Removes the entries under `prefix` in the sensor index for those of
+    /// `keys` whose events are gone.
+    ///
+    /// An event that cannot be decoded is removed without its index entries,
+    /// so its sensor entry is only removed here.
+    fn remove_dangling_sensor_entries(&self, prefix: &[u8], keys: &[[u8; 16]]) -> Result<()> {
+        let sensor_cf = self.index_cf(Index::Sensor)?;
+        loop {
+            let txn = self.inner.transaction();
+            for key in keys {
+                let stored = txn
+                    .get_for_update(key, super::EXCLUSIVE)
+                    .context("cannot read from event database")?;
+                if stored.is_none() {
+                    txn.delete_cf(sensor_cf, index::entry(prefix.to_vec(), key))
+                        .context("cannot delete event index entry")?;
+                }
+            }
+            match txn.commit() {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    if !e.as_ref().starts_with("Resource busy:") {
+                        return Err(e).context("failed to delete event index entries");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns whether the secondary indexes are maintained.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a database operation fails.
+    pub fn indexes_enabled(&self) -> Result<bool> {
+        Ok(self
+            .inner
+            .get_pinned_cf(self.meta_cf()?, EVENT_INDEXES)
+            .context("cannot read from database")?
+            .is_some())
+    }
+
+    /// Starts maintaining the secondary indexes on the originator addresses,
+    /// responder addresses, and sensor of events, and builds them for the
+    /// events already stored.
+    ///
+    /// Every write from then on keeps the indexes in step with the events in
+    /// the same transaction or batch. Enabling indexes that are already
+    /// enabled rebuilds any missing entry and changes nothing else.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an event cannot be read or if a database operation
+    /// fails.
+    pub fn enable_indexes(&self) -> Result<()> {
+        self.inner
+            .put_cf(self.meta_cf()?, EVENT_INDEXES, b"")
+            .context("cannot enable event indexes")?;
+
+        // From here on every write indexes the events it touches, so only the
+        // events stored before this point need to be visited. Each chunk is
+        // read again inside its transaction, so an event updated or removed
+        // meanwhile is indexed as it now is, or not at all.
+        let mut from: Option<Box<[u8]>> = None;
+        loop {
+            let mode = match &from {
+                Some(key) => IteratorMode::From(key, Direction::Forward),
+                None => IteratorMode::Start,
+            };
+            let mut keys = Vec::with_capacity(EVENT_DELETION_BATCH_SIZE);
+            for item in self.inner.iterator(mode) {
+                let (key, _value) = item.context("cannot read from event database")?;
+                if from.as_deref() == Some(key.as_ref()) {
+                    continue;
+                }
+                keys.push(key);
+                if keys.len() >= EVENT_DELETION_BATCH_SIZE {
+                    break;
+                }
+            }
+            let Some(last) = keys.last() else {
+                break;
+            };
+            from = Some(last.clone());
+
+            loop {
+                let txn = self.inner.transaction();
+                for key in &keys {
+                    let Some(value) = txn
+                        .get_for_update(key, super::EXCLUSIVE)
+                        .context("cannot read from event database")?
+                    else {
+                        continue;
+                    };
+                    match decode_entry(key, &value) {
+                        Ok((key, event)) => self.put_index_entries(&txn, key, &event)?,
+                        Err(e) => warn!("Event not indexed: {e:#}"),
+                    }
+                }
+                match txn.commit() {
+                    Ok(()) => break,
+                    Err(e) => {
+                        if !e.as_ref().starts_with("Resource busy:") {
+                            return Err(e).context("failed to index events");
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Stops maintaining the secondary indexes and removes their entries.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a database operation fails.
+    pub fn disable_indexes(&self) -> Result<()> {
+        self.inner
+            .delete_cf(self.meta_cf()?, EVENT_INDEXES)
+            .context("cannot disable event indexes")?;
+
+        for index in Index::ALL {
+            let cf = self.index_cf(index)?;
+            loop {
+                let mut batch = rocksdb::WriteBatchWithTransaction::<true>::default();
+                for item in self
+                    .inner
+                    .iterator_cf(cf, IteratorMode::Start)
+                    .take(EVENT_DELETION_BATCH_SIZE)
+                {
+                    let (entry, _) = item.context("cannot read the event index")?;
+                    batch.delete_cf(cf, entry);
+                }
+                if batch.is_empty() {
+                    break;
+                }
+                self.inner
+                    .write(batch)
+                    .context("failed to remove event index entries")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether the hourly and daily rollups are maintained.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a database operation fails.
+    pub fn rollups_enabled(&self) -> Result<bool> {
+        Ok(self
+            .inner
+            .get_pinned_cf(self.meta_cf()?, EVENT_ROLLUP_STATE)
+            .context("cannot read from database")?
+            .is_some())
+    }
+
+    /// Starts maintaining the hourly and daily rollups of the events, and
+    /// counts the events already stored in them.
+    ///
+    /// Every write from then on updates the rollups in the same transaction.
+    /// The events already stored are counted a chunk at a time; if this is
+    /// interrupted, calling it again resumes where it stopped. Enabling
+    /// rollups that are already complete changes nothing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an event cannot be read or if a database operation
+    /// fails.
+    pub fn enable_rollups(&self) -> Result<()> {
+        let meta = self.meta_cf()?;
+        let cf = self.rollup_cf()?;
+        loop {
+            let txn = self.inner.transaction();
+            let bound = match self.rollup_state(&txn)? {
+                rollup::State::Built => return Ok(()),
+                rollup::State::Building(bound) => bound,
+                rollup::State::Disabled => {
+                    // Start the build in a transaction of its own, so that
+                    // every write committed after it sees the build and every
+                    // event committed before it is there for the first chunk.
+                    txn.put_cf(meta, EVENT_ROLLUP_STATE, rollup::State::START)
+                        .context("cannot enable event rollups")?;
+                    if let Err(e) = txn.commit()
+                        && !e.as_ref().starts_with("Resource busy:")
+                    {
+                        return Err(e).context("cannot enable event rollups");
+                    }
+                    continue;
+                }
+            };
+
+            // The chunk is read after the marker, and any write to an event
+            // the chunk has not counted rewrites the marker, so a chunk that
+            // misses a concurrent write fails to commit and is read again.
+            let mut delta = rollup::Delta::default();
+            let mut last = None;
+            for item in self
+                .inner
+                .iterator(IteratorMode::From(&bound, Direction::Forward))
+                .take(EVENT_DELETION_BATCH_SIZE)
+            {
+                let (key, _value) = item.context("cannot read from event database")?;
+                if let Some(value) = txn
+                    .get_for_update(&key, super::EXCLUSIVE)
+                    .context("cannot read from event database")?
+                {
+                    match decode_entry(&key, &value) {
+                        Ok((key, event)) => delta.add(key, &event),
+                        Err(e) => warn!("Event not counted in rollups: {e:#}"),
+                    }
+                }
+                last = Some(key);
+            }
+            let marker = last.map_or_else(Vec::new, |key| rollup::bound_after(&key));
+            txn.put_cf(meta, EVENT_ROLLUP_STATE, marker)
+                .context("cannot write event rollup state")?;
+            delta.write(&txn, cf)?;
+            if let Err(e) = txn.commit()
+                && !e.as_ref().starts_with("Resource busy:")
+            {
+                return Err(e).context("failed to count events in rollups");
+            }
+        }
+    }
+
+    /// Stops maintaining the rollups and removes them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a database operation fails.
+    pub fn disable_rollups(&self) -> Result<()> {
+        self.inner
+            .delete_cf(self.meta_cf()?, EVENT_ROLLUP_STATE)
+            .context("cannot disable event rollups")?;
+
+        let cf = self.rollup_cf()?;
+        loop {
+            let mut batch = rocksdb::WriteBatchWithTransaction::<true>::default();
+            for item in self
+                .inner
+                .iterator_cf(cf, IteratorMode::Start)
+                .take(EVENT_DELETION_BATCH_SIZE)
+            {
+                let (bucket, _) = item.context("cannot read event rollups")?;
+                batch.delete_cf(cf, bucket);
+            }
+            if batch.is_empty() {
+                break;
+            }
+            self.inner
+                .write(batch)
+                .context("failed to remove event rollups")?;
+        }
+        Ok(())
+    }
+
+    /// Returns the rollups of `granularity` for the buckets that start in the
+    /// half-open range `[start, end)`, in ascending order. The bucket `start`
+    /// falls in is included. Buckets without events are omitted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the rollups are not enabled or not yet complete, if
+    /// they are not kept at `granularity`, or if a database operation fails.
+    pub fn rollups(
+        &self,
+        start: Timestamp,
+        end: Timestamp,
+        granularity: Granularity,
+    ) -> Result<Vec<Rollup>> {
+        let state = self
+            .inner
+            .get_pinned_cf(self.meta_cf()?, EVENT_ROLLUP_STATE)
+            .context("cannot read from database")?;
+        match rollup::State::from_marker(state.as_deref()) {
+            rollup::State::Built => {}
+            rollup::State::Disabled => bail!("event rollups are not enabled"),
+            rollup::State::Building(_) => bail!("event rollups are not complete yet"),
+        }
+
+        let lower = granularity.bucket_nanos(timestamp::event_key_nanos(start));
+        let upper = timestamp::event_key_nanos(end);
+        let mut opts = rocksdb::ReadOptions::default();
+        opts.set_iterate_lower_bound(rollup::bucket_key(granularity, lower)?);
+        opts.set_iterate_upper_bound(rollup::bucket_key(granularity, upper)?);
+        let mut rollups: Vec<Rollup> = Vec::new();
+        for item in self
+            .inner
+            .iterator_cf_opt(self.rollup_cf()?, opts, IteratorMode::Start)
+        {
+            let (key, value) = item.context("cannot read event rollups")?;
+            let shard = rollup::decode(&key, &value)?;
+            match rollups.last_mut() {
+                Some(last) if last.start == shard.start => last.merge(shard),
+                _ => rollups.push(shard),
+            }
+        }
+        Ok(rollups)
+    }
+
+    /// Returns the keys of the events with `addr` as an originator
+    /// (`Some(TrafficDirection::From)`), as a responder
+    /// (`Some(TrafficDirection::To)`), or as either (`None`), in ascending
+    /// order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the secondary indexes are not enabled or if a
+    /// database operation fails.
+    pub fn keys_by_address(
+        &self,
+        addr: IpAddr,
+        direction: Option<TrafficDirection>,
+    ) -> Result<Vec<i128>> {
+        let prefix = index::address_prefix(addr);
+        match direction {
+            Some(TrafficDirection::From) => self.index_keys(Index::Originator, &prefix),
+            Some(TrafficDirection::To) => self.index_keys(Index::Responder, &prefix),
+            None => {
+                let mut keys = self.index_keys(Index::Originator, &prefix)?;
+                keys.extend(self.index_keys(Index::Responder, &prefix)?);
+                keys.sort_unstable();
+                keys.dedup();
+                Ok(keys)
+            }
+        }
+    }
+
+    /// Returns the keys of the events from `orig` to `resp`, in ascending
+    /// order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the secondary indexes are not enabled or if a
+    /// database operation fails.
+    pub fn keys_by_address_pair(&self, orig: IpAddr, resp: IpAddr) -> Result<Vec<i128>> {
+        let responder: HashSet<i128> = self
+            .index_keys(Index::Responder, &index::address_prefix(resp))?
+            .into_iter()
+            .collect();
+        let mut keys = self.index_keys(Index::Originator, &index::address_prefix(orig))?;
+        keys.retain(|key| responder.contains(key));
+        Ok(keys)
+    }
+
+    /// Returns the keys of the events reported by `sensor`, in ascending
+    /// order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the secondary indexes are not enabled or if a
+    /// database operation fails.
+    pub fn keys_by_sensor(&self, sensor: &str) -> Result<Vec<i128>> {
+        self.index_keys(Index::Sensor, &index::sensor_prefix(sensor)?)
+    }
+
+    /// Returns the event keys of the entries in `index` under `prefix`.
+    fn index_keys(&self, index: Index, prefix: &[u8]) -> Result<Vec<i128>> {
+        if !self.indexes_enabled()? {
+            bail!("event indexes are not enabled");
+        }
+        let cf = self.index_cf(index)?;
+        let mut keys = Vec::new();
+        for item in self
+            .inner
+            .iterator_cf(cf, IteratorMode::From(prefix, Direction::Forward))
+        {
+            let (entry, _) = item.context("cannot read the event index")?;
+            if !entry.starts_with(prefix) {
+                break;
+            }
+            keys.push(index::event_key(&entry)?);
+        }
+        Ok(keys)
+    }
+
+    fn put_index_entries(
+        &self,
+        txn: &rocksdb::Transaction<rocksdb::OptimisticTransactionDB>,
+        key: i128,
+        event: &Event,
+    ) -> Result<()> {
+        for (index, entry) in index::entries(key, event)? {
+            txn.put_cf(self.index_cf(index)?, entry, b"")
+                .context("cannot write event index entry")?;
+        }
+        Ok(())
+    }
+
+    fn delete_index_entries(
+        &self,
+        txn: &rocksdb::Transaction<rocksdb::OptimisticTransactionDB>,
+        key: i128,
+        event: &Event,
+    ) -> Result<()> {
+        for (index, entry) in index::entries(key, event)? {
+            txn.delete_cf(self.index_cf(index)?, entry)
+                .context("cannot delete event index entry")?;
+        }
+        Ok(())
+    }
+
+    /// Reads how far the rollups cover the stored events within `txn`.
+    fn rollup_state(
+        &self,
+        txn: &rocksdb::Transaction<rocksdb::OptimisticTransactionDB>,
+    ) -> Result<rollup::State> {
+        let marker = txn
+            .get_for_update_cf(self.meta_cf()?, EVENT_ROLLUP_STATE, super::EXCLUSIVE)
+            .context("cannot read from database")?;
+        Ok(rollup::State::from_marker(marker.as_deref()))
+    }
+
+    /// Applies `delta` to the rollups within `txn`.
+    ///
+    /// While the rollups are being built, the build marker is rewritten as it
+    /// is, so that the write and a build chunk running at the same time
+    /// conflict and one of them is retried.
+    fn write_rollups(
+        &self,
+        txn: &rocksdb::Transaction<rocksdb::OptimisticTransactionDB>,
+        state: &rollup::State,
+        delta: rollup::Delta,
+    ) -> Result<()> {
+        if let rollup::State::Building(bound) = state {
+            txn.put_cf(self.meta_cf()?, EVENT_ROLLUP_STATE, bound)
+                .context("cannot write event rollup state")?;
+        }
+        delta.write(txn, self.rollup_cf()?)
+    }
+
+    fn consumer_offset_cf(&self) -> Result<&rocksdb::ColumnFamily> {
+        self.inner
+            .cf_handle(EVENT_CONSUMER_OFFSETS)
+            .with_context(|| format!("{EVENT_CONSUMER_OFFSETS} column family not found"))
+    }
+
+    fn triage_result_cf(&self) -> Result<&rocksdb::ColumnFamily> {
+        self.inner
+            .cf_handle(EVENT_TRIAGE_RESULTS)
+            .with_context(|| format!("{EVENT_TRIAGE_RESULTS} column family not found"))
+    }
+
+    fn rollup_cf(&self) -> Result<&rocksdb::ColumnFamily> {
+        self.inner
+            .cf_handle(EVENT_ROLLUPS)
+            .with_context(|| format!("{EVENT_ROLLUPS} column family not found"))
+    }
+
+    fn index_cf(&self, index: Index) -> Result<&rocksdb::ColumnFamily> {
+        self.inner
+            .cf_handle(index.cf_name())
+            .with_context(|| format!("{} column family not found", index.cf_name()))
+    }
+
+    fn meta_cf(&self) -> Result<&rocksdb::ColumnFamily> {
+        self.inner
+            .cf_handle(META)
+            .with_context(|| format!("{META} column family not found"))
+    }
+
+    /// Inserts a raw key-value pair into the event database.
+    #[cfg(test)]
+    fn put_raw(&self, key: &[u8], value: &[u8]) {
+        self.inner.put(key, value).expect("put_raw should succeed");
+    }
+
+    /// Returns the first event's on-disk key bytes in forward iteration order.
+    #[cfg(test)]
+    fn first_raw_event_key(&self) -> Result<Option<[u8; 16]>> {
         let mut iter = self.inner.iterator(IteratorMode::Start);
         match iter
             .next()
@@ -3443,6 +4391,7 @@ pub struct EventIterator<'i> {
         'i,
         rocksdb::OptimisticTransactionDB<rocksdb::SingleThreaded>,
     >,
+    kinds: Option<Vec<EventKind>>,
 }
 
 #[allow(clippy::module_name_repetitions)]
@@ -3483,411 +4432,274 @@ impl Iterator for EventIterator<'_> {
                 .expect(timestamp::I64_NANOS_JIFF_INVARIANT);
             let kind_num = (key & 0xffff_ffff_0000_0000) >> 32;
             if let Some(kind) = EventKind::from_i128(kind_num) {
+                if self
+                    .kinds
+                    .as_ref()
+                    .is_some_and(|kinds| !kinds.contains(&kind))
+                {
+                    continue;
+                }
                 break (key, kind, time, v);
             }
             warn!("Unknown event kind: {kind_num}; skipped");
         };
-        match kind {
-            EventKind::BlocklistBootp => {
-                let Ok(fields) = bincode::deserialize::<BlocklistBootpFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::Blocklist(RecordType::Bootp(BlocklistBootp::new(time, fields))),
-                )))
-            }
-            EventKind::BlocklistConn => {
-                let Ok(fields) = bincode::deserialize::<BlocklistConnFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::Blocklist(RecordType::Conn(BlocklistConn::new(time, fields))),
-                )))
-            }
-            EventKind::BlocklistDceRpc => {
-                let Ok(fields) = bincode::deserialize::<BlocklistDceRpcFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::Blocklist(RecordType::DceRpc(BlocklistDceRpc::new(time, fields))),
-                )))
-            }
-            EventKind::BlocklistDhcp => {
-                let Ok(fields) = bincode::deserialize::<BlocklistDhcpFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::Blocklist(RecordType::Dhcp(BlocklistDhcp::new(time, fields))),
-                )))
-            }
-            EventKind::BlocklistDns => {
-                let Ok(fields) = bincode::deserialize::<BlocklistDnsFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::Blocklist(RecordType::Dns(BlocklistDns::new(time, fields))),
-                )))
-            }
-            EventKind::BlocklistFtp => {
-                let Ok(fields) = bincode::deserialize::<FtpEventFieldsStored>(v.as_ref()) else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::Blocklist(RecordType::Ftp(BlocklistFtp::new(time, fields))),
-                )))
-            }
-            EventKind::BlocklistHttp => {
-                let Ok(fields) = bincode::deserialize::<BlocklistHttpFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::Blocklist(RecordType::Http(BlocklistHttp::new(time, fields))),
-                )))
-            }
-            EventKind::BlocklistKerberos => {
-                let Ok(fields) = bincode::deserialize::<BlocklistKerberosFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::Blocklist(RecordType::Kerberos(BlocklistKerberos::new(time, fields))),
-                )))
-            }
-            EventKind::BlocklistLdap => {
-                let Ok(fields) = bincode::deserialize::<LdapEventFieldsStored>(v.as_ref()) else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::Blocklist(RecordType::Ldap(BlocklistLdap::new(time, fields))),
-                )))
-            }
-            EventKind::BlocklistMalformedDns => {
-                let Ok(fields) =
-                    bincode::deserialize::<BlocklistMalformedDnsFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::Blocklist(RecordType::MalformedDns(BlocklistMalformedDns::new(
-                        time, fields,
-                    ))),
-                )))
-            }
-            EventKind::BlocklistMqtt => {
-                let Ok(fields) = bincode::deserialize::<BlocklistMqttFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::Blocklist(RecordType::Mqtt(BlocklistMqtt::new(time, fields))),
-                )))
-            }
-            EventKind::BlocklistNfs => {
-                let Ok(fields) = bincode::deserialize::<BlocklistNfsFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::Blocklist(RecordType::Nfs(BlocklistNfs::new(time, fields))),
-                )))
-            }
-            EventKind::BlocklistNtlm => {
-                let Ok(fields) = bincode::deserialize::<BlocklistNtlmFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::Blocklist(RecordType::Ntlm(BlocklistNtlm::new(time, fields))),
-                )))
-            }
-            EventKind::BlocklistRadius => {
-                let Ok(fields) = bincode::deserialize::<BlocklistRadiusFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::Blocklist(RecordType::Radius(BlocklistRadius::new(time, fields))),
-                )))
-            }
-            EventKind::BlocklistRdp => {
-                let Ok(fields) = bincode::deserialize::<BlocklistRdpFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::Blocklist(RecordType::Rdp(BlocklistRdp::new(time, fields))),
-                )))
-            }
-            EventKind::BlocklistSmb => {
-                let Ok(fields) = bincode::deserialize::<BlocklistSmbFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::Blocklist(RecordType::Smb(BlocklistSmb::new(time, fields))),
-                )))
-            }
-            EventKind::BlocklistSmtp => {
-                let Ok(fields) = bincode::deserialize::<BlocklistSmtpFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::Blocklist(RecordType::Smtp(BlocklistSmtp::new(time, fields))),
-                )))
-            }
-            EventKind::BlocklistSsh => {
-                let Ok(fields) = bincode::deserialize::<BlocklistSshFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::Blocklist(RecordType::Ssh(BlocklistSsh::new(time, fields))),
-                )))
-            }
-            EventKind::BlocklistTls => {
-                let Ok(fields) = bincode::deserialize::<BlocklistTlsFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::Blocklist(RecordType::Tls(BlocklistTls::new(time, fields))),
-                )))
-            }
-            EventKind::CryptocurrencyMiningPool => {
-                let Ok(fields) =
-                    bincode::deserialize::<CryptocurrencyMiningPoolFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::CryptocurrencyMiningPool(CryptocurrencyMiningPool::new(time, fields)),
-                )))
-            }
-            EventKind::DnsCovertChannel => {
-                let Ok(fields) = bincode::deserialize::<DnsEventFieldsStored>(v.as_ref()) else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::DnsCovertChannel(DnsCovertChannel::new(time, fields)),
-                )))
-            }
-            EventKind::DomainGenerationAlgorithm => {
-                let Ok(fields) = bincode::deserialize::<DgaFieldsStored>(v.as_ref()) else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::DomainGenerationAlgorithm(DomainGenerationAlgorithm::new(time, fields)),
-                )))
-            }
-            EventKind::ExternalDdos => {
-                let Ok(fields) = bincode::deserialize::<ExternalDdosFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::ExternalDdos(ExternalDdos::new(time, &fields)),
-                )))
-            }
-            EventKind::ExtraThreat => {
-                let Ok(fields) = bincode::deserialize::<ExtraThreatFieldsStored>(v.as_ref()) else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::ExtraThreat(ExtraThreat::new(fields.time, fields)),
-                )))
-            }
-            EventKind::FtpBruteForce => {
-                let Ok(fields) = bincode::deserialize::<FtpBruteForceFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::FtpBruteForce(FtpBruteForce::new(time, &fields)),
-                )))
-            }
-            EventKind::FtpPlainText => {
-                let Ok(fields) = bincode::deserialize::<FtpEventFieldsStored>(v.as_ref()) else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::FtpPlainText(FtpPlainText::new(time, fields)),
-                )))
-            }
-            EventKind::HttpThreat => {
-                let Ok(fields) = bincode::deserialize::<HttpThreatFieldsStored>(v.as_ref()) else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::HttpThreat(HttpThreat::new(fields.time, fields)),
-                )))
-            }
-            EventKind::LdapBruteForce => {
-                let Ok(fields) = bincode::deserialize::<LdapBruteForceFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::LdapBruteForce(LdapBruteForce::new(time, &fields)),
-                )))
-            }
-            EventKind::LdapPlainText => {
-                let Ok(fields) = bincode::deserialize::<LdapEventFieldsStored>(v.as_ref()) else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::LdapPlainText(LdapPlainText::new(time, fields)),
-                )))
-            }
-            EventKind::LockyRansomware => {
-                let Ok(fields) = bincode::deserialize::<DnsEventFieldsStored>(v.as_ref()) else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::LockyRansomware(LockyRansomware::new(time, fields)),
-                )))
-            }
-            EventKind::MultiHostPortScan => {
-                let Ok(fields) = bincode::deserialize::<MultiHostPortScanFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::MultiHostPortScan(MultiHostPortScan::new(time, &fields)),
-                )))
-            }
-            EventKind::NetworkThreat => {
-                let Ok(fields) = bincode::deserialize::<NetworkThreatFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::NetworkThreat(NetworkThreat::new(fields.time, fields)),
-                )))
-            }
-            EventKind::NonBrowser => {
-                let Ok(fields) = bincode::deserialize::<HttpEventFieldsStored>(v.as_ref()) else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((key, Event::NonBrowser(NonBrowser::new(time, &fields)))))
-            }
-            EventKind::PortScan => {
-                let Ok(fields) = bincode::deserialize::<PortScanFieldsStored>(v.as_ref()) else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((key, Event::PortScan(PortScan::new(time, &fields)))))
-            }
-            EventKind::RdpBruteForce => {
-                let Ok(fields) = bincode::deserialize::<RdpBruteForceFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::RdpBruteForce(RdpBruteForce::new(time, &fields)),
-                )))
-            }
-            EventKind::RepeatedHttpSessions => {
-                let Ok(fields) =
-                    bincode::deserialize::<RepeatedHttpSessionsFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::RepeatedHttpSessions(RepeatedHttpSessions::new(time, &fields)),
-                )))
-            }
-            EventKind::SuspiciousTlsTraffic => {
-                let Ok(fields) = bincode::deserialize::<BlocklistTlsFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::SuspiciousTlsTraffic(SuspiciousTlsTraffic::new(time, fields)),
-                )))
-            }
-            EventKind::UnusualDestinationPattern => {
-                let Ok(fields) =
-                    bincode::deserialize::<UnusualDestinationPatternFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::Blocklist(RecordType::UnusualDestinationPattern(
-                        UnusualDestinationPattern::new(time, fields),
-                    )),
-                )))
-            }
-            EventKind::TorConnection => {
-                let Ok(fields) = bincode::deserialize::<HttpEventFieldsStored>(v.as_ref()) else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::TorConnection(TorConnection::new(time, &fields)),
-                )))
-            }
-            EventKind::TorConnectionConn => {
-                let Ok(fields) = bincode::deserialize::<BlocklistConnFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::TorConnectionConn(TorConnectionConn::new(time, fields)),
-                )))
-            }
-            EventKind::WindowsThreat => {
-                let Ok(fields) = bincode::deserialize::<WindowsThreatFieldsStored>(v.as_ref())
-                else {
-                    return Some(Err(InvalidEvent::Value(v)));
-                };
-                Some(Ok((
-                    key,
-                    Event::WindowsThreat(WindowsThreat::new(fields.time, fields)),
-                )))
-            }
+        match decode_stored(kind, time, v.as_ref()) {
+            Some(event) => Some(Ok((key, event))),
+            None => Some(Err(InvalidEvent::Value(v))),
+        }
+    }
+}
+
+/// Returns the kind of the event stored under `key`.
+fn stored_kind(key: &[u8]) -> Result<EventKind> {
+    let key_bytes: [u8; 16] = key
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("event key must be 16 bytes, got {}", key.len()))?;
+    let key_number = i128::from_be_bytes(key_bytes);
+    let kind_number = (key_number & 0xffff_ffff_0000_0000) >> 32;
+    EventKind::from_i128(kind_number)
+        .ok_or_else(|| anyhow::anyhow!("unknown event kind: {kind_number}"))
+}
+
+/// Decodes an event from its on-disk key and value.
+fn decode_entry(key: &[u8], value: &[u8]) -> Result<(i128, Event)> {
+    let kind = stored_kind(key)?;
+    let key = i128::from_be_bytes(key.try_into().expect("16-byte event key"));
+    let time = timestamp::from_i64_nanos((key >> 64).try_into().expect("valid i64"))
+        .expect(timestamp::I64_NANOS_JIFF_INVARIANT);
+    let event = decode_stored(kind, time, value)
+        .ok_or_else(|| anyhow::anyhow!("invalid stored fields for event {key}"))?;
+    Ok((key, event))
+}
+
+/// Decodes the stored fields of an event of `kind` recorded at `time`.
+///
+/// Returns `None` if `value` is not a valid stored representation of `kind`.
+fn decode_stored(kind: EventKind, time: Timestamp, value: &[u8]) -> Option<Event> {
+    match kind {
+        EventKind::BlocklistBootp => {
+            let fields = bincode::deserialize::<BlocklistBootpFieldsStored>(value).ok()?;
+            Some(Event::Blocklist(RecordType::Bootp(BlocklistBootp::new(
+                time, fields,
+            ))))
+        }
+        EventKind::BlocklistConn => {
+            let fields = bincode::deserialize::<BlocklistConnFieldsStored>(value).ok()?;
+            Some(Event::Blocklist(RecordType::Conn(BlocklistConn::new(
+                time, fields,
+            ))))
+        }
+        EventKind::BlocklistDceRpc => {
+            let fields = bincode::deserialize::<BlocklistDceRpcFieldsStored>(value).ok()?;
+            Some(Event::Blocklist(RecordType::DceRpc(BlocklistDceRpc::new(
+                time, fields,
+            ))))
+        }
+        EventKind::BlocklistDhcp => {
+            let fields = bincode::deserialize::<BlocklistDhcpFieldsStored>(value).ok()?;
+            Some(Event::Blocklist(RecordType::Dhcp(BlocklistDhcp::new(
+                time, fields,
+            ))))
+        }
+        EventKind::BlocklistDns => {
+            let fields = bincode::deserialize::<BlocklistDnsFieldsStored>(value).ok()?;
+            Some(Event::Blocklist(RecordType::Dns(BlocklistDns::new(
+                time, fields,
+            ))))
+        }
+        EventKind::BlocklistFtp => {
+            let fields = bincode::deserialize::<FtpEventFieldsStored>(value).ok()?;
+            Some(Event::Blocklist(RecordType::Ftp(BlocklistFtp::new(
+                time, fields,
+            ))))
+        }
+        EventKind::BlocklistHttp => {
+            let fields = bincode::deserialize::<BlocklistHttpFieldsStored>(value).ok()?;
+            Some(Event::Blocklist(RecordType::Http(BlocklistHttp::new(
+                time, fields,
+            ))))
+        }
+        EventKind::BlocklistKerberos => {
+            let fields = bincode::deserialize::<BlocklistKerberosFieldsStored>(value).ok()?;
+            Some(Event::Blocklist(RecordType::Kerberos(
+                BlocklistKerberos::new(time, fields),
+            )))
+        }
+        EventKind::BlocklistLdap => {
+            let fields = bincode::deserialize::<LdapEventFieldsStored>(value).ok()?;
+            Some(Event::Blocklist(RecordType::Ldap(BlocklistLdap::new(
+                time, fields,
+            ))))
+        }
+        EventKind::BlocklistMalformedDns => {
+            let fields = bincode::deserialize::<BlocklistMalformedDnsFieldsStored>(value).ok()?;
+            Some(Event::Blocklist(RecordType::MalformedDns(
+                BlocklistMalformedDns::new(time, fields),
+            )))
+        }
+        EventKind::BlocklistMqtt => {
+            let fields = bincode::deserialize::<BlocklistMqttFieldsStored>(value).ok()?;
+            Some(Event::Blocklist(RecordType::Mqtt(BlocklistMqtt::new(
+                time, fields,
+            ))))
+        }
+        EventKind::BlocklistNfs => {
+            let fields = bincode::deserialize::<BlocklistNfsFieldsStored>(value).ok()?;
+            Some(Event::Blocklist(RecordType::Nfs(BlocklistNfs::new(
+                time, fields,
+            ))))
+        }
+        EventKind::BlocklistNtlm => {
+            let fields = bincode::deserialize::<BlocklistNtlmFieldsStored>(value).ok()?;
+            Some(Event::Blocklist(RecordType::Ntlm(BlocklistNtlm::new(
+                time, fields,
+            ))))
+        }
+        EventKind::BlocklistRadius => {
+            let fields = bincode::deserialize::<BlocklistRadiusFieldsStored>(value).ok()?;
+            Some(Event::Blocklist(RecordType::Radius(BlocklistRadius::new(
+                time, fields,
+            ))))
+        }
+        EventKind::BlocklistRdp => {
+            let fields = bincode::deserialize::<BlocklistRdpFieldsStored>(value).ok()?;
+            Some(Event::Blocklist(RecordType::Rdp(BlocklistRdp::new(
+                time, fields,
+            ))))
+        }
+        EventKind::BlocklistSmb => {
+            let fields = bincode::deserialize::<BlocklistSmbFieldsStored>(value).ok()?;
+            Some(Event::Blocklist(RecordType::Smb(BlocklistSmb::new(
+                time, fields,
+            ))))
+        }
+        EventKind::BlocklistSmtp => {
+            let fields = bincode::deserialize::<BlocklistSmtpFieldsStored>(value).ok()?;
+            Some(Event::Blocklist(RecordType::Smtp(BlocklistSmtp::new(
+                time, fields,
+            ))))
+        }
+        EventKind::BlocklistSsh => {
+            let fields = bincode::deserialize::<BlocklistSshFieldsStored>(value).ok()?;
+            Some(Event::Blocklist(RecordType::Ssh(BlocklistSsh::new(
+                time, fields,
+            ))))
+        }
+        EventKind::BlocklistTls => {
+            let fields = bincode::deserialize::<BlocklistTlsFieldsStored>(value).ok()?;
+            Some(Event::Blocklist(RecordType::Tls(BlocklistTls::new(
+                time, fields,
+            ))))
+        }
+        EventKind::CryptocurrencyMiningPool => {
+            let fields =
+                bincode::deserialize::<CryptocurrencyMiningPoolFieldsStored>(value).ok()?;
+            Some(Event::CryptocurrencyMiningPool(
+                CryptocurrencyMiningPool::new(time, fields),
+            ))
+        }
+        EventKind::DnsCovertChannel => {
+            let fields = bincode::deserialize::<DnsEventFieldsStored>(value).ok()?;
+            Some(Event::DnsCovertChannel(DnsCovertChannel::new(time, fields)))
+        }
+        EventKind::DomainGenerationAlgorithm => {
+            let fields = bincode::deserialize::<DgaFieldsStored>(value).ok()?;
+            Some(Event::DomainGenerationAlgorithm(
+                DomainGenerationAlgorithm::new(time, fields),
+            ))
+        }
+        EventKind::ExternalDdos => {
+            let fields = bincode::deserialize::<ExternalDdosFieldsStored>(value).ok()?;
+            Some(Event::ExternalDdos(ExternalDdos::new(time, &fields)))
+        }
+        EventKind::ExtraThreat => {
+            let fields = bincode::deserialize::<ExtraThreatFieldsStored>(value).ok()?;
+            Some(Event::ExtraThreat(ExtraThreat::new(fields.time, fields)))
+        }
+        EventKind::FtpBruteForce => {
+            let fields = bincode::deserialize::<FtpBruteForceFieldsStored>(value).ok()?;
+            Some(Event::FtpBruteForce(FtpBruteForce::new(time, &fields)))
+        }
+        EventKind::FtpPlainText => {
+            let fields = bincode::deserialize::<FtpEventFieldsStored>(value).ok()?;
+            Some(Event::FtpPlainText(FtpPlainText::new(time, fields)))
+        }
+        EventKind::HttpThreat => {
+            let fields = bincode::deserialize::<HttpThreatFieldsStored>(value).ok()?;
+            Some(Event::HttpThreat(HttpThreat::new(fields.time, fields)))
+        }
+        EventKind::LdapBruteForce => {
+            let fields = bincode::deserialize::<LdapBruteForceFieldsStored>(value).ok()?;
+            Some(Event::LdapBruteForce(LdapBruteForce::new(time, &fields)))
+        }
+        EventKind::LdapPlainText => {
+            let fields = bincode::deserialize::<LdapEventFieldsStored>(value).ok()?;
+            Some(Event::LdapPlainText(LdapPlainText::new(time, fields)))
+        }
+        EventKind::LockyRansomware => {
+            let fields = bincode::deserialize::<DnsEventFieldsStored>(value).ok()?;
+            Some(Event::LockyRansomware(LockyRansomware::new(time, fields)))
+        }
+        EventKind::MultiHostPortScan => {
+            let fields = bincode::deserialize::<MultiHostPortScanFieldsStored>(value).ok()?;
+            Some(Event::MultiHostPortScan(MultiHostPortScan::new(
+                time, &fields,
+            )))
+        }
+        EventKind::NetworkThreat => {
+            let fields = bincode::deserialize::<NetworkThreatFieldsStored>(value).ok()?;
+            Some(Event::NetworkThreat(NetworkThreat::new(
+                fields.time,
+                fields,
+            )))
+        }
+        EventKind::NonBrowser => {
+            let fields = bincode::deserialize::<HttpEventFieldsStored>(value).ok()?;
+            Some(Event::NonBrowser(NonBrowser::new(time, &fields)))
+        }
+        EventKind::PortScan => {
+            let fields = bincode::deserialize::<PortScanFieldsStored>(value).ok()?;
+            Some(Event::PortScan(PortScan::new(time, &fields)))
+        }
+        EventKind::RdpBruteForce => {
+            let fields = bincode::deserialize::<RdpBruteForceFieldsStored>(value).ok()?;
+            Some(Event::RdpBruteForce(RdpBruteForce::new(time, &fields)))
+        }
+        EventKind::RepeatedHttpSessions => {
+            let fields = bincode::deserialize::<RepeatedHttpSessionsFieldsStored>(value).ok()?;
+            Some(Event::RepeatedHttpSessions(RepeatedHttpSessions::new(
+                time, &fields,
+            )))
+        }
+        EventKind::SuspiciousTlsTraffic => {
+            let fields = bincode::deserialize::<BlocklistTlsFieldsStored>(value).ok()?;
+            Some(Event::SuspiciousTlsTraffic(SuspiciousTlsTraffic::new(
+                time, fields,
+            )))
+        }
+        EventKind::UnusualDestinationPattern => {
+            let fields =
+                bincode::deserialize::<UnusualDestinationPatternFieldsStored>(value).ok()?;
+            Some(Event::Blocklist(RecordType::UnusualDestinationPattern(
+                UnusualDestinationPattern::new(time, fields),
+            )))
+        }
+        EventKind::TorConnection => {
+            let fields = bincode::deserialize::<HttpEventFieldsStored>(value).ok()?;
+            Some(Event::TorConnection(TorConnection::new(time, &fields)))
+        }
+        EventKind::TorConnectionConn => {
+            let fields = bincode::deserialize::<BlocklistConnFieldsStored>(value).ok()?;
+            Some(Event::TorConnectionConn(TorConnectionConn::new(
+                time, fields,
+            )))
+        }
+        EventKind::WindowsThreat => {
+            let fields = bincode::deserialize::<WindowsThreatFieldsStored>(value).ok()?;
+            Some(Event::WindowsThreat(WindowsThreat::new(
+                fields.time,
+                fields,
+            )))
         }
     }
 }
@@ -8038,6 +8850,1204 @@ mod tests {
         assert_eq!(db.iter_forward().count(), 1);
     }
 
+    #[test]
+    fn range_bounds_by_time() {
+        let (_permit, store) = setup_store();
+        let db = store.events();
+
+        for day in [1, 2, 3] {
+            let msg = dns_message(
+                "sensor1",
+                Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap(),
+            );
+            db.put(&msg).unwrap();
+        }
+
+        let start = msg_time(Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap());
+        let end = msg_time(Utc.with_ymd_and_hms(2024, 1, 3, 0, 0, 0).unwrap());
+        let events: Vec<_> = db.range(start, end, None).map(Result::unwrap).collect();
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].0 >> 64,
+            i128::from(timestamp::event_key_nanos(start))
+        );
+
+        let end = msg_time(Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap());
+        assert_eq!(db.range(start, end, None).count(), 2);
+        assert_eq!(db.range(end, end, None).count(), 0);
+    }
+
+    #[test]
+    fn range_filters_kinds() {
+        let (_permit, store) = setup_store();
+        let db = store.events();
+
+        let dns = example_message(
+            EventKind::DnsCovertChannel,
+            EventCategory::CommandAndControl,
+        );
+        let locky = EventMessage {
+            time: dns.time,
+            kind: EventKind::LockyRansomware,
+            fields: dns.fields.clone(),
+        };
+        db.put(&dns).unwrap();
+        db.put(&locky).unwrap();
+
+        let start = msg_time(Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap());
+        let end = msg_time(Utc::now() + chrono::Duration::days(1));
+        assert_eq!(db.range(start, end, None).count(), 2);
+
+        let events: Vec<_> = db
+            .range(start, end, Some(&[EventKind::LockyRansomware]))
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0].1, Event::LockyRansomware(_)));
+
+        assert_eq!(
+            db.range(start, end, Some(&[EventKind::PortScan])).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn aggregate_matches_per_dimension_counts() {
+        use super::{Dimension, Granularity};
+
+        let (_permit, store) = setup_store();
+        let db = store.events();
+        for (day, hour, sensor) in [(1, 0, "sensor1"), (1, 5, "sensor2"), (2, 3, "sensor1")] {
+            let msg = dns_message(
+                sensor,
+                Utc.with_ymd_and_hms(2024, 1, day, hour, 0, 0).unwrap(),
+            );
+            db.put(&msg).unwrap();
+        }
+        let mut locky = example_message(EventKind::LockyRansomware, EventCategory::Impact);
+        locky.time = msg_time(Utc.with_ymd_and_hms(2024, 1, 2, 4, 0, 0).unwrap());
+        db.put(&locky).unwrap();
+
+        let start = msg_time(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        let end = msg_time(Utc.with_ymd_and_hms(2024, 1, 3, 0, 0, 0).unwrap());
+        let filter = sensor_filter(Some(vec!["sensor1".to_string(), "collector1".to_string()]));
+        let aggregation = db
+            .aggregate(
+                start,
+                end,
+                &filter,
+                &[
+                    Dimension::Country,
+                    Dimension::Category,
+                    Dimension::IpAddress,
+                    Dimension::IpAddressPair,
+                    Dimension::Kind,
+                    Dimension::Level,
+                    Dimension::Time(Granularity::Day),
+                ],
+            )
+            .unwrap();
+
+        let mut country = HashMap::new();
+        let mut category = HashMap::new();
+        let mut ip_address = HashMap::new();
+        let mut ip_address_pair = HashMap::new();
+        let mut kind = HashMap::new();
+        let mut level = HashMap::new();
+        for item in db.iter_forward() {
+            let (_, event) = item.unwrap();
+            event.count_country(&mut country, &filter).unwrap();
+            event.count_category(&mut category, &filter).unwrap();
+            event.count_ip_address(&mut ip_address, &filter).unwrap();
+            event
+                .count_ip_address_pair(&mut ip_address_pair, &filter)
+                .unwrap();
+            event.count_kind(&mut kind, &filter).unwrap();
+            event.count_level(&mut level, &filter).unwrap();
+        }
+
+        assert_eq!(aggregation.total, 3);
+        assert_eq!(aggregation.country, country);
+        assert_eq!(aggregation.category, category);
+        assert_eq!(aggregation.ip_address, ip_address);
+        assert_eq!(aggregation.ip_address_pair, ip_address_pair);
+        assert_eq!(aggregation.kind, kind);
+        assert_eq!(aggregation.level, level);
+        assert!(aggregation.originator_ip_address.is_empty());
+
+        let days: Vec<_> = aggregation.time[&Granularity::Day]
+            .iter()
+            .map(|(time, count)| (*time, *count))
+            .collect();
+        assert_eq!(
+            days,
+            [
+                (start, 1),
+                (
+                    msg_time(Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap()),
+                    2
+                )
+            ]
+        );
+    }
+
+    fn sensor_filter(sensors: Option<Vec<String>>) -> EventFilter {
+        EventFilter::new(
+            None, None, None, None, None, None, None, None, None, None, sensors, None, None, None,
+        )
+    }
+
+    #[test]
+    fn query_pages_forward_and_reverse() {
+        use super::{Direction, PageRequest};
+
+        let (_permit, store) = setup_store();
+        let db = store.events();
+        for day in 1..=5 {
+            let msg = dns_message(
+                "sensor1",
+                Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap(),
+            );
+            db.put(&msg).unwrap();
+        }
+        let all: Vec<i128> = db.iter_forward().map(|e| e.unwrap().0).collect();
+        let filter = sensor_filter(None);
+
+        let first = db
+            .query(&filter, PageRequest::first(2, Direction::Forward))
+            .unwrap();
+        let keys: Vec<i128> = first.items.iter().map(|(key, _, _)| *key).collect();
+        assert_eq!(keys, all[..2]);
+        assert!(first.has_more);
+
+        let cursor = first.end_cursor.unwrap().to_string().parse().unwrap();
+        let second = db
+            .query(&filter, PageRequest::after(cursor, 2, Direction::Forward))
+            .unwrap();
+        let keys: Vec<i128> = second.items.iter().map(|(key, _, _)| *key).collect();
+        assert_eq!(keys, all[2..4]);
+        assert!(second.has_more);
+
+        let last = db
+            .query(
+                &filter,
+                PageRequest::after(second.end_cursor.unwrap(), 2, Direction::Forward),
+            )
+            .unwrap();
+        assert_eq!(last.items.len(), 1);
+        assert_eq!(last.items[0].0, all[4]);
+        assert!(!last.has_more);
+
+        let back = db
+            .query(
+                &filter,
+                PageRequest::after(second.start_cursor.unwrap(), 10, Direction::Reverse),
+            )
+            .unwrap();
+        let keys: Vec<i128> = back.items.iter().map(|(key, _, _)| *key).collect();
+        assert_eq!(keys, [all[1], all[0]]);
+        assert!(!back.has_more);
+
+        let newest = db
+            .query(&filter, PageRequest::first(1, Direction::Reverse))
+            .unwrap();
+        assert_eq!(newest.items[0].0, all[4]);
+        assert!(newest.has_more);
+    }
+
+    #[test]
+    fn query_applies_filter_and_survives_removed_cursor() {
+        use super::{Direction, PageRequest};
+
+        let (_permit, store) = setup_store();
+        let db = store.events();
+        for (day, sensor) in [
+            (1, "sensor1"),
+            (2, "sensor2"),
+            (3, "sensor1"),
+            (4, "sensor1"),
+        ] {
+            let msg = dns_message(sensor, Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap());
+            db.put(&msg).unwrap();
+        }
+        let filter = sensor_filter(Some(vec!["sensor1".to_string()]));
+
+        let first = db
+            .query(&filter, PageRequest::first(2, Direction::Forward))
+            .unwrap();
+        assert_eq!(first.items.len(), 2);
+        assert!(first.items.iter().all(
+            |(_, event, _)| matches!(event, Event::DnsCovertChannel(e) if e.sensor == "sensor1")
+        ));
+        assert!(first.has_more);
+
+        // Removing the event a cursor points at does not invalidate it.
+        let cursor = first.end_cursor.unwrap();
+        db.remove_before(msg_time(
+            Utc.with_ymd_and_hms(2024, 1, 3, 12, 0, 0).unwrap(),
+        ))
+        .unwrap();
+        let next = db
+            .query(&filter, PageRequest::after(cursor, 2, Direction::Forward))
+            .unwrap();
+        assert_eq!(next.items.len(), 1);
+        assert!(!next.has_more);
+
+        let empty = db
+            .query(
+                &filter,
+                PageRequest::after(next.end_cursor.unwrap(), 2, Direction::Forward),
+            )
+            .unwrap();
+        assert!(empty.items.is_empty());
+        assert!(empty.start_cursor.is_none() && empty.end_cursor.is_none());
+        assert!(!empty.has_more);
+    }
+
+    #[test]
+    fn query_rejects_empty_pages() {
+        use super::{Direction, PageRequest};
+
+        let (_permit, store) = setup_store();
+        let db = store.events();
+        db.put(&dns_message(
+            "sensor1",
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        ))
+        .unwrap();
+
+        let filter = sensor_filter(None);
+        assert!(
+            db.query(&filter, PageRequest::first(0, Direction::Forward))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn indexes_track_put_and_removal() {
+        let (_permit, store) = setup_store();
+        let db = store.events();
+        db.enable_indexes().unwrap();
+
+        let old = db
+            .put(&dns_message(
+                "sensor1",
+                Utc.with_ymd_and_hms(2023, 6, 1, 0, 0, 0).unwrap(),
+            ))
+            .unwrap();
+        let new = db
+            .put(&dns_message(
+                "sensor2",
+                Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap(),
+            ))
+            .unwrap();
+        let orig = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let resp = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2));
+
+        assert_eq!(db.keys_by_sensor("sensor1").unwrap(), vec![old]);
+        assert_eq!(db.keys_by_sensor("sensor2").unwrap(), vec![new]);
+        assert!(db.keys_by_sensor("sensor").unwrap().is_empty());
+        assert_eq!(
+            db.keys_by_address(orig, Some(super::TrafficDirection::From))
+                .unwrap(),
+            vec![old, new]
+        );
+        assert!(
+            db.keys_by_address(orig, Some(super::TrafficDirection::To))
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(db.keys_by_address(resp, None).unwrap(), vec![old, new]);
+        assert_eq!(db.keys_by_address_pair(orig, resp).unwrap(), vec![old, new]);
+        assert!(db.keys_by_address_pair(resp, orig).unwrap().is_empty());
+
+        let cutoff = msg_time(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        assert_eq!(db.remove_before(cutoff).unwrap(), 1);
+        assert!(db.keys_by_sensor("sensor1").unwrap().is_empty());
+        assert_eq!(db.keys_by_address(orig, None).unwrap(), vec![new]);
+
+        db.remove_by_sensors(&["sensor2".to_string()]).unwrap();
+        assert_eq!(db.iter_forward().count(), 0);
+        assert!(db.keys_by_sensor("sensor2").unwrap().is_empty());
+        assert!(db.keys_by_address(orig, None).unwrap().is_empty());
+        assert!(db.keys_by_address(resp, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn indexed_removal_skips_undecodable_events() {
+        let (_permit, store) = setup_store();
+        let db = store.events();
+        db.enable_indexes().unwrap();
+
+        let broken = db
+            .put(&dns_message(
+                "sensor1",
+                Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            ))
+            .unwrap();
+        db.put(&dns_message(
+            "sensor1",
+            Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap(),
+        ))
+        .unwrap();
+        db.put_raw(&broken.to_be_bytes(), b"invalid");
+
+        db.remove_by_sensors(&["sensor1".to_string()]).unwrap();
+        assert_eq!(db.iter_forward().count(), 0);
+        assert!(db.keys_by_sensor("sensor1").unwrap().is_empty());
+        // Only the address entries of the undecodable event are left.
+        assert_eq!(
+            db.keys_by_address(IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+                .unwrap(),
+            vec![broken]
+        );
+    }
+
+    #[test]
+    fn indexes_follow_update() {
+        let (_permit, store) = setup_store();
+        let db = store.events();
+        db.enable_indexes().unwrap();
+
+        let first = db
+            .put(&dns_message(
+                "sensor1",
+                Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            ))
+            .unwrap();
+        let second = db
+            .put(&dns_message(
+                "sensor2",
+                Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap(),
+            ))
+            .unwrap();
+        let raw: Vec<_> = db.raw_iter().map(Result::unwrap).collect();
+        let (first_key, first_value) = &raw[0];
+        let (_, second_value) = &raw[1];
+
+        db.update(
+            (first_key.as_slice(), first_value.as_slice()),
+            (first_key.as_slice(), second_value.as_slice()),
+        )
+        .unwrap();
+
+        assert!(db.keys_by_sensor("sensor1").unwrap().is_empty());
+        assert_eq!(db.keys_by_sensor("sensor2").unwrap(), vec![first, second]);
+    }
+
+    #[test]
+    fn enable_indexes_covers_existing_events() {
+        let (_permit, store) = setup_store();
+        let db = store.events();
+
+        let key = db
+            .put(&dns_message(
+                "sensor1",
+                Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            ))
+            .unwrap();
+        assert!(!db.indexes_enabled().unwrap());
+        assert!(db.keys_by_sensor("sensor1").is_err());
+
+        db.enable_indexes().unwrap();
+        assert!(db.indexes_enabled().unwrap());
+        assert_eq!(db.keys_by_sensor("sensor1").unwrap(), vec![key]);
+        assert_eq!(
+            db.keys_by_address(IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+                .unwrap(),
+            vec![key]
+        );
+
+        db.disable_indexes().unwrap();
+        assert!(!db.indexes_enabled().unwrap());
+        assert!(db.keys_by_sensor("sensor1").is_err());
+
+        // Removal without indexes leaves nothing behind for a later rebuild.
+        db.remove_by_sensors(&["sensor1".to_string()]).unwrap();
+        db.enable_indexes().unwrap();
+        assert!(db.keys_by_sensor("sensor1").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rollups_follow_puts_and_removals() {
+        use super::Granularity;
+
+        let (_permit, store) = setup_store();
+        let db = store.events();
+        db.enable_rollups().unwrap();
+
+        for (hour, minute, sensor) in [(1, 10, "sensor1"), (1, 20, "sensor2"), (3, 0, "sensor1")] {
+            let msg = dns_message(
+                sensor,
+                Utc.with_ymd_and_hms(2024, 1, 1, hour, minute, 0).unwrap(),
+            );
+            db.put(&msg).unwrap();
+        }
+        let start = msg_time(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        let end = msg_time(Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap());
+
+        let hours = db.rollups(start, end, Granularity::Hour).unwrap();
+        assert_eq!(hours.len(), 2);
+        assert_eq!(
+            hours[0].start,
+            msg_time(Utc.with_ymd_and_hms(2024, 1, 1, 1, 0, 0).unwrap())
+        );
+        assert_eq!(hours[0].total, 2);
+        assert_eq!(hours[0].kinds[&EventKind::DnsCovertChannel], 2);
+        assert_eq!(hours[0].sensors["sensor1"], 1);
+        assert_eq!(hours[0].sensors["sensor2"], 1);
+        assert_eq!(hours[0].categories[&EventCategory::CommandAndControl], 2);
+        assert_eq!(hours[1].total, 1);
+
+        let days = db.rollups(start, end, Granularity::Day).unwrap();
+        assert_eq!(days.len(), 1);
+        assert_eq!(days[0].start, start);
+        assert_eq!(days[0].total, 3);
+        assert!(db.rollups(start, end, Granularity::Minute).is_err());
+
+        db.remove_by_sensors(&["sensor2".to_string()]).unwrap();
+        let hours = db.rollups(start, end, Granularity::Hour).unwrap();
+        assert_eq!(hours[0].total, 1);
+        assert!(!hours[0].sensors.contains_key("sensor2"));
+
+        let removed = db
+            .remove_before(msg_time(Utc.with_ymd_and_hms(2024, 1, 1, 2, 0, 0).unwrap()))
+            .unwrap();
+        assert_eq!(removed, 1);
+        let hours = db.rollups(start, end, Granularity::Hour).unwrap();
+        assert_eq!(hours.len(), 1);
+        assert_eq!(hours[0].total, 1);
+        assert_eq!(
+            db.rollups(start, end, Granularity::Day).unwrap()[0].total,
+            1
+        );
+    }
+
+    #[test]
+    fn enable_rollups_counts_existing_events() {
+        use super::Granularity;
+
+        let (_permit, store) = setup_store();
+        let db = store.events();
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        db.put(&dns_message("sensor1", time)).unwrap();
+        db.put(&dns_message("sensor1", time)).unwrap();
+        let start = msg_time(time);
+        let end = msg_time(Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap());
+        assert!(!db.rollups_enabled().unwrap());
+        assert!(db.rollups(start, end, Granularity::Day).is_err());
+
+        db.enable_rollups().unwrap();
+        assert!(db.rollups_enabled().unwrap());
+        let days = db.rollups(start, end, Granularity::Day).unwrap();
+        assert_eq!(days.len(), 1);
+        assert_eq!(days[0].total, 2);
+
+        // Enabling again does not count the events twice.
+        db.enable_rollups().unwrap();
+        db.put(&dns_message("sensor1", time)).unwrap();
+        assert_eq!(
+            db.rollups(start, end, Granularity::Day).unwrap()[0].total,
+            3
+        );
+
+        db.disable_rollups().unwrap();
+        assert!(!db.rollups_enabled().unwrap());
+        assert!(db.rollups(start, end, Granularity::Day).is_err());
+        db.enable_rollups().unwrap();
+        assert_eq!(
+            db.rollups(start, end, Granularity::Day).unwrap()[0].total,
+            3
+        );
+    }
+
+    #[test]
+    fn jsonl_round_trips_every_kind() {
+        use num_traits::ToPrimitive;
+
+        fn without_country_codes(line: &str) -> serde_json::Value {
+            let mut value: serde_json::Value = serde_json::from_str(line).unwrap();
+            value["fields"]
+                .as_object_mut()
+                .unwrap()
+                .retain(|name, _| !name.contains("country_code"));
+            value
+        }
+
+        let (_permit, store) = setup_store();
+        let db = store.events();
+        let samples = super::stored_event_samples_v0_46();
+        for (index, (kind, value)) in samples.iter().enumerate() {
+            let nanos = (i128::try_from(index).unwrap() + 1) * 1_000_000_000;
+            let key = (nanos << 64) | (kind.to_i128().unwrap() << 32);
+            db.put_raw(&key.to_be_bytes(), value);
+        }
+        let mut exported = Vec::new();
+        let count = db.export_jsonl(&mut exported, None).unwrap();
+        assert_eq!(count, u64::try_from(samples.len()).unwrap());
+        let exported = String::from_utf8(exported).unwrap();
+
+        let cutoff = msg_time(Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap());
+        db.remove_before(cutoff).unwrap();
+        assert_eq!(db.import_jsonl(exported.as_bytes()).unwrap(), count);
+        let mut reexported = Vec::new();
+        db.export_jsonl(&mut reexported, None).unwrap();
+        let reexported = String::from_utf8(reexported).unwrap();
+
+        assert_eq!(exported.lines().count(), samples.len());
+        for (line, reline) in exported.lines().zip(reexported.lines()) {
+            assert_eq!(without_country_codes(line), without_country_codes(reline));
+        }
+    }
+
+    #[test]
+    fn jsonl_export_applies_filter_and_import_reports_line() {
+        let (_permit, store) = setup_store();
+        let db = store.events();
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        db.put(&dns_message("sensor1", time)).unwrap();
+        db.put(&dns_message("sensor2", time)).unwrap();
+
+        let mut exported = Vec::new();
+        let filter = sensor_filter(Some(vec!["sensor2".to_string()]));
+        assert_eq!(db.export_jsonl(&mut exported, Some(&filter)).unwrap(), 1);
+        let line: serde_json::Value = serde_json::from_slice(&exported).unwrap();
+        assert_eq!(line["schema_version"], super::JSONL_SCHEMA_VERSION);
+        assert_eq!(line["kind"], "DnsCovertChannel");
+        assert_eq!(line["fields"]["sensor"], "sensor2");
+
+        let mut input = String::from_utf8(exported).unwrap();
+        input.push_str("\n{\"schema_version\":1}\n");
+        let err = db.import_jsonl(input.as_bytes()).unwrap_err();
+        assert!(format!("{err:#}").contains("line 3"));
+        assert_eq!(db.iter_forward().count(), 3);
+    }
+
+    #[test]
+    fn siem_formats_cover_every_kind() {
+        use super::{Device, SiemFormat};
+
+        let device = Device {
+            vendor: "Vendor|Inc",
+            product: "Product",
+            version: "1.0",
+        };
+        for (index, (kind, value)) in super::stored_event_samples_v0_46().iter().enumerate() {
+            let key = (i128::try_from(index).unwrap() + 1) << 64;
+            let mut line = Vec::new();
+            super::jsonl::write_line(&mut line, *kind, key, value, None).unwrap();
+            let message = super::jsonl::parse_line(std::str::from_utf8(&line).unwrap()).unwrap();
+
+            let cef = message.cef(&device).unwrap();
+            assert!(cef.starts_with(&format!("CEF:0|Vendor\\|Inc|Product|1.0|{kind:?}|")));
+            let category = kind.categories()[0].to_string();
+            assert!(cef.contains(&format!(" cat={category}")), "{cef}");
+            assert!(!cef.contains('\n'));
+
+            let leef = message.leef(&device).unwrap();
+            assert!(leef.starts_with(&format!("LEEF:1.0|Vendor\\|Inc|Product|1.0|{kind:?}|")));
+            assert!(leef.contains(&format!("\tcat={category}")), "{leef}");
+        }
+    }
+
+    #[test]
+    fn siem_formats_map_endpoints_and_severity() {
+        use super::{Device, SiemFormat};
+
+        let device = Device {
+            vendor: "Vendor",
+            product: "Product",
+            version: "1.0",
+        };
+        let message = dns_message(
+            "sensor=1",
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        );
+        let cef = message.cef(&device).unwrap();
+        assert!(cef.contains("|DnsCovertChannel|DNS Covert Channel|5|rt=1704067200000 "));
+        assert!(cef.contains(" cat=CommandAndControl,Exfiltration "));
+        assert!(cef.contains(" dvchost=sensor\\=1 "));
+        for pair in [
+            "src=127.0.0.1",
+            "spt=10000",
+            "dst=127.0.0.2",
+            "dpt=53",
+            "proto=UDP",
+            "start=61000",
+            "origL2Bytes=0",
+        ] {
+            assert!(cef.contains(&format!(" {pair} ")), "{cef}");
+        }
+
+        let leef = message.leef(&device).unwrap();
+        let attributes: Vec<&str> = leef.split('\t').collect();
+        assert!(attributes[0].ends_with("|DnsCovertChannel|devTime=Jan 01 2024 00:00:00.000 UTC"));
+        assert!(attributes.contains(&"sev=5"));
+        assert!(attributes.contains(&"identHostName=sensor=1"));
+        assert!(attributes.contains(&"srcPort=10000"));
+        assert!(attributes.contains(&"dstPackets=0"));
+    }
+
+    #[test]
+    fn ocsf_and_ecs_mappings_cover_every_kind() {
+        use super::OcsfClass;
+
+        let time = msg_time(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        for (kind, value) in super::stored_event_samples_v0_46() {
+            let event = super::decode_stored(kind, time, &value).unwrap();
+            let code = format!("{kind:?}");
+            let orig = event
+                .as_match()
+                .orig_addrs()
+                .iter()
+                .find(|addr| !addr.is_unspecified())
+                .map(ToString::to_string);
+
+            let finding = event.to_ocsf(OcsfClass::DetectionFinding).unwrap();
+            assert_eq!(finding["class_uid"], 2004, "{code}");
+            assert_eq!(finding["time"], 1_704_067_200_000_i64, "{code}");
+            assert_eq!(finding["metadata"]["event_code"], code);
+            let attacks = finding["finding_info"]["attacks"].as_array().unwrap();
+            assert_eq!(attacks.len(), kind.categories().len(), "{code}");
+            assert!(finding["unmapped"].is_object(), "{code}");
+
+            let activity = event.to_ocsf(OcsfClass::NetworkActivity).unwrap();
+            assert_eq!(activity["class_uid"], 4001, "{code}");
+            assert_eq!(
+                activity["src_endpoint"]["ip"]
+                    .as_str()
+                    .map(ToString::to_string),
+                orig,
+                "{code}"
+            );
+
+            let ecs = event.to_ecs().unwrap();
+            assert_eq!(ecs["@timestamp"], "2024-01-01T00:00:00+00:00", "{code}");
+            assert_eq!(ecs["event"]["code"], code);
+            assert_eq!(
+                ecs["threat"]["tactic"]["id"].as_array().unwrap().len(),
+                kind.categories().len(),
+                "{code}"
+            );
+            assert_eq!(
+                ecs["source"]["ip"].as_str().map(ToString::to_string),
+                orig,
+                "{code}"
+            );
+            assert!(ecs["review"].is_object(), "{code}");
+        }
+    }
+
+    #[test]
+    fn ocsf_and_ecs_map_protocol_fields() {
+        use super::OcsfClass;
+
+        let (_permit, store) = setup_store();
+        let db = store.events();
+        db.put(&dns_message(
+            "sensor1",
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        ))
+        .unwrap();
+        let (_, event) = db.iter_forward().next().unwrap().unwrap();
+
+        let finding = event.to_ocsf(OcsfClass::DetectionFinding).unwrap();
+        assert_eq!(finding["severity"], "Medium");
+        assert_eq!(finding["confidence_score"], 80);
+        assert_eq!(
+            finding["finding_info"]["attacks"][0]["tactic"]["name"],
+            "Command and Control"
+        );
+        let evidence = &finding["evidences"][0];
+        assert_eq!(evidence["device"]["hostname"], "sensor1");
+        assert_eq!(evidence["dst_endpoint"]["port"], 53);
+        assert_eq!(evidence["connection_info"]["protocol_name"], "UDP");
+        assert_eq!(evidence["query"]["hostname"], "foo.com");
+        assert_eq!(evidence["answers"][0]["rdata"], "1.1.1.1");
+
+        let ecs = event.to_ecs().unwrap();
+        assert_eq!(ecs["event"]["category"][0], "network");
+        assert_eq!(ecs["observer"]["hostname"], "sensor1");
+        assert_eq!(ecs["source"]["port"], 10000);
+        assert_eq!(ecs["network"]["transport"], "udp");
+        assert_eq!(ecs["dns"]["question"]["name"], "foo.com");
+        assert_eq!(
+            ecs["threat"]["tactic"]["id"],
+            serde_json::json!(["TA0011", "TA0010"])
+        );
+        assert_eq!(
+            ecs["related"]["ip"],
+            serde_json::json!(["127.0.0.1", "127.0.0.2"])
+        );
+        assert!(ecs["source"].get("geo").is_none());
+    }
+
+    #[test]
+    fn subscriptions_receive_stored_events() {
+        let (_permit, store) = setup_store();
+        let db = store.events();
+        assert!(db.subscribe(0).is_err());
+        let subscription = db.subscribe(1).unwrap();
+        let other = store.events().subscribe(8).unwrap();
+
+        let first = db
+            .put(&dns_message(
+                "sensor1",
+                Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            ))
+            .unwrap();
+        let notice = subscription.try_recv().unwrap();
+        assert_eq!(notice.key, first);
+        assert_eq!(notice.kind, EventKind::DnsCovertChannel);
+
+        let second = db
+            .put(&dns_message(
+                "sensor2",
+                Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap(),
+            ))
+            .unwrap();
+        let raw: Vec<_> = db.raw_iter().map(Result::unwrap).collect();
+        let (first_key, first_value) = &raw[0];
+        db.update(
+            (first_key.as_slice(), first_value.as_slice()),
+            (first_key.as_slice(), raw[1].1.as_slice()),
+        )
+        .unwrap();
+        assert_eq!(subscription.try_recv().map(|n| n.key), Some(second));
+        assert_eq!(subscription.try_recv(), None);
+        assert_eq!(subscription.lagged(), 1);
+
+        let keys: Vec<_> = std::iter::from_fn(|| other.try_recv())
+            .map(|n| n.key)
+            .collect();
+        assert_eq!(keys, vec![first, second, first]);
+        assert_eq!(other.lagged(), 0);
+    }
+
+    #[test]
+    fn consumer_offsets_resume_iteration() {
+        let (_permit, store) = setup_store();
+        let db = store.events();
+        let keys: Vec<_> = (1..=3)
+            .map(|day| {
+                db.put(&dns_message(
+                    "sensor1",
+                    Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap(),
+                ))
+                .unwrap()
+            })
+            .collect();
+        let remaining = |consumer| -> Vec<i128> {
+            store
+                .events()
+                .iter_after_consumer_offset(consumer)
+                .unwrap()
+                .map(|item| item.unwrap().0)
+                .collect()
+        };
+
+        assert_eq!(db.consumer_offset("relay").unwrap(), None);
+        assert_eq!(remaining("relay"), keys);
+
+        db.set_consumer_offset("relay", keys[1]).unwrap();
+        db.set_consumer_offset("notifier", keys[2]).unwrap();
+        assert_eq!(
+            store.events().consumer_offset("relay").unwrap(),
+            Some(keys[1])
+        );
+        assert_eq!(remaining("relay"), &keys[2..]);
+        assert!(remaining("notifier").is_empty());
+
+        db.remove_consumer_offset("relay").unwrap();
+        assert_eq!(db.consumer_offset("relay").unwrap(), None);
+        assert_eq!(db.consumer_offset("notifier").unwrap(), Some(keys[2]));
+    }
+
+    #[test]
+    fn score_range_scores_stored_events() {
+        use crate::{Confidence, Response, ResponseKind, TriagePolicyInput};
+
+        let (_permit, store) = setup_store();
+        let db = store.events();
+        let keys: Vec<_> = (1..=3)
+            .map(|day| {
+                db.put(&dns_message(
+                    "sensor1",
+                    Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap(),
+                ))
+                .unwrap()
+            })
+            .collect();
+        let policy = |id, threat_kind: &str| {
+            super::CompiledTriagePolicy::new(&TriagePolicyInput {
+                id,
+                name: format!("policy-{id}"),
+                creation_time: Utc::now(),
+                triage_exclusion: Vec::new(),
+                rules: crate::TriageRules::default(),
+                rule_exclusions: std::collections::HashMap::new(),
+                confidence: vec![Confidence {
+                    threat_category: Some(EventCategory::CommandAndControl),
+                    threat_kind: threat_kind.to_string(),
+                    confidence: 0.5,
+                    weight: Some(2.0),
+                }],
+                response: vec![Response {
+                    minimum_score: 1.0,
+                    kind: ResponseKind::Manual,
+                }],
+            })
+            .unwrap()
+        };
+        let policies = [policy(1, "DNS Covert Channel"), policy(2, "port scan")];
+
+        let scored = db.score_range(
+            &policies,
+            msg_time(Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap()),
+            msg_time(Utc.with_ymd_and_hms(2024, 1, 4, 0, 0, 0).unwrap()),
+        );
+        assert_eq!(
+            scored
+                .iter()
+                .map(|(key, scores)| (*key, scores.len(), scores[0].policy_id))
+                .collect::<Vec<_>>(),
+            vec![(keys[1], 1, 1), (keys[2], 1, 1)]
+        );
+    }
+
+    #[test]
+    fn preview_triage_policy_compares_with_stored_version() {
+        use crate::{Confidence, Response, ResponseKind, TriagePolicy, TriageRules};
+
+        let (_permit, store) = setup_store();
+        let db = store.events();
+        let keys: Vec<_> = [("sensor1", 1), ("sensor2", 2), ("sensor1", 3)]
+            .into_iter()
+            .map(|(sensor, day)| {
+                db.put(&dns_message(
+                    sensor,
+                    Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap(),
+                ))
+                .unwrap()
+            })
+            .collect();
+
+        let confidence = |threat_kind: &str| Confidence {
+            threat_category: Some(EventCategory::CommandAndControl),
+            threat_kind: threat_kind.to_string(),
+            confidence: 0.5,
+            weight: Some(2.0),
+        };
+        let response = |minimum_score| Response {
+            minimum_score,
+            kind: ResponseKind::Manual,
+        };
+        let id = store
+            .triage_policy_map()
+            .put(TriagePolicy {
+                id: u32::MAX,
+                name: "policy".to_string(),
+                triage_exclusion_id: Vec::new(),
+                rules: TriageRules::default(),
+                confidence: vec![confidence("port scan")],
+                response: vec![response(1.0)],
+                creation_time: Utc::now(),
+                customer_id: None,
+            })
+            .unwrap();
+        let current = store.triage_policy_input(id).unwrap().unwrap();
+        assert!(store.triage_policy_input(id + 1).unwrap().is_none());
+        let mut candidate = current.clone();
+        candidate.confidence = vec![confidence("DNS Covert Channel")];
+        candidate.response = vec![response(1.0), response(3.0)];
+
+        let preview = db
+            .preview_triage_policy(
+                msg_time(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()),
+                msg_time(Utc.with_ymd_and_hms(2024, 1, 4, 0, 0, 0).unwrap()),
+                &sensor_filter(Some(vec!["sensor1".to_string()])),
+                &candidate,
+                Some(&current),
+                1,
+            )
+            .unwrap();
+        assert_eq!(preview.matched, 2);
+        assert_eq!(preview.candidate.scored, 2);
+        assert_eq!(preview.candidate.responses_fired, vec![2, 0]);
+        assert_eq!(preview.candidate.sample, vec![keys[0]]);
+        assert_eq!(preview.candidate.distribution.max, Some(2.0));
+        assert_eq!(
+            preview
+                .candidate
+                .distribution
+                .buckets
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![(20, 2)]
+        );
+        let current = preview.current.unwrap();
+        assert_eq!(current.scored, 0);
+        assert_eq!(current.distribution.min, None);
+        assert_eq!(preview.newly_scored, 2);
+        assert_eq!(preview.no_longer_scored, 0);
+    }
+
+    #[test]
+    fn triage_results_are_stored_and_removed_with_events() {
+        use crate::{
+            Confidence, Response, ResponseKind, TriageCondition, TriagePolicyInput, TriageRule,
+            TriageRules,
+        };
+
+        let (_permit, store) = setup_store();
+        let db = store.events();
+        let keys: Vec<_> = (1..=3)
+            .map(|day| {
+                db.put(&dns_message(
+                    "sensor1",
+                    Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap(),
+                ))
+                .unwrap()
+            })
+            .collect();
+        let policy = |id, minimum_score| TriagePolicyInput {
+            id,
+            name: format!("policy-{id}"),
+            creation_time: Utc::now(),
+            triage_exclusion: Vec::new(),
+            rules: TriageRules::V1(vec![TriageRule {
+                condition: TriageCondition::All(Vec::new()),
+                weight: 0.5,
+            }]),
+            rule_exclusions: std::collections::HashMap::new(),
+            confidence: vec![Confidence {
+                threat_category: Some(EventCategory::CommandAndControl),
+                threat_kind: "DNS Covert Channel".to_string(),
+                confidence: 0.5,
+                weight: Some(2.0),
+            }],
+            response: vec![Response {
+                minimum_score,
+                kind: ResponseKind::Manual,
+            }],
+        };
+        let policies = [policy(1, 1.0), policy(2, 3.0)];
+
+        let stored = db
+            .store_triage_results(
+                msg_time(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()),
+                msg_time(Utc.with_ymd_and_hms(2024, 1, 3, 0, 0, 0).unwrap()),
+                &policies,
+            )
+            .unwrap();
+        assert_eq!(stored, 2);
+        assert!(db.triage_results(keys[2]).unwrap().is_none());
+        let results = db.triage_results(keys[0]).unwrap().unwrap();
+        assert_eq!(results.len(), 1);
+        let result = &results[0];
+        assert_eq!(result.policy_id, 1);
+        assert!((result.score - 2.5).abs() < f64::EPSILON);
+        assert!((result.triage_score().score - 2.5).abs() < f64::EPSILON);
+        assert_eq!(result.rules.len(), 1);
+        assert!((result.rules[0].weight - 0.5).abs() < f64::EPSILON);
+        assert_eq!(result.confidence.len(), 1);
+        assert!((result.confidence[0].weight - 2.0).abs() < f64::EPSILON);
+
+        // A policy that no longer scores the event takes its results away.
+        db.store_triage_results(
+            msg_time(Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap()),
+            msg_time(Utc.with_ymd_and_hms(2024, 1, 3, 0, 0, 0).unwrap()),
+            &policies[1..],
+        )
+        .unwrap();
+        assert!(db.triage_results(keys[1]).unwrap().is_none());
+
+        db.remove_before(msg_time(Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap()))
+            .unwrap();
+        assert!(db.triage_results(keys[0]).unwrap().is_none());
+    }
+
+    #[test]
+    fn response_actions_are_proposed_once() {
+        use crate::{
+            BlockNetwork, Confidence, HostNetworkGroup, Response, ResponseActionKind,
+            ResponseActionState, ResponseKind, ResponseTargets, TriagePolicyInput, TriageRules,
+        };
+
+        let (_permit, store) = setup_store();
+        let db = store.events();
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let key = db.put(&dns_message("sensor1", time)).unwrap();
+        let network_id = store
+            .block_network_map()
+            .put(BlockNetwork {
+                id: u32::MAX,
+                name: "blocked".to_string(),
+                networks: HostNetworkGroup::default(),
+                description: String::new(),
+                customer_id: 0,
+            })
+            .unwrap();
+        let policy = TriagePolicyInput {
+            id: 1,
+            name: "policy".to_string(),
+            creation_time: Utc::now(),
+            triage_exclusion: Vec::new(),
+            rules: TriageRules::default(),
+            rule_exclusions: std::collections::HashMap::new(),
+            confidence: vec![Confidence {
+                threat_category: Some(EventCategory::CommandAndControl),
+                threat_kind: "DNS Covert Channel".to_string(),
+                confidence: 0.5,
+                weight: None,
+            }],
+            response: vec![
+                Response {
+                    minimum_score: 0.5,
+                    kind: ResponseKind::Blacklist,
+                },
+                Response {
+                    minimum_score: 0.5,
+                    kind: ResponseKind::Whitelist,
+                },
+                Response {
+                    minimum_score: 2.0,
+                    kind: ResponseKind::Manual,
+                },
+            ],
+        };
+        let targets = ResponseTargets {
+            block_network_id: Some(network_id),
+            allow_network_id: None,
+            tag_ids: vec![1],
+        };
+        let start = msg_time(time);
+        let end = msg_time(Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap());
+
+        let proposed = store
+            .propose_response_actions(start, end, std::slice::from_ref(&policy), &targets)
+            .unwrap();
+        let event = db.iter_forward().next().unwrap().unwrap().1;
+        let addrs = event.as_match().orig_addrs().to_vec();
+        assert_eq!(proposed, u64::try_from(addrs.len()).unwrap());
+        let pending = store
+            .response_actions()
+            .by_state(ResponseActionState::Pending)
+            .unwrap();
+        for action in &pending {
+            assert_eq!(action.policy_id, 1);
+            assert_eq!(action.event_key, key);
+            let ResponseActionKind::Block {
+                network_id: id,
+                addr,
+            } = action.kind
+            else {
+                panic!("expected a block action");
+            };
+            assert_eq!(id, network_id);
+            assert!(addrs.contains(&addr));
+        }
+        assert_eq!(
+            store
+                .propose_response_actions(start, end, std::slice::from_ref(&policy), &targets)
+                .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn stix_bundle_links_sightings() {
+        use crate::{BlockNetwork, HostNetworkGroup, TorExitNode};
+
+        use super::StixBundle;
+
+        let time = msg_time(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        let events: Vec<_> = super::stored_event_samples_v0_46()
+            .into_iter()
+            .map(|(kind, value)| super::decode_stored(kind, time, &value).unwrap())
+            .collect();
+        let tor_addr = events
+            .iter()
+            .find(|event| matches!(event, Event::TorConnection(_)))
+            .unwrap()
+            .as_match()
+            .resp_addrs()[0];
+        let loopback = HostNetworkGroup::new(vec![], vec!["127.0.0.0/8".parse().unwrap()], vec![]);
+
+        let mut bundle = StixBundle::new(time).unwrap();
+        for (key, event) in (0..).zip(&events) {
+            bundle.add_event(key, event).unwrap();
+        }
+        bundle
+            .add_block_network(&BlockNetwork {
+                id: 0,
+                name: "loopback".to_string(),
+                networks: loopback.clone(),
+                description: String::new(),
+                customer_id: 0,
+            })
+            .unwrap();
+        bundle
+            .add_tor_exit_node(&TorExitNode {
+                ip_address: tor_addr.to_string(),
+                updated_at: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            })
+            .unwrap();
+        let bundle = bundle.into_json();
+
+        assert_eq!(bundle["type"], "bundle");
+        let objects = bundle["objects"].as_array().unwrap();
+        let ids: HashSet<_> = objects.iter().map(|o| o["id"].as_str().unwrap()).collect();
+        assert_eq!(ids.len(), objects.len());
+        let object = |id: &serde_json::Value| {
+            objects
+                .iter()
+                .find(|o| o["id"] == *id)
+                .unwrap_or_else(|| panic!("{id} is not in the bundle"))
+        };
+
+        let sightings: Vec<_> = objects.iter().filter(|o| o["type"] == "sighting").collect();
+        assert_eq!(sightings.len(), events.len());
+        for (sighting, event) in sightings.into_iter().zip(&events) {
+            let observed_data = object(&sighting["observed_data_refs"][0]);
+            assert_eq!(observed_data["first_observed"], "2024-01-01T00:00:00.000Z");
+            for reference in observed_data["object_refs"].as_array().unwrap() {
+                object(reference);
+            }
+            assert_eq!(
+                object(&sighting["where_sighted_refs"][0])["name"],
+                event.as_match().sensor()
+            );
+
+            let target = object(&sighting["sighting_of_ref"]);
+            let addrs: Vec<_> = event
+                .as_match()
+                .orig_addrs()
+                .iter()
+                .chain(event.as_match().resp_addrs())
+                .copied()
+                .collect();
+            let kind = event.kind_and_category().0;
+            match event {
+                Event::Blocklist(_) if addrs.iter().any(|&addr| loopback.contains(addr)) => {
+                    assert_eq!(target["name"], "loopback");
+                }
+                Event::TorConnection(_) | Event::TorConnectionConn(_)
+                    if addrs.contains(&tor_addr) =>
+                {
+                    assert_eq!(target["indicator_types"][0], "anonymization");
+                }
+                _ => {
+                    assert_eq!(target["type"], "attack-pattern");
+                    assert_eq!(target["name"], event.kind_name());
+                }
+            }
+            let phases = target["kill_chain_phases"].as_array().unwrap();
+            assert_eq!(phases.len(), kind.categories().len(), "{kind:?}");
+            assert_eq!(phases[0]["kill_chain_name"], "mitre-attack");
+        }
+    }
+
     #[test]
     fn remove_before_no_events_to_delete() {
         let (_permit, store) = setup_store();
@@ -0,0 +1,216 @@
+//! Single-pass aggregation over the stored events.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::IpAddr,
+};
+
+use anyhow::{Result, bail};
+use jiff::Timestamp;
+
+use super::{Event, EventCategory, Network, ThreatLevel, find_network, timestamp};
+
+/// A dimension to group the events by in [`EventDb::aggregate`].
+///
+/// Each dimension counts the events the same way as the `Event::count_*`
+/// method of the same name.
+///
+/// [`EventDb::aggregate`]: super::EventDb::aggregate
+#[derive(Clone, Copy, Debug)]
+pub enum Dimension<'a> {
+    Country,
+    Category,
+    IpAddress,
+    IpAddressPair,
+    IpAddressPairAndKind,
+    OriginatorIpAddress,
+    ResponderIpAddress,
+    Kind,
+    Level,
+    /// The networks the addresses of an event belong to.
+    Network(&'a [Network]),
+    /// The time buckets of the given width the events fall in.
+    Time(Granularity),
+}
+
+impl Dimension<'_> {
+    /// Returns whether `self` and `other` fill the same counter.
+    fn same_counter(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Time(a), Self::Time(b)) => a == b,
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        }
+    }
+}
+
+/// The width of a time bucket. Buckets are aligned to the Unix epoch, so a
+/// day runs from midnight to midnight in UTC.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Granularity {
+    Minute,
+    Hour,
+    Day,
+}
+
+impl Granularity {
+    fn nanos(self) -> i64 {
+        const MINUTE: i64 = 60 * 1_000_000_000;
+        match self {
+            Self::Minute => MINUTE,
+            Self::Hour => 60 * MINUTE,
+            Self::Day => 24 * 60 * MINUTE,
+        }
+    }
+
+    /// Returns the start of the bucket the nanosecond timestamp `nanos` falls
+    /// in.
+    pub(super) fn bucket_nanos(self, nanos: i64) -> i64 {
+        nanos.saturating_sub(nanos.rem_euclid(self.nanos()))
+    }
+}
+
+/// The counters [`EventDb::aggregate`] fills in one pass.
+///
+/// Only the counters of the requested dimensions are filled; the others stay
+/// empty.
+///
+/// [`EventDb::aggregate`]: super::EventDb::aggregate
+#[derive(Debug, Default)]
+pub struct Aggregation {
+    /// The number of events that matched the filter.
+    pub total: usize,
+    pub country: HashMap<String, usize>,
+    pub category: HashMap<EventCategory, usize>,
+    pub ip_address: HashMap<IpAddr, usize>,
+    pub ip_address_pair: HashMap<(IpAddr, IpAddr), usize>,
+    pub ip_address_pair_and_kind: HashMap<(IpAddr, IpAddr, &'static str), usize>,
+    pub originator_ip_address: HashMap<IpAddr, usize>,
+    pub responder_ip_address: HashMap<IpAddr, usize>,
+    pub kind: HashMap<String, usize>,
+    pub level: HashMap<ThreatLevel, usize>,
+    pub network: HashMap<u32, usize>,
+    /// The number of events per bucket start, for each requested granularity.
+    pub time: HashMap<Granularity, BTreeMap<Timestamp, usize>>,
+}
+
+impl Aggregation {
+    /// Creates an empty aggregation for `dimensions`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a dimension is requested more than once.
+    pub(super) fn new(dimensions: &[Dimension<'_>]) -> Result<Self> {
+        for (i, dimension) in dimensions.iter().enumerate() {
+            if dimensions[..i].iter().any(|d| d.same_counter(dimension)) {
+                bail!("dimension {dimension:?} is requested more than once");
+            }
+        }
+        Ok(Self::default())
+    }
+
+    /// Counts `event`, stored under `key`, which matched the filter.
+    pub(super) fn add(&mut self, dimensions: &[Dimension<'_>], key: i128, event: &Event) {
+        self.total += 1;
+        let addr_pair = event.addresses();
+        for dimension in dimensions {
+            match dimension {
+                Dimension::Country => event.tally_country(&mut self.country, addr_pair),
+                Dimension::Category => {
+                    if let Some(category) = event.as_match().category() {
+                        increment(&mut self.category, category);
+                    }
+                }
+                Dimension::IpAddress => {
+                    for addr in [addr_pair.0, addr_pair.1].into_iter().flatten() {
+                        increment(&mut self.ip_address, addr);
+                    }
+                }
+                Dimension::IpAddressPair => {
+                    if let (Some(orig), Some(resp)) = addr_pair {
+                        increment(&mut self.ip_address_pair, (orig, resp));
+                    }
+                }
+                Dimension::IpAddressPairAndKind => {
+                    if let (Some(orig), Some(resp)) = addr_pair {
+                        increment(
+                            &mut self.ip_address_pair_and_kind,
+                            (orig, resp, event.kind_name()),
+                        );
+                    }
+                }
+                Dimension::OriginatorIpAddress => {
+                    if let Some(orig) = addr_pair.0 {
+                        increment(&mut self.originator_ip_address, orig);
+                    }
+                }
+                Dimension::ResponderIpAddress => {
+                    if let Some(resp) = addr_pair.1 {
+                        increment(&mut self.responder_ip_address, resp);
+                    }
+                }
+                Dimension::Kind => {
+                    let kind = if let Event::HttpThreat(event) = event {
+                        event.attack_kind.clone()
+                    } else {
+                        event.kind_name().to_string()
+                    };
+                    increment(&mut self.kind, kind);
+                }
+                Dimension::Level => increment(&mut self.level, event.as_match().level()),
+                Dimension::Network(networks) => {
+                    for addr in [addr_pair.0, addr_pair.1].into_iter().flatten() {
+                        if let Some(id) = find_network(addr, networks) {
+                            increment(&mut self.network, id);
+                        }
+                    }
+                }
+                Dimension::Time(granularity) => {
+                    let time = i64::try_from(key >> 64).expect("valid i64");
+                    let start = timestamp::from_i64_nanos(granularity.bucket_nanos(time))
+                        .expect(timestamp::I64_NANOS_JIFF_INVARIANT);
+                    *self
+                        .time
+                        .entry(*granularity)
+                        .or_default()
+                        .entry(start)
+                        .or_insert(0) += 1;
+                }
+            }
+        }
+    }
+}
+
+fn increment<K: Eq + std::hash::Hash>(counter: &mut HashMap<K, usize>, key: K) {
+    *counter.entry(key).or_insert(0) += 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Dimension, Granularity};
+
+    #[test]
+    fn buckets_align_to_epoch() {
+        let hour = 3_600_000_000_000;
+        assert_eq!(Granularity::Hour.bucket_nanos(hour + 1), hour);
+        assert_eq!(Granularity::Hour.bucket_nanos(hour), hour);
+        assert_eq!(Granularity::Hour.bucket_nanos(-1), -hour);
+        assert_eq!(Granularity::Day.bucket_nanos(25 * hour), 24 * hour);
+        assert_eq!(Granularity::Minute.bucket_nanos(i64::MIN), i64::MIN);
+    }
+
+    #[test]
+    fn repeated_dimensions_are_rejected() {
+        assert!(super::Aggregation::new(&[Dimension::Kind, Dimension::Level]).is_ok());
+        assert!(
+            super::Aggregation::new(&[
+                Dimension::Time(Granularity::Hour),
+                Dimension::Time(Granularity::Day),
+            ])
+            .is_ok()
+        );
+        assert!(super::Aggregation::new(&[Dimension::Kind, Dimension::Kind]).is_err());
+        assert!(
+            super::Aggregation::new(&[Dimension::Network(&[]), Dimension::Network(&[]),]).is_err()
+        );
+    }
+}
@@ -5,7 +5,7 @@ use jiff::Timestamp;
 use serde::{Deserialize, Serialize};
 
 use super::timestamp;
-use super::{EventCategory, LearningMethod, ThreatLevel, TriageScore, common::Match};
+use super::{EventCategory, EventKind, LearningMethod, ThreatLevel, TriageScore, common::Match};
 use crate::event::common::{AttrValue, to_hardware_address, triage_scores_to_string};
 
 macro_rules! find_bootp_attr_by_kind {
@@ -328,6 +328,14 @@ impl Match for BlocklistBootp {
         "blocklist bootp"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::BlocklistBootp
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
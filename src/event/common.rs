@@ -1,18 +1,24 @@
 use std::{
     borrow::Cow,
+    collections::HashMap,
     fmt::{self, Formatter, Write},
     net::IpAddr,
+    slice,
 };
 
 use anyhow::Result;
 use attrievent::attribute::RawEventAttrKind;
-use bincode::Options;
+use jiff::Timestamp;
 use num_traits::ToPrimitive;
 use serde::{Deserialize, Serialize};
 
-use super::{EventCategory, EventFilter, FlowKind, LearningMethod, ThreatLevel, TrafficDirection};
+use super::{
+    ConfidenceContribution, EventCategory, EventFilter, EventKind, FlowKind, LearningMethod,
+    RuleContribution, ThreatLevel, TrafficDirection, TriageExplanation, timestamp,
+};
 use crate::{
-    AttrCmpKind, Confidence, PacketAttr, Response, TriageExclusion, TriagePolicyInput, ValueKind,
+    AttrCmpKind, Confidence, PacketAttr, Response, TriageCondition, TriageExclusion,
+    TriagePolicyInput, TriageRules, tables::AttrOperand,
 };
 
 /// Epsilon value for inclusive confidence comparisons
@@ -33,28 +39,64 @@ pub(super) trait Match {
     fn category(&self) -> Option<EventCategory>;
     fn level(&self) -> ThreatLevel;
     fn kind(&self) -> &str;
+    fn event_kind(&self) -> EventKind;
+    fn time(&self) -> Timestamp;
     fn sensor(&self) -> &str;
     fn confidence(&self) -> Option<f32>;
     fn learning_method(&self) -> LearningMethod;
     fn find_attr_by_kind(&self, raw_event_attr: RawEventAttrKind) -> Option<AttrValue<'_>>;
-    fn score_by_attr(&self, attr_triage: &[PacketAttr]) -> f64 {
-        let total_score = attr_triage.iter().fold(0.0, |score_acc, item| {
-            let Ok(kind) =
-                RawEventAttrKind::from_kind_and_attr_name(&item.raw_event_kind, &item.attr_name)
-            else {
-                return score_acc;
-            };
-
-            let Some(value) = self.find_attr_by_kind(kind) else {
-                return score_acc;
-            };
-
-            if is_attr_matched(value, item) {
-                score_acc + item.weight.unwrap_or_default()
-            } else {
-                score_acc
-            }
-        });
+
+    /// Returns whether the attribute of the event named by `attr` matches it.
+    /// An attribute the event does not have never matches.
+    fn attr_matches(&self, attr: &PacketAttr) -> bool {
+        let Ok(kind) =
+            RawEventAttrKind::from_kind_and_attr_name(&attr.raw_event_kind, &attr.attr_name)
+        else {
+            return false;
+        };
+        self.find_attr_by_kind(kind)
+            .is_some_and(|value| is_attr_matched(value, attr))
+    }
+
+    /// Returns whether `condition` holds for the event, looking up the
+    /// exclusions it refers to in `exclusions`. A reference to an exclusion
+    /// missing from `exclusions` never matches.
+    fn condition_holds(
+        &self,
+        condition: &TriageCondition,
+        exclusions: &HashMap<u32, TriageExclusion>,
+    ) -> bool {
+        match condition {
+            TriageCondition::Attr(attr) => self.attr_matches(attr),
+            TriageCondition::Exclusion(id) => exclusions
+                .get(id)
+                .is_some_and(|exclusion| self.matched_any_exclusion(slice::from_ref(exclusion))),
+            TriageCondition::All(conditions) => conditions
+                .iter()
+                .all(|condition| self.condition_holds(condition, exclusions)),
+            TriageCondition::Any(conditions) => conditions
+                .iter()
+                .any(|condition| self.condition_holds(condition, exclusions)),
+            TriageCondition::Not(condition) => !self.condition_holds(condition, exclusions),
+        }
+    }
+
+    /// Returns the sum of the weights of the rules holding for the event,
+    /// truncated to two decimal places.
+    fn score_by_rules(
+        &self,
+        rules: &TriageRules,
+        exclusions: &HashMap<u32, TriageExclusion>,
+    ) -> f64 {
+        let total_score = match rules {
+            TriageRules::V1(rules) => rules.iter().fold(0.0, |score_acc, rule| {
+                if self.condition_holds(&rule.condition, exclusions) {
+                    score_acc + rule.weight
+                } else {
+                    score_acc
+                }
+            }),
+        };
         (total_score * 100.0).trunc() / 100.0
     }
 
@@ -231,7 +273,7 @@ pub(super) trait Match {
                 .iter()
                 .filter_map(|triage| {
                     let score = self.score_by_triage_exclusion(&triage.triage_exclusion)
-                        + self.score_by_attr(&triage.packet_attr)
+                        + self.score_by_rules(&triage.rules, &triage.rule_exclusions)
                         + self.score_by_confidence(&triage.confidence);
                     self.build_triage_score(triage.id, score, &triage.response)
                 })
@@ -245,13 +287,46 @@ pub(super) trait Match {
         Ok((true, None))
     }
 
+    /// Returns the names of the event that domain, hostname, and URI
+    /// exclusions are compared with. An event without any is excluded only by
+    /// its addresses.
+    fn exclusion_targets(&self) -> ExclusionTargets<'_> {
+        ExclusionTargets::default()
+    }
+
     fn score_by_triage_exclusion(&self, triage_exclusion: &[TriageExclusion]) -> f64 {
-        let matched = triage_exclusion.iter().any(|ti| {
-            if let TriageExclusion::IpAddress(filter) = ti {
-                self.orig_addrs().iter().any(|&addr| filter.contains(addr))
-                    || self.resp_addrs().iter().any(|&addr| filter.contains(addr))
-            } else {
-                false
+        let targets = self.exclusion_targets();
+        let matched = triage_exclusion.iter().any(|ti| match ti {
+            TriageExclusion::IpAddress(filter) => self
+                .orig_addrs()
+                .iter()
+                .chain(self.resp_addrs().iter())
+                .any(|&ip| filter.contains(ip)),
+            TriageExclusion::Domain(regex_set) => targets
+                .domain
+                .is_some_and(|domain| regex_set.is_match(domain)),
+            TriageExclusion::Hostname(hostnames) => targets
+                .hostname
+                .is_some_and(|hostname| hostnames.iter().any(|h| h == hostname)),
+            TriageExclusion::Uri(uris) => {
+                targets.uri.is_some_and(|uri| uris.iter().any(|u| u == uri))
+            }
+            TriageExclusion::HostnamePattern(set) => targets
+                .hostname
+                .is_some_and(|hostname| set.is_match(hostname)),
+            TriageExclusion::UriPattern(set) => targets.uri.is_some_and(|uri| set.is_match(uri)),
+            TriageExclusion::UserAgent(set) => targets
+                .user_agent
+                .is_some_and(|user_agent| set.is_match(user_agent)),
+            TriageExclusion::Ja3(fingerprints) => targets.ja3.is_some_and(|ja3| {
+                fingerprints
+                    .iter()
+                    .any(|fingerprint| fingerprint.eq_ignore_ascii_case(ja3))
+            }),
+            TriageExclusion::Scoped(scope, exclusion) => {
+                let time = timestamp::event_key_time(self.time());
+                scope.applies(self.event_kind(), self.sensor(), time)
+                    && self.matched_any_exclusion(slice::from_ref(exclusion))
             }
         });
         if matched { f64::MIN } else { 0.0 }
@@ -291,7 +366,7 @@ pub(super) trait Match {
     /// `triage_exclusion` as already applied by the caller.
     ///
     /// Each policy contributes a `TriageScore` only when
-    /// `score_by_attr + score_by_confidence` reaches at least one
+    /// `score_by_rules + score_by_confidence` reaches at least one
     /// `response.minimum_score` threshold. Policies whose `response` is
     /// empty contribute no score.
     fn inline_scores_against_policies(&self, policies: &[TriagePolicyInput]) -> Vec<TriageScore> {
@@ -302,8 +377,8 @@ pub(super) trait Match {
                     p.triage_exclusion.is_empty(),
                     "inline scoring expects exclusions to have been applied by the caller"
                 );
-                let score =
-                    self.score_by_attr(&p.packet_attr) + self.score_by_confidence(&p.confidence);
+                let score = self.score_by_rules(&p.rules, &p.rule_exclusions)
+                    + self.score_by_confidence(&p.confidence);
                 self.build_triage_score(p.id, score, &p.response)
             })
             .collect()
@@ -311,18 +386,150 @@ pub(super) trait Match {
 
     fn score_by_confidence(&self, confidence: &[Confidence]) -> f64 {
         confidence.iter().fold(0.0, |score, conf| {
-            if conf.threat_category == self.category()
-                && conf.threat_kind.to_lowercase() == self.kind().to_lowercase()
-                && self
-                    .confidence()
-                    .is_none_or(|c| c.to_f64().expect("safe: f32 -> f64") >= conf.confidence)
-            {
+            if self.confidence_matches(conf) {
                 score + conf.weight.unwrap_or(1.0)
             } else {
                 score
             }
         })
     }
+
+    /// Returns whether `conf` names the category and kind of the event, and
+    /// the event is at least as confident as `conf` requires.
+    fn confidence_matches(&self, conf: &Confidence) -> bool {
+        conf.threat_category == self.category()
+            && conf.threat_kind.to_lowercase() == self.kind().to_lowercase()
+            && self
+                .confidence()
+                .is_none_or(|c| c.to_f64().expect("safe: f32 -> f64") >= conf.confidence)
+    }
+
+    /// Explains the score of the event against `policy`, which is computed
+    /// the same way `other_matches` computes it.
+    fn explain_policy(&self, policy: &TriagePolicyInput) -> TriageExplanation {
+        let score = self.score_by_triage_exclusion(&policy.triage_exclusion)
+            + self.score_by_rules(&policy.rules, &policy.rule_exclusions)
+            + self.score_by_confidence(&policy.confidence);
+        let TriageRules::V1(rules) = &policy.rules;
+        TriageExplanation {
+            policy_id: policy.id,
+            score,
+            scored: self
+                .build_triage_score(policy.id, score, &policy.response)
+                .is_some(),
+            exclusions: policy
+                .triage_exclusion
+                .iter()
+                .enumerate()
+                .filter(|(_, exclusion)| self.matched_any_exclusion(slice::from_ref(*exclusion)))
+                .map(|(index, _)| index)
+                .collect(),
+            rules: rules
+                .iter()
+                .enumerate()
+                .filter(|(_, rule)| self.condition_holds(&rule.condition, &policy.rule_exclusions))
+                .map(|(index, rule)| {
+                    let mut contribution = RuleContribution {
+                        index,
+                        weight: rule.weight,
+                        attrs: Vec::new(),
+                        exclusions: Vec::new(),
+                    };
+                    self.collect_matching_leaves(
+                        &rule.condition,
+                        &policy.rule_exclusions,
+                        &mut contribution,
+                    );
+                    contribution
+                })
+                .collect(),
+            confidence: policy
+                .confidence
+                .iter()
+                .filter(|conf| self.confidence_matches(conf))
+                .map(|conf| ConfidenceContribution {
+                    confidence: conf.clone(),
+                    weight: conf.weight.unwrap_or(1.0),
+                })
+                .collect(),
+        }
+    }
+
+    /// Adds the attributes and exclusion references in `condition`, outside of
+    /// any `Not`, that match the event to `contribution`.
+    fn collect_matching_leaves(
+        &self,
+        condition: &TriageCondition,
+        exclusions: &HashMap<u32, TriageExclusion>,
+        contribution: &mut RuleContribution,
+    ) {
+        match condition {
+            TriageCondition::Attr(attr) => {
+                if self.attr_matches(attr) {
+                    contribution.attrs.push(attr.clone());
+                }
+            }
+            TriageCondition::Exclusion(id) => {
+                if self.condition_holds(condition, exclusions) {
+                    contribution.exclusions.push(*id);
+                }
+            }
+            TriageCondition::All(conditions) | TriageCondition::Any(conditions) => {
+                for condition in conditions {
+                    self.collect_matching_leaves(condition, exclusions, contribution);
+                }
+            }
+            // What holds under a negation keeps the rule from holding rather
+            // than making it hold.
+            TriageCondition::Not(_) => {}
+        }
+    }
+}
+
+/// The names of an event that domain, hostname, URI, user-agent, and JA3
+/// triage exclusions are compared with.
+#[derive(Clone, Copy, Default)]
+pub(super) struct ExclusionTargets<'a> {
+    pub(super) domain: Option<&'a str>,
+    pub(super) hostname: Option<&'a str>,
+    pub(super) uri: Option<&'a str>,
+    pub(super) user_agent: Option<&'a str>,
+    pub(super) ja3: Option<&'a str>,
+}
+
+impl<'a> ExclusionTargets<'a> {
+    /// The targets of a DNS event: its query, whose first label is taken as
+    /// the hostname.
+    pub(super) fn dns(query: &'a str) -> Self {
+        Self {
+            domain: Some(query),
+            hostname: Some(query.split_once('.').map_or(query, |(label, _)| label)),
+            ..Self::default()
+        }
+    }
+
+    /// The targets of an HTTP event: its host, as both the domain and the
+    /// hostname, its URI, and its user agent.
+    pub(super) fn http(host: &'a str, uri: &'a str, user_agent: &'a str) -> Self {
+        Self {
+            domain: Some(host),
+            hostname: Some(host),
+            uri: Some(uri),
+            user_agent: Some(user_agent),
+            ja3: None,
+        }
+    }
+
+    /// The targets of a TLS event: its server name, as both the domain and
+    /// the hostname, and its JA3 fingerprint.
+    pub(super) fn tls(server_name: &'a str, ja3: &'a str) -> Self {
+        Self {
+            domain: Some(server_name),
+            hostname: Some(server_name),
+            ja3: Some(ja3),
+            ..Self::default()
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -434,14 +641,14 @@ where
 
 fn is_attr_matched(target_value: AttrValue, attr: &PacketAttr) -> bool {
     match target_value {
-        AttrValue::Addr(ip_addr) => matches_addr_attr(&ip_addr, attr),
+        AttrValue::Addr(ip_addr) => matches_ordered_attr(&ip_addr, attr),
         AttrValue::Bool(bool_val) => matches_bool_attr(bool_val, attr),
-        AttrValue::Float(float_val) => matches_number_attr::<_, f64>(&float_val, attr),
-        AttrValue::SInt(signed_int_val) => matches_number_attr::<_, i64>(&signed_int_val, attr),
-        AttrValue::UInt(unsigned_int_val) => matches_number_attr::<_, u64>(&unsigned_int_val, attr),
+        AttrValue::Float(float_val) => matches_ordered_attr(&float_val, attr),
+        AttrValue::SInt(signed_int_val) => matches_ordered_attr(&signed_int_val, attr),
+        AttrValue::UInt(unsigned_int_val) => matches_ordered_attr(&unsigned_int_val, attr),
         AttrValue::String(str_val) => matches_string_attr(str_val, attr),
         AttrValue::VecAddr(vec_addr_val) => {
-            is_matching_list(vec_addr_val.as_ref(), attr, matches_addr_attr)
+            is_matching_list(vec_addr_val.as_ref(), attr, matches_ordered_attr)
         }
         AttrValue::VecBool(vec_bool_val) => {
             is_matching_list(vec_bool_val.as_ref(), attr, |val: &bool, attr| {
@@ -449,13 +656,13 @@ fn is_attr_matched(target_value: AttrValue, attr: &PacketAttr) -> bool {
             })
         }
         AttrValue::VecFloat(vec_float_val) => {
-            is_matching_list(vec_float_val.as_ref(), attr, matches_number_attr::<_, f64>)
+            is_matching_list(vec_float_val.as_ref(), attr, matches_ordered_attr)
         }
         AttrValue::VecSInt(vec_sint_val) => {
-            is_matching_list(vec_sint_val.as_ref(), attr, matches_number_attr::<_, i64>)
+            is_matching_list(vec_sint_val.as_ref(), attr, matches_ordered_attr)
         }
         AttrValue::VecUInt(vec_uint_val) => {
-            is_matching_list(vec_uint_val.as_ref(), attr, matches_number_attr::<_, u64>)
+            is_matching_list(vec_uint_val.as_ref(), attr, matches_ordered_attr)
         }
         AttrValue::VecString(vec_str_val) => {
             is_matching_list(vec_str_val.as_ref(), attr, |val: &String, attr| {
@@ -469,36 +676,7 @@ fn is_attr_matched(target_value: AttrValue, attr: &PacketAttr) -> bool {
     }
 }
 
-fn deserialize<'de, T>(value: &'de [u8]) -> Option<T>
-where
-    T: Deserialize<'de>,
-{
-    bincode::DefaultOptions::new().deserialize::<T>(value).ok()
-}
-
-fn check_second_value<'de, T, K>(kind: AttrCmpKind, value: Option<&'de Vec<u8>>) -> Option<T>
-where
-    T: TryFrom<K> + std::cmp::PartialOrd,
-    K: Deserialize<'de>,
-{
-    match kind {
-        AttrCmpKind::OpenRange
-        | AttrCmpKind::CloseRange
-        | AttrCmpKind::LeftOpenRange
-        | AttrCmpKind::RightOpenRange
-        | AttrCmpKind::NotOpenRange
-        | AttrCmpKind::NotCloseRange
-        | AttrCmpKind::NotLeftOpenRange
-        | AttrCmpKind::NotRightOpenRange => {
-            let value = value.as_ref()?;
-            let de_second_value: K = deserialize(value)?;
-            T::try_from(de_second_value).ok()
-        }
-        _ => None,
-    }
-}
-
-fn matches_attr<T>(
+pub(super) fn matches_attr<T>(
     cmp_kind: AttrCmpKind,
     attr_val: &T,
     first_val: &T,
@@ -543,18 +721,19 @@ where
 }
 
 fn matches_bool_attr(attr_val: bool, packet_attr: &PacketAttr) -> bool {
-    deserialize::<bool>(&packet_attr.first_value).is_some_and(|compare_val| {
-        match packet_attr.cmp_kind {
+    packet_attr
+        .value
+        .operands::<bool>()
+        .is_some_and(|(compare_val, _)| match packet_attr.cmp_kind {
             AttrCmpKind::Equal => attr_val == compare_val,
             AttrCmpKind::NotEqual => attr_val != compare_val,
             _ => false,
-        }
-    })
+        })
 }
 
 fn matches_string_attr(attr_val: &str, packet_attr: &PacketAttr) -> bool {
-    deserialize::<String>(&packet_attr.first_value).is_some_and(|compare_val| {
-        let cmp_result = attr_val.contains(&compare_val);
+    packet_attr.value.as_str().is_some_and(|compare_val| {
+        let cmp_result = attr_val.contains(compare_val);
         match packet_attr.cmp_kind {
             AttrCmpKind::Contain => cmp_result,
             AttrCmpKind::NotContain => !cmp_result,
@@ -563,44 +742,21 @@ fn matches_string_attr(attr_val: &str, packet_attr: &PacketAttr) -> bool {
     })
 }
 
-fn matches_addr_attr(attr_val: &IpAddr, packet_attr: &PacketAttr) -> bool {
-    if let Some(first_val) = deserialize::<IpAddr>(&packet_attr.first_value) {
-        let second_val = packet_attr
-            .second_value
-            .as_ref()
-            .and_then(|serde_val| deserialize::<IpAddr>(serde_val));
-        return matches_attr(packet_attr.cmp_kind, attr_val, &first_val, second_val);
-    }
-    false
-}
-
-fn matches_number_attr<T, K>(attr_val: &T, packet_attr: &PacketAttr) -> bool
+fn matches_ordered_attr<T>(attr_val: &T, packet_attr: &PacketAttr) -> bool
 where
-    T: TryFrom<K> + PartialOrd,
-    K: for<'de> Deserialize<'de>,
+    T: AttrOperand + PartialOrd,
 {
-    if let Some(first_val) = deserialize::<K>(&packet_attr.first_value)
-        && let Ok(first_val) = T::try_from(first_val)
-    {
-        let second_val =
-            check_second_value::<T, K>(packet_attr.cmp_kind, packet_attr.second_value.as_ref());
+    if let Some((first_val, second_val)) = packet_attr.value.operands::<T>() {
         return matches_attr(packet_attr.cmp_kind, attr_val, &first_val, second_val);
     }
     false
 }
 
 fn matches_vec_raw_attr(attr_val: &[u8], packet_attr: &PacketAttr) -> bool {
-    match packet_attr.value_kind {
-        ValueKind::String => {
-            deserialize::<String>(&packet_attr.first_value).is_some_and(|compare_val| {
-                matches_byte_attr(packet_attr.cmp_kind, attr_val, compare_val.as_bytes())
-            })
-        }
-        ValueKind::Vector => {
-            matches_byte_attr(packet_attr.cmp_kind, attr_val, &packet_attr.first_value)
-        }
-        _ => false,
-    }
+    packet_attr
+        .value
+        .as_bytes()
+        .is_some_and(|compare_val| matches_byte_attr(packet_attr.cmp_kind, attr_val, compare_val))
 }
 
 fn matches_byte_attr(cmp_kind: AttrCmpKind, target_val: &[u8], compare_val: &[u8]) -> bool {
@@ -616,22 +772,21 @@ fn matches_byte_attr(cmp_kind: AttrCmpKind, target_val: &[u8], compare_val: &[u8
 pub(crate) mod tests {
     use std::{
         cmp::Ordering,
+        collections::HashMap,
         net::{IpAddr, Ipv4Addr},
     };
 
     use attrievent::attribute::{
         ConnAttr, DhcpAttr, DnsAttr, FtpAttr, HttpAttr, RadiusAttr, RawEventKind,
     };
-    use bincode::Options;
     use chrono::{DateTime, TimeZone, Utc};
     use jiff::Timestamp;
-    use serde::Serialize;
 
-    use super::{AttrValue, Match, is_attr_matched};
+    use super::{AttrValue as EventAttrValue, Match, is_attr_matched};
     use crate::event::timestamp;
     use crate::{
-        AttrCmpKind, Customer, CustomerNetwork, EventCategory, HostNetworkGroup, PacketAttr,
-        ValueKind,
+        AttrCmpKind, AttrValue, Customer, CustomerNetwork, EventCategory, HostNetworkGroup,
+        PacketAttr, TriageRules,
         event::{
             BlocklistBootp, BlocklistBootpFieldsStored, BlocklistConn, BlocklistConnFieldsStored,
             BlocklistDceRpc, BlocklistDceRpcFieldsStored, BlocklistDhcp, BlocklistDhcpFieldsStored,
@@ -1273,46 +1428,43 @@ pub(crate) mod tests {
             PacketAttr {
                 raw_event_kind: RawEventKind::Http,
                 attr_name: HttpAttr::SrcAddr.to_string(),
-                value_kind: ValueKind::IpAddr,
                 cmp_kind: AttrCmpKind::CloseRange,
-                first_value: serialize(&IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap(),
-                second_value: serialize(&IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2))),
+                value: AttrValue::IpAddrRange(
+                    IpAddr::V4(Ipv4Addr::LOCALHOST),
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2)),
+                ),
                 weight: Some(0.1),
             },
             PacketAttr {
                 raw_event_kind: RawEventKind::Http,
                 attr_name: HttpAttr::Uri.to_string(),
-                value_kind: ValueKind::String,
                 cmp_kind: AttrCmpKind::Contain,
-                first_value: serialize(&"path").unwrap(),
-                second_value: None,
+                value: AttrValue::String("path".to_string()),
                 weight: Some(0.2),
             },
         ];
-        let score_result = http_event.score_by_attr(&success_packet_attr);
+        let score_result =
+            http_event.score_by_rules(&TriageRules::from(success_packet_attr), &HashMap::new());
         assert_eq!(score_result.partial_cmp(&0.3), Some(Ordering::Equal));
 
         let fail_packet_attr = vec![
             PacketAttr {
                 raw_event_kind: RawEventKind::Http,
                 attr_name: HttpAttr::DstPort.to_string(),
-                value_kind: ValueKind::UInteger,
                 cmp_kind: AttrCmpKind::OpenRange,
-                first_value: serialize(&80_u64).unwrap(),
-                second_value: serialize(&82_u64),
+                value: AttrValue::UIntegerRange(80, 82),
                 weight: Some(0.1),
             },
             PacketAttr {
                 raw_event_kind: RawEventKind::Http,
                 attr_name: HttpAttr::MimeTypes.to_string(),
-                value_kind: ValueKind::String,
                 cmp_kind: AttrCmpKind::NotContain,
-                first_value: serialize(&"b1").unwrap(),
-                second_value: None,
+                value: AttrValue::String("b1".to_string()),
                 weight: Some(0.1),
             },
         ];
-        let score_result = http_event.score_by_attr(&fail_packet_attr);
+        let score_result =
+            http_event.score_by_rules(&TriageRules::from(fail_packet_attr), &HashMap::new());
         assert_eq!(score_result.partial_cmp(&0.0), Some(Ordering::Equal));
 
         // Compare `Bool`, `SInt`, `VecSInt` type
@@ -1321,35 +1473,31 @@ pub(crate) mod tests {
             PacketAttr {
                 raw_event_kind: RawEventKind::Dns,
                 attr_name: DnsAttr::Rtt.to_string(),
-                value_kind: ValueKind::Integer,
                 cmp_kind: AttrCmpKind::Less,
-                first_value: serialize(&6_i64).unwrap(),
-                second_value: None,
+                value: AttrValue::Integer(6),
                 weight: Some(0.3),
             },
             PacketAttr {
                 raw_event_kind: RawEventKind::Dns,
                 attr_name: DnsAttr::Ttl.to_string(),
-                value_kind: ValueKind::Integer,
                 cmp_kind: AttrCmpKind::NotEqual,
-                first_value: serialize(&9_i64).unwrap(),
-                second_value: None,
+                value: AttrValue::Integer(9),
                 weight: Some(0.5),
             },
         ];
-        let score_result = dns_event.score_by_attr(&success_packet_attr);
+        let score_result =
+            dns_event.score_by_rules(&TriageRules::from(success_packet_attr), &HashMap::new());
         assert_eq!(score_result.partial_cmp(&0.8), Some(Ordering::Equal));
 
         let fail_packet_attr = vec![PacketAttr {
             raw_event_kind: RawEventKind::Dns,
             attr_name: DnsAttr::AA.to_string(),
-            value_kind: ValueKind::Bool,
             cmp_kind: AttrCmpKind::Equal,
-            first_value: serialize(&true).unwrap(),
-            second_value: None,
+            value: AttrValue::Bool(true),
             weight: Some(0.2),
         }];
-        let score_result = dns_event.score_by_attr(&fail_packet_attr);
+        let score_result =
+            dns_event.score_by_rules(&TriageRules::from(fail_packet_attr), &HashMap::new());
         assert_eq!(score_result.partial_cmp(&0.0), Some(Ordering::Equal));
 
         // Compare `VecAddr`, `VecUInt`, `VecRaw` type
@@ -1358,35 +1506,34 @@ pub(crate) mod tests {
             PacketAttr {
                 raw_event_kind: RawEventKind::Dhcp,
                 attr_name: DhcpAttr::Router.to_string(),
-                value_kind: ValueKind::IpAddr,
                 cmp_kind: AttrCmpKind::LeftOpenRange,
-                first_value: serialize(&IpAddr::V4(Ipv4Addr::new(127, 0, 0, 0))).unwrap(),
-                second_value: serialize(&IpAddr::V4(Ipv4Addr::LOCALHOST)),
+                value: AttrValue::IpAddrRange(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 0)),
+                    IpAddr::V4(Ipv4Addr::LOCALHOST),
+                ),
                 weight: Some(0.15),
             },
             PacketAttr {
                 raw_event_kind: RawEventKind::Dhcp,
                 attr_name: DhcpAttr::ClientId.to_string(),
-                value_kind: ValueKind::Vector,
                 cmp_kind: AttrCmpKind::Contain,
-                first_value: vec![7, 8, 9],
-                second_value: None,
+                value: AttrValue::Vector(vec![7, 8, 9]),
                 weight: Some(0.2),
             },
         ];
-        let score_result = dhcp_event.score_by_attr(&success_packet_attr);
+        let score_result =
+            dhcp_event.score_by_rules(&TriageRules::from(success_packet_attr), &HashMap::new());
         assert_eq!(score_result.partial_cmp(&0.35), Some(Ordering::Equal));
 
         let fail_packet_attr = vec![PacketAttr {
             raw_event_kind: RawEventKind::Dhcp,
             attr_name: DhcpAttr::ParamReqList.to_string(),
-            value_kind: ValueKind::UInteger,
             cmp_kind: AttrCmpKind::RightOpenRange,
-            first_value: serialize(&0_u64).unwrap(),
-            second_value: serialize(&1_u64),
+            value: AttrValue::UIntegerRange(0, 1),
             weight: Some(0.35),
         }];
-        let score_result = dhcp_event.score_by_attr(&fail_packet_attr);
+        let score_result =
+            dhcp_event.score_by_rules(&TriageRules::from(fail_packet_attr), &HashMap::new());
         assert_eq!(score_result.partial_cmp(&0.0), Some(Ordering::Equal));
 
         // Compare `VecBool` type for FtpAttr::DataPassive
@@ -1440,41 +1587,42 @@ pub(crate) mod tests {
         let success_packet_attr_vec_bool_true = vec![PacketAttr {
             raw_event_kind: RawEventKind::Ftp,
             attr_name: FtpAttr::DataPassive.to_string(),
-            value_kind: ValueKind::Bool,
             cmp_kind: AttrCmpKind::Equal,
-            first_value: serialize(&true).unwrap(),
-            second_value: None,
+            value: AttrValue::Bool(true),
             weight: Some(0.6),
         }];
-        let score_result_true = ftp_passive_event.score_by_attr(&success_packet_attr_vec_bool_true);
+        let score_result_true = ftp_passive_event.score_by_rules(
+            &TriageRules::from(success_packet_attr_vec_bool_true),
+            &HashMap::new(),
+        );
         assert_eq!(score_result_true.partial_cmp(&0.6), Some(Ordering::Equal));
 
         // For Vec<Bool>, AttrCmpKind::Equal means score if at least one 'false' is found.
         let success_packet_attr_vec_bool_false = vec![PacketAttr {
             raw_event_kind: RawEventKind::Ftp,
             attr_name: FtpAttr::DataPassive.to_string(),
-            value_kind: ValueKind::Bool,
             cmp_kind: AttrCmpKind::Equal,
-            first_value: serialize(&false).unwrap(),
-            second_value: None,
+            value: AttrValue::Bool(false),
             weight: Some(0.3),
         }];
-        let score_result_false =
-            ftp_passive_event.score_by_attr(&success_packet_attr_vec_bool_false);
+        let score_result_false = ftp_passive_event.score_by_rules(
+            &TriageRules::from(success_packet_attr_vec_bool_false),
+            &HashMap::new(),
+        );
         assert_eq!(score_result_false.partial_cmp(&0.3), Some(Ordering::Equal));
 
         // For Vec<Bool>, AttrCmpKind::NotEqual means score if NONE of the 'true' are found.
         let fail_packet_attr_vec_bool_not_true = vec![PacketAttr {
             raw_event_kind: RawEventKind::Ftp,
             attr_name: FtpAttr::DataPassive.to_string(),
-            value_kind: ValueKind::Bool,
             cmp_kind: AttrCmpKind::NotEqual,
-            first_value: serialize(&true).unwrap(),
-            second_value: None,
+            value: AttrValue::Bool(true),
             weight: Some(0.5),
         }];
-        let score_result_not_true =
-            ftp_passive_event.score_by_attr(&fail_packet_attr_vec_bool_not_true);
+        let score_result_not_true = ftp_passive_event.score_by_rules(
+            &TriageRules::from(fail_packet_attr_vec_bool_not_true),
+            &HashMap::new(),
+        );
         assert_eq!(
             score_result_not_true.partial_cmp(&0.0),
             Some(Ordering::Equal)
@@ -1488,28 +1636,24 @@ pub(crate) mod tests {
         let contain_attr = PacketAttr {
             raw_event_kind: RawEventKind::Http,
             attr_name: "mime_types".to_string(),
-            value_kind: ValueKind::String,
             cmp_kind: AttrCmpKind::Contain,
-            first_value: serialize(&"beta").unwrap(),
-            second_value: None,
+            value: AttrValue::String("beta".to_string()),
             weight: None,
         };
         assert!(is_attr_matched(
-            AttrValue::VecString(std::borrow::Cow::Borrowed(&borrowed_strings)),
+            EventAttrValue::VecString(std::borrow::Cow::Borrowed(&borrowed_strings)),
             &contain_attr
         ));
 
         let not_contain_attr = PacketAttr {
             raw_event_kind: RawEventKind::Http,
             attr_name: "mime_types".to_string(),
-            value_kind: ValueKind::String,
             cmp_kind: AttrCmpKind::NotContain,
-            first_value: serialize(&"gamma").unwrap(),
-            second_value: None,
+            value: AttrValue::String("gamma".to_string()),
             weight: None,
         };
         assert!(is_attr_matched(
-            AttrValue::VecString(std::borrow::Cow::Borrowed(&borrowed_strings)),
+            EventAttrValue::VecString(std::borrow::Cow::Borrowed(&borrowed_strings)),
             &not_contain_attr
         ));
 
@@ -1518,28 +1662,24 @@ pub(crate) mod tests {
         let equal_attr = PacketAttr {
             raw_event_kind: RawEventKind::Conn,
             attr_name: "dst_port".to_string(),
-            value_kind: ValueKind::UInteger,
             cmp_kind: AttrCmpKind::Equal,
-            first_value: serialize(&200_u64).unwrap(),
-            second_value: None,
+            value: AttrValue::UInteger(200),
             weight: None,
         };
         assert!(is_attr_matched(
-            AttrValue::VecUInt(std::borrow::Cow::Owned(owned_ports.clone())),
+            EventAttrValue::VecUInt(std::borrow::Cow::Owned(owned_ports.clone())),
             &equal_attr
         ));
 
         let not_equal_attr = PacketAttr {
             raw_event_kind: RawEventKind::Conn,
             attr_name: "dst_port".to_string(),
-            value_kind: ValueKind::UInteger,
             cmp_kind: AttrCmpKind::NotEqual,
-            first_value: serialize(&300_u64).unwrap(),
-            second_value: None,
+            value: AttrValue::UInteger(300),
             weight: None,
         };
         assert!(is_attr_matched(
-            AttrValue::VecUInt(std::borrow::Cow::Owned(owned_ports)),
+            EventAttrValue::VecUInt(std::borrow::Cow::Owned(owned_ports)),
             &not_equal_attr
         ));
     }
@@ -1552,28 +1692,24 @@ pub(crate) mod tests {
         let contain_attr = PacketAttr {
             raw_event_kind: RawEventKind::Dhcp,
             attr_name: DhcpAttr::OptionData.to_string(),
-            value_kind: ValueKind::Vector,
             cmp_kind: AttrCmpKind::Contain,
-            first_value: vec![0x02, 0x03],
-            second_value: None,
+            value: AttrValue::Vector(vec![0x02, 0x03]),
             weight: None,
         };
         assert!(is_attr_matched(
-            AttrValue::VecRawList(std::borrow::Cow::Borrowed(&slices)),
+            EventAttrValue::VecRawList(std::borrow::Cow::Borrowed(&slices)),
             &contain_attr
         ));
 
         let not_contain_attr = PacketAttr {
             raw_event_kind: RawEventKind::Dhcp,
             attr_name: DhcpAttr::OptionData.to_string(),
-            value_kind: ValueKind::Vector,
             cmp_kind: AttrCmpKind::NotContain,
-            first_value: vec![0xff],
-            second_value: None,
+            value: AttrValue::Vector(vec![0xff]),
             weight: None,
         };
         assert!(is_attr_matched(
-            AttrValue::VecRawList(std::borrow::Cow::Borrowed(&slices)),
+            EventAttrValue::VecRawList(std::borrow::Cow::Borrowed(&slices)),
             &not_contain_attr
         ));
     }
@@ -1587,14 +1723,14 @@ pub(crate) mod tests {
         let radius_attr = vec![PacketAttr {
             raw_event_kind: RawEventKind::Radius,
             attr_name: RadiusAttr::NasIp.to_string(),
-            value_kind: ValueKind::IpAddr,
             cmp_kind: AttrCmpKind::Equal,
-            first_value: serialize(&IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap(),
-            second_value: None,
+            value: AttrValue::IpAddr(IpAddr::V4(Ipv4Addr::LOCALHOST)),
             weight: Some(1.0),
         }];
         assert_eq!(
-            radius_event.score_by_attr(&radius_attr).partial_cmp(&1.0),
+            radius_event
+                .score_by_rules(&TriageRules::from(radius_attr), &HashMap::new())
+                .partial_cmp(&1.0),
             Some(Ordering::Equal)
         );
 
@@ -1604,15 +1740,13 @@ pub(crate) mod tests {
         let dns_attr = vec![PacketAttr {
             raw_event_kind: RawEventKind::Dns,
             attr_name: DnsAttr::TransId.to_string(),
-            value_kind: ValueKind::UInteger,
             cmp_kind: AttrCmpKind::Equal,
-            first_value: serialize(&12345_u64).unwrap(),
-            second_value: None,
+            value: AttrValue::UInteger(12345),
             weight: Some(1.0),
         }];
         assert_eq!(
             malformed_dns_event
-                .score_by_attr(&dns_attr)
+                .score_by_rules(&TriageRules::from(dns_attr), &HashMap::new())
                 .partial_cmp(&1.0),
             Some(Ordering::Equal)
         );
@@ -1624,25 +1758,169 @@ pub(crate) mod tests {
         let conn_attr = vec![PacketAttr {
             raw_event_kind: RawEventKind::Conn,
             attr_name: ConnAttr::DstAddr.to_string(),
-            value_kind: ValueKind::IpAddr,
             cmp_kind: AttrCmpKind::Equal, // Check if vector contains this IP
-            first_value: serialize(&IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))).unwrap(),
-            second_value: None,
+            value: AttrValue::IpAddr(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))),
             weight: Some(1.0),
         }];
         assert_eq!(
             unusual_dest_event
-                .score_by_attr(&conn_attr)
+                .score_by_rules(&TriageRules::from(conn_attr), &HashMap::new())
                 .partial_cmp(&1.0),
             Some(Ordering::Equal)
         );
     }
 
-    fn serialize<T>(v: &T) -> Option<Vec<u8>>
-    where
-        T: Serialize,
-    {
-        bincode::DefaultOptions::new().serialize(v).ok()
+    #[test]
+    fn score_by_condition_tree() {
+        use crate::{ExclusionReason, TriageCondition, TriageExclusion, TriageRule};
+
+        let time = stored_time(Utc.with_ymd_and_hms(1970, 1, 1, 0, 1, 1).unwrap());
+        let http_event = DomainGenerationAlgorithm::new(time, dga_fields());
+        let http_attr = |attr: HttpAttr, cmp_kind, value| {
+            TriageCondition::Attr(PacketAttr {
+                raw_event_kind: RawEventKind::Http,
+                attr_name: attr.to_string(),
+                cmp_kind,
+                value,
+                weight: Some(5.0),
+            })
+        };
+        // An HTTP GET with status 200 to a domain not in exclusion 1.
+        let rules = TriageRules::V1(vec![
+            TriageRule {
+                condition: TriageCondition::All(vec![
+                    http_attr(
+                        HttpAttr::Method,
+                        AttrCmpKind::Contain,
+                        AttrValue::String("GET".to_string()),
+                    ),
+                    TriageCondition::Not(Box::new(TriageCondition::Exclusion(1))),
+                    http_attr(
+                        HttpAttr::StatusCode,
+                        AttrCmpKind::Equal,
+                        AttrValue::UInteger(200),
+                    ),
+                ]),
+                weight: 0.6,
+            },
+            TriageRule {
+                condition: TriageCondition::Any(vec![
+                    http_attr(
+                        HttpAttr::Method,
+                        AttrCmpKind::Contain,
+                        AttrValue::String("POST".to_string()),
+                    ),
+                    TriageCondition::Exclusion(2),
+                ]),
+                weight: 0.3,
+            },
+            TriageRule {
+                condition: TriageCondition::Any(Vec::new()),
+                weight: 0.05,
+            },
+        ]);
+
+        let trusted = |domain: &str| {
+            HashMap::from([(
+                1,
+                TriageExclusion::from(ExclusionReason::Domain(vec![domain.to_string()])),
+            )])
+        };
+        let score = http_event.score_by_rules(&rules, &trusted("trusted.org"));
+        assert_eq!(score.partial_cmp(&0.6), Some(Ordering::Equal));
+        let score = http_event.score_by_rules(&rules, &trusted("example.com"));
+        assert_eq!(score.partial_cmp(&0.0), Some(Ordering::Equal));
+        // A reference to a missing exclusion never matches.
+        let score = http_event.score_by_rules(&rules, &HashMap::new());
+        assert_eq!(score.partial_cmp(&0.6), Some(Ordering::Equal));
+    }
+
+    #[test]
+    fn explain_policy_breaks_down_score() {
+        use crate::{ExclusionReason, TriageCondition, TriageExclusion, TriageRule};
+
+        let time = stored_time(Utc.with_ymd_and_hms(1970, 1, 1, 0, 1, 1).unwrap());
+        let http_event = DomainGenerationAlgorithm::new(time, dga_fields());
+        let http_attr = |attr: HttpAttr, cmp_kind, value| PacketAttr {
+            raw_event_kind: RawEventKind::Http,
+            attr_name: attr.to_string(),
+            cmp_kind,
+            value,
+            weight: None,
+        };
+        let get = http_attr(
+            HttpAttr::Method,
+            AttrCmpKind::Contain,
+            AttrValue::String("GET".to_string()),
+        );
+        let ok = http_attr(
+            HttpAttr::StatusCode,
+            AttrCmpKind::Equal,
+            AttrValue::UInteger(200),
+        );
+        let post = http_attr(
+            HttpAttr::Method,
+            AttrCmpKind::Contain,
+            AttrValue::String("POST".to_string()),
+        );
+        let domain =
+            |domain: &str| TriageExclusion::from(ExclusionReason::Domain(vec![domain.to_string()]));
+
+        let mut policy = make_policy(
+            1,
+            vec![make_confidence(None, "port scan", 0.0, Some(1.0))],
+            vec![make_response(0.5)],
+        );
+        policy.rules = TriageRules::V1(vec![
+            TriageRule {
+                condition: TriageCondition::All(vec![
+                    TriageCondition::Attr(get.clone()),
+                    TriageCondition::Not(Box::new(TriageCondition::Exclusion(1))),
+                    TriageCondition::Attr(ok.clone()),
+                ]),
+                weight: 0.6,
+            },
+            TriageRule {
+                condition: TriageCondition::Any(vec![
+                    TriageCondition::Attr(post),
+                    TriageCondition::Exclusion(2),
+                ]),
+                weight: 0.3,
+            },
+            TriageRule {
+                condition: TriageCondition::Any(Vec::new()),
+                weight: 0.05,
+            },
+        ]);
+        policy.rule_exclusions =
+            HashMap::from([(1, domain("trusted.org")), (2, domain("example.com"))]);
+
+        let explanation = http_event.explain_policy(&policy);
+        assert_eq!(explanation.policy_id, 1);
+        assert_eq!(explanation.score.partial_cmp(&0.9), Some(Ordering::Equal));
+        assert!(explanation.scored);
+        assert!(explanation.exclusions.is_empty());
+        assert!(explanation.confidence.is_empty());
+        let rules = &explanation.rules;
+        assert_eq!(rules.iter().map(|r| r.index).collect::<Vec<_>>(), [0, 1]);
+        assert!(rules[0].attrs == [get, ok]);
+        assert!(rules[0].exclusions.is_empty());
+        assert!(rules[1].attrs.is_empty());
+        assert_eq!(rules[1].exclusions, [2]);
+
+        // The score is the one filtering by the policy gives.
+        let mut filter = event_filter();
+        filter.triage_policies = Some(vec![policy.clone()]);
+        let (_, scores) = http_event.matches(&filter).unwrap();
+        assert_eq!(
+            scores.unwrap()[0].score.partial_cmp(&explanation.score),
+            Some(Ordering::Equal)
+        );
+
+        policy.triage_exclusion = vec![domain("trusted.org"), domain("example.com")];
+        let explanation = http_event.explain_policy(&policy);
+        assert_eq!(explanation.exclusions, [1]);
+        assert!(!explanation.scored);
     }
 
     fn create_directions(kind: FlowKind, addr: IpAddr) -> (Vec<FlowKind>, Vec<HostNetworkGroup>) {
@@ -3311,7 +3589,8 @@ pub(crate) mod tests {
             name: format!("policy-{id}"),
             creation_time: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
             triage_exclusion: Vec::new(),
-            packet_attr: Vec::new(),
+            rules: crate::TriageRules::default(),
+            rule_exclusions: HashMap::new(),
             confidence,
             response,
         }
@@ -3352,6 +3631,51 @@ pub(crate) mod tests {
         assert!(!event.matches_exclusion(&non_matching));
     }
 
+    #[test]
+    fn matches_exclusion_within_scope_only() {
+        use chrono::Duration;
+
+        use crate::{EventKind, ExclusionScope};
+
+        let event = blocklist_http_event();
+        let scoped = |scope: ExclusionScope| {
+            vec![crate::TriageExclusion::Scoped(
+                scope,
+                Box::new(crate::TriageExclusion::Uri(vec!["/uri/path".to_string()])),
+            )]
+        };
+        // The validity period is compared with the time of the event, not
+        // with the current time.
+        let time = Utc.with_ymd_and_hms(1970, 1, 1, 0, 1, 1).unwrap();
+
+        assert!(event.matches_exclusion(&scoped(ExclusionScope {
+            valid_from: Some(time - Duration::hours(1)),
+            valid_until: Some(time + Duration::hours(1)),
+            event_kind: Some(EventKind::BlocklistHttp),
+            sensor: Some("sensor".to_string()),
+        })));
+        assert!(!event.matches_exclusion(&scoped(ExclusionScope {
+            valid_until: Some(time),
+            ..ExclusionScope::default()
+        })));
+        assert!(!event.matches_exclusion(&scoped(ExclusionScope {
+            valid_from: Some(time + Duration::hours(1)),
+            ..ExclusionScope::default()
+        })));
+        assert!(!event.matches_exclusion(&scoped(ExclusionScope {
+            valid_from: Some(Utc::now() - Duration::hours(1)),
+            ..ExclusionScope::default()
+        })));
+        assert!(!event.matches_exclusion(&scoped(ExclusionScope {
+            event_kind: Some(EventKind::HttpThreat),
+            ..ExclusionScope::default()
+        })));
+        assert!(!event.matches_exclusion(&scoped(ExclusionScope {
+            sensor: Some("other".to_string()),
+            ..ExclusionScope::default()
+        })));
+    }
+
     #[test]
     fn matches_exclusion_mixed_ipv4_ipv6_ip_address() {
         use std::ops::RangeInclusive;
@@ -3448,7 +3772,7 @@ pub(crate) mod tests {
         let event = dns_covert_channel_event();
 
         // The legacy formula sums score_by_triage_exclusion +
-        // score_by_attr + score_by_confidence and threshold-filters by
+        // score_by_rules + score_by_confidence and threshold-filters by
         // response.minimum_score. None of the policies below should pass.
         let unreachable_policy = make_policy(
             1,
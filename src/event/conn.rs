@@ -5,7 +5,7 @@ use jiff::Timestamp;
 use serde::{Deserialize, Serialize};
 
 use super::timestamp::{self, ts_nanoseconds as jiff_ts_nanoseconds};
-use super::{EventCategory, LearningMethod, ThreatLevel, TriageScore, common::Match};
+use super::{EventCategory, EventKind, LearningMethod, ThreatLevel, TriageScore, common::Match};
 use crate::event::common::{AttrValue, triage_scores_to_string, vector_to_string};
 
 #[macro_export]
@@ -220,6 +220,14 @@ impl Match for PortScan {
         "port scan"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::PortScan
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -434,6 +442,14 @@ impl Match for MultiHostPortScan {
         "multi host port scan"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::MultiHostPortScan
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -641,6 +657,14 @@ impl Match for ExternalDdos {
         "external ddos"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::ExternalDdos
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -909,6 +933,14 @@ impl Match for BlocklistConn {
         "blocklist conn"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::BlocklistConn
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -5,7 +5,7 @@ use jiff::Timestamp;
 use serde::{Deserialize, Serialize};
 
 use super::timestamp;
-use super::{EventCategory, LearningMethod, ThreatLevel, TriageScore, common::Match};
+use super::{EventCategory, EventKind, LearningMethod, ThreatLevel, TriageScore, common::Match};
 use crate::event::common::{AttrValue, triage_scores_to_string};
 
 // request entry format: "{context_id}:{opnum}"
@@ -190,6 +190,24 @@ pub struct DceRpcContext {
     pub reason: u16,
 }
 
+impl DceRpcContext {
+    /// Returns the context as a JSON object, with its syntax UUIDs in their
+    /// canonical text form.
+    pub(super) fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "abstract_syntax": format_dce_uuid(self.abstract_syntax),
+            "abstract_major": self.abstract_major,
+            "abstract_minor": self.abstract_minor,
+            "transfer_syntax": format_dce_uuid(self.transfer_syntax),
+            "transfer_major": self.transfer_major,
+            "transfer_minor": self.transfer_minor,
+            "acceptance": self.acceptance,
+            "reason": self.reason,
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct BlocklistDceRpcFields {
     pub sensor: String,
@@ -466,6 +484,14 @@ impl Match for BlocklistDceRpc {
         "blocklist dcerpc"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::BlocklistDceRpc
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -487,13 +513,12 @@ impl Match for BlocklistDceRpc {
 mod tests {
     use std::{
         cmp::Ordering,
+        collections::HashMap,
         net::{IpAddr, Ipv4Addr},
     };
 
     use attrievent::attribute::{DceRpcAttr, RawEventAttrKind, RawEventKind};
-    use bincode::Options;
     use chrono::{TimeZone, Utc};
-    use serde::Serialize;
 
     use super::{
         BlocklistDceRpc, BlocklistDceRpcFieldsStored, DceRpcContext, collect_request_part,
@@ -501,8 +526,8 @@ mod tests {
     };
     use crate::event::timestamp;
     use crate::{
-        AttrCmpKind, PacketAttr, ValueKind,
-        event::common::{AttrValue, Match},
+        AttrCmpKind, AttrValue, PacketAttr, TriageRules,
+        event::common::{AttrValue as EventAttrValue, Match},
     };
 
     #[test]
@@ -582,14 +607,14 @@ mod tests {
             .expect(timestamp::I64_NANOS_JIFF_INVARIANT);
         let event = BlocklistDceRpc::new(time, dcerpc_fields_with_context());
 
-        let Some(AttrValue::VecUInt(ids)) =
+        let Some(EventAttrValue::VecUInt(ids)) =
             event.find_attr_by_kind(RawEventAttrKind::DceRpc(DceRpcAttr::ContextId))
         else {
             panic!("Expected ContextId");
         };
         assert_eq!(ids.as_ref(), &[0_u64]);
 
-        let Some(AttrValue::VecString(syntaxes)) =
+        let Some(EventAttrValue::VecString(syntaxes)) =
             event.find_attr_by_kind(RawEventAttrKind::DceRpc(DceRpcAttr::AbstractSyntax))
         else {
             panic!("Expected AbstractSyntax");
@@ -599,7 +624,7 @@ mod tests {
             &["12345678-1234-5678-1234-56789abcdef0".to_string()]
         );
 
-        let Some(AttrValue::VecUInt(opnums)) =
+        let Some(EventAttrValue::VecUInt(opnums)) =
             event.find_attr_by_kind(RawEventAttrKind::DceRpc(DceRpcAttr::RequestOpnum))
         else {
             panic!("Expected RequestOpnum");
@@ -622,13 +647,6 @@ mod tests {
         );
     }
 
-    fn serialize<T>(value: &T) -> Option<Vec<u8>>
-    where
-        T: Serialize,
-    {
-        bincode::DefaultOptions::new().serialize(value).ok()
-    }
-
     #[test]
     fn dcerpc_score_by_attr() {
         let time = timestamp::from_chrono(Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap())
@@ -639,33 +657,29 @@ mod tests {
             PacketAttr {
                 raw_event_kind: RawEventKind::DceRpc,
                 attr_name: DceRpcAttr::AbstractSyntax.to_string(),
-                value_kind: ValueKind::String,
                 cmp_kind: AttrCmpKind::Contain,
-                first_value: serialize(&"12345678-1234-5678-1234-56789abcdef0").unwrap(),
-                second_value: None,
+                value: AttrValue::String("12345678-1234-5678-1234-56789abcdef0".to_string()),
                 weight: Some(0.5),
             },
             PacketAttr {
                 raw_event_kind: RawEventKind::DceRpc,
                 attr_name: DceRpcAttr::RequestContextId.to_string(),
-                value_kind: ValueKind::UInteger,
                 cmp_kind: AttrCmpKind::Equal,
-                first_value: serialize(&0_u64).unwrap(),
-                second_value: None,
+                value: AttrValue::UInteger(0),
                 weight: Some(0.25),
             },
             PacketAttr {
                 raw_event_kind: RawEventKind::DceRpc,
                 attr_name: DceRpcAttr::RequestOpnum.to_string(),
-                value_kind: ValueKind::UInteger,
                 cmp_kind: AttrCmpKind::Equal,
-                first_value: serialize(&42_u64).unwrap(),
-                second_value: None,
+                value: AttrValue::UInteger(42),
                 weight: Some(0.25),
             },
         ];
         assert_eq!(
-            event.score_by_attr(&packet_attrs).partial_cmp(&1.0),
+            event
+                .score_by_rules(&TriageRules::from(packet_attrs), &HashMap::new())
+                .partial_cmp(&1.0),
             Some(Ordering::Equal)
         );
     }
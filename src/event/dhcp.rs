@@ -6,7 +6,7 @@ use serde::{Deserialize, Serialize};
 
 use super::timestamp;
 use super::{
-    EventCategory, LearningMethod, ThreatLevel, TriageScore,
+    EventCategory, EventKind, LearningMethod, ThreatLevel, TriageScore,
     common::{AttrValue, Match},
 };
 use crate::event::common::{
@@ -427,6 +427,14 @@ impl Match for BlocklistDhcp {
         "blocklist dhcp"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::BlocklistDhcp
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -448,6 +456,7 @@ impl Match for BlocklistDhcp {
 mod tests {
     use std::{
         cmp::Ordering,
+        collections::HashMap,
         net::{IpAddr, Ipv4Addr},
     };
 
@@ -457,8 +466,8 @@ mod tests {
     use super::{BlocklistDhcp, BlocklistDhcpFieldsStored};
     use crate::event::timestamp;
     use crate::{
-        AttrCmpKind, PacketAttr, ValueKind,
-        event::common::{AttrValue, Match},
+        AttrCmpKind, AttrValue, PacketAttr, TriageRules,
+        event::common::{AttrValue as EventAttrValue, Match},
     };
 
     fn dhcp_fields_with_options() -> BlocklistDhcpFieldsStored {
@@ -512,7 +521,7 @@ mod tests {
         let event = BlocklistDhcp::new(time, dhcp_fields_with_options());
         let attr = RawEventAttrKind::Dhcp(DhcpAttr::OptionCode);
 
-        let Some(AttrValue::VecUInt(codes)) = event.find_attr_by_kind(attr) else {
+        let Some(EventAttrValue::VecUInt(codes)) = event.find_attr_by_kind(attr) else {
             panic!("Expected OptionCode as VecUInt");
         };
         assert_eq!(codes.as_ref(), &[53_u64, 61_u64]);
@@ -525,7 +534,7 @@ mod tests {
         let event = BlocklistDhcp::new(time, dhcp_fields_with_options());
         let attr = RawEventAttrKind::Dhcp(DhcpAttr::OptionData);
 
-        let Some(AttrValue::VecRawList(data)) = event.find_attr_by_kind(attr) else {
+        let Some(EventAttrValue::VecRawList(data)) = event.find_attr_by_kind(attr) else {
             panic!("Expected OptionData as VecRawList");
         };
         assert_eq!(data.as_ref(), &[&[1_u8][..], &[0x01, 0x02, 0x03][..]]);
@@ -540,28 +549,28 @@ mod tests {
         let match_attr = vec![PacketAttr {
             raw_event_kind: RawEventKind::Dhcp,
             attr_name: DhcpAttr::OptionData.to_string(),
-            value_kind: ValueKind::Vector,
             cmp_kind: AttrCmpKind::Contain,
-            first_value: vec![0x02, 0x03],
-            second_value: None,
+            value: AttrValue::Vector(vec![0x02, 0x03]),
             weight: Some(1.0),
         }];
         assert_eq!(
-            event.score_by_attr(&match_attr).partial_cmp(&1.0),
+            event
+                .score_by_rules(&TriageRules::from(match_attr), &HashMap::new())
+                .partial_cmp(&1.0),
             Some(Ordering::Equal)
         );
 
         let no_match_attr = vec![PacketAttr {
             raw_event_kind: RawEventKind::Dhcp,
             attr_name: DhcpAttr::OptionData.to_string(),
-            value_kind: ValueKind::Vector,
             cmp_kind: AttrCmpKind::Contain,
-            first_value: vec![0xff],
-            second_value: None,
+            value: AttrValue::Vector(vec![0xff]),
             weight: Some(1.0),
         }];
         assert_eq!(
-            event.score_by_attr(&no_match_attr).partial_cmp(&0.0),
+            event
+                .score_by_rules(&TriageRules::from(no_match_attr), &HashMap::new())
+                .partial_cmp(&0.0),
             Some(Ordering::Equal)
         );
     }
@@ -6,10 +6,9 @@ use jiff::Timestamp;
 use serde::{Deserialize, Serialize};
 
 use super::timestamp::{self, ts_nanoseconds as jiff_ts_nanoseconds};
-use super::{EventCategory, LearningMethod, ThreatLevel, TriageScore, common::Match};
-use crate::{
-    TriageExclusion,
-    event::common::{AttrValue, triage_scores_to_string, vector_to_string},
+use super::{EventCategory, EventKind, LearningMethod, ThreatLevel, TriageScore, common::Match};
+use crate::event::common::{
+    AttrValue, ExclusionTargets, triage_scores_to_string, vector_to_string,
 };
 
 macro_rules! find_dns_attr_by_kind {
@@ -346,6 +345,14 @@ impl Match for DnsCovertChannel {
         "dns covert channel"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::DnsCovertChannel
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -362,24 +369,8 @@ impl Match for DnsCovertChannel {
         find_dns_attr_by_kind!(self, raw_event_attr)
     }
 
-    fn score_by_triage_exclusion(&self, triage_exclusion: &[TriageExclusion]) -> f64 {
-        let matched = triage_exclusion.iter().any(|ti| match ti {
-            TriageExclusion::IpAddress(filter) => self
-                .orig_addrs()
-                .iter()
-                .chain(self.resp_addrs().iter())
-                .any(|&ip| filter.contains(ip)),
-            TriageExclusion::Domain(regex_set) => regex_set.is_match(&self.query),
-            TriageExclusion::Hostname(hostnames) => {
-                let hostname = self
-                    .query
-                    .split_once('.')
-                    .map_or(self.query.clone(), |(label, _)| label.to_string());
-                hostnames.contains(&hostname)
-            }
-            TriageExclusion::Uri(_) => false, // DNS queries don't match URIs
-        });
-        if matched { f64::MIN } else { 0.0 }
+    fn exclusion_targets(&self) -> ExclusionTargets<'_> {
+        ExclusionTargets::dns(&self.query)
     }
 }
 
@@ -540,6 +531,14 @@ impl Match for LockyRansomware {
         "locky ransomware"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::LockyRansomware
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -556,24 +555,8 @@ impl Match for LockyRansomware {
         find_dns_attr_by_kind!(self, raw_event_attr)
     }
 
-    fn score_by_triage_exclusion(&self, triage_exclusion: &[TriageExclusion]) -> f64 {
-        let matched = triage_exclusion.iter().any(|ti| match ti {
-            TriageExclusion::IpAddress(filter) => self
-                .orig_addrs()
-                .iter()
-                .chain(self.resp_addrs().iter())
-                .any(|&ip| filter.contains(ip)),
-            TriageExclusion::Domain(regex_set) => regex_set.is_match(&self.query),
-            TriageExclusion::Hostname(hostnames) => {
-                let hostname = self
-                    .query
-                    .split_once('.')
-                    .map_or(self.query.clone(), |(label, _)| label.to_string());
-                hostnames.contains(&hostname)
-            }
-            TriageExclusion::Uri(_) => false, // DNS queries don't match URIs
-        });
-        if matched { f64::MIN } else { 0.0 }
+    fn exclusion_targets(&self) -> ExclusionTargets<'_> {
+        ExclusionTargets::dns(&self.query)
     }
 }
 
@@ -881,6 +864,14 @@ impl Match for CryptocurrencyMiningPool {
         "cryptocurrency mining pool"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::CryptocurrencyMiningPool
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -897,24 +888,8 @@ impl Match for CryptocurrencyMiningPool {
         find_dns_attr_by_kind!(self, raw_event_attr)
     }
 
-    fn score_by_triage_exclusion(&self, triage_exclusion: &[TriageExclusion]) -> f64 {
-        let matched = triage_exclusion.iter().any(|ti| match ti {
-            TriageExclusion::IpAddress(filter) => self
-                .orig_addrs()
-                .iter()
-                .chain(self.resp_addrs().iter())
-                .any(|&ip| filter.contains(ip)),
-            TriageExclusion::Domain(regex_set) => regex_set.is_match(&self.query),
-            TriageExclusion::Hostname(hostnames) => {
-                let hostname = self
-                    .query
-                    .split_once('.')
-                    .map_or(self.query.clone(), |(label, _)| label.to_string());
-                hostnames.contains(&hostname)
-            }
-            TriageExclusion::Uri(_) => false, // DNS queries don't match URIs
-        });
-        if matched { f64::MIN } else { 0.0 }
+    fn exclusion_targets(&self) -> ExclusionTargets<'_> {
+        ExclusionTargets::dns(&self.query)
     }
 }
 
@@ -1215,6 +1190,14 @@ impl Match for BlocklistDns {
         "blocklist dns"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::BlocklistDns
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -1231,23 +1214,7 @@ impl Match for BlocklistDns {
         find_dns_attr_by_kind!(self, raw_event_attr)
     }
 
-    fn score_by_triage_exclusion(&self, triage_exclusion: &[TriageExclusion]) -> f64 {
-        let matched = triage_exclusion.iter().any(|ti| match ti {
-            TriageExclusion::IpAddress(filter) => self
-                .orig_addrs()
-                .iter()
-                .chain(self.resp_addrs().iter())
-                .any(|&ip| filter.contains(ip)),
-            TriageExclusion::Domain(regex_set) => regex_set.is_match(&self.query),
-            TriageExclusion::Hostname(hostnames) => {
-                let hostname = self
-                    .query
-                    .split_once('.')
-                    .map_or(self.query.clone(), |(label, _)| label.to_string());
-                hostnames.contains(&hostname)
-            }
-            TriageExclusion::Uri(_) => false, // DNS queries don't match URIs
-        });
-        if matched { f64::MIN } else { 0.0 }
+    fn exclusion_targets(&self) -> ExclusionTargets<'_> {
+        ExclusionTargets::dns(&self.query)
     }
 }
@@ -0,0 +1,61 @@
+//! Explanations of the triage scores of events.
+
+use serde::{Deserialize, Serialize};
+
+use super::TriageScore;
+use crate::{Confidence, PacketAttr};
+
+/// The score of an event against a triage policy, broken down into the
+/// entries of the policy that contributed to it.
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct TriageExplanation {
+    pub policy_id: u32,
+    /// The score, the same as the one filtering by the policy gives.
+    pub score: f64,
+    /// Whether the score reaches at least one response threshold of the
+    /// policy.
+    pub scored: bool,
+    /// The positions in the policy's `triage_exclusion` of the exclusions
+    /// matching the event. Any of them brings the score down to `f64::MIN`.
+    pub exclusions: Vec<usize>,
+    /// The rules holding for the event, in the order of the policy's rules.
+    /// Their weights add up to the part of the score from the rules before it
+    /// is truncated to two decimal places.
+    pub rules: Vec<RuleContribution>,
+    /// The confidence entries matching the event, in the order of the
+    /// policy's `confidence`.
+    pub confidence: Vec<ConfidenceContribution>,
+}
+
+impl TriageExplanation {
+    /// Returns the policy and the score without the breakdown.
+    #[must_use]
+    pub fn triage_score(&self) -> TriageScore {
+        TriageScore {
+            policy_id: self.policy_id,
+            score: self.score,
+        }
+    }
+}
+
+/// A rule of a triage policy holding for an event.
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct RuleContribution {
+    /// The position of the rule in the policy's rules.
+    pub index: usize,
+    pub weight: f64,
+    /// The attributes in the rule's condition, outside of any `Not`, that
+    /// match the event.
+    pub attrs: Vec<PacketAttr>,
+    /// The IDs of the exclusion reasons in the rule's condition, outside of
+    /// any `Not`, that match the event.
+    pub exclusions: Vec<u32>,
+}
+
+/// A confidence entry of a triage policy matching an event.
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct ConfidenceContribution {
+    pub confidence: Confidence,
+    /// The weight added to the score, which is 1.0 for an entry without one.
+    pub weight: f64,
+}
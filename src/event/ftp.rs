@@ -6,7 +6,7 @@ use jiff::Timestamp;
 use serde::{Deserialize, Serialize};
 
 use super::timestamp::{self, ts_nanoseconds as jiff_ts_nanoseconds};
-use super::{EventCategory, LearningMethod, ThreatLevel, TriageScore, common::Match};
+use super::{EventCategory, EventKind, LearningMethod, ThreatLevel, TriageScore, common::Match};
 use crate::event::common::{AttrValue, triage_scores_to_string};
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -354,6 +354,14 @@ impl Match for FtpBruteForce {
         "ftp brute force"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::FtpBruteForce
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -632,6 +640,14 @@ impl Match for FtpPlainText {
         "ftp plain text"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::FtpPlainText
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -787,6 +803,14 @@ impl Match for BlocklistFtp {
         "blocklist ftp"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::BlocklistFtp
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
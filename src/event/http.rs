@@ -6,11 +6,10 @@ use jiff::Timestamp;
 use serde::{Deserialize, Serialize};
 
 use super::timestamp::{self, ts_nanoseconds as jiff_ts_nanoseconds};
-use super::{EventCategory, EventFilter, LearningMethod, ThreatLevel, TriageScore, common::Match};
-use crate::{
-    TriageExclusion,
-    event::common::{AttrValue, triage_scores_to_string},
+use super::{
+    EventCategory, EventFilter, EventKind, LearningMethod, ThreatLevel, TriageScore, common::Match,
 };
+use crate::event::common::{AttrValue, ExclusionTargets, triage_scores_to_string};
 
 macro_rules! find_http_attr_by_kind {
     ($event: expr, $raw_event_attr: expr) => {
@@ -422,6 +421,14 @@ impl Match for RepeatedHttpSessions {
         "repeated http sessions"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::RepeatedHttpSessions
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -861,6 +868,14 @@ impl Match for HttpThreat {
         "http threat"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::HttpThreat
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -903,18 +918,8 @@ impl Match for HttpThreat {
         true
     }
 
-    fn score_by_triage_exclusion(&self, triage_exclusion: &[TriageExclusion]) -> f64 {
-        let matched = triage_exclusion.iter().any(|ti| match ti {
-            TriageExclusion::IpAddress(filter) => self
-                .orig_addrs()
-                .iter()
-                .chain(self.resp_addrs().iter())
-                .any(|&ip| filter.contains(ip)),
-            TriageExclusion::Domain(regex_set) => regex_set.is_match(&self.host),
-            TriageExclusion::Hostname(hostnames) => hostnames.contains(&self.host),
-            TriageExclusion::Uri(uris) => uris.contains(&self.uri),
-        });
-        if matched { f64::MIN } else { 0.0 }
+    fn exclusion_targets(&self) -> ExclusionTargets<'_> {
+        ExclusionTargets::http(&self.host, &self.uri, &self.user_agent)
     }
 }
 
@@ -1274,6 +1279,14 @@ impl Match for DomainGenerationAlgorithm {
         "dga"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::DomainGenerationAlgorithm
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -1290,18 +1303,8 @@ impl Match for DomainGenerationAlgorithm {
         find_http_attr_by_kind!(self, raw_event_attr)
     }
 
-    fn score_by_triage_exclusion(&self, triage_exclusion: &[TriageExclusion]) -> f64 {
-        let matched = triage_exclusion.iter().any(|ti| match ti {
-            TriageExclusion::IpAddress(filter) => self
-                .orig_addrs()
-                .iter()
-                .chain(self.resp_addrs().iter())
-                .any(|&ip| filter.contains(ip)),
-            TriageExclusion::Domain(regex_set) => regex_set.is_match(&self.host),
-            TriageExclusion::Hostname(hostnames) => hostnames.contains(&self.host),
-            TriageExclusion::Uri(uris) => uris.contains(&self.uri),
-        });
-        if matched { f64::MIN } else { 0.0 }
+    fn exclusion_targets(&self) -> ExclusionTargets<'_> {
+        ExclusionTargets::http(&self.host, &self.uri, &self.user_agent)
     }
 }
 
@@ -1488,6 +1491,14 @@ impl Match for NonBrowser {
         "non browser"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::NonBrowser
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -1504,18 +1515,8 @@ impl Match for NonBrowser {
         find_http_attr_by_kind!(self, raw_event_attr)
     }
 
-    fn score_by_triage_exclusion(&self, triage_exclusion: &[TriageExclusion]) -> f64 {
-        let matched = triage_exclusion.iter().any(|ti| match ti {
-            TriageExclusion::IpAddress(filter) => self
-                .orig_addrs()
-                .iter()
-                .chain(self.resp_addrs().iter())
-                .any(|&ip| filter.contains(ip)),
-            TriageExclusion::Domain(regex_set) => regex_set.is_match(&self.host),
-            TriageExclusion::Hostname(hostnames) => hostnames.contains(&self.host),
-            TriageExclusion::Uri(uris) => uris.contains(&self.uri),
-        });
-        if matched { f64::MIN } else { 0.0 }
+    fn exclusion_targets(&self) -> ExclusionTargets<'_> {
+        ExclusionTargets::http(&self.host, &self.uri, &self.user_agent)
     }
 }
 
@@ -1706,6 +1707,14 @@ impl Match for BlocklistHttp {
         "blocklist http"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::BlocklistHttp
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -1722,18 +1731,8 @@ impl Match for BlocklistHttp {
         find_http_attr_by_kind!(self, raw_event_attr)
     }
 
-    fn score_by_triage_exclusion(&self, triage_exclusion: &[TriageExclusion]) -> f64 {
-        let matched = triage_exclusion.iter().any(|ti| match ti {
-            TriageExclusion::IpAddress(filter) => self
-                .orig_addrs()
-                .iter()
-                .chain(self.resp_addrs().iter())
-                .any(|&ip| filter.contains(ip)),
-            TriageExclusion::Domain(regex_set) => regex_set.is_match(&self.host),
-            TriageExclusion::Hostname(hostnames) => hostnames.contains(&self.host),
-            TriageExclusion::Uri(uris) => uris.contains(&self.uri),
-        });
-        if matched { f64::MIN } else { 0.0 }
+    fn exclusion_targets(&self) -> ExclusionTargets<'_> {
+        ExclusionTargets::http(&self.host, &self.uri, &self.user_agent)
     }
 }
 
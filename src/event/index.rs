@@ -0,0 +1,151 @@
+//! Secondary indexes over the stored events.
+//!
+//! Each index lives in a column family of its own, so a scan of one never
+//! touches the events or another index:
+//!
+//! | Column family | Indexed value |
+//! |---------------|---------------|
+//! | `event originator index` | every originator address of an event |
+//! | `event responder index` | every responder address of an event |
+//! | `event sensor index` | the sensor of an event |
+//!
+//! An entry's key is the encoded value followed by the 16-byte key of the
+//! event, and its value is empty. The entries for one value are therefore
+//! adjacent and ordered by event key, which orders them by time. An address is
+//! encoded as its family (`4` or `6`) followed by its octets, and a sensor as
+//! its length, a big-endian `u32`, followed by its bytes, so that no encoded
+//! value is a prefix of another.
+//!
+//! The unspecified address is not indexed: the events without network
+//! endpoints report it as a placeholder, and an index entry for it would point
+//! at every one of them.
+
+use std::net::IpAddr;
+
+use anyhow::{Context, Result, anyhow};
+
+use super::Event;
+use crate::tables::{EVENT_ORIGINATOR_INDEX, EVENT_RESPONDER_INDEX, EVENT_SENSOR_INDEX};
+
+/// A secondary index over the stored events.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(super) enum Index {
+    Originator,
+    Responder,
+    Sensor,
+}
+
+impl Index {
+    pub(super) const ALL: [Index; 3] = [Index::Originator, Index::Responder, Index::Sensor];
+
+    /// Returns the name of the column family holding the index.
+    pub(super) fn cf_name(self) -> &'static str {
+        match self {
+            Index::Originator => EVENT_ORIGINATOR_INDEX,
+            Index::Responder => EVENT_RESPONDER_INDEX,
+            Index::Sensor => EVENT_SENSOR_INDEX,
+        }
+    }
+}
+
+/// Returns the prefix shared by the address index entries for `addr`.
+pub(super) fn address_prefix(addr: IpAddr) -> Vec<u8> {
+    match addr {
+        IpAddr::V4(addr) => {
+            let mut prefix = Vec::with_capacity(1 + 4 + 16);
+            prefix.push(4);
+            prefix.extend_from_slice(&addr.octets());
+            prefix
+        }
+        IpAddr::V6(addr) => {
+            let mut prefix = Vec::with_capacity(1 + 16 + 16);
+            prefix.push(6);
+            prefix.extend_from_slice(&addr.octets());
+            prefix
+        }
+    }
+}
+
+/// Returns the prefix shared by the sensor index entries for `sensor`.
+///
+/// # Errors
+///
+/// Returns an error if `sensor` is longer than `u32::MAX` bytes.
+pub(super) fn sensor_prefix(sensor: &str) -> Result<Vec<u8>> {
+    let len = u32::try_from(sensor.len()).context("sensor name is too long to index")?;
+    let mut prefix = Vec::with_capacity(4 + sensor.len() + 16);
+    prefix.extend_from_slice(&len.to_be_bytes());
+    prefix.extend_from_slice(sensor.as_bytes());
+    Ok(prefix)
+}
+
+/// Returns every index entry the event stored under `key` owns.
+///
+/// # Errors
+///
+/// Returns an error if the sensor of the event cannot be encoded.
+pub(super) fn entries(key: i128, event: &Event) -> Result<Vec<(Index, Vec<u8>)>> {
+    let event = event.as_match();
+    let key = key.to_be_bytes();
+    let mut entries = Vec::new();
+    for &addr in event.orig_addrs() {
+        if !addr.is_unspecified() {
+            entries.push((Index::Originator, entry(address_prefix(addr), &key)));
+        }
+    }
+    for &addr in event.resp_addrs() {
+        if !addr.is_unspecified() {
+            entries.push((Index::Responder, entry(address_prefix(addr), &key)));
+        }
+    }
+    entries.push((Index::Sensor, entry(sensor_prefix(event.sensor())?, &key)));
+    Ok(entries)
+}
+
+/// Returns the index entry for the event stored under `key` under `prefix`.
+pub(super) fn entry(mut prefix: Vec<u8>, key: &[u8; 16]) -> Vec<u8> {
+    prefix.extend_from_slice(key);
+    prefix
+}
+
+/// Returns the key of the event an index entry points at.
+///
+/// # Errors
+///
+/// Returns an error if `entry` is too short to end with an event key.
+pub(super) fn event_key(entry: &[u8]) -> Result<i128> {
+    let start = entry
+        .len()
+        .checked_sub(16)
+        .ok_or_else(|| anyhow!("event index entry is too short: {} bytes", entry.len()))?;
+    let key: [u8; 16] = entry[start..].try_into().expect("16 bytes remain");
+    Ok(i128::from_be_bytes(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use super::{address_prefix, entry, event_key, sensor_prefix};
+
+    #[test]
+    fn prefixes_do_not_overlap() {
+        let v4 = address_prefix(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+        let v6 = address_prefix(IpAddr::V6(Ipv6Addr::LOCALHOST));
+        assert_eq!(v4.len(), 5);
+        assert_eq!(v6.len(), 17);
+        assert!(!v6.starts_with(&v4));
+
+        let short = sensor_prefix("sensor").unwrap();
+        let long = sensor_prefix("sensor1").unwrap();
+        assert!(!long.starts_with(&short));
+    }
+
+    #[test]
+    fn entry_round_trips_event_key() {
+        let key = (1_700_000_000_i128 << 64) | (5 << 32) | 7;
+        let entry = entry(sensor_prefix("sensor").unwrap(), &key.to_be_bytes());
+        assert_eq!(event_key(&entry).unwrap(), key);
+        assert!(event_key(&[0; 15]).is_err());
+    }
+}
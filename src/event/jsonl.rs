@@ -0,0 +1,265 @@
+//! JSON Lines export and import of the stored events.
+//!
+//! Each line is one JSON object describing one event:
+//!
+//! ```json
+//! {"schema_version":1,"kind":"HttpThreat","time":"2024-01-01T00:00:00Z","fields":{...}}
+//! ```
+//!
+//! `fields` holds every member of the event's `*Fields` type, along with the
+//! country codes resolved for its addresses when it was stored. An exported
+//! line also carries `triage_scores` when the export was filtered and the
+//! filter scored the event. The importer reads `fields` as the `*Fields` type
+//! of `kind`, so the country codes and triage scores are not imported: the
+//! country codes are resolved again when the event is stored, and the triage
+//! scores are computed against the policies of the importing deployment.
+//!
+//! [`JSONL_SCHEMA_VERSION`] is bumped whenever a change to the line format or
+//! to a `*Fields` type makes earlier lines unreadable as they are.
+
+use std::io::Write;
+
+use anyhow::{Context, Result, bail};
+use jiff::Timestamp;
+use serde::{
+    Deserialize, Serialize,
+    de::{DeserializeOwned, IgnoredAny},
+};
+
+use super::{
+    BlocklistBootpFields, BlocklistBootpFieldsStored, BlocklistConnFields,
+    BlocklistConnFieldsStored, BlocklistDceRpcFields, BlocklistDceRpcFieldsStored,
+    BlocklistDhcpFields, BlocklistDhcpFieldsStored, BlocklistDnsFields, BlocklistDnsFieldsStored,
+    BlocklistHttpFields, BlocklistHttpFieldsStored, BlocklistKerberosFields,
+    BlocklistKerberosFieldsStored, BlocklistMalformedDnsFields, BlocklistMalformedDnsFieldsStored,
+    BlocklistMqttFields, BlocklistMqttFieldsStored, BlocklistNfsFields, BlocklistNfsFieldsStored,
+    BlocklistNtlmFields, BlocklistNtlmFieldsStored, BlocklistRadiusFields,
+    BlocklistRadiusFieldsStored, BlocklistRdpFields, BlocklistRdpFieldsStored, BlocklistSmbFields,
+    BlocklistSmbFieldsStored, BlocklistSmtpFields, BlocklistSmtpFieldsStored, BlocklistSshFields,
+    BlocklistSshFieldsStored, BlocklistTlsFields, BlocklistTlsFieldsStored,
+    CryptocurrencyMiningPoolFields, CryptocurrencyMiningPoolFieldsStored, DgaFields,
+    DgaFieldsStored, DnsEventFields, DnsEventFieldsStored, EventKind, EventMessage,
+    ExternalDdosFields, ExternalDdosFieldsStored, ExtraThreatFields, ExtraThreatFieldsStored,
+    FtpBruteForceFields, FtpBruteForceFieldsStored, FtpEventFields, FtpEventFieldsStored,
+    HttpEventFields, HttpEventFieldsStored, HttpThreatFields, HttpThreatFieldsStored,
+    LdapBruteForceFields, LdapBruteForceFieldsStored, LdapEventFields, LdapEventFieldsStored,
+    MultiHostPortScanFields, MultiHostPortScanFieldsStored, NetworkThreatFields,
+    NetworkThreatFieldsStored, PortScanFields, PortScanFieldsStored, RdpBruteForceFields,
+    RdpBruteForceFieldsStored, RepeatedHttpSessionsFields, RepeatedHttpSessionsFieldsStored,
+    TriageScore, UnusualDestinationPatternFields, UnusualDestinationPatternFieldsStored,
+    WindowsThreatFields, WindowsThreatFieldsStored, timestamp,
+};
+
+/// The version of the line format written by [`EventDb::export_jsonl`] and
+/// read by [`EventDb::import_jsonl`].
+///
+/// [`EventDb::export_jsonl`]: super::EventDb::export_jsonl
+/// [`EventDb::import_jsonl`]: super::EventDb::import_jsonl
+pub const JSONL_SCHEMA_VERSION: u32 = 1;
+
+/// An exported line, borrowing its parts.
+#[derive(Serialize)]
+struct LineOut<'a, T> {
+    schema_version: u32,
+    kind: EventKind,
+    time: &'a str,
+    fields: &'a T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    triage_scores: Option<&'a [TriageScore]>,
+}
+
+/// An imported line. `triage_scores`, if present, is ignored.
+#[derive(Deserialize)]
+struct LineIn<T> {
+    schema_version: u32,
+    kind: EventKind,
+    time: String,
+    fields: T,
+}
+
+/// Writes the event of `kind` stored under `key` with `value` as one line.
+///
+/// # Errors
+///
+/// Returns an error if `value` is not a valid stored representation of
+/// `kind`, or if the line cannot be written.
+pub(super) fn write_line<W: Write>(
+    writer: &mut W,
+    kind: EventKind,
+    key: i128,
+    value: &[u8],
+    triage_scores: Option<&[TriageScore]>,
+) -> Result<()> {
+    let nanos = i64::try_from(key >> 64).context("invalid event key")?;
+    let time = timestamp::format_i64_nanos_rfc3339(nanos)?;
+    macro_rules! write_as {
+        ($stored:ty) => {
+            write_fields::<_, $stored>(writer, kind, &time, value, triage_scores)
+        };
+    }
+
+    match kind {
+        EventKind::BlocklistBootp => write_as!(BlocklistBootpFieldsStored),
+        EventKind::BlocklistConn | EventKind::TorConnectionConn => {
+            write_as!(BlocklistConnFieldsStored)
+        }
+        EventKind::BlocklistDceRpc => write_as!(BlocklistDceRpcFieldsStored),
+        EventKind::BlocklistDhcp => write_as!(BlocklistDhcpFieldsStored),
+        EventKind::BlocklistDns => write_as!(BlocklistDnsFieldsStored),
+        EventKind::BlocklistFtp | EventKind::FtpPlainText => write_as!(FtpEventFieldsStored),
+        EventKind::BlocklistHttp => write_as!(BlocklistHttpFieldsStored),
+        EventKind::BlocklistKerberos => write_as!(BlocklistKerberosFieldsStored),
+        EventKind::BlocklistLdap | EventKind::LdapPlainText => write_as!(LdapEventFieldsStored),
+        EventKind::BlocklistMalformedDns => write_as!(BlocklistMalformedDnsFieldsStored),
+        EventKind::BlocklistMqtt => write_as!(BlocklistMqttFieldsStored),
+        EventKind::BlocklistNfs => write_as!(BlocklistNfsFieldsStored),
+        EventKind::BlocklistNtlm => write_as!(BlocklistNtlmFieldsStored),
+        EventKind::BlocklistRadius => write_as!(BlocklistRadiusFieldsStored),
+        EventKind::BlocklistRdp => write_as!(BlocklistRdpFieldsStored),
+        EventKind::BlocklistSmb => write_as!(BlocklistSmbFieldsStored),
+        EventKind::BlocklistSmtp => write_as!(BlocklistSmtpFieldsStored),
+        EventKind::BlocklistSsh => write_as!(BlocklistSshFieldsStored),
+        EventKind::BlocklistTls | EventKind::SuspiciousTlsTraffic => {
+            write_as!(BlocklistTlsFieldsStored)
+        }
+        EventKind::CryptocurrencyMiningPool => write_as!(CryptocurrencyMiningPoolFieldsStored),
+        EventKind::DnsCovertChannel | EventKind::LockyRansomware => write_as!(DnsEventFieldsStored),
+        EventKind::DomainGenerationAlgorithm => write_as!(DgaFieldsStored),
+        EventKind::ExternalDdos => write_as!(ExternalDdosFieldsStored),
+        EventKind::FtpBruteForce => write_as!(FtpBruteForceFieldsStored),
+        EventKind::HttpThreat => write_as!(HttpThreatFieldsStored),
+        EventKind::LdapBruteForce => write_as!(LdapBruteForceFieldsStored),
+        EventKind::MultiHostPortScan => write_as!(MultiHostPortScanFieldsStored),
+        EventKind::NonBrowser | EventKind::TorConnection => write_as!(HttpEventFieldsStored),
+        EventKind::PortScan => write_as!(PortScanFieldsStored),
+        EventKind::RdpBruteForce => write_as!(RdpBruteForceFieldsStored),
+        EventKind::RepeatedHttpSessions => write_as!(RepeatedHttpSessionsFieldsStored),
+        EventKind::UnusualDestinationPattern => write_as!(UnusualDestinationPatternFieldsStored),
+        EventKind::ExtraThreat => write_as!(ExtraThreatFieldsStored),
+        EventKind::NetworkThreat => write_as!(NetworkThreatFieldsStored),
+        EventKind::WindowsThreat => write_as!(WindowsThreatFieldsStored),
+    }
+}
+
+fn write_fields<W: Write, S: DeserializeOwned + Serialize>(
+    writer: &mut W,
+    kind: EventKind,
+    time: &str,
+    value: &[u8],
+    triage_scores: Option<&[TriageScore]>,
+) -> Result<()> {
+    let fields: S = bincode::deserialize(value).context("invalid stored event fields")?;
+    let line = LineOut {
+        schema_version: JSONL_SCHEMA_VERSION,
+        kind,
+        time,
+        fields: &fields,
+        triage_scores,
+    };
+    serde_json::to_writer(&mut *writer, &line).context("cannot write event")?;
+    writer.write_all(b"\n").context("cannot write event")?;
+    Ok(())
+}
+
+/// Parses one line into the message to store the event with.
+///
+/// # Errors
+///
+/// Returns an error if the line is not valid JSON, if its schema version is
+/// not [`JSONL_SCHEMA_VERSION`], if its time is not an RFC 3339 timestamp
+/// within the range of an event key, or if its fields do not match its kind.
+pub(super) fn parse_line(line: &str) -> Result<EventMessage> {
+    let header: LineIn<IgnoredAny> = serde_json::from_str(line).context("invalid event line")?;
+    if header.schema_version != JSONL_SCHEMA_VERSION {
+        bail!(
+            "unsupported schema version {} (expected {JSONL_SCHEMA_VERSION})",
+            header.schema_version
+        );
+    }
+    let time: Timestamp = header
+        .time
+        .parse()
+        .with_context(|| format!("invalid event time: {}", header.time))?;
+    timestamp::to_i64_nanos(time)?;
+
+    let kind = header.kind;
+    let fields = match kind {
+        EventKind::BlocklistBootp => read_fields::<BlocklistBootpFields>(line),
+        EventKind::BlocklistConn | EventKind::TorConnectionConn => {
+            read_fields::<BlocklistConnFields>(line)
+        }
+        EventKind::BlocklistDceRpc => read_fields::<BlocklistDceRpcFields>(line),
+        EventKind::BlocklistDhcp => read_fields::<BlocklistDhcpFields>(line),
+        EventKind::BlocklistDns => read_fields::<BlocklistDnsFields>(line),
+        EventKind::BlocklistFtp | EventKind::FtpPlainText => read_fields::<FtpEventFields>(line),
+        EventKind::BlocklistHttp => read_fields::<BlocklistHttpFields>(line),
+        EventKind::BlocklistKerberos => read_fields::<BlocklistKerberosFields>(line),
+        EventKind::BlocklistLdap | EventKind::LdapPlainText => read_fields::<LdapEventFields>(line),
+        EventKind::BlocklistMalformedDns => read_fields::<BlocklistMalformedDnsFields>(line),
+        EventKind::BlocklistMqtt => read_fields::<BlocklistMqttFields>(line),
+        EventKind::BlocklistNfs => read_fields::<BlocklistNfsFields>(line),
+        EventKind::BlocklistNtlm => read_fields::<BlocklistNtlmFields>(line),
+        EventKind::BlocklistRadius => read_fields::<BlocklistRadiusFields>(line),
+        EventKind::BlocklistRdp => read_fields::<BlocklistRdpFields>(line),
+        EventKind::BlocklistSmb => read_fields::<BlocklistSmbFields>(line),
+        EventKind::BlocklistSmtp => read_fields::<BlocklistSmtpFields>(line),
+        EventKind::BlocklistSsh => read_fields::<BlocklistSshFields>(line),
+        EventKind::BlocklistTls | EventKind::SuspiciousTlsTraffic => {
+            read_fields::<BlocklistTlsFields>(line)
+        }
+        EventKind::CryptocurrencyMiningPool => read_fields::<CryptocurrencyMiningPoolFields>(line),
+        EventKind::DnsCovertChannel | EventKind::LockyRansomware => {
+            read_fields::<DnsEventFields>(line)
+        }
+        EventKind::DomainGenerationAlgorithm => read_fields::<DgaFields>(line),
+        EventKind::ExternalDdos => read_fields::<ExternalDdosFields>(line),
+        EventKind::FtpBruteForce => read_fields::<FtpBruteForceFields>(line),
+        EventKind::HttpThreat => read_fields::<HttpThreatFields>(line),
+        EventKind::LdapBruteForce => read_fields::<LdapBruteForceFields>(line),
+        EventKind::MultiHostPortScan => read_fields::<MultiHostPortScanFields>(line),
+        EventKind::NonBrowser | EventKind::TorConnection => read_fields::<HttpEventFields>(line),
+        EventKind::PortScan => read_fields::<PortScanFields>(line),
+        EventKind::RdpBruteForce => read_fields::<RdpBruteForceFields>(line),
+        EventKind::RepeatedHttpSessions => read_fields::<RepeatedHttpSessionsFields>(line),
+        EventKind::UnusualDestinationPattern => {
+            read_fields::<UnusualDestinationPatternFields>(line)
+        }
+        EventKind::ExtraThreat => read_fields::<ExtraThreatFields>(line),
+        EventKind::NetworkThreat => read_fields::<NetworkThreatFields>(line),
+        EventKind::WindowsThreat => read_fields::<WindowsThreatFields>(line),
+    }
+    .with_context(|| format!("invalid fields for {kind:?}"))?;
+
+    Ok(EventMessage { time, kind, fields })
+}
+
+/// Reads the `fields` of `line` as `F` and encodes them the way producers
+/// send them.
+fn read_fields<F: DeserializeOwned + Serialize>(line: &str) -> Result<Vec<u8>> {
+    let line: LineIn<F> = serde_json::from_str(line)?;
+    Ok(bincode::serialize(&line.fields)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JSONL_SCHEMA_VERSION, parse_line};
+
+    fn line(version: u32, kind: &str, time: &str) -> String {
+        format!(r#"{{"schema_version":{version},"kind":"{kind}","time":"{time}","fields":{{}}}}"#)
+    }
+
+    #[test]
+    fn rejects_unsupported_lines() {
+        const TIME: &str = "2024-01-01T00:00:00Z";
+        const VERSION: u32 = JSONL_SCHEMA_VERSION;
+
+        let err = parse_line(&line(VERSION + 1, "HttpThreat", TIME)).unwrap_err();
+        assert!(err.to_string().contains("schema version"));
+        assert!(parse_line(&line(VERSION, "NoSuchKind", TIME)).is_err());
+        assert!(parse_line(&line(VERSION, "HttpThreat", "yesterday")).is_err());
+        assert!(parse_line(&line(VERSION, "HttpThreat", "2300-01-01T00:00:00Z")).is_err());
+        let err = parse_line(&line(VERSION, "HttpThreat", TIME)).unwrap_err();
+        assert!(err.to_string().contains("HttpThreat"));
+        assert!(parse_line("not json").is_err());
+    }
+}
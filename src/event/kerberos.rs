@@ -5,7 +5,7 @@ use jiff::Timestamp;
 use serde::{Deserialize, Serialize};
 
 use super::timestamp;
-use super::{EventCategory, LearningMethod, ThreatLevel, TriageScore, common::Match};
+use super::{EventCategory, EventKind, LearningMethod, ThreatLevel, TriageScore, common::Match};
 use crate::event::common::{AttrValue, triage_scores_to_string};
 
 macro_rules! find_kerberos_attr_by_kind {
@@ -317,6 +317,14 @@ impl Match for BlocklistKerberos {
         "blocklist kerberos"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::BlocklistKerberos
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -6,7 +6,7 @@ use jiff::Timestamp;
 use serde::{Deserialize, Serialize};
 
 use super::timestamp::{self, ts_nanoseconds as jiff_ts_nanoseconds};
-use super::{EventCategory, LearningMethod, ThreatLevel, TriageScore, common::Match};
+use super::{EventCategory, EventKind, LearningMethod, ThreatLevel, TriageScore, common::Match};
 use crate::event::common::{AttrValue, triage_scores_to_string};
 
 macro_rules! find_ldap_attr_by_kind {
@@ -250,6 +250,14 @@ impl Match for LdapBruteForce {
         "ldap brute force"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::LdapBruteForce
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -539,6 +547,14 @@ impl Match for LdapPlainText {
         "ldap plain text"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::LdapPlainText
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -699,6 +715,14 @@ impl Match for BlocklistLdap {
         "blocklist ldap"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::BlocklistLdap
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -0,0 +1,577 @@
+//! Mappings of events into the Open Cybersecurity Schema Framework (OCSF) and
+//! the Elastic Common Schema (ECS).
+//!
+//! Both mappings fill the attributes their schema defines from the fields an
+//! event shares with others, such as its endpoints, and from the fields of the
+//! protocol it was detected in, such as the query of a DNS event or the
+//! request of an HTTP one. Every field of the event is also kept as it is,
+//! under `unmapped` in OCSF and under `review` in ECS, so that nothing is lost
+//! to a consumer that knows the event kinds of this crate.
+
+use std::net::IpAddr;
+
+use anyhow::{Context, Result};
+use jiff::Timestamp;
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+
+use super::{Event, EventCategory, EventKind, RecordType, ThreatLevel, siem::severity, timestamp};
+use crate::util::{COUNTRY_CODE_INVALID, COUNTRY_CODE_PENDING, country_code_as_str};
+
+/// The OCSF version the mapping follows.
+const OCSF_VERSION: &str = "1.1.0";
+
+/// The ECS version the mapping follows.
+const ECS_VERSION: &str = "8.11.0";
+
+/// An OCSF event class an event can be mapped into.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OcsfClass {
+    /// Network Activity (4001), describing the traffic the event was detected
+    /// in.
+    NetworkActivity,
+    /// Detection Finding (2004), describing the detection itself, with the
+    /// traffic as its evidence.
+    DetectionFinding,
+}
+
+impl Event {
+    /// Maps the event into `class` of OCSF, as a JSON object.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the fields of the event cannot be converted to
+    /// JSON.
+    pub fn to_ocsf(&self, class: OcsfClass) -> Result<Value> {
+        let mapping = Mapping::new(self)?;
+        let (severity_id, severity) = ocsf_severity(mapping.level);
+        let mut object = json!({
+            "time": mapping.millis,
+            "severity_id": severity_id,
+            "severity": severity,
+            "metadata": {
+                "version": OCSF_VERSION,
+                "product": { "name": "review-database" },
+                "event_code": format!("{:?}", mapping.kind_and_category.0),
+            },
+        });
+        let evidence = mapping.ocsf_network();
+        match class {
+            OcsfClass::NetworkActivity => {
+                merge(
+                    &mut object,
+                    json!({
+                        "class_uid": 4001,
+                        "class_name": "Network Activity",
+                        "category_uid": 4,
+                        "category_name": "Network Activity",
+                        "activity_id": 6,
+                        "activity_name": "Traffic",
+                        "type_uid": 400_106,
+                    }),
+                );
+                merge(&mut object, evidence);
+            }
+            OcsfClass::DetectionFinding => {
+                let attacks: Vec<Value> = mapping
+                    .categories()
+                    .map(|category| {
+                        let (uid, name) = tactic(category);
+                        json!({ "tactic": { "uid": uid, "name": name } })
+                    })
+                    .collect();
+                merge(
+                    &mut object,
+                    json!({
+                        "class_uid": 2004,
+                        "class_name": "Detection Finding",
+                        "category_uid": 2,
+                        "category_name": "Findings",
+                        "activity_id": 1,
+                        "activity_name": "Create",
+                        "type_uid": 200_401,
+                        "finding_info": {
+                            "title": mapping.name,
+                            "types": [format!("{:?}", mapping.kind_and_category.0)],
+                            "attacks": attacks,
+                        },
+                        "evidences": [evidence],
+                    }),
+                );
+                if let Some(confidence) = mapping.confidence {
+                    object["confidence_score"] = json!(confidence_percent(confidence));
+                }
+            }
+        }
+        object["unmapped"] = Value::Object(mapping.fields);
+        Ok(object)
+    }
+
+    /// Maps the event into ECS fields, as a JSON object.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the fields of the event cannot be converted to
+    /// JSON.
+    pub fn to_ecs(&self) -> Result<Value> {
+        let mapping = Mapping::new(self)?;
+        let (kind, category) = mapping.kind_and_category;
+        let tactics: Vec<Value> = mapping
+            .categories()
+            .map(|category| {
+                let (id, name) = tactic(category);
+                json!({ "id": id, "name": name })
+            })
+            .collect();
+        let event_category = if mapping.has_endpoints() {
+            "network"
+        } else {
+            "intrusion_detection"
+        };
+        let mut object = json!({
+            "@timestamp": timestamp::format_rfc3339(mapping.time)?,
+            "ecs": { "version": ECS_VERSION },
+            "event": {
+                "kind": "alert",
+                "category": [event_category],
+                "type": ["info"],
+                "code": format!("{kind:?}"),
+                "action": mapping.name,
+                "severity": severity(mapping.level),
+            },
+            "observer": { "hostname": mapping.sensor },
+            "threat": {
+                "framework": "MITRE ATT&CK",
+                "tactic": {
+                    "id": tactics.iter().map(|t| t["id"].clone()).collect::<Vec<_>>(),
+                    "name": tactics.iter().map(|t| t["name"].clone()).collect::<Vec<_>>(),
+                },
+            },
+        });
+        if let Some(category) = category {
+            object["rule"] = json!({ "category": category.to_string() });
+        }
+        if let Some(confidence) = mapping.confidence {
+            object["event"]["risk_score"] = json!(confidence_percent(confidence));
+        }
+        merge(&mut object, mapping.ecs_network());
+        merge(&mut object, mapping.ecs_protocol());
+        object["review"] = Value::Object(mapping.fields);
+        Ok(object)
+    }
+}
+
+/// The parts of an event both mappings are built from.
+struct Mapping<'a> {
+    kind_and_category: (EventKind, Option<EventCategory>),
+    time: Timestamp,
+    millis: i64,
+    name: &'static str,
+    level: ThreatLevel,
+    confidence: Option<f32>,
+    sensor: &'a str,
+    orig_addrs: &'a [IpAddr],
+    orig_port: u16,
+    orig_country_codes: &'a [[u8; 2]],
+    resp_addrs: &'a [IpAddr],
+    resp_port: u16,
+    resp_country_codes: &'a [[u8; 2]],
+    proto: u8,
+    fields: Map<String, Value>,
+}
+
+impl<'a> Mapping<'a> {
+    fn new(event: &'a Event) -> Result<Self> {
+        let event_match = event.as_match();
+        let time = event.time();
+        Ok(Self {
+            kind_and_category: event.kind_and_category(),
+            time,
+            millis: timestamp::to_i64_nanos(time)? / 1_000_000,
+            name: event.kind_name(),
+            level: event_match.level(),
+            confidence: event_match.confidence(),
+            sensor: event_match.sensor(),
+            orig_addrs: event_match.orig_addrs(),
+            orig_port: event_match.orig_port(),
+            orig_country_codes: event_match.orig_country_codes(),
+            resp_addrs: event_match.resp_addrs(),
+            resp_port: event_match.resp_port(),
+            resp_country_codes: event_match.resp_country_codes(),
+            proto: event_match.proto(),
+            fields: fields(event)?,
+        })
+    }
+
+    fn categories(&self) -> impl Iterator<Item = EventCategory> {
+        self.kind_and_category.0.categories().iter().copied()
+    }
+
+    fn has_endpoints(&self) -> bool {
+        self.orig_addrs
+            .iter()
+            .chain(self.resp_addrs)
+            .any(|addr| !addr.is_unspecified())
+    }
+
+    fn field(&self, name: &str) -> Option<&Value> {
+        self.fields
+            .get(name)
+            .filter(|value| !value.is_null() && *value != "")
+    }
+
+    /// Returns the number of bytes sent by one side, preferring the payload
+    /// count to the layer-2 one.
+    fn bytes(&self, side: &str) -> Option<&Value> {
+        self.field(&format!("{side}_bytes"))
+            .or_else(|| self.field(&format!("{side}_l2_bytes")))
+    }
+
+    fn ocsf_network(&self) -> Value {
+        let mut object = json!({ "device": { "hostname": self.sensor } });
+        if let Some(endpoint) =
+            ocsf_endpoint(self.orig_addrs, self.orig_port, self.orig_country_codes)
+        {
+            object["src_endpoint"] = endpoint;
+        }
+        if let Some(endpoint) =
+            ocsf_endpoint(self.resp_addrs, self.resp_port, self.resp_country_codes)
+        {
+            object["dst_endpoint"] = endpoint;
+        }
+        if self.has_endpoints() {
+            object["connection_info"] = json!({
+                "protocol_num": self.proto,
+                "protocol_name": protocol_name(self.proto),
+            });
+            let mut traffic = Map::new();
+            for (key, value) in [
+                ("bytes_out", self.bytes("orig")),
+                ("bytes_in", self.bytes("resp")),
+                ("packets_out", self.field("orig_pkts")),
+                ("packets_in", self.field("resp_pkts")),
+            ] {
+                if let Some(value) = value {
+                    traffic.insert(key.to_string(), value.clone());
+                }
+            }
+            if !traffic.is_empty() {
+                object["traffic"] = Value::Object(traffic);
+            }
+        }
+        if let Some(query) = self.field("query") {
+            object["query"] = json!({ "hostname": query });
+            if let Some(answers) = self.field("answer").and_then(Value::as_array) {
+                object["answers"] = answers
+                    .iter()
+                    .map(|rdata| json!({ "rdata": rdata }))
+                    .collect();
+            }
+        }
+        if self.field("method").is_some() || self.field("uri").is_some() {
+            object["http_request"] = compact(json!({
+                "http_method": self.field("method"),
+                "url": compact(json!({
+                    "hostname": self.field("host"),
+                    "path": self.field("uri"),
+                })),
+                "user_agent": self.field("user_agent"),
+                "referrer": self.field("referer"),
+            }));
+            if let Some(code) = self.field("status_code") {
+                object["http_response"] = json!({ "code": code });
+            }
+        }
+        if self.field("ja3").is_some() || self.field("server_name").is_some() {
+            object["tls"] = compact(json!({
+                "sni": self.field("server_name"),
+                "version": self.field("version"),
+                "ja3_hash": self.field("ja3").map(|ja3| json!({ "value": ja3 })),
+                "ja3s_hash": self.field("ja3s").map(|ja3s| json!({ "value": ja3s })),
+            }));
+        }
+        if let Some(file_name) = self.field("file_name") {
+            object["file"] = compact(json!({
+                "name": file_name,
+                "path": self.field("path"),
+                "size": self.field("file_size"),
+            }));
+        }
+        object
+    }
+
+    fn ecs_network(&self) -> Value {
+        let mut object = json!({});
+        if let Some(source) = ecs_endpoint(self.orig_addrs, self.orig_port, self.orig_country_codes)
+        {
+            object["source"] = source;
+        }
+        if let Some(destination) =
+            ecs_endpoint(self.resp_addrs, self.resp_port, self.resp_country_codes)
+        {
+            object["destination"] = destination;
+        }
+        for (side, target) in [("orig", "source"), ("resp", "destination")] {
+            if object.get(target).is_none() {
+                continue;
+            }
+            if let Some(bytes) = self.bytes(side) {
+                object[target]["bytes"] = bytes.clone();
+            }
+            if let Some(packets) = self.field(&format!("{side}_pkts")) {
+                object[target]["packets"] = packets.clone();
+            }
+        }
+        if self.has_endpoints() {
+            object["network"] = json!({
+                "iana_number": self.proto.to_string(),
+                "transport": protocol_name(self.proto).to_lowercase(),
+            });
+            let related: Vec<String> = self
+                .orig_addrs
+                .iter()
+                .chain(self.resp_addrs)
+                .filter(|addr| !addr.is_unspecified())
+                .map(ToString::to_string)
+                .collect();
+            object["related"] = json!({ "ip": related });
+        }
+        object
+    }
+
+    fn ecs_protocol(&self) -> Value {
+        let mut object = json!({});
+        if let Some(query) = self.field("query") {
+            object["dns"] = compact(json!({
+                "question": { "name": query },
+                "id": self.field("trans_id"),
+                "answers": self
+                    .field("answer")
+                    .and_then(Value::as_array)
+                    .map(|answers| answers.iter().map(|data| json!({ "data": data })).collect::<Vec<_>>()),
+            }));
+        }
+        if self.field("method").is_some() || self.field("uri").is_some() {
+            object["url"] = compact(json!({
+                "domain": self.field("host"),
+                "original": self.field("uri"),
+            }));
+            object["http"] = compact(json!({
+                "request": compact(json!({
+                    "method": self.field("method"),
+                    "referrer": self.field("referer"),
+                })),
+                "response": compact(json!({ "status_code": self.field("status_code") })),
+            }));
+            if let Some(user_agent) = self.field("user_agent") {
+                object["user_agent"] = json!({ "original": user_agent });
+            }
+        }
+        if self.field("ja3").is_some() || self.field("server_name").is_some() {
+            object["tls"] = compact(json!({
+                "client": compact(json!({
+                    "server_name": self.field("server_name"),
+                    "ja3": self.field("ja3"),
+                })),
+                "server": compact(json!({
+                    "ja3s": self.field("ja3s"),
+                    "issuer": self.field("issuer_common_name"),
+                    "subject": self.field("subject_common_name"),
+                })),
+            }));
+        }
+        if let Some(file_name) = self.field("file_name") {
+            object["file"] = compact(json!({
+                "name": file_name,
+                "path": self.field("path"),
+                "size": self.field("file_size"),
+            }));
+        }
+        object
+    }
+}
+
+/// Returns the OCSF severity ID and name of `level`.
+fn ocsf_severity(level: ThreatLevel) -> (u8, &'static str) {
+    match level {
+        ThreatLevel::VeryLow => (1, "Informational"),
+        ThreatLevel::Low => (2, "Low"),
+        ThreatLevel::Medium => (3, "Medium"),
+        ThreatLevel::High => (4, "High"),
+        ThreatLevel::VeryHigh => (5, "Critical"),
+    }
+}
+
+/// Returns the ID and name of the MITRE ATT&CK tactic `category` stands for.
+pub(super) fn tactic(category: EventCategory) -> (&'static str, &'static str) {
+    match category {
+        EventCategory::Reconnaissance => ("TA0043", "Reconnaissance"),
+        EventCategory::ResourceDevelopment => ("TA0042", "Resource Development"),
+        EventCategory::InitialAccess => ("TA0001", "Initial Access"),
+        EventCategory::Execution => ("TA0002", "Execution"),
+        EventCategory::Persistence => ("TA0003", "Persistence"),
+        EventCategory::PrivilegeEscalation => ("TA0004", "Privilege Escalation"),
+        EventCategory::DefenseEvasion => ("TA0005", "Defense Evasion"),
+        EventCategory::CredentialAccess => ("TA0006", "Credential Access"),
+        EventCategory::Discovery => ("TA0007", "Discovery"),
+        EventCategory::LateralMovement => ("TA0008", "Lateral Movement"),
+        EventCategory::Collection => ("TA0009", "Collection"),
+        EventCategory::Exfiltration => ("TA0010", "Exfiltration"),
+        EventCategory::CommandAndControl => ("TA0011", "Command and Control"),
+        EventCategory::Impact => ("TA0040", "Impact"),
+    }
+}
+
+pub(super) fn protocol_name(proto: u8) -> String {
+    match proto {
+        1 => "ICMP".to_string(),
+        6 => "TCP".to_string(),
+        17 => "UDP".to_string(),
+        _ => proto.to_string(),
+    }
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // bounded to 0..=100
+pub(super) fn confidence_percent(confidence: f32) -> u8 {
+    (confidence.clamp(0.0, 1.0) * 100.0).round() as u8
+}
+
+/// Returns the endpoint of one side in OCSF, or `None` if the side has no
+/// address. A side with several addresses is described by its first one.
+fn ocsf_endpoint(addrs: &[IpAddr], port: u16, country_codes: &[[u8; 2]]) -> Option<Value> {
+    let addr = addrs.iter().find(|addr| !addr.is_unspecified())?;
+    let mut endpoint = json!({ "ip": addr.to_string(), "port": port });
+    if let Some(country) = known_country(country_codes) {
+        endpoint["location"] = json!({ "country": country });
+    }
+    Some(endpoint)
+}
+
+/// Returns the endpoint of one side in ECS, or `None` if the side has no
+/// address. A side with several addresses is described by its first one;
+/// `related.ip` lists them all.
+fn ecs_endpoint(addrs: &[IpAddr], port: u16, country_codes: &[[u8; 2]]) -> Option<Value> {
+    let addr = addrs.iter().find(|addr| !addr.is_unspecified())?;
+    let mut endpoint = json!({ "ip": addr.to_string(), "port": port });
+    if let Some(country) = known_country(country_codes) {
+        endpoint["geo"] = json!({ "country_iso_code": country });
+    }
+    Some(endpoint)
+}
+
+/// Returns the first country code that was resolved to a country.
+fn known_country(country_codes: &[[u8; 2]]) -> Option<&str> {
+    country_codes
+        .iter()
+        .find(|&&code| code != COUNTRY_CODE_PENDING && code != COUNTRY_CODE_INVALID)
+        .map(country_code_as_str)
+}
+
+/// Adds the members of `other` to `object`.
+fn merge(object: &mut Value, other: Value) {
+    if let (Value::Object(object), Value::Object(other)) = (object, other) {
+        object.extend(other);
+    }
+}
+
+/// Removes the null and empty members of an object.
+fn compact(mut value: Value) -> Value {
+    if let Value::Object(object) = &mut value {
+        object.retain(|_, member| match member {
+            Value::Null => false,
+            Value::Object(inner) => !inner.is_empty(),
+            _ => true,
+        });
+    }
+    value
+}
+
+/// Returns the fields of `event` as a JSON object with a member per field.
+pub(super) fn fields(event: &Event) -> Result<Map<String, Value>> {
+    fn to_object<T: Serialize>(event: &T) -> Result<Map<String, Value>> {
+        match serde_json::to_value(event).context("failed to convert event fields to JSON")? {
+            Value::Object(object) => Ok(object),
+            _ => anyhow::bail!("event fields are not a struct"),
+        }
+    }
+
+    match event {
+        Event::DnsCovertChannel(event) => to_object(event),
+        Event::HttpThreat(event) => to_object(event),
+        Event::RdpBruteForce(event) => to_object(event),
+        Event::RepeatedHttpSessions(event) => to_object(event),
+        Event::TorConnection(event) => to_object(event),
+        Event::TorConnectionConn(event) => to_object(event),
+        Event::DomainGenerationAlgorithm(event) => to_object(event),
+        Event::FtpBruteForce(event) => to_object(event),
+        Event::FtpPlainText(event) => to_object(event),
+        Event::PortScan(event) => to_object(event),
+        Event::MultiHostPortScan(event) => to_object(event),
+        Event::ExternalDdos(event) => to_object(event),
+        Event::NonBrowser(event) => to_object(event),
+        Event::LdapBruteForce(event) => to_object(event),
+        Event::LdapPlainText(event) => to_object(event),
+        Event::CryptocurrencyMiningPool(event) => to_object(event),
+        Event::Blocklist(RecordType::Conn(event)) => to_object(event),
+        Event::Blocklist(RecordType::Dns(event)) => to_object(event),
+        Event::Blocklist(RecordType::DceRpc(event)) => {
+            // A syntax UUID does not fit in a JSON number, so the contexts are
+            // converted on their own.
+            let json =
+                serde_json::to_string(event).context("failed to convert event fields to JSON")?;
+            let mut object: Map<String, Value> = serde_json::from_str(&json)?;
+            object.insert(
+                "context".to_string(),
+                event
+                    .context
+                    .iter()
+                    .map(super::DceRpcContext::to_json)
+                    .collect(),
+            );
+            Ok(object)
+        }
+        Event::Blocklist(RecordType::Ftp(event)) => to_object(event),
+        Event::Blocklist(RecordType::Http(event)) => to_object(event),
+        Event::Blocklist(RecordType::Kerberos(event)) => to_object(event),
+        Event::Blocklist(RecordType::Ldap(event)) => to_object(event),
+        Event::Blocklist(RecordType::MalformedDns(event)) => to_object(event),
+        Event::Blocklist(RecordType::Mqtt(event)) => to_object(event),
+        Event::Blocklist(RecordType::Nfs(event)) => to_object(event),
+        Event::Blocklist(RecordType::Ntlm(event)) => to_object(event),
+        Event::Blocklist(RecordType::Radius(event)) => to_object(event),
+        Event::Blocklist(RecordType::Rdp(event)) => to_object(event),
+        Event::Blocklist(RecordType::Smb(event)) => to_object(event),
+        Event::Blocklist(RecordType::Smtp(event)) => to_object(event),
+        Event::Blocklist(RecordType::Ssh(event)) => to_object(event),
+        Event::Blocklist(RecordType::Tls(event)) => to_object(event),
+        Event::Blocklist(RecordType::Bootp(event)) => to_object(event),
+        Event::Blocklist(RecordType::Dhcp(event)) => to_object(event),
+        Event::Blocklist(RecordType::UnusualDestinationPattern(event)) => to_object(event),
+        Event::WindowsThreat(event) => to_object(event),
+        Event::NetworkThreat(event) => to_object(event),
+        Event::ExtraThreat(event) => to_object(event),
+        Event::LockyRansomware(event) => to_object(event),
+        Event::SuspiciousTlsTraffic(event) => to_object(event),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{EventCategory, compact, confidence_percent, tactic};
+
+    #[test]
+    fn helpers() {
+        assert_eq!(
+            tactic(EventCategory::CommandAndControl),
+            ("TA0011", "Command and Control")
+        );
+        assert_eq!(confidence_percent(0.834), 83);
+        assert_eq!(confidence_percent(1.5), 100);
+        assert_eq!(
+            compact(json!({ "a": null, "b": {}, "c": "", "d": 1 })),
+            json!({ "c": "", "d": 1 })
+        );
+    }
+}
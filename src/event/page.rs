@@ -0,0 +1,115 @@
+//! Cursor-based pagination over the stored events.
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{Context, Result, anyhow};
+use data_encoding::BASE64URL_NOPAD;
+use rocksdb::Direction;
+
+/// A position in the stored events, returned with a [`Page`] and passed back
+/// in a [`PageRequest`] to continue from it.
+///
+/// A cursor names the event it was taken from rather than an offset, so it
+/// stays valid while events are added or removed: a page requested from it
+/// starts right after that event's place, even if the event itself is gone.
+/// Its string form is the only representation meant for clients to hold.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Cursor {
+    key: i128,
+}
+
+impl Cursor {
+    pub(super) fn new(key: i128) -> Self {
+        Self { key }
+    }
+
+    pub(super) fn key(self) -> i128 {
+        self.key
+    }
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&BASE64URL_NOPAD.encode(&self.key.to_be_bytes()))
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = BASE64URL_NOPAD
+            .decode(s.as_bytes())
+            .context("invalid cursor")?;
+        let key: [u8; 16] = bytes
+            .try_into()
+            .map_err(|_| anyhow!("invalid cursor: wrong length"))?;
+        Ok(Self::new(i128::from_be_bytes(key)))
+    }
+}
+
+/// A request for one page of events.
+#[derive(Clone, Copy)]
+pub struct PageRequest {
+    /// The cursor the page continues from, exclusive. `None` starts from the
+    /// oldest event when going forward, and from the newest going in reverse.
+    pub cursor: Option<Cursor>,
+    /// `Forward` pages from older to newer events, and `Reverse` from newer to
+    /// older ones.
+    pub direction: Direction,
+    /// The maximum number of events in the page.
+    pub size: usize,
+}
+
+impl PageRequest {
+    /// Creates a request for the first `size` events in `direction`.
+    #[must_use]
+    pub fn first(size: usize, direction: Direction) -> Self {
+        Self {
+            cursor: None,
+            direction,
+            size,
+        }
+    }
+
+    /// Creates a request for the `size` events after `cursor` in `direction`.
+    #[must_use]
+    pub fn after(cursor: Cursor, size: usize, direction: Direction) -> Self {
+        Self {
+            cursor: Some(cursor),
+            direction,
+            size,
+        }
+    }
+}
+
+/// One page of events, in the direction they were requested in.
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// The cursor of the first item, to page back from in the opposite
+    /// direction. `None` if the page is empty.
+    pub start_cursor: Option<Cursor>,
+    /// The cursor of the last item, to request the next page from in the same
+    /// direction. `None` if the page is empty.
+    pub end_cursor: Option<Cursor>,
+    /// Whether a further event matched beyond the last item when the page was
+    /// read.
+    pub has_more: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cursor;
+
+    #[test]
+    fn cursor_round_trips_through_string() {
+        for key in [0, 1, -1, i128::MAX, i128::MIN, (1_700_000_000 << 64) | 42] {
+            let cursor = Cursor::new(key);
+            assert_eq!(cursor.to_string().parse::<Cursor>().unwrap(), cursor);
+        }
+        assert!("".parse::<Cursor>().is_err());
+        assert!("not a cursor".parse::<Cursor>().is_err());
+        assert!("AAAA".parse::<Cursor>().is_err());
+    }
+}
@@ -0,0 +1,206 @@
+//! Previews of what a triage policy would do to the stored events.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use num_traits::ToPrimitive;
+
+use super::{CompiledTriagePolicy, Event};
+use crate::TriagePolicyInput;
+
+/// The width of the score ranges [`ScoreDistribution::buckets`] counts the
+/// scored events in.
+pub const SCORE_BUCKET_WIDTH: f64 = 0.1;
+
+/// [`SCORE_BUCKET_WIDTH`] in hundredths. Scores are bucketed by their
+/// hundredths, as dividing by the width in floating point puts a score on the
+/// boundary of a range, such as 0.3, in the range below it.
+const SCORE_BUCKET_HUNDREDTHS: i64 = 10;
+
+/// What [`EventDb::preview_triage_policy`] found a candidate policy would do,
+/// compared with the stored version of the policy.
+///
+/// [`EventDb::preview_triage_policy`]: super::EventDb::preview_triage_policy
+#[derive(Debug, Default)]
+pub struct PolicyImpactPreview {
+    /// The number of events that matched the filter.
+    pub matched: u64,
+    pub candidate: PolicyImpact,
+    /// What the stored version does, or `None` if none was given.
+    pub current: Option<PolicyImpact>,
+    /// The number of events the candidate scores and the stored version does
+    /// not.
+    pub newly_scored: u64,
+    /// The number of events the stored version scores and the candidate does
+    /// not.
+    pub no_longer_scored: u64,
+}
+
+/// What a policy does to the events that matched the filter.
+#[derive(Debug, Default)]
+pub struct PolicyImpact {
+    /// The number of events whose score reaches at least one response
+    /// threshold of the policy.
+    pub scored: u64,
+    pub distribution: ScoreDistribution,
+    /// The number of scored events reaching the `minimum_score` of each
+    /// response, in the order of the policy's `response`.
+    pub responses_fired: Vec<u64>,
+    /// The keys of the first scored events, oldest first.
+    pub sample: Vec<i128>,
+}
+
+/// The scores of the scored events.
+#[derive(Debug, Default)]
+pub struct ScoreDistribution {
+    /// The lowest score, or `None` if no event was scored.
+    pub min: Option<f64>,
+    /// The highest score, or `None` if no event was scored.
+    pub max: Option<f64>,
+    /// The number of scores in each range of [`SCORE_BUCKET_WIDTH`], keyed by
+    /// the lower end of the range divided by the width. Only the ranges with
+    /// a score in them are present.
+    pub buckets: BTreeMap<i64, u64>,
+}
+
+impl ScoreDistribution {
+    fn add(&mut self, score: f64) {
+        self.min = Some(self.min.map_or(score, |min| min.min(score)));
+        self.max = Some(self.max.map_or(score, |max| max.max(score)));
+        let bucket = (score * 100.0)
+            .round()
+            .to_i64()
+            .map_or(i64::MIN, |hundredths| {
+                hundredths.div_euclid(SCORE_BUCKET_HUNDREDTHS)
+            });
+        *self.buckets.entry(bucket).or_default() += 1;
+    }
+}
+
+/// The policies being previewed and what they were found to do so far.
+pub(super) struct Preview {
+    candidate: Tally,
+    current: Option<Tally>,
+    matched: u64,
+    newly_scored: u64,
+    no_longer_scored: u64,
+}
+
+impl Preview {
+    pub(super) fn new(
+        candidate: &TriagePolicyInput,
+        current: Option<&TriagePolicyInput>,
+        sample_size: usize,
+    ) -> Result<Self> {
+        Ok(Self {
+            candidate: Tally::new(candidate, sample_size)?,
+            current: current
+                .map(|current| Tally::new(current, sample_size))
+                .transpose()?,
+            matched: 0,
+            newly_scored: 0,
+            no_longer_scored: 0,
+        })
+    }
+
+    /// Scores `event`, which matched the filter, against the policies.
+    pub(super) fn add(&mut self, key: i128, event: &Event) {
+        self.matched += 1;
+        let by_candidate = self.candidate.add(key, event);
+        let Some(current) = &mut self.current else {
+            return;
+        };
+        match (by_candidate, current.add(key, event)) {
+            (true, false) => self.newly_scored += 1,
+            (false, true) => self.no_longer_scored += 1,
+            _ => {}
+        }
+    }
+
+    pub(super) fn finish(self) -> PolicyImpactPreview {
+        PolicyImpactPreview {
+            matched: self.matched,
+            candidate: self.candidate.impact,
+            current: self.current.map(|current| current.impact),
+            newly_scored: self.newly_scored,
+            no_longer_scored: self.no_longer_scored,
+        }
+    }
+}
+
+/// A policy and what it was found to do so far.
+struct Tally {
+    policy: CompiledTriagePolicy,
+    thresholds: Vec<f64>,
+    sample_size: usize,
+    impact: PolicyImpact,
+}
+
+impl Tally {
+    fn new(policy: &TriagePolicyInput, sample_size: usize) -> Result<Self> {
+        let thresholds: Vec<_> = policy
+            .response
+            .iter()
+            .map(|response| response.minimum_score)
+            .collect();
+        Ok(Self {
+            policy: CompiledTriagePolicy::new(policy)?,
+            impact: PolicyImpact {
+                responses_fired: vec![0; thresholds.len()],
+                ..PolicyImpact::default()
+            },
+            thresholds,
+            sample_size,
+        })
+    }
+
+    /// Scores `event` and returns whether it reached a response threshold.
+    fn add(&mut self, key: i128, event: &Event) -> bool {
+        let Some(score) = self.policy.score(event) else {
+            return false;
+        };
+        let impact = &mut self.impact;
+        impact.scored += 1;
+        impact.distribution.add(score.score);
+        for (fired, threshold) in impact.responses_fired.iter_mut().zip(&self.thresholds) {
+            if score.score >= *threshold {
+                *fired += 1;
+            }
+        }
+        if impact.sample.len() < self.sample_size {
+            impact.sample.push(key);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ScoreDistribution;
+
+    #[test]
+    fn distribution_buckets_scores() {
+        let mut distribution = ScoreDistribution::default();
+        for score in [0.05, 0.0, 0.25, 1.55, -0.3, 0.29] {
+            distribution.add(score);
+        }
+        assert_eq!(distribution.min, Some(-0.3));
+        assert_eq!(distribution.max, Some(1.55));
+        assert_eq!(
+            distribution.buckets.into_iter().collect::<Vec<_>>(),
+            vec![(-3, 1), (0, 2), (2, 2), (15, 1)]
+        );
+    }
+
+    #[test]
+    fn distribution_buckets_boundary_scores() {
+        let mut distribution = ScoreDistribution::default();
+        for score in [0.3, 0.6, 0.7, 0.69] {
+            distribution.add(score);
+        }
+        assert_eq!(
+            distribution.buckets.into_iter().collect::<Vec<_>>(),
+            vec![(3, 1), (6, 2), (7, 1)]
+        );
+    }
+}
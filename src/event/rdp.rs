@@ -8,7 +8,7 @@ use serde::{Deserialize, Serialize};
 
 use super::timestamp::{self, ts_nanoseconds as jiff_ts_nanoseconds};
 use super::{
-    EventCategory, LearningMethod, ThreatLevel, TriageScore,
+    EventCategory, EventKind, LearningMethod, ThreatLevel, TriageScore,
     common::{Match, vector_to_string},
 };
 use crate::event::common::{AttrValue, triage_scores_to_string};
@@ -213,6 +213,14 @@ impl Match for RdpBruteForce {
         "rdp brute force"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::RdpBruteForce
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -457,6 +465,14 @@ impl Match for BlocklistRdp {
         "blocklist rdp"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::BlocklistRdp
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -0,0 +1,315 @@
+//! Pre-aggregated event counts per hour and per day.
+//!
+//! The rollups live in the `event rollups` column family. Each time bucket is
+//! split into [`SHARDS`] entries, so that events written at the same time
+//! mostly update different entries and do not conflict with each other. An
+//! entry's key is the granularity (`0` for an hour, `1` for a day), the start
+//! of the bucket in nanoseconds, a big-endian `i64` like the timestamp in an
+//! event key, and the shard, which is derived from the event key so that an
+//! event is counted and uncounted in the same entry. Its value is the
+//! [`Rollup`] of the events in the shard, and the rollup of a bucket is the sum
+//! of its shards. A shard whose count drops to zero is removed.
+//!
+//! The `event rollups` entry in the meta map tells how far the rollups cover
+//! the stored events. It is absent while rollups are disabled and empty once
+//! they cover every event. While [`EventDb::enable_rollups`] is counting the
+//! events stored before, it holds a bound on the keys counted so far: a write
+//! counts an event itself only if the event's key sorts before the bound, and
+//! otherwise leaves it to the build.
+//!
+//! [`EventDb::enable_rollups`]: super::EventDb::enable_rollups
+
+use std::{collections::HashMap, hash::Hash};
+
+use anyhow::{Context, Result, anyhow, bail};
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+
+use super::{Event, EventCategory, EventKind, Granularity, ThreatLevel, timestamp};
+
+/// The granularities the rollups are kept at.
+pub(super) const GRANULARITIES: [Granularity; 2] = [Granularity::Hour, Granularity::Day];
+
+/// The number of entries each bucket is split into.
+const SHARDS: u8 = 16;
+
+/// The counts of the events in one time bucket.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Rollup {
+    /// The start of the bucket.
+    #[serde(skip)]
+    pub start: Timestamp,
+    /// The number of events in the bucket.
+    pub total: u64,
+    pub kinds: HashMap<EventKind, u64>,
+    pub levels: HashMap<ThreatLevel, u64>,
+    pub categories: HashMap<EventCategory, u64>,
+    pub sensors: HashMap<String, u64>,
+    /// The number of events per country, counted the same way as
+    /// [`Event::count_country`].
+    pub countries: HashMap<String, u64>,
+}
+
+impl Rollup {
+    /// Counts `event` in the rollup.
+    fn count(&mut self, event: &Event) {
+        let (kind, category) = event.kind_and_category();
+        let event_match = event.as_match();
+        self.total += 1;
+        *self.kinds.entry(kind).or_insert(0) += 1;
+        *self.levels.entry(event_match.level()).or_insert(0) += 1;
+        if let Some(category) = category {
+            *self.categories.entry(category).or_insert(0) += 1;
+        }
+        *self
+            .sensors
+            .entry(event_match.sensor().to_string())
+            .or_insert(0) += 1;
+        let mut countries = HashMap::new();
+        event.tally_country(&mut countries, event.addresses());
+        for (country, count) in countries {
+            *self.countries.entry(country).or_insert(0) += count as u64;
+        }
+    }
+
+    /// Adds the counts of `other` to the rollup.
+    pub(super) fn merge(&mut self, other: Rollup) {
+        self.total += other.total;
+        merge_counts(&mut self.kinds, other.kinds);
+        merge_counts(&mut self.levels, other.levels);
+        merge_counts(&mut self.categories, other.categories);
+        merge_counts(&mut self.sensors, other.sensors);
+        merge_counts(&mut self.countries, other.countries);
+    }
+
+    fn subtract(&mut self, other: Rollup) {
+        self.total = self.total.saturating_sub(other.total);
+        subtract_counts(&mut self.kinds, other.kinds);
+        subtract_counts(&mut self.levels, other.levels);
+        subtract_counts(&mut self.categories, other.categories);
+        subtract_counts(&mut self.sensors, other.sensors);
+        subtract_counts(&mut self.countries, other.countries);
+    }
+}
+
+fn merge_counts<K: Eq + Hash>(counts: &mut HashMap<K, u64>, other: HashMap<K, u64>) {
+    for (key, count) in other {
+        *counts.entry(key).or_insert(0) += count;
+    }
+}
+
+fn subtract_counts<K: Eq + Hash>(counts: &mut HashMap<K, u64>, other: HashMap<K, u64>) {
+    for (key, count) in other {
+        if let Some(current) = counts.get_mut(&key) {
+            *current = current.saturating_sub(count);
+            if *current == 0 {
+                counts.remove(&key);
+            }
+        }
+    }
+}
+
+/// How far the rollups cover the stored events.
+#[derive(Debug, PartialEq)]
+pub(super) enum State {
+    Disabled,
+    /// The events whose keys sort before this bound are counted.
+    Building(Vec<u8>),
+    Built,
+}
+
+impl State {
+    /// The bound the build starts from, below every event key.
+    pub(super) const START: &[u8] = &[0];
+
+    /// Reads the state from the value of the `event rollups` meta entry.
+    pub(super) fn from_marker(marker: Option<&[u8]>) -> Self {
+        match marker {
+            None => Self::Disabled,
+            Some([]) => Self::Built,
+            Some(bound) => Self::Building(bound.to_vec()),
+        }
+    }
+
+    /// Returns whether the event stored under `key` is counted in the rollups.
+    pub(super) fn covers(&self, key: i128) -> bool {
+        match self {
+            Self::Disabled => false,
+            Self::Building(bound) => key.to_be_bytes().as_slice() < bound.as_slice(),
+            Self::Built => true,
+        }
+    }
+}
+
+/// Returns the bound that covers every event key up to and including `key`.
+pub(super) fn bound_after(key: &[u8]) -> Vec<u8> {
+    let mut bound = Vec::with_capacity(key.len() + 1);
+    bound.extend_from_slice(key);
+    bound.push(0);
+    bound
+}
+
+/// Returns the key of the bucket of `granularity` that starts at `start`
+/// nanoseconds.
+///
+/// # Errors
+///
+/// Returns an error if rollups are not kept at `granularity`.
+pub(super) fn bucket_key(granularity: Granularity, start: i64) -> Result<[u8; 9]> {
+    let tag = match granularity {
+        Granularity::Hour => 0,
+        Granularity::Day => 1,
+        Granularity::Minute => bail!("event rollups are kept per hour and per day only"),
+    };
+    let mut key = [0; 9];
+    key[0] = tag;
+    key[1..].copy_from_slice(&start.to_be_bytes());
+    Ok(key)
+}
+
+/// Returns the key of `shard` of `bucket`.
+fn shard_key(bucket: [u8; 9], shard: u8) -> [u8; 10] {
+    let mut key = [0; 10];
+    key[..9].copy_from_slice(&bucket);
+    key[9] = shard;
+    key
+}
+
+/// Returns the shard the event stored under `key` is counted in.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // bit pattern
+fn shard(key: i128) -> u8 {
+    let bits = key as u128;
+    let folded = (bits >> 64) as u64 ^ bits as u64;
+    (folded.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 56) as u8 % SHARDS
+}
+
+/// Decodes a shard entry into its [`Rollup`].
+///
+/// # Errors
+///
+/// Returns an error if the entry is malformed.
+pub(super) fn decode(key: &[u8], value: &[u8]) -> Result<Rollup> {
+    let start: [u8; 8] = key
+        .get(1..9)
+        .and_then(|start| start.try_into().ok())
+        .ok_or_else(|| anyhow!("invalid event rollup key"))?;
+    let mut rollup: Rollup = bincode::deserialize(value).context("invalid event rollup")?;
+    rollup.start = timestamp::from_i64_nanos(i64::from_be_bytes(start))?;
+    Ok(rollup)
+}
+
+/// The changes a write makes to the rollups, gathered per shard entry so that
+/// each entry is read and written once.
+#[derive(Default)]
+pub(super) struct Delta {
+    buckets: HashMap<[u8; 10], (Rollup, Rollup)>,
+}
+
+impl Delta {
+    /// Counts the event stored under `key` in its buckets.
+    pub(super) fn add(&mut self, key: i128, event: &Event) {
+        for bucket in buckets(key) {
+            self.buckets.entry(bucket).or_default().0.count(event);
+        }
+    }
+
+    /// Takes the event stored under `key` out of its buckets.
+    pub(super) fn remove(&mut self, key: i128, event: &Event) {
+        for bucket in buckets(key) {
+            self.buckets.entry(bucket).or_default().1.count(event);
+        }
+    }
+
+    /// Applies the changes to the shard entries in `cf` within `txn`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an entry cannot be read, decoded, or written.
+    pub(super) fn write(
+        self,
+        txn: &rocksdb::Transaction<rocksdb::OptimisticTransactionDB>,
+        cf: &rocksdb::ColumnFamily,
+    ) -> Result<()> {
+        for (bucket, (added, removed)) in self.buckets {
+            let mut rollup = match txn
+                .get_for_update_cf(cf, bucket, crate::EXCLUSIVE)
+                .context("cannot read event rollup")?
+            {
+                Some(value) => bincode::deserialize(&value).context("invalid event rollup")?,
+                None => Rollup::default(),
+            };
+            rollup.merge(added);
+            rollup.subtract(removed);
+            if rollup.total == 0 {
+                txn.delete_cf(cf, bucket)
+                    .context("cannot delete event rollup")?;
+            } else {
+                let value = bincode::serialize(&rollup).context("cannot encode event rollup")?;
+                txn.put_cf(cf, bucket, value)
+                    .context("cannot write event rollup")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the keys of the shard entries the event stored under `key` is
+/// counted in, one per granularity.
+fn buckets(key: i128) -> impl Iterator<Item = [u8; 10]> {
+    let time = i64::try_from(key >> 64).expect("valid i64");
+    let shard = shard(key);
+    GRANULARITIES.into_iter().map(move |granularity| {
+        let bucket =
+            bucket_key(granularity, granularity.bucket_nanos(time)).expect("rollup granularity");
+        shard_key(bucket, shard)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Granularity, SHARDS, State, bound_after, bucket_key, buckets, decode};
+
+    #[test]
+    fn bucket_keys_order_by_granularity_then_time() {
+        let hour = bucket_key(Granularity::Hour, 3_600_000_000_000).unwrap();
+        let later_hour = bucket_key(Granularity::Hour, 7_200_000_000_000).unwrap();
+        let day = bucket_key(Granularity::Day, 0).unwrap();
+        assert!(hour < later_hour);
+        assert!(later_hour < day);
+        assert!(bucket_key(Granularity::Minute, 0).is_err());
+
+        let value = bincode::serialize(&super::Rollup::default()).unwrap();
+        let rollup = decode(&later_hour, &value).unwrap();
+        assert_eq!(rollup.start.as_second(), 7200);
+    }
+
+    #[test]
+    fn events_of_one_bucket_spread_over_shards() {
+        let second = 1_000_000_000_i128;
+        let keys: Vec<i128> = (0..64).map(|offset| (offset * second) << 64).collect();
+        let hour = bucket_key(Granularity::Hour, 0).unwrap();
+        let mut shards = std::collections::HashSet::new();
+        for &key in &keys {
+            let entries: Vec<_> = buckets(key).collect();
+            assert_eq!(entries, buckets(key).collect::<Vec<_>>());
+            assert_eq!(entries[0][..9], hour);
+            assert!(entries[0][9] < SHARDS);
+            assert_eq!(entries[0][9], entries[1][9]);
+            shards.insert(entries[0][9]);
+        }
+        assert!(shards.len() > 1);
+    }
+
+    #[test]
+    fn state_covers_events_below_bound() {
+        assert!(!State::from_marker(None).covers(0));
+        assert!(State::from_marker(Some(&[])).covers(-1));
+        assert!(!State::from_marker(Some(State::START)).covers(0));
+
+        let bound = bound_after(&5_i128.to_be_bytes());
+        let building = State::from_marker(Some(&bound));
+        assert!(building.covers(5));
+        assert!(!building.covers(6));
+        assert!(!building.covers(-1));
+    }
+}
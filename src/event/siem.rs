@@ -0,0 +1,308 @@
+//! ArcSight Common Event Format (CEF) and QRadar Log Event Extended Format
+//! (LEEF) records of events.
+//!
+//! Both formats carry the kind of an event in their header and its fields as
+//! `key=value` pairs. The fields with a counterpart among the keys a format
+//! defines, such as the endpoint addresses and ports, are given under that
+//! key; the others keep their names, in camel case. A list of plain values is
+//! joined with commas, and a nested structure is given as compact JSON.
+
+use anyhow::{Context, Result};
+use jiff::Timestamp;
+use serde_json::Value;
+
+use super::{
+    EventKind, EventMessage, ThreatLevel, convert_for_storage, decode_stored, fields_to_json,
+    timestamp,
+};
+
+/// The product a record is reported as coming from.
+#[derive(Clone, Copy, Debug)]
+pub struct Device<'a> {
+    pub vendor: &'a str,
+    pub product: &'a str,
+    pub version: &'a str,
+}
+
+/// Formatting of an event as a CEF or LEEF record, for SIEM integrations.
+pub trait SiemFormat {
+    /// Returns the event as a CEF record.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event fields cannot be decoded.
+    fn cef(&self, device: &Device) -> Result<String>;
+
+    /// Returns the event as a LEEF 1.0 record, with its attributes separated
+    /// by tabs.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event fields cannot be decoded.
+    fn leef(&self, device: &Device) -> Result<String>;
+}
+
+impl SiemFormat for EventMessage {
+    fn cef(&self, device: &Device) -> Result<String> {
+        let record = Record::new(self)?;
+        let mut extension = vec![
+            ("rt".to_string(), record.millis.to_string()),
+            ("cat".to_string(), record.categories()),
+        ];
+        extension.extend(record.attributes(CEF_KEYS));
+        let extension = extension
+            .into_iter()
+            .map(|(key, value)| format!("{key}={}", escape_cef_value(&value)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        Ok(format!(
+            "CEF:0|{}|{}|{}|{:?}|{}|{}|{extension}",
+            escape_header(device.vendor),
+            escape_header(device.product),
+            escape_header(device.version),
+            self.kind,
+            escape_header(record.name),
+            severity(record.level),
+        ))
+    }
+
+    fn leef(&self, device: &Device) -> Result<String> {
+        let record = Record::new(self)?;
+        let mut attributes = vec![
+            ("devTime".to_string(), record.dev_time()),
+            ("devTimeFormat".to_string(), LEEF_TIME_FORMAT.to_string()),
+            ("sev".to_string(), severity(record.level).to_string()),
+            ("cat".to_string(), record.categories()),
+        ];
+        attributes.extend(record.attributes(LEEF_KEYS));
+        let attributes = attributes
+            .into_iter()
+            .map(|(key, value)| format!("{key}={}", escape_leef_value(&value)))
+            .collect::<Vec<_>>()
+            .join("\t");
+        Ok(format!(
+            "LEEF:1.0|{}|{}|{}|{:?}|{attributes}",
+            escape_header(device.vendor),
+            escape_header(device.product),
+            escape_header(device.version),
+            self.kind,
+        ))
+    }
+}
+
+/// The fields given under a key of CEF's own.
+const CEF_KEYS: &[(&str, &str)] = &[
+    ("sensor", "dvchost"),
+    ("orig_addr", "src"),
+    ("orig_port", "spt"),
+    ("resp_addr", "dst"),
+    ("resp_port", "dpt"),
+    ("proto", "proto"),
+    ("start_time", "start"),
+    ("end_time", "end"),
+];
+
+/// The fields given under a key of LEEF's own.
+const LEEF_KEYS: &[(&str, &str)] = &[
+    ("sensor", "identHostName"),
+    ("orig_addr", "src"),
+    ("orig_port", "srcPort"),
+    ("resp_addr", "dst"),
+    ("resp_port", "dstPort"),
+    ("proto", "proto"),
+    ("orig_bytes", "srcBytes"),
+    ("resp_bytes", "dstBytes"),
+    ("orig_pkts", "srcPackets"),
+    ("resp_pkts", "dstPackets"),
+];
+
+/// The format of `devTime` in a LEEF record, in the notation LEEF expects.
+const LEEF_TIME_FORMAT: &str = "MMM dd yyyy HH:mm:ss.SSS z";
+
+/// The parts of an event both formats are built from.
+struct Record {
+    kind: EventKind,
+    time: Timestamp,
+    millis: i64,
+    name: &'static str,
+    level: ThreatLevel,
+    fields: serde_json::Map<String, Value>,
+}
+
+impl Record {
+    fn new(message: &EventMessage) -> Result<Self> {
+        let stored = convert_for_storage(message.kind, &message.fields, None)?;
+        let event = decode_stored(message.kind, message.time, &stored)
+            .context("failed to decode event fields")?;
+        let millis = timestamp::to_i64_nanos(message.time)? / 1_000_000;
+        Ok(Self {
+            kind: message.kind,
+            time: message.time,
+            millis,
+            name: event.kind_name(),
+            level: event.as_match().level(),
+            fields: fields_to_json(message.kind, &message.fields)?,
+        })
+    }
+
+    /// Returns the MITRE ATT&CK categories of the event kind, separated by
+    /// commas.
+    fn categories(&self) -> String {
+        self.kind
+            .categories()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    fn dev_time(&self) -> String {
+        self.time.strftime("%b %d %Y %H:%M:%S%.3f UTC").to_string()
+    }
+
+    /// Returns the fields as `key=value` pairs, giving a field listed in
+    /// `keys` under the key it maps to.
+    fn attributes(&self, keys: &[(&str, &str)]) -> Vec<(String, String)> {
+        self.fields
+            .iter()
+            .filter_map(|(name, value)| {
+                let key = keys.iter().find(|(field, _)| field == name);
+                let value = match (name.as_str(), value) {
+                    ("proto", Value::Number(proto)) => protocol_name(proto.as_u64()?),
+                    ("start_time" | "end_time", Value::Number(nanos)) if key.is_some() => {
+                        (nanos.as_i64()? / 1_000_000).to_string()
+                    }
+                    _ => render(value)?,
+                };
+                let key = key.map_or_else(|| camel_case(name), |(_, key)| (*key).to_string());
+                Some((key, value))
+            })
+            .collect()
+    }
+}
+
+/// Maps a threat level to the 0 to 10 severity scale both formats use.
+pub(super) fn severity(level: ThreatLevel) -> u8 {
+    match level {
+        ThreatLevel::VeryLow => 1,
+        ThreatLevel::Low => 3,
+        ThreatLevel::Medium => 5,
+        ThreatLevel::High => 8,
+        ThreatLevel::VeryHigh => 10,
+    }
+}
+
+fn protocol_name(proto: u64) -> String {
+    match proto {
+        1 => "ICMP".to_string(),
+        6 => "TCP".to_string(),
+        17 => "UDP".to_string(),
+        _ => proto.to_string(),
+    }
+}
+
+/// Renders a field value, or returns `None` for an absent one.
+fn render(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        Value::Array(items)
+            if items
+                .iter()
+                .all(|item| !item.is_array() && !item.is_object()) =>
+        {
+            Some(
+                items
+                    .iter()
+                    .filter_map(render)
+                    .collect::<Vec<_>>()
+                    .join(","),
+            )
+        }
+        _ => Some(value.to_string()),
+    }
+}
+
+fn camel_case(name: &str) -> String {
+    let mut key = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            key.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            key.push(c);
+        }
+    }
+    key
+}
+
+/// Escapes a header field of either format.
+fn escape_header(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '|' => escaped.push_str("\\|"),
+            '\r' | '\n' => escaped.push(' '),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn escape_cef_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '=' => escaped.push_str("\\="),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn escape_leef_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{camel_case, escape_cef_value, escape_header, escape_leef_value, render};
+
+    #[test]
+    fn escapes_per_format() {
+        assert_eq!(escape_header(r"a|b\c"), r"a\|b\\c");
+        assert_eq!(escape_header("a\nb"), "a b");
+        assert_eq!(escape_cef_value("a=b\\c\nd|e"), r"a\=b\\c\nd|e");
+        assert_eq!(escape_leef_value("a\tb=c\\d\r"), r"a\tb=c\\d\r");
+    }
+
+    #[test]
+    fn renders_values() {
+        assert_eq!(render(&json!(null)), None);
+        assert_eq!(render(&json!("a b")).unwrap(), "a b");
+        assert_eq!(render(&json!(3)).unwrap(), "3");
+        assert_eq!(render(&json!(["a", 1, true])).unwrap(), "a,1,true");
+        assert_eq!(render(&json!([[1, 2]])).unwrap(), "[[1,2]]");
+        assert_eq!(render(&json!({"id": 1})).unwrap(), r#"{"id":1}"#);
+        assert_eq!(camel_case("orig_l2_bytes"), "origL2Bytes");
+    }
+}
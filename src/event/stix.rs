@@ -0,0 +1,743 @@
+//! STIX 2.1 bundles of events and of the indicators they are detected with.
+//!
+//! An event becomes an `observed-data` object, referring to the addresses,
+//! traffic, and domain names it was observed in, and a `sighting` of what it
+//! was detected with. A blocklist or Tor event is a sighting of the indicator
+//! of the block network or Tor exit node its addresses belong to, if the
+//! bundle has one; any other event is a sighting of the `attack-pattern` of
+//! its kind. The kill-chain phases of an attack pattern or an indicator are
+//! the MITRE ATT&CK tactics of [`EventKind::categories`].
+//!
+//! The IDs of the cyber-observable objects are derived from their values as
+//! STIX specifies, and the IDs of the other objects from what they describe,
+//! so exporting the same event or indicator again yields the same ID.
+
+use std::{collections::HashSet, fmt::Write, net::IpAddr};
+
+use anyhow::{Context, Result, bail};
+use ipnet::{IpNet, Ipv4Subnets, Ipv6Subnets};
+use jiff::Timestamp;
+use ring::digest;
+use serde_json::{Map, Value, json};
+
+use super::{
+    Event, EventCategory, EventKind,
+    mapping::{self, confidence_percent, protocol_name, tactic},
+    timestamp,
+};
+use crate::{BlockNetwork, HostNetworkGroup, LabelDb, LabelDbKind, ModelIndicator, TorExitNode};
+
+/// The namespace STIX defines for the IDs of cyber-observable objects.
+const SCO_NAMESPACE: [u8; 16] = [
+    0x00, 0xab, 0xed, 0xb4, 0xaa, 0x42, 0x52, 0xd4, 0xba, 0x3a, 0x55, 0xfa, 0x0c, 0xa3, 0xf5, 0x7f,
+];
+
+/// The namespace the IDs of the other objects are derived in.
+const SDO_NAMESPACE: [u8; 16] = [
+    0x6f, 0x1c, 0x3e, 0x52, 0x8d, 0x4b, 0x4a, 0x27, 0x9e, 0x05, 0x2b, 0xd1, 0x73, 0xc8, 0x40, 0x96,
+];
+
+/// The name of the identity the objects are created by.
+const PRODUCER: &str = "REview";
+
+/// A STIX 2.1 bundle being built from events and indicators.
+pub struct StixBundle {
+    created: String,
+    producer: String,
+    objects: Vec<Value>,
+    ids: HashSet<String>,
+    block_networks: Vec<(String, HostNetworkGroup)>,
+    tor_exit_nodes: Vec<(String, IpAddr)>,
+    sightings: Vec<Sighting>,
+}
+
+/// A sighting whose `sighting_of_ref` is resolved once every indicator is in
+/// the bundle.
+struct Sighting {
+    object: Value,
+    hit: Option<Hit>,
+    addrs: Vec<IpAddr>,
+    attack_pattern: String,
+}
+
+/// The kind of indicator an event may be a sighting of.
+#[derive(Clone, Copy)]
+enum Hit {
+    BlockNetwork,
+    TorExitNode,
+}
+
+impl StixBundle {
+    /// Creates an empty bundle. `created` is the creation time of the
+    /// objects whose source has no time of its own.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `created` is out of the range of event timestamps.
+    pub fn new(created: Timestamp) -> Result<Self> {
+        let created = format_time(created)?;
+        let producer = sdo_id("identity", PRODUCER);
+        let mut bundle = Self {
+            created,
+            producer: producer.clone(),
+            objects: Vec::new(),
+            ids: HashSet::new(),
+            block_networks: Vec::new(),
+            tor_exit_nodes: Vec::new(),
+            sightings: Vec::new(),
+        };
+        let identity = json!({
+            "type": "identity",
+            "spec_version": "2.1",
+            "id": producer,
+            "created": bundle.created,
+            "modified": bundle.created,
+            "name": PRODUCER,
+            "identity_class": "system",
+        });
+        bundle.push(identity);
+        Ok(bundle)
+    }
+
+    /// Adds `event`, stored under `key`, as an `observed-data` object and a
+    /// sighting.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the fields of the event cannot be converted to
+    /// JSON.
+    pub fn add_event(&mut self, key: i128, event: &Event) -> Result<()> {
+        let event_match = event.as_match();
+        let (kind, _) = event.kind_and_category();
+        let time = format_time(event.time())?;
+        let fields = mapping::fields(event)?;
+
+        let orig = self.add_addresses(event_match.orig_addrs());
+        let resp = self.add_addresses(event_match.resp_addrs());
+        let mut refs: Vec<String> = orig.iter().chain(&resp).cloned().collect();
+        if let (Some(src), Some(dst)) = (orig.first(), resp.first()) {
+            let mut traffic = json!({
+                "type": "network-traffic",
+                "start": time,
+                "src_ref": src,
+                "dst_ref": dst,
+                "src_port": event_match.orig_port(),
+                "dst_port": event_match.resp_port(),
+                "protocols": [protocol_name(event_match.proto()).to_lowercase()],
+            });
+            traffic["id"] = json!(sco_id("network-traffic", &traffic));
+            refs.push(self.push(traffic));
+        }
+        for name in ["query", "server_name"] {
+            if let Some(Value::String(domain)) = fields.get(name)
+                && !domain.is_empty()
+            {
+                refs.push(self.push_sco("domain-name", domain));
+            }
+        }
+
+        let name = key.to_string();
+        let observed_data = sdo_id("observed-data", &name);
+        self.push(json!({
+            "type": "observed-data",
+            "spec_version": "2.1",
+            "id": observed_data,
+            "created_by_ref": self.producer,
+            "created": time,
+            "modified": time,
+            "first_observed": time,
+            "last_observed": time,
+            "number_observed": 1,
+            "object_refs": refs,
+        }));
+
+        let sensor = sdo_id("identity", &format!("sensor/{}", event_match.sensor()));
+        self.push(json!({
+            "type": "identity",
+            "spec_version": "2.1",
+            "id": sensor,
+            "created": self.created,
+            "modified": self.created,
+            "name": event_match.sensor(),
+            "identity_class": "system",
+        }));
+
+        let attack_pattern = sdo_id("attack-pattern", &format!("{kind:?}"));
+        self.push(json!({
+            "type": "attack-pattern",
+            "spec_version": "2.1",
+            "id": attack_pattern,
+            "created_by_ref": self.producer,
+            "created": self.created,
+            "modified": self.created,
+            "name": event.kind_name(),
+            "kill_chain_phases": kill_chain_phases(kind.categories().iter().copied()),
+        }));
+
+        let mut sighting = json!({
+            "type": "sighting",
+            "spec_version": "2.1",
+            "id": sdo_id("sighting", &name),
+            "created_by_ref": self.producer,
+            "created": time,
+            "modified": time,
+            "first_seen": time,
+            "last_seen": time,
+            "count": 1,
+            "observed_data_refs": [observed_data],
+            "where_sighted_refs": [sensor],
+        });
+        if let Some(confidence) = event_match.confidence() {
+            sighting["confidence"] = json!(confidence_percent(confidence));
+        }
+        let hit = match event {
+            Event::Blocklist(_) => Some(Hit::BlockNetwork),
+            Event::TorConnection(_) | Event::TorConnectionConn(_) => Some(Hit::TorExitNode),
+            _ => None,
+        };
+        self.sightings.push(Sighting {
+            object: sighting,
+            hit,
+            addrs: event_match
+                .orig_addrs()
+                .iter()
+                .chain(event_match.resp_addrs())
+                .copied()
+                .collect(),
+            attack_pattern,
+        });
+        Ok(())
+    }
+
+    /// Adds `network` as an indicator matching its hosts, networks, and
+    /// ranges.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `network` has no address, or if one of its ranges
+    /// spans IPv4 and IPv6 addresses.
+    pub fn add_block_network(&mut self, network: &BlockNetwork) -> Result<()> {
+        let pattern = network_pattern(&network.networks)
+            .with_context(|| format!("invalid block network {}", network.name))?;
+        let id = sdo_id(
+            "indicator",
+            &format!("block-network/{}/{}", network.customer_id, network.name),
+        );
+        let mut indicator = self.indicator(
+            &id,
+            &network.name,
+            &pattern,
+            "stix",
+            &self.created,
+            EventKind::BlocklistConn.categories().iter().copied(),
+        );
+        indicator["indicator_types"] = json!(["malicious-activity"]);
+        if !network.description.is_empty() {
+            indicator["description"] = json!(network.description);
+        }
+        self.push(indicator);
+        self.block_networks.push((id, network.networks.clone()));
+        Ok(())
+    }
+
+    /// Adds `node` as an indicator matching its address.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address of `node` is invalid, or if its update
+    /// time is out of the range of event timestamps.
+    pub fn add_tor_exit_node(&mut self, node: &TorExitNode) -> Result<()> {
+        let addr: IpAddr = node
+            .ip_address
+            .parse()
+            .with_context(|| format!("invalid Tor exit node address {}", node.ip_address))?;
+        let updated_at = format_time(timestamp::from_chrono(node.updated_at)?)?;
+        let id = sdo_id("indicator", &format!("tor-exit-node/{addr}"));
+        let pattern = format!(
+            "[{}:value = '{addr}']",
+            if addr.is_ipv4() {
+                "ipv4-addr"
+            } else {
+                "ipv6-addr"
+            }
+        );
+        let mut indicator = self.indicator(
+            &id,
+            &format!("Tor exit node {addr}"),
+            &pattern,
+            "stix",
+            &updated_at,
+            EventKind::TorConnection.categories().iter().copied(),
+        );
+        indicator["indicator_types"] = json!(["anonymization"]);
+        self.push(indicator);
+        self.tor_exit_nodes.push((id, addr));
+        Ok(())
+    }
+
+    /// Adds the rules of `db` as indicators, each in the kill-chain phase of
+    /// its own category. Rules without signatures are skipped, and the number
+    /// of rules added is returned.
+    ///
+    /// Signatures of an IP database are matched as addresses or networks, and
+    /// those of a URL database as URLs. Token and regular-expression
+    /// signatures become PCRE patterns.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a signature of an IP database is not an address or
+    /// a network.
+    pub fn add_label_db(&mut self, db: &LabelDb) -> Result<usize> {
+        let mut count = 0;
+        for rule in &db.patterns {
+            let Some(signatures) = rule.signatures.as_deref().filter(|s| !s.is_empty()) else {
+                continue;
+            };
+            let (pattern, pattern_type) = match db.kind {
+                LabelDbKind::Ip => {
+                    let mut comparisons = Vec::with_capacity(signatures.len());
+                    for signature in signatures {
+                        comparisons.push(address_comparison(signature).with_context(|| {
+                            format!("invalid signature of rule {} in {}", rule.rule_id, db.name)
+                        })?);
+                    }
+                    (format!("[{}]", comparisons.join(" OR ")), "stix")
+                }
+                LabelDbKind::Url => {
+                    let comparisons: Vec<_> = signatures
+                        .iter()
+                        .map(|url| format!("url:value = '{}'", escape_pattern(url)))
+                        .collect();
+                    (format!("[{}]", comparisons.join(" OR ")), "stix")
+                }
+                LabelDbKind::Token => (
+                    signatures
+                        .iter()
+                        .map(|token| regex::escape(token))
+                        .collect::<Vec<_>>()
+                        .join("|"),
+                    "pcre",
+                ),
+                LabelDbKind::Regex => (
+                    signatures
+                        .iter()
+                        .map(|regex| format!("(?:{regex})"))
+                        .collect::<Vec<_>>()
+                        .join("|"),
+                    "pcre",
+                ),
+            };
+            let id = sdo_id(
+                "indicator",
+                &format!("label-db/{}/{}", db.name, rule.rule_id),
+            );
+            let mut indicator = self.indicator(
+                &id,
+                &rule.name,
+                &pattern,
+                pattern_type,
+                &self.created,
+                std::iter::once(rule.category),
+            );
+            indicator["indicator_types"] = json!(["malicious-activity"]);
+            if let Some(description) = &rule.description {
+                indicator["description"] = json!(description);
+            }
+            if let Some(confidence) = rule.confidence {
+                indicator["confidence"] = json!(confidence_percent(confidence));
+            }
+            if let Some(references) = rule.references.as_deref().filter(|r| !r.is_empty()) {
+                indicator["external_references"] = references
+                    .iter()
+                    .map(|url| json!({ "source_name": db.name, "url": url }))
+                    .collect();
+            }
+            self.push(indicator);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Adds `indicator` as an indicator with a PCRE pattern matching any of
+    /// its token sequences, the tokens of a sequence in order.
+    ///
+    /// The model indicators are matched against the HTTP requests a model
+    /// clusters, so their kill-chain phases are those of
+    /// [`EventKind::HttpThreat`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `indicator` has no tokens, or if its modification
+    /// time is out of the range of event timestamps.
+    pub fn add_model_indicator(&mut self, indicator: &ModelIndicator) -> Result<()> {
+        let mut sequences: Vec<String> = indicator
+            .tokens
+            .iter()
+            .filter(|tokens| !tokens.is_empty())
+            .map(|tokens| {
+                tokens
+                    .iter()
+                    .map(|token| regex::escape(token))
+                    .collect::<Vec<_>>()
+                    .join(".*?")
+            })
+            .collect();
+        if sequences.is_empty() {
+            bail!("model indicator {} has no tokens", indicator.name);
+        }
+        sequences.sort_unstable();
+        let modified = format_time(timestamp::from_chrono(indicator.last_modification_time)?)?;
+        let id = sdo_id("indicator", &format!("model-indicator/{}", indicator.name));
+        let mut object = self.indicator(
+            &id,
+            &indicator.name,
+            &sequences.join("|"),
+            "pcre",
+            &modified,
+            EventKind::HttpThreat.categories().iter().copied(),
+        );
+        object["indicator_types"] = json!(["anomalous-activity"]);
+        if !indicator.description.is_empty() {
+            object["description"] = json!(indicator.description);
+        }
+        object["x_review_model_id"] = json!(indicator.model_id);
+        self.push(object);
+        Ok(())
+    }
+
+    /// Returns the bundle as a JSON object.
+    #[must_use]
+    pub fn into_json(mut self) -> Value {
+        for sighting in std::mem::take(&mut self.sightings) {
+            let indicator = match sighting.hit {
+                Some(Hit::BlockNetwork) => self
+                    .block_networks
+                    .iter()
+                    .find(|(_, networks)| sighting.addrs.iter().any(|&a| networks.contains(a)))
+                    .map(|(id, _)| id),
+                Some(Hit::TorExitNode) => self
+                    .tor_exit_nodes
+                    .iter()
+                    .find(|(_, addr)| sighting.addrs.contains(addr))
+                    .map(|(id, _)| id),
+                None => None,
+            };
+            let mut object = sighting.object;
+            object["sighting_of_ref"] = json!(indicator.unwrap_or(&sighting.attack_pattern));
+            self.objects.push(object);
+        }
+        let mut random = rand::random::<[u8; 16]>();
+        random[6] = (random[6] & 0x0f) | 0x40;
+        random[8] = (random[8] & 0x3f) | 0x80;
+        json!({
+            "type": "bundle",
+            "id": format!("bundle--{}", format_uuid(&random)),
+            "objects": self.objects,
+        })
+    }
+
+    fn indicator(
+        &self,
+        id: &str,
+        name: &str,
+        pattern: &str,
+        pattern_type: &str,
+        modified: &str,
+        categories: impl Iterator<Item = EventCategory>,
+    ) -> Value {
+        json!({
+            "type": "indicator",
+            "spec_version": "2.1",
+            "id": id,
+            "created_by_ref": self.producer,
+            "created": modified,
+            "modified": modified,
+            "name": name,
+            "pattern": pattern,
+            "pattern_type": pattern_type,
+            "valid_from": modified,
+            "kill_chain_phases": kill_chain_phases(categories),
+        })
+    }
+
+    /// Adds the addresses among `addrs` and returns their IDs.
+    fn add_addresses(&mut self, addrs: &[IpAddr]) -> Vec<String> {
+        addrs
+            .iter()
+            .filter(|addr| !addr.is_unspecified())
+            .map(|addr| {
+                let kind = if addr.is_ipv4() {
+                    "ipv4-addr"
+                } else {
+                    "ipv6-addr"
+                };
+                self.push_sco(kind, &addr.to_string())
+            })
+            .collect()
+    }
+
+    /// Adds a cyber-observable object identified by its `value`.
+    fn push_sco(&mut self, kind: &str, value: &str) -> String {
+        let mut object = json!({ "type": kind, "value": value });
+        object["id"] = json!(sco_id(kind, &object));
+        self.push(object)
+    }
+
+    /// Adds `object` unless the bundle already has an object with its ID, and
+    /// returns the ID.
+    fn push(&mut self, object: Value) -> String {
+        let id = object["id"].as_str().unwrap_or_default().to_string();
+        if self.ids.insert(id.clone()) {
+            self.objects.push(object);
+        }
+        id
+    }
+}
+
+/// Returns the kill-chain phases of the MITRE ATT&CK tactics `categories`
+/// stand for.
+fn kill_chain_phases(categories: impl Iterator<Item = EventCategory>) -> Vec<Value> {
+    categories
+        .map(|category| {
+            let (_, name) = tactic(category);
+            json!({
+                "kill_chain_name": "mitre-attack",
+                "phase_name": name.to_lowercase().replace(' ', "-"),
+            })
+        })
+        .collect()
+}
+
+/// Returns a STIX pattern matching the addresses in `group`.
+fn network_pattern(group: &HostNetworkGroup) -> Result<String> {
+    let mut comparisons = Vec::new();
+    for host in group.hosts() {
+        comparisons.push(address_comparison(&host.to_string())?);
+    }
+    for network in group.networks() {
+        comparisons.push(network_comparison(network));
+    }
+    for range in group.ip_ranges() {
+        let networks: Vec<IpNet> = match (range.start(), range.end()) {
+            (IpAddr::V4(start), IpAddr::V4(end)) => {
+                Ipv4Subnets::new(*start, *end, 0).map(IpNet::V4).collect()
+            }
+            (IpAddr::V6(start), IpAddr::V6(end)) => {
+                Ipv6Subnets::new(*start, *end, 0).map(IpNet::V6).collect()
+            }
+            _ => bail!("range {range:?} spans IPv4 and IPv6 addresses"),
+        };
+        comparisons.extend(networks.iter().map(network_comparison));
+    }
+    if comparisons.is_empty() {
+        bail!("no address to match");
+    }
+    Ok(format!("[{}]", comparisons.join(" OR ")))
+}
+
+/// Returns the comparison matching `signature`, an address or a network.
+fn address_comparison(signature: &str) -> Result<String> {
+    let signature = signature.trim();
+    if let Ok(addr) = signature.parse::<IpAddr>() {
+        let kind = if addr.is_ipv4() {
+            "ipv4-addr"
+        } else {
+            "ipv6-addr"
+        };
+        return Ok(format!("{kind}:value = '{addr}'"));
+    }
+    let network: IpNet = signature
+        .parse()
+        .with_context(|| format!("{signature} is neither an address nor a network"))?;
+    Ok(network_comparison(&network))
+}
+
+fn network_comparison(network: &IpNet) -> String {
+    let kind = match network {
+        IpNet::V4(_) => "ipv4-addr",
+        IpNet::V6(_) => "ipv6-addr",
+    };
+    format!("{kind}:value ISSUBSET '{network}'")
+}
+
+/// Escapes a string literal in a STIX pattern.
+fn escape_pattern(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+/// Formats `time` as a STIX timestamp, in UTC with milliseconds.
+fn format_time(time: Timestamp) -> Result<String> {
+    timestamp::to_i64_nanos(time)?;
+    Ok(time.strftime("%Y-%m-%dT%H:%M:%S%.3fZ").to_string())
+}
+
+/// Returns the ID of a cyber-observable object, derived from its properties
+/// other than its ID, as STIX specifies.
+fn sco_id(kind: &str, object: &Value) -> String {
+    let mut properties = Map::new();
+    if let Value::Object(object) = object {
+        for (key, value) in object {
+            if key != "type" && key != "id" {
+                properties.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    // The members of a `Map` are sorted, as in the canonical JSON STIX asks
+    // for.
+    let name = Value::Object(properties).to_string();
+    format!("{kind}--{}", uuid_v5(&SCO_NAMESPACE, &name))
+}
+
+fn sdo_id(kind: &str, name: &str) -> String {
+    format!(
+        "{kind}--{}",
+        uuid_v5(&SDO_NAMESPACE, &format!("{kind}/{name}"))
+    )
+}
+
+/// Returns the name-based UUID of `name` in `namespace`, as in RFC 9562.
+fn uuid_v5(namespace: &[u8; 16], name: &str) -> String {
+    let mut data = Vec::with_capacity(namespace.len() + name.len());
+    data.extend_from_slice(namespace);
+    data.extend_from_slice(name.as_bytes());
+    let hash = digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, &data);
+    let mut uuid = [0; 16];
+    uuid.copy_from_slice(&hash.as_ref()[..16]);
+    uuid[6] = (uuid[6] & 0x0f) | 0x50;
+    uuid[8] = (uuid[8] & 0x3f) | 0x80;
+    format_uuid(&uuid)
+}
+
+fn format_uuid(uuid: &[u8; 16]) -> String {
+    let mut formatted = String::with_capacity(36);
+    for (i, byte) in uuid.iter().enumerate() {
+        if matches!(i, 4 | 6 | 8 | 10) {
+            formatted.push('-');
+        }
+        let _ = write!(formatted, "{byte:02x}");
+    }
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use serde_json::json;
+
+    use super::{
+        EventCategory, HostNetworkGroup, LabelDb, LabelDbKind, ModelIndicator, SCO_NAMESPACE,
+        StixBundle, address_comparison, escape_pattern, network_pattern, sco_id, uuid_v5,
+    };
+    use crate::LabelDbRule;
+
+    fn rule(rule_id: u32, signatures: Option<Vec<String>>) -> LabelDbRule {
+        LabelDbRule {
+            rule_id,
+            category: EventCategory::Exfiltration,
+            name: format!("rule {rule_id}"),
+            kind: None,
+            description: None,
+            references: Some(vec!["https://example.com/rule".to_string()]),
+            samples: None,
+            signatures,
+            confidence: Some(0.9),
+        }
+    }
+
+    #[test]
+    fn ids_follow_stix() {
+        assert_eq!(
+            uuid_v5(&SCO_NAMESPACE, r#"{"value":"198.51.100.3"}"#),
+            "a7b17001-f20f-5170-885a-d8b87bf94252"
+        );
+        let object = json!({ "type": "ipv4-addr", "id": "", "value": "198.51.100.3" });
+        assert_eq!(
+            sco_id("ipv4-addr", &object),
+            "ipv4-addr--a7b17001-f20f-5170-885a-d8b87bf94252"
+        );
+    }
+
+    #[test]
+    fn patterns() {
+        let group = HostNetworkGroup::new(
+            vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))],
+            vec!["10.0.0.0/8".parse().unwrap()],
+            vec![
+                IpAddr::V4(Ipv4Addr::new(198, 51, 100, 0))
+                    ..=IpAddr::V4(Ipv4Addr::new(198, 51, 100, 5)),
+            ],
+        );
+        assert_eq!(
+            network_pattern(&group).unwrap(),
+            "[ipv4-addr:value = '192.0.2.1' OR ipv4-addr:value ISSUBSET '10.0.0.0/8' \
+             OR ipv4-addr:value ISSUBSET '198.51.100.0/30' \
+             OR ipv4-addr:value ISSUBSET '198.51.100.4/31']"
+        );
+        assert!(network_pattern(&HostNetworkGroup::new(vec![], vec![], vec![])).is_err());
+        assert_eq!(
+            address_comparison("2001:db8::/32").unwrap(),
+            "ipv6-addr:value ISSUBSET '2001:db8::/32'"
+        );
+        assert!(address_comparison("example.com").is_err());
+        assert_eq!(escape_pattern(r"a'b\c"), r"a\'b\\c");
+    }
+
+    #[test]
+    fn label_db_rules_and_model_indicators() {
+        let mut bundle = StixBundle::new(jiff::Timestamp::UNIX_EPOCH).unwrap();
+        let db = LabelDb {
+            id: 1,
+            name: "tokens".to_string(),
+            description: None,
+            kind: LabelDbKind::Token,
+            category: EventCategory::CommandAndControl,
+            version: "1".to_string(),
+            patterns: vec![
+                rule(1, Some(vec!["a.b".to_string(), "c".to_string()])),
+                rule(2, None),
+            ],
+        };
+        assert_eq!(bundle.add_label_db(&db).unwrap(), 1);
+        let ip_db = LabelDb {
+            name: "addresses".to_string(),
+            kind: LabelDbKind::Ip,
+            patterns: vec![rule(1, Some(vec!["example.com".to_string()]))],
+            ..db.clone()
+        };
+        assert!(bundle.add_label_db(&ip_db).is_err());
+
+        let mut indicator = ModelIndicator {
+            name: "model".to_string(),
+            model_id: 7,
+            ..ModelIndicator::default()
+        };
+        assert!(bundle.add_model_indicator(&indicator).is_err());
+        indicator
+            .tokens
+            .insert(vec!["GET".to_string(), "/a?b".to_string()]);
+        bundle.add_model_indicator(&indicator).unwrap();
+
+        let bundle = bundle.into_json();
+        let objects = bundle["objects"].as_array().unwrap();
+        let rule = &objects[1];
+        assert_eq!(rule["name"], "rule 1");
+        assert_eq!(rule["pattern"], r"a\.b|c");
+        assert_eq!(rule["pattern_type"], "pcre");
+        assert_eq!(rule["confidence"], 90);
+        assert_eq!(rule["valid_from"], "1970-01-01T00:00:00.000Z");
+        assert_eq!(
+            rule["kill_chain_phases"],
+            json!([{ "kill_chain_name": "mitre-attack", "phase_name": "exfiltration" }])
+        );
+        assert_eq!(rule["external_references"][0]["source_name"], "tokens");
+        let model = &objects[2];
+        assert_eq!(model["pattern"], r"GET.*?/a\?b");
+        assert_eq!(model["x_review_model_id"], 7);
+        assert_eq!(
+            model["kill_chain_phases"][0]["phase_name"],
+            "reconnaissance"
+        );
+        assert_eq!(objects.len(), 3);
+    }
+}
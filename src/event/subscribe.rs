@@ -0,0 +1,148 @@
+//! In-process subscriptions to the events being stored.
+//!
+//! [`EventDb::put`] and [`EventDb::update`] publish an [`EventNotice`] to every
+//! [`Subscription`] once their transaction commits. Each subscription buffers
+//! up to the number of notices it was created with; a notice that does not fit
+//! is dropped and counted, so a slow subscriber never holds up a write. A
+//! subscriber that sees [`Subscription::lagged`] return a nonzero count has
+//! missed events, and can catch up by reading the events after the last key it
+//! received with [`EventDb::iter_from`].
+//!
+//! [`EventDb::put`]: super::EventDb::put
+//! [`EventDb::update`]: super::EventDb::update
+//! [`EventDb::iter_from`]: super::EventDb::iter_from
+
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{Receiver, RecvTimeoutError, SyncSender, TrySendError, sync_channel},
+    },
+    time::Duration,
+};
+
+use super::EventKind;
+
+/// A notice that an event was stored under `key`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EventNotice {
+    pub key: i128,
+    pub kind: EventKind,
+}
+
+/// A subscription to the events being stored, created by
+/// [`EventDb::subscribe`].
+///
+/// Dropping the subscription unsubscribes it.
+///
+/// [`EventDb::subscribe`]: super::EventDb::subscribe
+pub struct Subscription {
+    receiver: Receiver<EventNotice>,
+    lagged: Arc<AtomicU64>,
+}
+
+impl Subscription {
+    /// Waits for the next notice. Returns `None` once the store is dropped and
+    /// every buffered notice has been received.
+    #[must_use]
+    pub fn recv(&self) -> Option<EventNotice> {
+        self.receiver.recv().ok()
+    }
+
+    /// Waits up to `timeout` for the next notice. Returns `None` if none
+    /// arrives in time or the store is dropped.
+    #[must_use]
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<EventNotice> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(notice) => Some(notice),
+            Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => None,
+        }
+    }
+
+    /// Returns the next buffered notice, if any, without waiting.
+    #[must_use]
+    pub fn try_recv(&self) -> Option<EventNotice> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Returns the number of notices dropped because the buffer was full since
+    /// the last call, and resets it.
+    #[must_use]
+    pub fn lagged(&self) -> u64 {
+        self.lagged.swap(0, Ordering::Relaxed)
+    }
+}
+
+struct Subscriber {
+    sender: SyncSender<EventNotice>,
+    lagged: Arc<AtomicU64>,
+}
+
+/// The subscriptions of a store.
+#[derive(Default)]
+pub(crate) struct Subscribers {
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+impl Subscribers {
+    /// Registers a subscription buffering up to `capacity` notices.
+    pub(super) fn subscribe(&self, capacity: usize) -> Subscription {
+        let (sender, receiver) = sync_channel(capacity);
+        let lagged = Arc::new(AtomicU64::new(0));
+        self.lock().push(Subscriber {
+            sender,
+            lagged: lagged.clone(),
+        });
+        Subscription { receiver, lagged }
+    }
+
+    /// Sends `notice` to every subscription, dropping the subscriptions that
+    /// were dropped by their subscribers.
+    pub(super) fn publish(&self, notice: EventNotice) {
+        self.lock()
+            .retain(|subscriber| match subscriber.sender.try_send(notice) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    subscriber.lagged.fetch_add(1, Ordering::Relaxed);
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            });
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Subscriber>> {
+        // A panic while the list is locked leaves it consistent, as every
+        // change to it is a single push or retain.
+        self.subscribers
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EventKind, EventNotice, Subscribers};
+
+    #[test]
+    fn full_buffers_count_lag() {
+        let subscribers = Subscribers::default();
+        let subscription = subscribers.subscribe(1);
+        for key in 0..3 {
+            subscribers.publish(EventNotice {
+                key,
+                kind: EventKind::PortScan,
+            });
+        }
+        assert_eq!(subscription.try_recv().map(|n| n.key), Some(0));
+        assert_eq!(subscription.try_recv(), None);
+        assert_eq!(subscription.lagged(), 2);
+        assert_eq!(subscription.lagged(), 0);
+
+        drop(subscription);
+        subscribers.publish(EventNotice {
+            key: 3,
+            kind: EventKind::PortScan,
+        });
+        assert!(subscribers.lock().is_empty());
+    }
+}
@@ -9,7 +9,7 @@ use jiff::Timestamp;
 use serde::{Deserialize, Serialize};
 
 use super::timestamp::ts_nanoseconds as jiff_ts_nanoseconds;
-use super::{EventCategory, LearningMethod, ThreatLevel, TriageScore, common::Match};
+use super::{EventCategory, EventKind, LearningMethod, ThreatLevel, TriageScore, common::Match};
 use crate::event::common::{AttrValue, triage_scores_to_string};
 
 // TODO: We plan to implement the triage feature only after we have cleaned up the range of
@@ -254,6 +254,14 @@ impl Match for WindowsThreat {
         "windows threat"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::WindowsThreat
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         &self.sensor
     }
@@ -5,7 +5,6 @@
 //! the [`ts_nanoseconds`] serde adapter maps to and from those eight-byte
 //! values without introducing Jiff's default `i128` nanosecond encoding.
 
-#[cfg(test)]
 use chrono::{DateTime, Utc};
 use jiff::{Timestamp, tz::Offset};
 use thiserror::Error;
@@ -81,6 +80,13 @@ pub fn event_key_nanos(time: Timestamp) -> i64 {
     to_i64_nanos(time).unwrap_or(i64::MAX)
 }
 
+/// Converts a timestamp to a chrono UTC datetime at the time encoded in event
+/// database keys.
+#[must_use]
+pub fn event_key_time(time: Timestamp) -> DateTime<Utc> {
+    DateTime::from_timestamp_nanos(event_key_nanos(time))
+}
+
 /// Converts a chrono UTC datetime to a timestamp when it fits the i64 contract.
 ///
 /// # Errors
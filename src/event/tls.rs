@@ -5,9 +5,10 @@ use jiff::Timestamp;
 use serde::{Deserialize, Serialize};
 
 use super::timestamp;
-use super::{EventCategory, LearningMethod, ThreatLevel, TriageScore, common::Match};
-use crate::TriageExclusion;
-use crate::event::common::{AttrValue, triage_scores_to_string, vector_to_string};
+use super::{EventCategory, EventKind, LearningMethod, ThreatLevel, TriageScore, common::Match};
+use crate::event::common::{
+    AttrValue, ExclusionTargets, triage_scores_to_string, vector_to_string,
+};
 
 macro_rules! find_tls_attr_by_kind {
     ($event: expr, $raw_event_attr: expr) => {{
@@ -429,6 +430,14 @@ impl Match for BlocklistTls {
         "blocklist tls"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::BlocklistTls
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -445,18 +454,8 @@ impl Match for BlocklistTls {
         find_tls_attr_by_kind!(self, raw_event_attr)
     }
 
-    fn score_by_triage_exclusion(&self, triage_exclusion: &[TriageExclusion]) -> f64 {
-        let matched = triage_exclusion.iter().any(|ti| match ti {
-            TriageExclusion::IpAddress(filter) => self
-                .orig_addrs()
-                .iter()
-                .chain(self.resp_addrs().iter())
-                .any(|&ip| filter.contains(ip)),
-            TriageExclusion::Domain(regex_set) => regex_set.is_match(&self.server_name),
-            TriageExclusion::Hostname(hostnames) => hostnames.contains(&self.server_name),
-            TriageExclusion::Uri(_) => false, // TLS records don't carry URIs
-        });
-        if matched { f64::MIN } else { 0.0 }
+    fn exclusion_targets(&self) -> ExclusionTargets<'_> {
+        ExclusionTargets::tls(&self.server_name, &self.ja3)
     }
 }
 
@@ -643,6 +642,14 @@ impl Match for SuspiciousTlsTraffic {
         "suspicious tls traffic"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::SuspiciousTlsTraffic
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -659,18 +666,8 @@ impl Match for SuspiciousTlsTraffic {
         find_tls_attr_by_kind!(self, raw_event_attr)
     }
 
-    fn score_by_triage_exclusion(&self, triage_exclusion: &[TriageExclusion]) -> f64 {
-        let matched = triage_exclusion.iter().any(|ti| match ti {
-            TriageExclusion::IpAddress(filter) => self
-                .orig_addrs()
-                .iter()
-                .chain(self.resp_addrs().iter())
-                .any(|&ip| filter.contains(ip)),
-            TriageExclusion::Domain(regex_set) => regex_set.is_match(&self.server_name),
-            TriageExclusion::Hostname(hostnames) => hostnames.contains(&self.server_name),
-            TriageExclusion::Uri(_) => false, // TLS records don't carry URIs
-        });
-        if matched { f64::MIN } else { 0.0 }
+    fn exclusion_targets(&self) -> ExclusionTargets<'_> {
+        ExclusionTargets::tls(&self.server_name, &self.ja3)
     }
 }
 
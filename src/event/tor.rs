@@ -5,10 +5,9 @@ use jiff::Timestamp;
 use serde::{Deserialize, Serialize};
 
 use super::timestamp::{self, ts_nanoseconds as jiff_ts_nanoseconds};
-use super::{EventCategory, LearningMethod, ThreatLevel, TriageScore, common::Match};
-use crate::TriageExclusion;
+use super::{EventCategory, EventKind, LearningMethod, ThreatLevel, TriageScore, common::Match};
 use crate::event::{
-    common::{AttrValue, triage_scores_to_string},
+    common::{AttrValue, ExclusionTargets, triage_scores_to_string},
     conn::{BlocklistConnFieldsStored, find_conn_attr_by_kind},
     http::{HttpEventFieldsStored, find_http_attr_by_kind, get_post_body},
 };
@@ -196,6 +195,14 @@ impl Match for TorConnection {
         "tor exit nodes"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::TorConnection
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
@@ -212,18 +219,8 @@ impl Match for TorConnection {
         find_http_attr_by_kind!(self, raw_event_attr)
     }
 
-    fn score_by_triage_exclusion(&self, triage_exclusion: &[TriageExclusion]) -> f64 {
-        let matched = triage_exclusion.iter().any(|ti| match ti {
-            TriageExclusion::IpAddress(filter) => self
-                .orig_addrs()
-                .iter()
-                .chain(self.resp_addrs().iter())
-                .any(|&ip| filter.contains(ip)),
-            TriageExclusion::Domain(regex_set) => regex_set.is_match(&self.host),
-            TriageExclusion::Hostname(hostnames) => hostnames.contains(&self.host),
-            TriageExclusion::Uri(uris) => uris.contains(&self.uri),
-        });
-        if matched { f64::MIN } else { 0.0 }
+    fn exclusion_targets(&self) -> ExclusionTargets<'_> {
+        ExclusionTargets::http(&self.host, &self.uri, &self.user_agent)
     }
 }
 
@@ -359,6 +356,14 @@ impl Match for TorConnectionConn {
         "tor exit nodes"
     }
 
+    fn event_kind(&self) -> EventKind {
+        EventKind::TorConnectionConn
+    }
+
+    fn time(&self) -> Timestamp {
+        self.time
+    }
+
     fn sensor(&self) -> &str {
         self.sensor.as_str()
     }
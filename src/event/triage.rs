@@ -0,0 +1,1183 @@
+//! Triage policies compiled for scoring many events.
+//!
+//! Scoring an event against a [`TriagePolicyInput`] converts the values of
+//! its packet attributes, resolves their names, and lowercases its threat
+//! kinds anew for every event. A [`CompiledTriagePolicy`] does all of this once,
+//! when it is built. It keeps, for each raw event kind, the rules that can
+//! hold for an event of that kind, so an event is evaluated only against
+//! those, and turns the exclusions, including those the rules refer to, into
+//! matchers: the excluded addresses into an interval tree, and the excluded
+//! domains into an Aho-Corasick automaton.
+//!
+//! The scores are the same as those [`Event::matches`] gives for an
+//! [`EventFilter`] with the same policies.
+//!
+//! [`EventFilter`]: super::EventFilter
+
+use std::{cell::OnceCell, collections::HashSet, net::IpAddr, slice};
+
+use aho_corasick::AhoCorasick;
+use anyhow::{Context, Result};
+use attrievent::attribute::{RawEventAttrKind, RawEventKind};
+use chrono::{DateTime, Utc};
+use memchr::memmem::Finder;
+use regex::RegexSet;
+
+use super::{
+    Event, EventCategory, EventKind, TriageScore,
+    common::{AttrValue, ExclusionTargets, Match, matches_attr},
+    timestamp,
+};
+use crate::{
+    AttrCmpKind, AttrValue as PolicyValue, ExclusionScope, PacketAttr, TriageCondition,
+    TriageExclusion, TriagePolicyInput, TriageRules, tables::AttrOperand,
+};
+
+/// A triage policy prepared for scoring many events.
+pub struct CompiledTriagePolicy {
+    id: u32,
+    exclusions: Exclusions,
+    rules: Vec<Rule>,
+    /// The indices of the rules that can hold for an event of each raw event
+    /// kind, in the order of the rules.
+    rules_by_kind: Vec<(RawEventKind, Vec<usize>)>,
+    /// The indices of the rules that can hold for an event of any raw event
+    /// kind, for the kinds not in `rules_by_kind`.
+    unrestricted_rules: Vec<usize>,
+    confidence: Vec<Confidence>,
+    /// The lowest `minimum_score` among the responses, or `None` if the
+    /// policy has no response.
+    threshold: Option<f64>,
+}
+
+impl CompiledTriagePolicy {
+    /// Compiles `policy`.
+    ///
+    /// Packet attributes whose names are unknown for their raw event kind,
+    /// and references to exclusions missing from `rule_exclusions`, are
+    /// compiled into conditions that never hold.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the automaton for the excluded domains cannot be
+    /// built.
+    pub fn new(policy: &TriagePolicyInput) -> Result<Self> {
+        let rules = match &policy.rules {
+            TriageRules::V1(rules) => rules
+                .iter()
+                .map(|rule| {
+                    Ok(Rule {
+                        condition: Condition::new(&rule.condition, policy)?,
+                        weight: rule.weight,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?,
+        };
+        let restrictions: Vec<_> = rules
+            .iter()
+            .map(|rule| rule.condition.raw_event_kind())
+            .collect();
+        let indices_of = |applies: &dyn Fn(Option<RawEventKind>) -> bool| {
+            restrictions
+                .iter()
+                .enumerate()
+                .filter(|(_, restriction)| applies(**restriction))
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>()
+        };
+        let mut rules_by_kind: Vec<(RawEventKind, Vec<usize>)> = Vec::new();
+        for &kind in restrictions.iter().flatten() {
+            if rules_by_kind.iter().all(|(k, _)| *k != kind) {
+                rules_by_kind.push((
+                    kind,
+                    indices_of(&|restriction| restriction.is_none_or(|k| k == kind)),
+                ));
+            }
+        }
+        let unrestricted_rules = indices_of(&|restriction| restriction.is_none());
+        let confidence = policy
+            .confidence
+            .iter()
+            .map(|conf| Confidence {
+                category: conf.threat_category,
+                kind: conf.threat_kind.to_lowercase(),
+                confidence: conf.confidence,
+                weight: conf.weight.unwrap_or(1.0),
+            })
+            .collect();
+        let threshold = policy
+            .response
+            .iter()
+            .map(|response| response.minimum_score)
+            .reduce(f64::min);
+        Ok(Self {
+            id: policy.id,
+            exclusions: Exclusions::new(&policy.triage_exclusion)?,
+            rules,
+            rules_by_kind,
+            unrestricted_rules,
+            confidence,
+            threshold,
+        })
+    }
+
+    #[must_use]
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Returns the score of `event`, or `None` if one of the exclusions
+    /// matches it or its score reaches none of the response thresholds.
+    #[must_use]
+    pub fn score(&self, event: &Event) -> Option<TriageScore> {
+        self.score_subject(&Subject::new(event))
+    }
+
+    fn score_subject(&self, subject: &Subject) -> Option<TriageScore> {
+        if self.exclusions.matches(subject) {
+            return None;
+        }
+        let score = self.score_by_rules(subject) + self.score_by_confidence(subject);
+        self.threshold
+            .is_some_and(|threshold| score >= threshold)
+            .then_some(TriageScore {
+                policy_id: self.id,
+                score,
+            })
+    }
+
+    fn score_by_rules(&self, subject: &Subject) -> f64 {
+        let indices = self
+            .rules_by_kind
+            .iter()
+            .find(|(kind, _)| *kind == subject.raw_event_kind)
+            .map_or(&self.unrestricted_rules, |(_, indices)| indices);
+        let total = indices.iter().fold(0.0, |score, &i| {
+            let rule = &self.rules[i];
+            if rule.condition.holds(subject) {
+                score + rule.weight
+            } else {
+                score
+            }
+        });
+        (total * 100.0).trunc() / 100.0
+    }
+
+    fn score_by_confidence(&self, subject: &Subject) -> f64 {
+        self.confidence.iter().fold(0.0, |score, conf| {
+            if conf.category == subject.event.category()
+                && conf.kind == subject.kind()
+                && subject
+                    .event
+                    .confidence()
+                    .is_none_or(|c| f64::from(c) >= conf.confidence)
+            {
+                score + conf.weight
+            } else {
+                score
+            }
+        })
+    }
+}
+
+impl Event {
+    /// Scores this event against each of `policies`, returning the scores
+    /// that reach a response threshold of their policy.
+    #[must_use]
+    pub fn score_against_compiled_policies(
+        &self,
+        policies: &[CompiledTriagePolicy],
+    ) -> Vec<TriageScore> {
+        let subject = Subject::new(self);
+        policies
+            .iter()
+            .filter_map(|policy| policy.score_subject(&subject))
+            .collect()
+    }
+}
+
+/// What scoring reads from an event, obtained once for all the policies it is
+/// scored against.
+struct Subject<'a> {
+    event: &'a dyn Match,
+    event_kind: EventKind,
+    raw_event_kind: RawEventKind,
+    /// The time the event is keyed by, at which the scopes of scoped
+    /// exclusions are checked.
+    time: DateTime<Utc>,
+    targets: ExclusionTargets<'a>,
+    kind: OnceCell<String>,
+}
+
+impl<'a> Subject<'a> {
+    fn new(event: &'a Event) -> Self {
+        let (event_kind, _) = event.kind_and_category();
+        let event = event.as_match();
+        Self {
+            event,
+            event_kind,
+            raw_event_kind: raw_event_kind(event_kind),
+            time: timestamp::event_key_time(event.time()),
+            targets: event.exclusion_targets(),
+            kind: OnceCell::new(),
+        }
+    }
+
+    /// Returns the kind of the event in lowercase.
+    fn kind(&self) -> &str {
+        self.kind.get_or_init(|| self.event.kind().to_lowercase())
+    }
+}
+
+/// Returns the kind of the raw events that an event of `kind` was detected
+/// in, which is the kind of the packet attributes it can be compared with.
+fn raw_event_kind(kind: EventKind) -> RawEventKind {
+    match kind {
+        EventKind::DnsCovertChannel
+        | EventKind::LockyRansomware
+        | EventKind::CryptocurrencyMiningPool
+        | EventKind::BlocklistDns
+        | EventKind::BlocklistMalformedDns => RawEventKind::Dns,
+        EventKind::HttpThreat
+        | EventKind::RepeatedHttpSessions
+        | EventKind::TorConnection
+        | EventKind::DomainGenerationAlgorithm
+        | EventKind::NonBrowser
+        | EventKind::BlocklistHttp => RawEventKind::Http,
+        EventKind::PortScan
+        | EventKind::MultiHostPortScan
+        | EventKind::ExternalDdos
+        | EventKind::BlocklistConn
+        | EventKind::TorConnectionConn
+        | EventKind::UnusualDestinationPattern => RawEventKind::Conn,
+        EventKind::RdpBruteForce | EventKind::BlocklistRdp => RawEventKind::Rdp,
+        EventKind::FtpBruteForce | EventKind::FtpPlainText | EventKind::BlocklistFtp => {
+            RawEventKind::Ftp
+        }
+        EventKind::LdapBruteForce | EventKind::LdapPlainText | EventKind::BlocklistLdap => {
+            RawEventKind::Ldap
+        }
+        EventKind::BlocklistTls | EventKind::SuspiciousTlsTraffic => RawEventKind::Tls,
+        EventKind::BlocklistBootp => RawEventKind::Bootp,
+        EventKind::BlocklistDceRpc => RawEventKind::DceRpc,
+        EventKind::BlocklistDhcp => RawEventKind::Dhcp,
+        EventKind::BlocklistKerberos => RawEventKind::Kerberos,
+        EventKind::BlocklistMqtt => RawEventKind::Mqtt,
+        EventKind::BlocklistNfs => RawEventKind::Nfs,
+        EventKind::BlocklistNtlm => RawEventKind::Ntlm,
+        EventKind::BlocklistRadius => RawEventKind::Radius,
+        EventKind::BlocklistSmb => RawEventKind::Smb,
+        EventKind::BlocklistSmtp => RawEventKind::Smtp,
+        EventKind::BlocklistSsh => RawEventKind::Ssh,
+        EventKind::ExtraThreat => RawEventKind::Log,
+        EventKind::NetworkThreat => RawEventKind::Network,
+        EventKind::WindowsThreat => RawEventKind::Window,
+    }
+}
+
+struct Rule {
+    condition: Condition,
+    weight: f64,
+}
+
+/// A condition of a rule, with its packet attributes decoded and the
+/// exclusions it refers to compiled.
+enum Condition {
+    Attr(Attr),
+    Exclusion(Exclusions),
+    All(Vec<Condition>),
+    /// Never holds if empty, which is what an attribute or exclusion that can
+    /// never match compiles into.
+    Any(Vec<Condition>),
+    Not(Box<Condition>),
+}
+
+impl Condition {
+    fn new(condition: &TriageCondition, policy: &TriagePolicyInput) -> Result<Self> {
+        let compile_all = |conditions: &[TriageCondition]| {
+            conditions
+                .iter()
+                .map(|condition| Self::new(condition, policy))
+                .collect::<Result<Vec<_>>>()
+        };
+        Ok(match condition {
+            TriageCondition::Attr(attr) => {
+                Attr::new(attr).map_or_else(|| Self::Any(Vec::new()), Self::Attr)
+            }
+            TriageCondition::Exclusion(id) => match policy.rule_exclusions.get(id) {
+                Some(exclusion) => Self::Exclusion(Exclusions::new(slice::from_ref(exclusion))?),
+                None => Self::Any(Vec::new()),
+            },
+            TriageCondition::All(conditions) => Self::All(compile_all(conditions)?),
+            TriageCondition::Any(conditions) => Self::Any(compile_all(conditions)?),
+            TriageCondition::Not(condition) => Self::Not(Box::new(Self::new(condition, policy)?)),
+        })
+    }
+
+    /// Returns the raw event kind an event must be of for the condition to
+    /// hold, or `None` if it can hold for an event of any kind.
+    fn raw_event_kind(&self) -> Option<RawEventKind> {
+        match self {
+            Self::Attr(attr) => Some(attr.raw_event_kind),
+            Self::Exclusion(_) | Self::Not(_) => None,
+            Self::All(conditions) => conditions.iter().find_map(Self::raw_event_kind),
+            Self::Any(conditions) => {
+                let mut kinds = conditions.iter().map(Self::raw_event_kind);
+                let first = kinds.next()??;
+                kinds.all(|kind| kind == Some(first)).then_some(first)
+            }
+        }
+    }
+
+    fn holds(&self, subject: &Subject) -> bool {
+        match self {
+            Self::Attr(attr) => {
+                attr.raw_event_kind == subject.raw_event_kind
+                    && subject
+                        .event
+                        .find_attr_by_kind(attr.kind)
+                        .is_some_and(|value| attr.matches(&value))
+            }
+            Self::Exclusion(exclusions) => exclusions.matches(subject),
+            Self::All(conditions) => conditions.iter().all(|condition| condition.holds(subject)),
+            Self::Any(conditions) => conditions.iter().any(|condition| condition.holds(subject)),
+            Self::Not(condition) => !condition.holds(subject),
+        }
+    }
+}
+
+/// A packet attribute with its values decoded.
+struct Attr {
+    raw_event_kind: RawEventKind,
+    kind: RawEventAttrKind,
+    cmp_kind: AttrCmpKind,
+    first: Operand,
+    second: Operand,
+    /// The finder of the bytes a raw attribute must contain, or `None` if the
+    /// value kind cannot be compared with raw bytes.
+    bytes: Option<Finder<'static>>,
+}
+
+impl Attr {
+    fn new(attr: &PacketAttr) -> Option<Self> {
+        let kind = RawEventAttrKind::from_kind_and_attr_name(&attr.raw_event_kind, &attr.attr_name)
+            .ok()?;
+        let (first, second) = Operand::new(&attr.value);
+        Some(Self {
+            raw_event_kind: attr.raw_event_kind,
+            kind,
+            cmp_kind: attr.cmp_kind,
+            first,
+            second,
+            bytes: attr
+                .value
+                .as_bytes()
+                .map(|bytes| Finder::new(bytes).into_owned()),
+        })
+    }
+
+    fn matches(&self, value: &AttrValue) -> bool {
+        match value {
+            AttrValue::Addr(v) => self.matches_addr(v),
+            AttrValue::Bool(v) => self.matches_bool(*v),
+            AttrValue::Float(v) => self.matches_number(v, self.first.float, self.second.float),
+            AttrValue::SInt(v) => self.matches_number(v, self.first.sint, self.second.sint),
+            AttrValue::UInt(v) => self.matches_number(v, self.first.uint, self.second.uint),
+            AttrValue::String(v) => self.matches_string(v),
+            AttrValue::VecAddr(vs) => self.matches_list(vs, |v| self.matches_addr(v)),
+            AttrValue::VecBool(vs) => self.matches_list(vs, |v| self.matches_bool(*v)),
+            AttrValue::VecFloat(vs) => self.matches_list(vs, |v| {
+                self.matches_number(v, self.first.float, self.second.float)
+            }),
+            AttrValue::VecSInt(vs) => self.matches_list(vs, |v| {
+                self.matches_number(v, self.first.sint, self.second.sint)
+            }),
+            AttrValue::VecUInt(vs) => self.matches_list(vs, |v| {
+                self.matches_number(v, self.first.uint, self.second.uint)
+            }),
+            AttrValue::VecString(vs) => self.matches_list(vs, |v| self.matches_string(v)),
+            AttrValue::VecRaw(v) => self.matches_bytes(v),
+            AttrValue::VecRawList(vs) => self.matches_list(vs, |v| self.matches_bytes(v)),
+        }
+    }
+
+    /// Compares each of `values`, requiring all of them to match for a
+    /// negated comparison and any of them otherwise.
+    fn matches_list<T>(&self, values: &[T], matches: impl Fn(&T) -> bool) -> bool {
+        match self.cmp_kind {
+            AttrCmpKind::NotEqual | AttrCmpKind::NotContain => values.iter().all(matches),
+            _ => values.iter().any(matches),
+        }
+    }
+
+    fn matches_addr(&self, value: &IpAddr) -> bool {
+        self.first
+            .addr
+            .is_some_and(|first| matches_attr(self.cmp_kind, value, &first, self.second.addr))
+    }
+
+    fn matches_bool(&self, value: bool) -> bool {
+        self.first.bool.is_some_and(|first| match self.cmp_kind {
+            AttrCmpKind::Equal => value == first,
+            AttrCmpKind::NotEqual => value != first,
+            _ => false,
+        })
+    }
+
+    fn matches_number<T: Copy + PartialOrd>(
+        &self,
+        value: &T,
+        first: Option<T>,
+        second: Option<T>,
+    ) -> bool {
+        first.is_some_and(|first| matches_attr(self.cmp_kind, value, &first, second))
+    }
+
+    fn matches_string(&self, value: &str) -> bool {
+        self.first.string.as_deref().is_some_and(|first| {
+            let contained = value.contains(first);
+            match self.cmp_kind {
+                AttrCmpKind::Contain => contained,
+                AttrCmpKind::NotContain => !contained,
+                _ => false,
+            }
+        })
+    }
+
+    fn matches_bytes(&self, value: &[u8]) -> bool {
+        self.bytes.as_ref().is_some_and(|finder| {
+            let contained = finder.find(value).is_some();
+            match self.cmp_kind {
+                AttrCmpKind::Contain => contained,
+                AttrCmpKind::NotContain => !contained,
+                _ => false,
+            }
+        })
+    }
+}
+
+/// The value of a packet attribute, or one bound of its range, as each type
+/// an attribute of an event can have. The types it cannot be compared as are
+/// `None`.
+struct Operand {
+    addr: Option<IpAddr>,
+    bool: Option<bool>,
+    float: Option<f64>,
+    sint: Option<i64>,
+    uint: Option<u64>,
+    string: Option<String>,
+}
+
+impl Operand {
+    /// Returns the value, or the lower bound of the range, and the upper
+    /// bound of the range.
+    fn new(value: &PolicyValue) -> (Self, Self) {
+        fn split<T: AttrOperand>(value: &PolicyValue) -> (Option<T>, Option<T>) {
+            value
+                .operands()
+                .map_or((None, None), |(first, second)| (Some(first), second))
+        }
+        let (first_addr, second_addr) = split(value);
+        let (first_bool, second_bool) = split(value);
+        let (first_float, second_float) = split(value);
+        let (first_sint, second_sint) = split(value);
+        let (first_uint, second_uint) = split(value);
+        let first = Self {
+            addr: first_addr,
+            bool: first_bool,
+            float: first_float,
+            sint: first_sint,
+            uint: first_uint,
+            string: value.as_str().map(str::to_owned),
+        };
+        let second = Self {
+            addr: second_addr,
+            bool: second_bool,
+            float: second_float,
+            sint: second_sint,
+            uint: second_uint,
+            string: None,
+        };
+        (first, second)
+    }
+}
+
+struct Confidence {
+    category: Option<EventCategory>,
+    /// The threat kind in lowercase.
+    kind: String,
+    confidence: f64,
+    weight: f64,
+}
+
+/// The exclusions of a policy, merged by kind.
+#[derive(Default)]
+struct Exclusions {
+    addresses: AddressTree,
+    domains: Option<AhoCorasick>,
+    /// The domain exclusions whose patterns were not built from a list of
+    /// domains, matched as they are.
+    domain_patterns: Vec<RegexSet>,
+    hostnames: HashSet<String>,
+    uris: HashSet<String>,
+    hostname_patterns: Vec<RegexSet>,
+    uri_patterns: Vec<RegexSet>,
+    user_agents: Vec<RegexSet>,
+    /// The JA3 fingerprints in lowercase.
+    ja3: HashSet<String>,
+    /// The exclusions that apply only within their scopes, each compiled on
+    /// its own.
+    scoped: Vec<(ExclusionScope, Exclusions)>,
+}
+
+impl Exclusions {
+    fn new(exclusions: &[TriageExclusion]) -> Result<Self> {
+        let mut ranges = Vec::new();
+        let mut domains = Vec::new();
+        let mut compiled = Self::default();
+        for exclusion in exclusions {
+            match exclusion {
+                TriageExclusion::IpAddress(filter) => ranges.extend(filter.ranges()),
+                TriageExclusion::Domain(set) => {
+                    match set
+                        .patterns()
+                        .iter()
+                        .map(|pattern| domain_of_pattern(pattern))
+                        .collect::<Option<Vec<_>>>()
+                    {
+                        Some(list) => domains.extend(list),
+                        None => compiled.domain_patterns.push(set.clone()),
+                    }
+                }
+                TriageExclusion::Hostname(hostnames) => {
+                    compiled.hostnames.extend(hostnames.iter().cloned());
+                }
+                TriageExclusion::Uri(uris) => compiled.uris.extend(uris.iter().cloned()),
+                TriageExclusion::HostnamePattern(set) => {
+                    compiled.hostname_patterns.push(set.clone());
+                }
+                TriageExclusion::UriPattern(set) => compiled.uri_patterns.push(set.clone()),
+                TriageExclusion::UserAgent(set) => compiled.user_agents.push(set.clone()),
+                TriageExclusion::Ja3(fingerprints) => {
+                    compiled.ja3.extend(fingerprints.iter().cloned());
+                }
+                TriageExclusion::Scoped(scope, exclusion) => compiled
+                    .scoped
+                    .push((scope.clone(), Self::new(slice::from_ref(exclusion))?)),
+            }
+        }
+        compiled.addresses = AddressTree::new(ranges);
+        if !domains.is_empty() {
+            compiled.domains = Some(
+                AhoCorasick::new(&domains).context("cannot build matcher of excluded domains")?,
+            );
+        }
+        Ok(compiled)
+    }
+
+    fn matches(&self, subject: &Subject) -> bool {
+        let event = subject.event;
+        let targets = &subject.targets;
+        event
+            .orig_addrs()
+            .iter()
+            .chain(event.resp_addrs().iter())
+            .any(|&addr| self.addresses.contains(addr))
+            || targets
+                .domain
+                .is_some_and(|domain| self.matches_domain(domain))
+            || targets.hostname.is_some_and(|hostname| {
+                self.hostnames.contains(hostname)
+                    || self
+                        .hostname_patterns
+                        .iter()
+                        .any(|set| set.is_match(hostname))
+            })
+            || targets.uri.is_some_and(|uri| {
+                self.uris.contains(uri) || self.uri_patterns.iter().any(|set| set.is_match(uri))
+            })
+            || targets.user_agent.is_some_and(|user_agent| {
+                self.user_agents.iter().any(|set| set.is_match(user_agent))
+            })
+            || targets
+                .ja3
+                .is_some_and(|ja3| self.ja3.contains(&ja3.to_ascii_lowercase()))
+            || self.scoped.iter().any(|(scope, exclusions)| {
+                scope.applies(subject.event_kind, event.sensor(), subject.time)
+                    && exclusions.matches(subject)
+            })
+    }
+
+    /// Returns whether `name` is one of the excluded domains or a subdomain
+    /// of one.
+    fn matches_domain(&self, name: &str) -> bool {
+        let suffix_matched = self.domains.as_ref().is_some_and(|domains| {
+            domains.find_overlapping_iter(name).any(|m| {
+                m.end() == name.len() && (m.start() == 0 || name.as_bytes()[m.start() - 1] == b'.')
+            })
+        });
+        suffix_matched || self.domain_patterns.iter().any(|set| set.is_match(name))
+    }
+}
+
+/// Returns the domain a pattern of a domain exclusion was built from, or
+/// `None` if it was not built by `TriageExclusion::from`.
+fn domain_of_pattern(pattern: &str) -> Option<String> {
+    let (exact, subdomain) = pattern
+        .strip_prefix("(^")?
+        .strip_suffix("$)")?
+        .split_once(r"$|\.")?;
+    if exact != subdomain {
+        return None;
+    }
+    let mut domain = String::with_capacity(exact.len());
+    let mut chars = exact.chars();
+    while let Some(c) = chars.next() {
+        domain.push(if c == '\\' { chars.next()? } else { c });
+    }
+    (regex::escape(&domain) == exact).then_some(domain)
+}
+
+/// An interval tree of address ranges.
+///
+/// The ranges are sorted by their starts and laid out as an implicit balanced
+/// binary search tree, in which the node of a subrange is its middle range.
+/// Each node also holds the largest end in its subtree, so a lookup skips
+/// every subtree that ends before the address and every right subtree that
+/// starts after it.
+#[derive(Default)]
+struct AddressTree {
+    ranges: Vec<(IpAddr, IpAddr)>,
+    /// The largest end of the ranges in the subtree of each node.
+    max_ends: Vec<IpAddr>,
+}
+
+impl AddressTree {
+    fn new(mut ranges: Vec<(IpAddr, IpAddr)>) -> Self {
+        ranges.retain(|(start, end)| start <= end);
+        ranges.sort_unstable();
+        let mut max_ends: Vec<IpAddr> = ranges.iter().map(|&(_, end)| end).collect();
+        fill_max_ends(&mut max_ends, 0, ranges.len());
+        Self { ranges, max_ends }
+    }
+
+    fn contains(&self, addr: IpAddr) -> bool {
+        self.stab(addr, 0, self.ranges.len())
+    }
+
+    /// Returns whether a range in the subtree of `ranges[lo..hi]` contains
+    /// `addr`.
+    fn stab(&self, addr: IpAddr, lo: usize, hi: usize) -> bool {
+        if lo >= hi {
+            return false;
+        }
+        let mid = lo + (hi - lo) / 2;
+        if self.max_ends[mid] < addr {
+            return false;
+        }
+        let (start, end) = self.ranges[mid];
+        if start <= addr && addr <= end {
+            return true;
+        }
+        self.stab(addr, lo, mid) || (start <= addr && self.stab(addr, mid + 1, hi))
+    }
+}
+
+/// Sets the node of the subtree of `max_ends[lo..hi]` to the largest end in
+/// the subtree, and returns it.
+fn fill_max_ends(max_ends: &mut [IpAddr], lo: usize, hi: usize) -> Option<IpAddr> {
+    if lo >= hi {
+        return None;
+    }
+    let mid = lo + (hi - lo) / 2;
+    let left = fill_max_ends(max_ends, lo, mid);
+    let right = fill_max_ends(max_ends, mid + 1, hi);
+    let max = [left, right]
+        .into_iter()
+        .flatten()
+        .fold(max_ends[mid], IpAddr::max);
+    max_ends[mid] = max;
+    Some(max)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        slice,
+    };
+
+    use attrievent::attribute::{ConnAttr, DnsAttr, HttpAttr, RawEventKind, TlsAttr};
+    use chrono::{Duration, TimeZone, Utc};
+    use ipnet::IpNet;
+    use jiff::Timestamp;
+
+    use super::{AddressTree, CompiledTriagePolicy, domain_of_pattern};
+    use crate::{
+        AttrCmpKind, AttrValue, Confidence, EventKind, ExclusionReason, ExclusionScope,
+        HostNetworkGroup, PacketAttr, Response, ResponseKind, TriageCondition, TriageExclusion,
+        TriagePolicyInput, TriageRule, TriageRules,
+        event::{Event, EventFilter, decode_stored, stored_event_samples_v0_46},
+    };
+
+    fn samples() -> Vec<Event> {
+        stored_event_samples_v0_46()
+            .into_iter()
+            .map(|(kind, value)| decode_stored(kind, Timestamp::UNIX_EPOCH, &value).unwrap())
+            .collect()
+    }
+
+    fn attr(
+        raw_event_kind: RawEventKind,
+        attr_name: String,
+        cmp_kind: AttrCmpKind,
+        value: AttrValue,
+        weight: f64,
+    ) -> PacketAttr {
+        PacketAttr {
+            raw_event_kind,
+            attr_name,
+            cmp_kind,
+            value,
+            weight: Some(weight),
+        }
+    }
+
+    fn policy(
+        id: u32,
+        triage_exclusion: Vec<TriageExclusion>,
+        packet_attr: Vec<PacketAttr>,
+        confidence: Vec<Confidence>,
+        minimum_score: f64,
+    ) -> TriagePolicyInput {
+        TriagePolicyInput {
+            id,
+            name: format!("policy-{id}"),
+            creation_time: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+            triage_exclusion,
+            rules: TriageRules::from(packet_attr),
+            rule_exclusions: std::collections::HashMap::new(),
+            confidence,
+            response: vec![Response {
+                minimum_score,
+                kind: ResponseKind::Manual,
+            }],
+        }
+    }
+
+    /// Returns the scores `Event::matches` gives for `policies`.
+    fn filter_scores(event: &Event, policies: &[TriagePolicyInput]) -> Vec<(u32, f64)> {
+        let filter = EventFilter::new(
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(policies.to_vec()),
+        );
+        event
+            .matches(&filter)
+            .unwrap()
+            .1
+            .unwrap_or_default()
+            .into_iter()
+            .map(|score| (score.policy_id, score.score))
+            .collect()
+    }
+
+    #[test]
+    fn scores_equal_filter_scores() {
+        let localhost = AttrValue::IpAddr(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let loopback = AttrValue::IpAddrRange(
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 0)),
+            IpAddr::V4(Ipv4Addr::new(127, 255, 255, 255)),
+        );
+        let packet_attr = vec![
+            attr(
+                RawEventKind::Conn,
+                ConnAttr::SrcAddr.to_string(),
+                AttrCmpKind::CloseRange,
+                loopback.clone(),
+                0.25,
+            ),
+            attr(
+                RawEventKind::Conn,
+                ConnAttr::DstPort.to_string(),
+                AttrCmpKind::Greater,
+                AttrValue::UInteger(0),
+                0.5,
+            ),
+            attr(
+                RawEventKind::Dns,
+                DnsAttr::SrcAddr.to_string(),
+                AttrCmpKind::Equal,
+                localhost.clone(),
+                0.333,
+            ),
+            attr(
+                RawEventKind::Dns,
+                DnsAttr::Query.to_string(),
+                AttrCmpKind::NotContain,
+                AttrValue::String("example".to_string()),
+                0.125,
+            ),
+            attr(
+                RawEventKind::Http,
+                HttpAttr::SrcAddr.to_string(),
+                AttrCmpKind::RightOpenRange,
+                loopback,
+                0.75,
+            ),
+            attr(
+                RawEventKind::Http,
+                HttpAttr::DstPort.to_string(),
+                AttrCmpKind::CloseRange,
+                AttrValue::UIntegerRange(1, 65_535),
+                0.2,
+            ),
+            attr(
+                RawEventKind::Tls,
+                TlsAttr::SrcAddr.to_string(),
+                AttrCmpKind::NotEqual,
+                localhost,
+                0.4,
+            ),
+        ];
+
+        for event in samples() {
+            let m = event.as_match();
+            let confidence = vec![Confidence {
+                threat_category: m.category(),
+                threat_kind: m.kind().to_uppercase(),
+                confidence: 0.0,
+                weight: Some(0.5),
+            }];
+            let policies = vec![
+                policy(1, Vec::new(), packet_attr.clone(), confidence.clone(), 0.0),
+                policy(2, Vec::new(), packet_attr.clone(), Vec::new(), 0.5),
+                policy(3, Vec::new(), Vec::new(), confidence, 1.0),
+            ];
+            let compiled = policies
+                .iter()
+                .map(|policy| CompiledTriagePolicy::new(policy).unwrap())
+                .collect::<Vec<_>>();
+
+            let scores = event
+                .score_against_compiled_policies(&compiled)
+                .into_iter()
+                .map(|score| (score.policy_id, score.score))
+                .collect::<Vec<_>>();
+            assert_eq!(scores, filter_scores(&event, &policies), "{}", m.kind());
+            assert!(
+                scores
+                    .first()
+                    .is_some_and(|(id, score)| *id == 1 && *score >= 0.5)
+            );
+        }
+    }
+
+    #[test]
+    fn condition_trees_equal_filter_scores() {
+        let loopback = AttrValue::IpAddrRange(
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 0)),
+            IpAddr::V4(Ipv4Addr::new(127, 255, 255, 255)),
+        );
+        let leaf = |raw_event_kind, attr_name: String, cmp_kind, value| {
+            TriageCondition::Attr(attr(raw_event_kind, attr_name, cmp_kind, value, 0.0))
+        };
+        let dns_loopback = leaf(
+            RawEventKind::Dns,
+            DnsAttr::SrcAddr.to_string(),
+            AttrCmpKind::CloseRange,
+            loopback.clone(),
+        );
+        let http_loopback = leaf(
+            RawEventKind::Http,
+            HttpAttr::SrcAddr.to_string(),
+            AttrCmpKind::CloseRange,
+            loopback.clone(),
+        );
+        let http_unknown = leaf(
+            RawEventKind::Http,
+            "no such attribute".to_string(),
+            AttrCmpKind::Equal,
+            AttrValue::IpAddr(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+        );
+        let conn_loopback = leaf(
+            RawEventKind::Conn,
+            ConnAttr::SrcAddr.to_string(),
+            AttrCmpKind::CloseRange,
+            loopback,
+        );
+        let rule = |condition, weight| TriageRule { condition, weight };
+        let rules = TriageRules::V1(vec![
+            rule(
+                TriageCondition::All(vec![
+                    dns_loopback,
+                    TriageCondition::Not(Box::new(TriageCondition::Exclusion(1))),
+                ]),
+                0.5,
+            ),
+            rule(TriageCondition::Not(Box::new(conn_loopback.clone())), 0.25),
+            rule(
+                TriageCondition::Any(vec![http_loopback.clone(), TriageCondition::Exclusion(2)]),
+                0.125,
+            ),
+            rule(
+                TriageCondition::Any(vec![http_loopback, http_unknown]),
+                0.75,
+            ),
+            rule(
+                TriageCondition::Not(Box::new(TriageCondition::Exclusion(3))),
+                0.333,
+            ),
+            rule(TriageCondition::All(Vec::new()), 0.1),
+            rule(conn_loopback, 0.2),
+        ]);
+        let mut tree = policy(1, Vec::new(), Vec::new(), Vec::new(), 0.0);
+        tree.rules = rules;
+        tree.rule_exclusions = std::collections::HashMap::from([
+            (
+                1,
+                TriageExclusion::from(ExclusionReason::Domain(vec!["com".to_string()])),
+            ),
+            (
+                2,
+                TriageExclusion::from(ExclusionReason::IpAddress(HostNetworkGroup::new(
+                    vec![],
+                    vec!["127.0.0.0/8".parse::<IpNet>().unwrap()],
+                    vec![],
+                ))),
+            ),
+        ]);
+        let policies = vec![tree];
+        let compiled = CompiledTriagePolicy::new(&policies[0]).unwrap();
+        assert_eq!(compiled.rules_by_kind.len(), 2);
+        assert_eq!(compiled.unrestricted_rules, vec![1, 2, 3, 4, 5]);
+
+        for event in samples() {
+            let scores = event
+                .score_against_compiled_policies(slice::from_ref(&compiled))
+                .into_iter()
+                .map(|score| (score.policy_id, score.score))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                scores,
+                filter_scores(&event, &policies),
+                "{}",
+                event.as_match().kind()
+            );
+        }
+    }
+
+    #[test]
+    fn exclusions_equal_filter_exclusions() {
+        let exclusion_sets = vec![
+            vec![TriageExclusion::from(ExclusionReason::IpAddress(
+                HostNetworkGroup::new(vec![IpAddr::V4(Ipv4Addr::LOCALHOST)], vec![], vec![]),
+            ))],
+            vec![TriageExclusion::from(ExclusionReason::IpAddress(
+                HostNetworkGroup::new(
+                    vec![],
+                    vec!["10.0.0.0/8".parse::<IpNet>().unwrap()],
+                    vec![
+                        IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2))
+                            ..=IpAddr::V4(Ipv4Addr::new(127, 0, 0, 3)),
+                    ],
+                ),
+            ))],
+            vec![TriageExclusion::from(ExclusionReason::Domain(vec![
+                "com".to_string(),
+            ]))],
+            vec![TriageExclusion::from(ExclusionReason::Domain(vec![
+                "oo.com".to_string(),
+                "a+b.org".to_string(),
+            ]))],
+            vec![TriageExclusion::Domain(
+                regex::RegexSet::new([r"^foo\.[a-z]+$"]).unwrap(),
+            )],
+            vec![TriageExclusion::from(ExclusionReason::Hostname(vec![
+                "foo".to_string(),
+            ]))],
+            vec![TriageExclusion::from(ExclusionReason::Uri(vec![
+                "/uri/path".to_string(),
+            ]))],
+            vec![TriageExclusion::from(ExclusionReason::Domain(vec![
+                "*.com".to_string(),
+            ]))],
+            vec![TriageExclusion::from(ExclusionReason::Domain(vec![
+                "*.example.com".to_string(),
+            ]))],
+            vec![TriageExclusion::from(ExclusionReason::HostnamePattern(
+                vec!["^exam".to_string()],
+            ))],
+            vec![TriageExclusion::from(ExclusionReason::UriPattern(vec![
+                "^/uri/".to_string(),
+            ]))],
+            vec![TriageExclusion::from(ExclusionReason::UserAgent(vec![
+                "brow".to_string(),
+            ]))],
+            vec![TriageExclusion::from(ExclusionReason::Ja3(vec![
+                "JA3".to_string(),
+            ]))],
+            vec![TriageExclusion::Scoped(
+                ExclusionScope {
+                    event_kind: Some(EventKind::HttpThreat),
+                    ..ExclusionScope::default()
+                },
+                Box::new(TriageExclusion::from(ExclusionReason::Domain(vec![
+                    "com".to_string(),
+                ]))),
+            )],
+            vec![TriageExclusion::Scoped(
+                ExclusionScope {
+                    sensor: Some("sensor".to_string()),
+                    ..ExclusionScope::default()
+                },
+                Box::new(TriageExclusion::from(ExclusionReason::Uri(vec![
+                    "/uri/path".to_string(),
+                ]))),
+            )],
+            vec![TriageExclusion::Scoped(
+                ExclusionScope {
+                    valid_until: Some(Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap()),
+                    ..ExclusionScope::default()
+                },
+                Box::new(TriageExclusion::from(ExclusionReason::Domain(vec![
+                    "com".to_string(),
+                ]))),
+            )],
+        ];
+
+        for exclusions in exclusion_sets {
+            let compiled =
+                CompiledTriagePolicy::new(&policy(1, exclusions.clone(), vec![], vec![], 0.0))
+                    .unwrap();
+            for event in samples() {
+                assert_eq!(
+                    compiled.score(&event).is_none(),
+                    event.matches_exclusion(&exclusions),
+                    "{}",
+                    event.as_match().kind()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn scoped_exclusions_apply_at_the_time_of_the_event() {
+        let epoch = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+        let everywhere = TriageExclusion::from(ExclusionReason::IpAddress(HostNetworkGroup::new(
+            Vec::new(),
+            vec!["0.0.0.0/0".parse().unwrap(), "::/0".parse().unwrap()],
+            Vec::new(),
+        )));
+        let scoped = |valid_from, valid_until| {
+            let scope = ExclusionScope {
+                valid_from: Some(valid_from),
+                valid_until: Some(valid_until),
+                ..ExclusionScope::default()
+            };
+            policy(
+                1,
+                vec![TriageExclusion::Scoped(scope, Box::new(everywhere.clone()))],
+                vec![],
+                vec![],
+                0.0,
+            )
+        };
+
+        // The window has long passed, but the events are from inside it.
+        let inside = scoped(epoch - Duration::hours(1), epoch + Duration::hours(1));
+        let compiled = CompiledTriagePolicy::new(&inside).unwrap();
+        let mut excluded = 0;
+        for event in samples() {
+            let matched = event.matches_exclusion(slice::from_ref(&everywhere));
+            assert_eq!(compiled.score(&event).is_none(), matched);
+            assert_eq!(
+                filter_scores(&event, slice::from_ref(&inside)).is_empty(),
+                matched
+            );
+            excluded += usize::from(matched);
+        }
+        assert!(excluded > 0);
+
+        let before = scoped(epoch - Duration::hours(2), epoch - Duration::hours(1));
+        let compiled = CompiledTriagePolicy::new(&before).unwrap();
+        for event in samples() {
+            assert!(compiled.score(&event).is_some());
+            assert_eq!(filter_scores(&event, slice::from_ref(&before)).len(), 1);
+        }
+    }
+
+    #[test]
+    fn domain_patterns() {
+        assert_eq!(
+            domain_of_pattern(&format!(
+                "(^{0}$|\\.{0}$)",
+                regex::escape("a+b.example.com")
+            ))
+            .as_deref(),
+            Some("a+b.example.com")
+        );
+        assert_eq!(domain_of_pattern(r"^foo\.[a-z]+$"), None);
+        assert_eq!(domain_of_pattern(r"(^a\.com$|\.b\.com$)"), None);
+
+        let compiled = CompiledTriagePolicy::new(&policy(
+            1,
+            vec![TriageExclusion::from(ExclusionReason::Domain(vec![
+                "example.com".to_string(),
+            ]))],
+            vec![],
+            vec![],
+            0.0,
+        ))
+        .unwrap();
+        assert!(compiled.exclusions.matches_domain("example.com"));
+        assert!(compiled.exclusions.matches_domain("www.example.com"));
+        assert!(!compiled.exclusions.matches_domain("badexample.com"));
+        assert!(!compiled.exclusions.matches_domain("example.com.org"));
+    }
+
+    #[test]
+    fn address_tree_finds_overlapping_ranges() {
+        let addr = |last: u8| IpAddr::V4(Ipv4Addr::new(10, 0, 0, last));
+        let ranges = AddressTree::new(vec![
+            (addr(5), addr(9)),
+            (addr(1), addr(3)),
+            (addr(2), addr(6)),
+            (addr(20), addr(20)),
+            (addr(30), addr(25)),
+            (addr(4), addr(4)),
+        ]);
+        assert_eq!(ranges.ranges.len(), 5);
+        assert!(!ranges.contains(addr(0)));
+        assert!(ranges.contains(addr(1)));
+        assert!(ranges.contains(addr(9)));
+        assert!(!ranges.contains(addr(10)));
+        assert!(ranges.contains(addr(20)));
+        assert!(!ranges.contains(addr(27)));
+        assert!(!ranges.contains(IpAddr::V6(std::net::Ipv6Addr::LOCALHOST)));
+        assert!(!AddressTree::default().contains(addr(1)));
+
+        // A long range early in the order covers addresses past later ones.
+        let ranges = AddressTree::new(
+            [(1, 200), (2, 3), (4, 5), (6, 7), (8, 9), (10, 11), (12, 13)]
+                .into_iter()
+                .map(|(start, end)| (addr(start), addr(end)))
+                .collect(),
+        );
+        for last in 1..=200 {
+            assert!(ranges.contains(addr(last)), "{last}");
+        }
+        assert!(!ranges.contains(addr(201)));
+    }
+}
@@ -19,13 +19,14 @@ mod top_n;
 pub mod types;
 mod util;
 
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::{io, sync::Arc};
 
 use anyhow::{Result, anyhow};
 pub use attrievent::attribute::RawEventKind;
 pub use rocksdb::backup::BackupEngineInfo;
-pub use tags::{CustomerTagSet, TagSet};
+pub use tags::{ChildTags, CustomerTagSet, Tag, TagDetails, TagNode, TagSet, Tags};
 use tags::{EventTagId, NetworkTagId, WorkflowTagId};
 use thiserror::Error;
 
@@ -42,25 +43,29 @@ pub use self::model::{Digest, Model};
 pub use self::scores::Scores;
 use self::tables::StateDb;
 pub use self::tables::{
-    AccessToken, Agent, AgentConfig, AgentKind, AgentStatus, AllowNetwork, AllowNetworkUpdate,
-    AttrCmpKind, BackupConfig, BackupConfigUpdate, BlockNetwork, BlockNetworkUpdate, Cluster,
-    ClusterTimeSeries, ColumnStats, ColumnTimeSeries, Confidence, CoreComponent,
-    CsvColumnExtra as CsvColumnExtraConfig, Customer, CustomerDataDeletionJob,
+    AccessToken, Actor, Agent, AgentConfig, AgentKind, AgentStatus, AllowNetwork,
+    AllowNetworkUpdate, AttrCmpKind, AttrValue, AuditAction, AuditEvent, AuditFilter, AuditIter,
+    AuditLog, BackupConfig, BackupConfigUpdate, BlockNetwork, BlockNetworkUpdate, ChangeIter,
+    ChangeLog, ChangeRecord, Cluster, ClusterTimeSeries, ColumnStats, ColumnTimeSeries, Confidence,
+    CoreComponent, CsvColumnExtra as CsvColumnExtraConfig, Customer, CustomerDataDeletionJob,
     CustomerDataDeletionService, CustomerDataDeletionServiceResult, CustomerDataDeletionStatus,
     CustomerNetwork, CustomerUpdate, DataSource, DataSourceUpdate, DataType, ExclusionReason,
-    ExternalService, ExternalServiceConfig, ExternalServiceKind, ExternalServiceStatus, Filter,
-    FilterValue, Host, IndexedTable, Iterable, LabelDb, LabelDbKind, LabelDbRule, LabelDbRuleKind,
-    Lifecycle, Model as ModelDigest, ModelIndicator, Network, NetworkFilter, NetworkUpdate, Node,
-    NodeProfile, NodeTable, NodeUpdate, OperationAction, OperationAttempt, OperationCleanupState,
-    OperationOutcome, OperationPhase, OperationRetentionBound, OperationRetryPolicy, OutlierInfo,
-    OutlierInfoKey, OutlierInfoValue, PacketAttr, PeriodForSearch, ProtocolPorts, Response,
-    ResponseKind, RetentionConfig, RetentionConfigUpdate, SamplingInterval, SamplingKind,
-    SamplingPeriod, SamplingPolicy, SamplingPolicyUpdate, Structured,
-    StructuredClusteringAlgorithm, Table, Template, TimeSeries, TopColumnsOfCluster, TopMultimaps,
-    TorExitNode, TrafficFilter, TriageExclusion, TriageExclusionReason,
-    TriageExclusionReasonUpdate, TriagePolicy, TriagePolicyInput, TriagePolicyUpdate,
-    TriageResponse, TriageResponseUpdate, TrustedDomain, TrustedUserAgent, UniqueKey, Unstructured,
-    UnstructuredClusteringAlgorithm, UserAgent, ValueKind,
+    ExclusionScope, ExternalService, ExternalServiceConfig, ExternalServiceKind,
+    ExternalServiceStatus, Filter, FilterValue, Host, IndexedTable, Iterable, LabelDb, LabelDbKind,
+    LabelDbRule, LabelDbRuleKind, Lifecycle, Model as ModelDigest, ModelIndicator, Network,
+    NetworkFilter, NetworkUpdate, Node, NodeProfile, NodeTable, NodeUpdate, OperationAction,
+    OperationAttempt, OperationCleanupState, OperationOutcome, OperationPhase,
+    OperationRetentionBound, OperationRetryPolicy, OutlierInfo, OutlierInfoKey, OutlierInfoValue,
+    PacketAttr, PeriodForSearch, PolicyFinding, ProtocolPorts, Response, ResponseAction,
+    ResponseActionKind, ResponseActionState, ResponseActions, ResponseEffect, ResponseKind,
+    ResponseTargets, RetentionConfig, RetentionConfigUpdate, RevisionAction, Revisioned,
+    SamplingInterval, SamplingKind, SamplingPeriod, SamplingPolicy, SamplingPolicyUpdate,
+    Structured, StructuredClusteringAlgorithm, Table, Template, TimeSeries, TopColumnsOfCluster,
+    TopMultimaps, TorExitNode, TrafficFilter, TriageCondition, TriageExclusion,
+    TriageExclusionReason, TriageExclusionReasonUpdate, TriageHistory, TriagePolicy,
+    TriagePolicyDiff, TriagePolicyInput, TriagePolicyUpdate, TriageResponse, TriageResponseUpdate,
+    TriageRevision, TriageRule, TriageRules, TrustedDomain, TrustedUserAgent, UniqueKey,
+    Unstructured, UnstructuredClusteringAlgorithm, UserAgent, ValueKind,
 };
 pub use self::top_n::*;
 #[allow(deprecated)]
@@ -79,6 +84,7 @@ pub struct Store {
     pretrained: PathBuf,
     classifier_fm: classifier_fs::ClassifierFileManager,
     country_lookup: Option<geo::SharedCountryLookup>,
+    event_subscribers: event::Subscribers,
 }
 
 impl Store {
@@ -122,6 +128,7 @@ impl Store {
             pretrained,
             classifier_fm,
             country_lookup,
+            event_subscribers: event::Subscribers::default(),
         };
         Ok(store)
     }
@@ -129,7 +136,9 @@ impl Store {
     #[must_use]
     #[allow(clippy::missing_panics_doc)]
     pub fn events(&self) -> EventDb<'_> {
-        self.states.events(self.country_lookup.clone())
+        self.states
+            .events(self.country_lookup.clone())
+            .with_subscribers(&self.event_subscribers)
     }
 
     #[must_use]
@@ -156,6 +165,13 @@ impl Store {
         self.states.allow_networks()
     }
 
+    /// Returns the trail of administrative actions.
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn audit_log(&self) -> AuditLog<'_> {
+        self.states.audit_log()
+    }
+
     #[must_use]
     #[allow(clippy::missing_panics_doc)]
     pub fn batch_info_map(&self) -> Table<'_, batch_info::BatchInfo> {
@@ -174,6 +190,14 @@ impl Store {
         self.states.categories()
     }
 
+    /// Returns the log of the changes made to the tables, which records
+    /// nothing until enabled.
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn change_log(&self) -> ChangeLog<'_> {
+        self.states.change_log()
+    }
+
     #[must_use]
     #[allow(clippy::missing_panics_doc)]
     pub fn cluster_map(&self) -> Table<'_, Cluster> {
@@ -523,77 +547,62 @@ impl Store {
         old_policy: &AccountPolicy,
         update: &AccountPolicyUpdate,
     ) -> Result<()> {
-        let config = self.config_map();
-        let mut updates = Vec::new();
-
-        let old_expiry_str = old_policy.expiry_period_in_secs.to_string();
-        let new_expiry_str;
-        if let Some(new_val) = update.expiry_period_in_secs {
-            new_expiry_str = new_val.to_string();
-            updates.push((
-                tables::KEY_EXPIRY_PERIOD,
-                old_expiry_str.as_str(),
-                new_expiry_str.as_str(),
-            ));
-        }
-
-        let old_lockout_threshold_str = old_policy.lockout_threshold.to_string();
-        let new_lockout_threshold_str;
-        if let Some(new_val) = update.lockout_threshold {
-            new_lockout_threshold_str = new_val.to_string();
-            updates.push((
-                tables::KEY_LOCKOUT_THRESHOLD,
-                old_lockout_threshold_str.as_str(),
-                new_lockout_threshold_str.as_str(),
-            ));
-        }
-
-        let old_lockout_duration_str = old_policy.lockout_duration_in_secs.to_string();
-        let new_lockout_duration_str;
-        if let Some(new_val) = update.lockout_duration_in_secs {
-            new_lockout_duration_str = new_val.to_string();
-            updates.push((
-                tables::KEY_LOCKOUT_DURATION,
-                old_lockout_duration_str.as_str(),
-                new_lockout_duration_str.as_str(),
-            ));
-        }
-
-        let old_suspension_threshold_str = old_policy.suspension_threshold.to_string();
-        let new_suspension_threshold_str;
-        if let Some(new_val) = update.suspension_threshold {
-            new_suspension_threshold_str = new_val.to_string();
-            updates.push((
-                tables::KEY_SUSPENSION_THRESHOLD,
-                old_suspension_threshold_str.as_str(),
-                new_suspension_threshold_str.as_str(),
-            ));
-        }
-
-        // Validate the NEW resulting policy
-        let resulting_policy = AccountPolicy {
-            expiry_period_in_secs: update
-                .expiry_period_in_secs
-                .unwrap_or(old_policy.expiry_period_in_secs),
-            lockout_threshold: update
-                .lockout_threshold
-                .unwrap_or(old_policy.lockout_threshold),
-            lockout_duration_in_secs: update
-                .lockout_duration_in_secs
-                .unwrap_or(old_policy.lockout_duration_in_secs),
-            suspension_threshold: update
-                .suspension_threshold
-                .unwrap_or(old_policy.suspension_threshold),
-        };
-        resulting_policy.validate()?;
-
+        let updates = account_policy_updates(old_policy, update)?;
         if !updates.is_empty() {
-            config.update_compare_multi(&updates)?;
+            let updates: Vec<_> = updates
+                .iter()
+                .map(|(key, old, new)| (*key, old.as_str(), new.as_str()))
+                .collect();
+            self.config_map().update_compare_multi(&updates)?;
         }
 
         Ok(())
     }
 
+    /// Updates account policy settings as [`update_account_policy`] does, and
+    /// records the change by `actor` in the audit trail in the same
+    /// transaction. Nothing is recorded if `update` changes no setting.
+    ///
+    /// [`update_account_policy`]: Self::update_account_policy
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the new policy is invalid, if any old value does not
+    /// match the current value in the database (indicating concurrent modification),
+    /// if the key does not exist, or if the database operation fails.
+    pub fn update_account_policy_as(
+        &self,
+        old_policy: &AccountPolicy,
+        update: &AccountPolicyUpdate,
+        actor: &Actor,
+    ) -> Result<()> {
+        let updates = account_policy_updates(old_policy, update)?;
+        if updates.is_empty() {
+            return Ok(());
+        }
+        let details = updates
+            .iter()
+            .map(|(key, old, new)| format!("{key}: {old} -> {new}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let updates: Vec<_> = updates
+            .iter()
+            .map(|(key, old, new)| (*key, old.as_str(), new.as_str()))
+            .collect();
+        let config = self.config_map();
+        config.audited(
+            |txn| config.update_compare_multi_with_transaction(&updates, txn),
+            |_| {
+                AuditEvent::new(
+                    actor.clone(),
+                    AuditAction::AccountPolicyChanged,
+                    "account policy",
+                )
+                .with_details(details.clone())
+            },
+        )
+    }
+
     /// Returns the current account policy settings from the config table.
     ///
     /// # Errors
@@ -783,12 +792,35 @@ impl Store {
         self.states.triage_policies()
     }
 
+    /// Returns the revisions of the triage policies, through which changes to
+    /// the policies are recorded.
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn triage_policy_history(&self) -> TriageHistory<'_, TriagePolicy> {
+        self.states.triage_history()
+    }
+
+    /// Returns the revisions of the triage exclusion reasons, through which
+    /// changes to the exclusion reasons are recorded.
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn triage_exclusion_reason_history(&self) -> TriageHistory<'_, TriageExclusionReason> {
+        self.states.triage_history()
+    }
+
     #[must_use]
     #[allow(clippy::missing_panics_doc)]
     pub fn triage_response_map(&self) -> IndexedTable<'_, TriageResponse> {
         self.states.triage_responses()
     }
 
+    /// Returns the actions the responses of triage policies called for.
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn response_actions(&self) -> ResponseActions<'_> {
+        self.states.response_actions()
+    }
+
     #[must_use]
     #[allow(clippy::missing_panics_doc)]
     pub fn trusted_domain_map(&self) -> Table<'_, TrustedDomain> {
@@ -827,6 +859,70 @@ impl Store {
         TagSet::new(set)
     }
 
+    /// Returns the stored triage policy with `id` as a `TriagePolicyInput`,
+    /// with the exclusion reasons it and its rules refer to, or `None` if
+    /// there is no such policy.
+    ///
+    /// An exclusion reason that no longer exists, or that a customer other
+    /// than the one of the policy owns, is left out, so the policy does not
+    /// exclude by it and a rule condition referring to it never holds. The
+    /// others keep their scopes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a database operation fails.
+    pub fn triage_policy_input(&self, id: u32) -> Result<Option<TriagePolicyInput>> {
+        let Some(policy) = self.triage_policy_map().get_by_id(id)? else {
+            return Ok(None);
+        };
+        let reasons = self.triage_exclusion_reason_map();
+        let mut exclusion_reason = Vec::with_capacity(policy.triage_exclusion_id.len());
+        for id in &policy.triage_exclusion_id {
+            if let Some(reason) = reasons.get_by_id(*id)? {
+                exclusion_reason.push(reason);
+            }
+        }
+        let mut rule_exclusion_reason = HashMap::new();
+        for id in policy.rule_exclusion_ids() {
+            if let Some(reason) = reasons.get_by_id(id)? {
+                rule_exclusion_reason.insert(id, reason);
+            }
+        }
+        Ok(Some(policy.into_input_with_scoped_exclusion_reason(
+            exclusion_reason,
+            rule_exclusion_reason,
+        )))
+    }
+
+    /// Proposes the actions the responses of `policies` call for on the
+    /// stored events from `start` (inclusive) to `end` (exclusive), as
+    /// [`Event::response_actions`] finds them, and returns the number of
+    /// actions newly proposed. An action already proposed, whatever its
+    /// state, is not proposed again. Events that cannot be decoded are
+    /// skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a database operation fails.
+    pub fn propose_response_actions(
+        &self,
+        start: jiff::Timestamp,
+        end: jiff::Timestamp,
+        policies: &[TriagePolicyInput],
+        targets: &ResponseTargets,
+    ) -> Result<u64> {
+        let actions = self.response_actions();
+        let mut proposed = 0;
+        for (key, event) in self.events().range(start, end, None).flatten() {
+            for action in event.response_actions(key, policies, targets) {
+                if actions.propose(&action)?.is_some() {
+                    proposed += 1;
+                }
+            }
+        }
+        Ok(proposed)
+    }
+
     /// Fetch the most recent pretrained model with `name`
     ///
     /// # Errors
@@ -1119,6 +1215,16 @@ impl Store {
         self.states.restore_from_latest_backup()
     }
 
+    /// Restores the backup with `backup_id`, or the latest backup if it is
+    /// `None`, keeping the entries the column families `kept` have now.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error when backup engine fails or restoration fails.
+    pub(crate) fn restore_keeping(&mut self, backup_id: Option<u32>, kept: &[&str]) -> Result<()> {
+        self.states.restore_keeping(backup_id, kept)
+    }
+
     /// Purge old backups and only keep `num_backups_to_keep` backups on file
     ///
     /// # Errors
@@ -1130,6 +1236,56 @@ impl Store {
     }
 }
 
+/// Returns the config keys `update` changes, with their values in
+/// `old_policy` and after the update, once the resulting policy is validated.
+fn account_policy_updates(
+    old_policy: &AccountPolicy,
+    update: &AccountPolicyUpdate,
+) -> Result<Vec<(&'static str, String, String)>> {
+    let resulting_policy = AccountPolicy {
+        expiry_period_in_secs: update
+            .expiry_period_in_secs
+            .unwrap_or(old_policy.expiry_period_in_secs),
+        lockout_threshold: update
+            .lockout_threshold
+            .unwrap_or(old_policy.lockout_threshold),
+        lockout_duration_in_secs: update
+            .lockout_duration_in_secs
+            .unwrap_or(old_policy.lockout_duration_in_secs),
+        suspension_threshold: update
+            .suspension_threshold
+            .unwrap_or(old_policy.suspension_threshold),
+    };
+    resulting_policy.validate()?;
+
+    let updates = [
+        (
+            tables::KEY_EXPIRY_PERIOD,
+            old_policy.expiry_period_in_secs,
+            update.expiry_period_in_secs,
+        ),
+        (
+            tables::KEY_LOCKOUT_THRESHOLD,
+            old_policy.lockout_threshold,
+            update.lockout_threshold,
+        ),
+        (
+            tables::KEY_LOCKOUT_DURATION,
+            old_policy.lockout_duration_in_secs,
+            update.lockout_duration_in_secs,
+        ),
+        (
+            tables::KEY_SUSPENSION_THRESHOLD,
+            old_policy.suspension_threshold,
+            update.suspension_threshold,
+        ),
+    ];
+    Ok(updates
+        .into_iter()
+        .filter_map(|(key, old, new)| new.map(|new| (key, old.to_string(), new.to_string())))
+        .collect())
+}
+
 fn parse_pretrained_file_name(name: &str) -> Result<(&str, crate::types::Timestamp)> {
     use crate::types::Timestamp;
 
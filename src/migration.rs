@@ -23,8 +23,9 @@ use crate::{
         BlocklistDceRpcFieldsStoredV0_42, BlocklistDceRpcFieldsStoredV0_44,
         BlocklistDhcpFieldsStoredV0_42, BlocklistDhcpFieldsStoredV0_44,
         ExternalServiceValueV0_47Alpha1, ExternalServiceValueV0_47Alpha2,
-        HttpThreatFieldsStoredV0_43, HttpThreatFieldsStoredV0_44,
-        migrate_event_stored_schema_to_v0_46, validate_event_stored_schema_v0_46,
+        HttpThreatFieldsStoredV0_43, HttpThreatFieldsStoredV0_44, TriageExclusionReasonV0_46,
+        TriagePolicyV0_46, migrate_event_stored_schema_to_v0_46,
+        validate_event_stored_schema_v0_46,
     },
     tables::{NETWORK_TAGS, TRIAGE_EXCLUSION_REASON},
 };
@@ -110,7 +111,7 @@ use crate::{
 /// // release that involves database format change) to 3.5.0, including
 /// // all alpha changes finalized in 3.5.0.
 /// ```
-const COMPATIBLE_VERSION_REQ: &str = ">=0.47.0-alpha.2,<0.47.0-alpha.3";
+const COMPATIBLE_VERSION_REQ: &str = ">=0.47.0-alpha.3,<0.47.0-alpha.4";
 
 /// Number of event records applied in each atomic migration write.
 const EVENT_MIGRATION_BATCH_SIZE: usize = 100;
@@ -220,8 +221,8 @@ pub fn migrate_data_dir<P: AsRef<Path>>(
             |data_dir, _backup_dir, locator| migrate_0_45_to_0_46(data_dir, locator),
         ),
         (
-            VersionReq::parse(">=0.46.0,<0.47.0-alpha.2")?,
-            Version::parse("0.47.0-alpha.2")?,
+            VersionReq::parse(">=0.46.0,<0.47.0-alpha.3")?,
+            Version::parse("0.47.0-alpha.3")?,
             |data_dir, _backup_dir, _locator| migrate_0_46_to_0_47(data_dir),
         ),
     ];
@@ -248,20 +249,20 @@ fn migrate_0_45_to_0_46(data_dir: &Path, locator: Option<&dyn CountryLookup>) ->
     migrate_event_country_codes(data_dir, locator).map(|_| ())
 }
 
-/// Migrates a database in any supported 0.46.x or 0.47.0-alpha.1 format to
-/// 0.47.0-alpha.2.
+/// Migrates a database in any supported 0.46.x, 0.47.0-alpha.1 or
+/// 0.47.0-alpha.2 format to 0.47.0-alpha.3.
 ///
-/// The two alpha formats share one migration because the format is still
+/// The alpha formats share one migration because the format is still
 /// changing during the prerelease: an alpha-to-alpha change extends the
 /// migration that produced the earlier alpha instead of adding one beside it,
 /// so a 0.46.x database reaches the newest alpha in a single step.
 ///
-/// Opening the pinned 0.47.0-alpha.2 list with
+/// Opening the pinned 0.47.0-alpha.3 list with
 /// [`create_missing_column_families`](rocksdb::Options::create_missing_column_families)
-/// creates whichever of the customer deletion jobs, core components and
-/// operation attempts families is absent and leaves the rest alone, so a retry
-/// after an interrupted run finds nothing to do rather than failing on a family
-/// that already exists.
+/// creates whichever of the families added since 0.46, such as the customer
+/// deletion jobs, core components, operation attempts and event families, is
+/// absent and leaves the rest alone, so a retry after an interrupted run finds
+/// nothing to do rather than failing on a family that already exists.
 fn migrate_0_46_to_0_47(data_dir: &Path) -> Result<()> {
     let db_path = data_dir.join("states.db");
     let mut opts = rocksdb::Options::default();
@@ -269,34 +270,50 @@ fn migrate_0_46_to_0_47(data_dir: &Path) -> Result<()> {
     opts.create_missing_column_families(true);
 
     let db: rocksdb::OptimisticTransactionDB<rocksdb::SingleThreaded> =
-        rocksdb::OptimisticTransactionDB::open_cf(&opts, &db_path, MAP_NAMES_V0_47_ALPHA_2)
-            .context("failed to open database for the 0.47.0-alpha.2 migration")?;
+        rocksdb::OptimisticTransactionDB::open_cf(&opts, &db_path, MAP_NAMES_V0_47_ALPHA_3)
+            .context("failed to open database for the 0.47.0-alpha.3 migration")?;
 
-    migrate_install_state::<AgentValueV0_47Alpha2, AgentValueV0_47Alpha1>(
+    migrate_table_values::<AgentValueV0_47Alpha2, AgentValueV0_47Alpha1>(
         &db,
         crate::tables::AGENTS,
         "agent",
     )?;
-    migrate_install_state::<ExternalServiceValueV0_47Alpha2, ExternalServiceValueV0_47Alpha1>(
+    migrate_table_values::<ExternalServiceValueV0_47Alpha2, ExternalServiceValueV0_47Alpha1>(
         &db,
         crate::tables::EXTERNAL_SERVICES,
         "external service",
     )?;
+    migrate_table_values::<crate::TriagePolicy, TriagePolicyV0_46>(
+        &db,
+        crate::tables::TRIAGE_POLICY,
+        "triage policy",
+    )?;
+    migrate_table_values::<crate::TriageExclusionReason, TriageExclusionReasonV0_46>(
+        &db,
+        TRIAGE_EXCLUSION_REASON,
+        "triage exclusion reason",
+    )?;
+    migrate_tag_sets(&db)?;
+    build_tag_index(&db)?;
     Ok(())
 }
 
-/// Rewrites every value in `cf_name` that predates the install-state fields.
+/// Rewrites every value in `cf_name` stored in the `Old` layout in the
+/// `Current` one.
 ///
-/// `Current` is probed first, so a row already carrying the four fields is
+/// The entry under the empty key, where an indexed table keeps its index, is
+/// not a record and is left alone.
+///
+/// `Current` is probed first, so a row already in the current layout is
 /// recognized as it is and its stored bytes are left untouched; only a row that
-/// fails that probe is read back as `Old` and rewritten with the documented
-/// defaults. A value that matches neither layout aborts the migration with its
+/// fails that probe is read back as `Old` and rewritten through
+/// `From<Old>`. A value that matches neither layout aborts the migration with its
 /// raw key and both decoding errors, rather than being skipped or overwritten.
 ///
 /// Both layouts are table values, so they are encoded with
 /// [`bincode::DefaultOptions`] — the varint encoding `crate::tables` uses — and
 /// not with the fixint helpers the event records go through.
-fn migrate_install_state<Current, Old>(
+fn migrate_table_values<Current, Old>(
     db: &rocksdb::OptimisticTransactionDB<rocksdb::SingleThreaded>,
     cf_name: &str,
     record: &str,
@@ -315,6 +332,9 @@ where
 
     for entry in db.iterator_cf(&cf, rocksdb::IteratorMode::Start) {
         let (key, value) = entry.with_context(|| format!("failed to read a {record} record"))?;
+        if key.is_empty() {
+            continue;
+        }
         let Err(current_error) = bincode::DefaultOptions::new().deserialize::<Current>(&value)
         else {
             already_current += 1;
@@ -341,11 +361,122 @@ where
 
     write_migration_batch(db, &mut batch, record)?;
     info!(
-        "Install-state migration of {record} records complete: converted_count={converted}, already_current_count={already_current}"
+        "Migration of {record} records complete: converted_count={converted}, already_current_count={already_current}"
     );
     Ok(())
 }
 
+/// Moves each tag set from the "meta" entry holding it as a whole into
+/// "tags", where every tag is stored under its own keys.
+///
+/// The old format did not reject a duplicate name, so a set may repeat one.
+/// The tag with the lowest ID keeps the name, and every other is renamed to
+/// `{name} ({id})`, keeping its ID so that the events, networks and triage
+/// responses referring to it are unaffected. A set is moved in one write with
+/// the removal of its old entry, so a retry moves only what is left.
+fn migrate_tag_sets(db: &rocksdb::OptimisticTransactionDB<rocksdb::SingleThreaded>) -> Result<()> {
+    use std::collections::HashSet;
+
+    use crate::collections::{IndexedSet, KeyIndex};
+    use crate::tables::{EVENT_TAGS, META, TAGS, WORKFLOW_TAGS};
+
+    let meta_cf = db
+        .cf_handle(META)
+        .ok_or_else(|| anyhow!("meta column family not found"))?;
+    let tags_cf = db
+        .cf_handle(TAGS)
+        .ok_or_else(|| anyhow!("tags column family not found"))?;
+
+    for name in [EVENT_TAGS, NETWORK_TAGS, WORKFLOW_TAGS] {
+        let set_name = String::from_utf8_lossy(name);
+        let Some(value) = db
+            .get_cf(meta_cf, name)
+            .with_context(|| format!("failed to read {set_name}"))?
+        else {
+            continue;
+        };
+        let index =
+            KeyIndex::from_bytes(&value).with_context(|| format!("invalid index of {set_name}"))?;
+
+        let mut taken: HashSet<Vec<u8>> = index.iter().map(|(_, key)| key.to_vec()).collect();
+        let mut seen = HashSet::new();
+        let mut entries = Vec::new();
+        let mut renamed = 0usize;
+        for (id, key) in index.iter() {
+            if seen.insert(key) {
+                entries.push((id, key.to_vec()));
+                continue;
+            }
+            let mut new_key = [key, format!(" ({id})").as_bytes()].concat();
+            while taken.contains(new_key.as_slice()) {
+                new_key.extend_from_slice(format!(" ({id})").as_bytes());
+            }
+            warn!(
+                "Renaming duplicate tag {:?} with ID {id} in {set_name} to {:?}",
+                String::from_utf8_lossy(key),
+                String::from_utf8_lossy(&new_key)
+            );
+            taken.insert(new_key.clone());
+            entries.push((id, new_key));
+            renamed += 1;
+        }
+        let next = entries
+            .iter()
+            .map(|(id, _)| id.saturating_add(1))
+            .max()
+            .unwrap_or_default();
+
+        let mut batch = rocksdb::WriteBatchWithTransaction::<true>::default();
+        IndexedSet::put_in_batch(&mut batch, tags_cf, name, &entries, next);
+        batch.delete_cf(meta_cf, name);
+        write_migration_batch(db, &mut batch, &set_name)?;
+        info!(
+            "Migration of {set_name} complete: tag_count={}, renamed_count={renamed}",
+            entries.len()
+        );
+    }
+    Ok(())
+}
+
+/// Adds an entry to "tag index" for every tag of every network and triage
+/// response.
+///
+/// The entries are written as they are, so a retry writes the same entries
+/// again and changes nothing.
+fn build_tag_index(db: &rocksdb::OptimisticTransactionDB<rocksdb::SingleThreaded>) -> Result<()> {
+    use crate::tables::{NETWORKS, TAG_INDEX, TRIAGE_RESPONSE, tag_index_entries};
+
+    let index_cf = db
+        .cf_handle(TAG_INDEX)
+        .ok_or_else(|| anyhow!("tag index column family not found"))?;
+    for table in [NETWORKS, TRIAGE_RESPONSE] {
+        let cf = db
+            .cf_handle(table)
+            .ok_or_else(|| anyhow!("{table} column family not found"))?;
+        let mut batch = rocksdb::WriteBatchWithTransaction::<true>::default();
+        let mut count = 0usize;
+        for item in db.iterator_cf(cf, rocksdb::IteratorMode::Start) {
+            let (key, value) = item.with_context(|| format!("failed to read {table}"))?;
+            if key.is_empty() {
+                continue;
+            }
+            let entries = tag_index_entries(table, &key, &value).with_context(|| {
+                format!(
+                    "invalid entry in {table}: {}",
+                    String::from_utf8_lossy(&key)
+                )
+            })?;
+            for entry in entries {
+                batch.put_cf(index_cf, entry, b"");
+                count += 1;
+            }
+        }
+        write_migration_batch(db, &mut batch, "tag index")?;
+        info!("Tag index of {table} built: entry_count={count}");
+    }
+    Ok(())
+}
+
 #[derive(Debug, Default, Eq, PartialEq)]
 pub(crate) struct EventMigrationStats {
     processed: usize,
@@ -556,19 +687,69 @@ const MAP_NAMES_V0_47_ALPHA_1: [&str; 37] = [
 
 /// Lists column family names for database format 0.47.0-alpha.2, which added
 /// "core components" and "operation attempts" to the 0.47.0-alpha.1 set.
+#[cfg(test)]
+const MAP_NAMES_V0_47_ALPHA_2: [&str; 39] = [
+    "access_tokens",
+    "accounts",
+    "agents",
+    "allow networks",
+    "batch_info",
+    "block networks",
+    "category",
+    "cluster",
+    "column stats",
+    "configs",
+    "core components",
+    "csv column extras",
+    "customers",
+    "customer deletion jobs",
+    "data sources",
+    "filters",
+    "hosts",
+    "models",
+    "model indicators",
+    "meta",
+    "networks",
+    "nodes",
+    "operation attempts",
+    "outliers",
+    "qualifiers",
+    "external services",
+    "sampling policy",
+    "scores",
+    "statuses",
+    "templates",
+    "label database",
+    "time series",
+    "Tor exit nodes",
+    "traffic filter rules",
+    "triage exclusion reason",
+    "triage policy",
+    "triage response",
+    "trusted DNS servers",
+    "trusted user agents",
+];
+
+/// Lists column family names for database format 0.47.0-alpha.3, which added
+/// "audit", "change log", "event consumer offsets", "event originator index",
+/// "event responder index", "event rollups", "event sensor index", "event
+/// triage results", "response actions", "tag index", "tags" and "triage
+/// history" to the 0.47.0-alpha.2 set.
 ///
 /// The names are written out rather than taken from
 /// [`crate::tables::MAP_NAMES`], as every other list here is: this one is what
 /// [`migrate_0_46_to_0_47`] creates, and a later rename or format bump must
 /// change what a future migration creates, never what this historical one did.
-const MAP_NAMES_V0_47_ALPHA_2: [&str; 39] = [
+const MAP_NAMES_V0_47_ALPHA_3: [&str; 51] = [
     "access_tokens",
     "accounts",
     "agents",
     "allow networks",
+    "audit",
     "batch_info",
     "block networks",
     "category",
+    "change log",
     "cluster",
     "column stats",
     "configs",
@@ -577,6 +758,12 @@ const MAP_NAMES_V0_47_ALPHA_2: [&str; 39] = [
     "customers",
     "customer deletion jobs",
     "data sources",
+    "event consumer offsets",
+    "event originator index",
+    "event responder index",
+    "event rollups",
+    "event sensor index",
+    "event triage results",
     "filters",
     "hosts",
     "models",
@@ -588,15 +775,19 @@ const MAP_NAMES_V0_47_ALPHA_2: [&str; 39] = [
     "outliers",
     "qualifiers",
     "external services",
+    "response actions",
     "sampling policy",
     "scores",
     "statuses",
+    "tags",
+    "tag index",
     "templates",
     "label database",
     "time series",
     "Tor exit nodes",
     "traffic filter rules",
     "triage exclusion reason",
+    "triage history",
     "triage policy",
     "triage response",
     "trusted DNS servers",
@@ -2667,6 +2858,7 @@ mod tests {
                 "0.43-through-0.46",
             ),
             (super::MAP_NAMES_V0_47_ALPHA_1.as_slice(), "0.47.0-alpha.1"),
+            (super::MAP_NAMES_V0_47_ALPHA_2.as_slice(), "0.47.0-alpha.2"),
         ] {
             assert!(
                 rocksdb::OptimisticTransactionDB::<rocksdb::SingleThreaded>::open_cf(
@@ -3019,7 +3211,7 @@ mod tests {
             read_version_file(&backup_dir.path().join("VERSION")).unwrap(),
             current_version
         );
-        assert_eq!(current_version.to_string(), "0.47.0-alpha.2");
+        assert_eq!(current_version.to_string(), "0.47.0-alpha.3");
 
         // The migration created both new families, and they start empty.
         {
@@ -3113,6 +3305,164 @@ mod tests {
         drop(permit);
     }
 
+    #[test]
+    fn migration_from_v0_47_alpha_2_creates_event_families() {
+        let _permit = acquire_db_permit();
+        let current_version = Version::parse(env!("CARGO_PKG_VERSION")).unwrap();
+        let data_dir = tempfile::tempdir().unwrap();
+        let backup_dir = tempfile::tempdir().unwrap();
+        let db_path = data_dir.path().join("states.db");
+
+        create_states_db(&db_path, super::MAP_NAMES_V0_47_ALPHA_2);
+        write_version(data_dir.path(), "0.47.0-alpha.2");
+        write_version(backup_dir.path(), "0.47.0-alpha.2");
+        migrate_data_dir(data_dir.path(), backup_dir.path(), None).unwrap();
+
+        assert_eq!(
+            read_version_file(&data_dir.path().join("VERSION")).unwrap(),
+            current_version
+        );
+        let db = open_states_db(&db_path, crate::tables::MAP_NAMES);
+        for name in [
+            crate::tables::AUDIT,
+            crate::tables::CHANGE_LOG,
+            crate::tables::EVENT_CONSUMER_OFFSETS,
+            crate::tables::EVENT_ORIGINATOR_INDEX,
+            crate::tables::EVENT_RESPONDER_INDEX,
+            crate::tables::EVENT_ROLLUPS,
+            crate::tables::EVENT_SENSOR_INDEX,
+            crate::tables::EVENT_TRIAGE_RESULTS,
+            crate::tables::RESPONSE_ACTIONS,
+            crate::tables::TAG_INDEX,
+            crate::tables::TAGS,
+            crate::tables::TRIAGE_HISTORY,
+        ] {
+            assert!(db.cf_handle(name).is_some(), "{name} must exist");
+        }
+    }
+
+    #[test]
+    fn migrate_tag_sets_into_keys() {
+        use crate::collections::{IndexedSet, KeyIndex};
+        use crate::tables::{EVENT_TAGS, META, TAGS, WORKFLOW_TAGS};
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let db_path = data_dir.path().join("states.db");
+        create_states_db(&db_path, super::MAP_NAMES_V0_47_ALPHA_2);
+
+        let serialize = |keys: &[&[u8]]| {
+            let mut index = KeyIndex::default();
+            for key in keys {
+                index.insert(key).unwrap();
+            }
+            bincode::DefaultOptions::new().serialize(&index).unwrap()
+        };
+        put_entries(
+            &db_path,
+            super::MAP_NAMES_V0_47_ALPHA_2,
+            META,
+            &[
+                (
+                    EVENT_TAGS.to_vec(),
+                    serialize(&[b"dup", b"other", b"dup", b"dup (2)", b"dup"]),
+                ),
+                (
+                    NETWORK_TAGS.to_vec(),
+                    serialize(&[b"1\0net", b"2\0net", b"1\0net"]),
+                ),
+            ],
+        );
+
+        super::migrate_0_46_to_0_47(data_dir.path()).unwrap();
+        // A retry finds nothing left to move.
+        super::migrate_0_46_to_0_47(data_dir.path()).unwrap();
+
+        let db = open_states_db(&db_path, crate::tables::MAP_NAMES);
+        let meta_cf = db.cf_handle(META).unwrap();
+        assert!(db.get_cf(meta_cf, EVENT_TAGS).unwrap().is_none());
+        assert!(db.get_cf(meta_cf, NETWORK_TAGS).unwrap().is_none());
+
+        let event_tags = IndexedSet::new(&db, TAGS, EVENT_TAGS).unwrap();
+        assert_eq!(
+            event_tags.entries().unwrap(),
+            vec![
+                (0, b"dup".to_vec()),
+                (1, b"other".to_vec()),
+                (2, b"dup (2) (2)".to_vec()),
+                (3, b"dup (2)".to_vec()),
+                (4, b"dup (4)".to_vec()),
+            ]
+        );
+        assert_eq!(event_tags.id(b"dup (4)").unwrap(), Some(4));
+        assert_eq!(event_tags.insert(b"new").unwrap(), 5);
+
+        let network_tags = IndexedSet::new(&db, TAGS, NETWORK_TAGS).unwrap();
+        assert_eq!(
+            network_tags.entries_starting_with(b"1\0").unwrap(),
+            vec![(0, b"1\0net".to_vec()), (2, b"1\0net (2)".to_vec())]
+        );
+        assert!(network_tags.insert(b"2\0net").is_err());
+
+        let workflow_tags = IndexedSet::new(&db, TAGS, WORKFLOW_TAGS).unwrap();
+        assert!(workflow_tags.entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn build_tag_index_from_tagged_records() {
+        use std::collections::BTreeSet;
+
+        use crate::Indexable;
+        use crate::tables::{NETWORKS, TAG_INDEX, TRIAGE_RESPONSE, tag_index_entries};
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let db_path = data_dir.path().join("states.db");
+        create_states_db(&db_path, super::MAP_NAMES_V0_47_ALPHA_2);
+
+        let mut network = crate::Network::new(
+            "network".to_string(),
+            String::new(),
+            crate::HostNetworkGroup::default(),
+            vec![3, 1],
+        );
+        network.set_index(7);
+        let network = (network.indexed_key().to_vec(), network.value());
+        let mut response = crate::TriageResponse::new(
+            "sensor".to_string(),
+            chrono::Utc::now(),
+            vec![1],
+            String::new(),
+        );
+        response.set_index(2);
+        let response = (response.indexed_key().to_vec(), response.value());
+        put_entries(
+            &db_path,
+            super::MAP_NAMES_V0_47_ALPHA_2,
+            NETWORKS,
+            std::slice::from_ref(&network),
+        );
+        put_entries(
+            &db_path,
+            super::MAP_NAMES_V0_47_ALPHA_2,
+            TRIAGE_RESPONSE,
+            std::slice::from_ref(&response),
+        );
+
+        super::migrate_0_46_to_0_47(data_dir.path()).unwrap();
+        // A retry writes the same entries again.
+        super::migrate_0_46_to_0_47(data_dir.path()).unwrap();
+
+        let db = open_states_db(&db_path, crate::tables::MAP_NAMES);
+        let cf = db.cf_handle(TAG_INDEX).unwrap();
+        let stored: BTreeSet<Vec<u8>> = db
+            .iterator_cf(&cf, rocksdb::IteratorMode::Start)
+            .map(|item| item.unwrap().0.to_vec())
+            .collect();
+        let mut expected = tag_index_entries(NETWORKS, &network.0, &network.1).unwrap();
+        expected.extend(tag_index_entries(TRIAGE_RESPONSE, &response.0, &response.1).unwrap());
+        assert_eq!(expected.len(), 3);
+        assert_eq!(stored, expected);
+    }
+
     /// Builds an alpha.1 database that also holds `extra` families, rewinds the
     /// version marker, and asserts that the retry completes.
     fn assert_retry_completes_with_families(extra: &[&str]) {
@@ -5377,4 +5727,251 @@ mod tests {
         );
         assert_eq!(migrated.confidence[1].threat_kind, "dns_tunnel");
     }
+
+    /// Test that the 0.47 migration wraps the flat `packet_attr` list of a
+    /// triage policy in rules with typed values, turns an attribute whose
+    /// values cannot be decoded into a condition that never holds, and leaves
+    /// a policy with rules as it is.
+    #[test]
+    fn migrate_triage_policy_packet_attr_into_rules() {
+        use std::cmp::Ordering;
+
+        use attrievent::attribute::RawEventKind;
+
+        use super::migration_structures::{PacketAttrV0_46, TriagePolicyV0_46};
+        use crate::{
+            AttrCmpKind, AttrValue, PacketAttr, TriageCondition, TriageRule, TriageRules, ValueKind,
+        };
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let db_path = data_dir.path().join("states.db");
+        create_states_db(&db_path, super::MAP_NAMES_V0_43_TO_V0_46);
+
+        fn encode<T: serde::Serialize>(value: &T) -> Vec<u8> {
+            bincode::DefaultOptions::new().serialize(value).unwrap()
+        }
+        let packet_attr = vec![
+            PacketAttrV0_46 {
+                raw_event_kind: RawEventKind::Http,
+                attr_name: "method".to_string(),
+                value_kind: ValueKind::String,
+                cmp_kind: AttrCmpKind::Contain,
+                first_value: encode(&"POST"),
+                second_value: None,
+                weight: Some(0.5),
+            },
+            PacketAttrV0_46 {
+                raw_event_kind: RawEventKind::Http,
+                attr_name: "status_code".to_string(),
+                value_kind: ValueKind::UInteger,
+                cmp_kind: AttrCmpKind::Equal,
+                first_value: vec![200],
+                second_value: None,
+                weight: None,
+            },
+            PacketAttrV0_46 {
+                raw_event_kind: RawEventKind::Http,
+                attr_name: "dst_port".to_string(),
+                value_kind: ValueKind::Integer,
+                cmp_kind: AttrCmpKind::CloseRange,
+                first_value: encode(&80_i64),
+                second_value: Some(encode(&443_i64)),
+                weight: Some(0.25),
+            },
+            // Raw bytes rather than an encoded string never matched.
+            PacketAttrV0_46 {
+                raw_event_kind: RawEventKind::Http,
+                attr_name: "uri".to_string(),
+                value_kind: ValueKind::String,
+                cmp_kind: AttrCmpKind::Contain,
+                first_value: b"POST".to_vec(),
+                second_value: None,
+                weight: Some(1.0),
+            },
+        ];
+        let old_policy = TriagePolicyV0_46 {
+            id: 1,
+            name: "flat".to_string(),
+            triage_exclusion_id: vec![3],
+            packet_attr,
+            confidence: vec![],
+            response: vec![],
+            creation_time: chrono::Utc::now(),
+            customer_id: None,
+        };
+        let current_policy = crate::TriagePolicy {
+            id: 2,
+            name: "tree".to_string(),
+            triage_exclusion_id: vec![],
+            rules: TriageRules::V1(vec![TriageRule {
+                condition: TriageCondition::Not(Box::new(TriageCondition::Exclusion(3))),
+                weight: 1.0,
+            }]),
+            confidence: vec![],
+            response: vec![],
+            creation_time: chrono::Utc::now(),
+            customer_id: Some(1),
+        };
+        let old_key = record_key(u32::MAX, "flat");
+        let current_key = record_key(1, "tree");
+        let current_value = bincode::DefaultOptions::new()
+            .serialize(&current_policy)
+            .unwrap();
+        let mut index = crate::collections::KeyIndex::default();
+        index.insert(b"flat").unwrap();
+        index.insert(b"tree").unwrap();
+        let index_value = bincode::DefaultOptions::new().serialize(&index).unwrap();
+        put_entries(
+            &db_path,
+            super::MAP_NAMES_V0_43_TO_V0_46,
+            crate::tables::TRIAGE_POLICY,
+            &[
+                (
+                    old_key.clone(),
+                    bincode::DefaultOptions::new()
+                        .serialize(&old_policy)
+                        .unwrap(),
+                ),
+                (current_key.clone(), current_value.clone()),
+                (Vec::new(), index_value.clone()),
+            ],
+        );
+
+        super::migrate_0_46_to_0_47(data_dir.path()).unwrap();
+
+        assert_eq!(
+            raw_value(
+                &db_path,
+                super::MAP_NAMES_V0_47_ALPHA_3,
+                crate::tables::TRIAGE_POLICY,
+                &[],
+            ),
+            Some(index_value)
+        );
+
+        let migrated = raw_value(
+            &db_path,
+            super::MAP_NAMES_V0_47_ALPHA_3,
+            crate::tables::TRIAGE_POLICY,
+            &old_key,
+        )
+        .unwrap();
+        let migrated: crate::TriagePolicy = bincode::DefaultOptions::new()
+            .deserialize(&migrated)
+            .unwrap();
+        assert_eq!(migrated.name, "flat");
+        assert_eq!(migrated.triage_exclusion_id, vec![3]);
+        let TriageRules::V1(rules) = &migrated.rules;
+        assert_eq!(rules.len(), 4);
+        assert_eq!(rules[0].weight.partial_cmp(&0.5), Some(Ordering::Equal));
+        assert_eq!(rules[1].weight.partial_cmp(&0.0), Some(Ordering::Equal));
+        let attr = |attr_name: &str, cmp_kind, value, weight| PacketAttr {
+            raw_event_kind: RawEventKind::Http,
+            attr_name: attr_name.to_string(),
+            cmp_kind,
+            value,
+            weight,
+        };
+        let rule = |condition, weight| TriageRule { condition, weight };
+        let expected = TriageRules::V1(vec![
+            rule(
+                TriageCondition::Attr(attr(
+                    "method",
+                    AttrCmpKind::Contain,
+                    AttrValue::String("POST".to_string()),
+                    Some(0.5),
+                )),
+                0.5,
+            ),
+            rule(
+                TriageCondition::Attr(attr(
+                    "status_code",
+                    AttrCmpKind::Equal,
+                    AttrValue::UInteger(200),
+                    None,
+                )),
+                0.0,
+            ),
+            rule(
+                TriageCondition::Attr(attr(
+                    "dst_port",
+                    AttrCmpKind::CloseRange,
+                    AttrValue::IntegerRange(80, 443),
+                    Some(0.25),
+                )),
+                0.25,
+            ),
+            rule(TriageCondition::Any(Vec::new()), 1.0),
+        ]);
+        assert!(migrated.rules == expected);
+        assert_eq!(
+            raw_value(
+                &db_path,
+                super::MAP_NAMES_V0_47_ALPHA_3,
+                crate::tables::TRIAGE_POLICY,
+                &current_key,
+            ),
+            Some(current_value)
+        );
+    }
+
+    /// Test that the 0.47 migration gives the stored triage exclusion reasons
+    /// no owner and no scope, and leaves the index of the table alone.
+    #[test]
+    fn migrate_triage_exclusion_reason_scope() {
+        use super::migration_structures::TriageExclusionReasonV0_46;
+        use crate::{ExclusionReason, ExclusionScope, TriageExclusionReason};
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let db_path = data_dir.path().join("states.db");
+        create_states_db(&db_path, super::MAP_NAMES_V0_43_TO_V0_46);
+
+        let old_reason = TriageExclusionReasonV0_46 {
+            id: 0,
+            name: "trusted".to_string(),
+            exclusion_reason: ExclusionReason::Domain(vec!["example.com".to_string()]),
+            description: "description".to_string(),
+        };
+        let index = b"index".to_vec();
+        put_entries(
+            &db_path,
+            super::MAP_NAMES_V0_43_TO_V0_46,
+            crate::tables::TRIAGE_EXCLUSION_REASON,
+            &[
+                (Vec::new(), index.clone()),
+                (
+                    b"trusted".to_vec(),
+                    bincode::DefaultOptions::new()
+                        .serialize(&old_reason)
+                        .unwrap(),
+                ),
+            ],
+        );
+
+        super::migrate_0_46_to_0_47(data_dir.path()).unwrap();
+
+        let migrated = raw_value(
+            &db_path,
+            super::MAP_NAMES_V0_47_ALPHA_3,
+            crate::tables::TRIAGE_EXCLUSION_REASON,
+            b"trusted",
+        )
+        .unwrap();
+        let migrated: TriageExclusionReason = bincode::DefaultOptions::new()
+            .deserialize(&migrated)
+            .unwrap();
+        assert_eq!(migrated.name, "trusted");
+        assert_eq!(migrated.description, "description");
+        assert_eq!(migrated.customer_id, None);
+        assert_eq!(migrated.scope, ExclusionScope::default());
+        assert_eq!(
+            raw_value(
+                &db_path,
+                super::MAP_NAMES_V0_47_ALPHA_3,
+                crate::tables::TRIAGE_EXCLUSION_REASON,
+                &[],
+            ),
+            Some(index)
+        );
+    }
 }
@@ -59,7 +59,91 @@ pub(crate) struct NetworkValueV0_43 {
 // (Confidence.threat_category: EventCategory -> Option<EventCategory>)
 // ============================================================================
 
-use crate::{PacketAttr, Response};
+use attrievent::attribute::RawEventKind;
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use tracing::warn;
+
+use crate::{
+    AttrCmpKind, AttrValue, PacketAttr, Response, TriageCondition, TriageRule, TriageRules,
+    ValueKind,
+};
+
+/// `PacketAttr` structure up to version 0.46.x, whose values are encoded
+/// bytes read as `value_kind` says. From 0.47.0-alpha.3, the values became
+/// an `AttrValue`.
+#[derive(Clone, Deserialize, Serialize)]
+pub(crate) struct PacketAttrV0_46 {
+    pub(crate) raw_event_kind: RawEventKind,
+    pub(crate) attr_name: String,
+    pub(crate) value_kind: ValueKind,
+    pub(crate) cmp_kind: AttrCmpKind,
+    pub(crate) first_value: Vec<u8>,
+    pub(crate) second_value: Option<Vec<u8>>,
+    pub(crate) weight: Option<f64>,
+}
+
+impl PacketAttrV0_46 {
+    /// Returns the values decoded as `value_kind` says, as a range if
+    /// `cmp_kind` compares with one, or `None` if they cannot be decoded.
+    fn value(&self) -> Option<AttrValue> {
+        fn decode<T: DeserializeOwned>(value: &[u8]) -> Option<T> {
+            bincode::DefaultOptions::new().deserialize(value).ok()
+        }
+
+        let first = &self.first_value;
+        if self.cmp_kind.is_range() {
+            let second = self.second_value.as_deref()?;
+            return match self.value_kind {
+                ValueKind::Integer => {
+                    Some(AttrValue::IntegerRange(decode(first)?, decode(second)?))
+                }
+                ValueKind::UInteger => {
+                    Some(AttrValue::UIntegerRange(decode(first)?, decode(second)?))
+                }
+                ValueKind::Float => Some(AttrValue::FloatRange(decode(first)?, decode(second)?)),
+                ValueKind::IpAddr => Some(AttrValue::IpAddrRange(decode(first)?, decode(second)?)),
+                ValueKind::String | ValueKind::Vector | ValueKind::Bool => None,
+            };
+        }
+        Some(match self.value_kind {
+            ValueKind::String => AttrValue::String(decode(first)?),
+            ValueKind::Integer => AttrValue::Integer(decode(first)?),
+            ValueKind::UInteger => AttrValue::UInteger(decode(first)?),
+            ValueKind::Float => AttrValue::Float(decode(first)?),
+            ValueKind::IpAddr => AttrValue::IpAddr(decode(first)?),
+            ValueKind::Bool => AttrValue::Bool(decode(first)?),
+            ValueKind::Vector => AttrValue::Vector(first.clone()),
+        })
+    }
+
+    /// Converts the attribute into a rule scoring its weight, as it did in a
+    /// flat list of attributes.
+    ///
+    /// An attribute whose values cannot be decoded never matched, so it
+    /// becomes a condition that never holds.
+    fn into_rule(self) -> TriageRule {
+        let weight = self.weight.unwrap_or_default();
+        let condition = match self.value() {
+            Some(value) => TriageCondition::Attr(PacketAttr {
+                raw_event_kind: self.raw_event_kind,
+                attr_name: self.attr_name,
+                cmp_kind: self.cmp_kind,
+                value,
+                weight: self.weight,
+            }),
+            None => {
+                warn!(
+                    "Attribute {:?} of a triage policy has values that cannot be decoded; it \
+                     never matches",
+                    self.attr_name
+                );
+                TriageCondition::Any(Vec::new())
+            }
+        };
+        TriageRule { condition, weight }
+    }
+}
 
 /// `Confidence` structure from version 0.44.0 (before `threat_category`
 /// became optional). In this version, `threat_category` was `EventCategory`.
@@ -79,14 +163,14 @@ pub(crate) struct TriagePolicyV0_44 {
     pub(crate) id: u32,
     pub(crate) name: String,
     pub(crate) triage_exclusion_id: Vec<u32>,
-    pub(crate) packet_attr: Vec<PacketAttr>,
+    pub(crate) packet_attr: Vec<PacketAttrV0_46>,
     pub(crate) confidence: Vec<ConfidenceV0_44>,
     pub(crate) response: Vec<Response>,
     pub(crate) creation_time: DateTime<Utc>,
     pub(crate) customer_id: Option<u32>,
 }
 
-impl From<TriagePolicyV0_44> for crate::TriagePolicy {
+impl From<TriagePolicyV0_44> for TriagePolicyV0_46 {
     fn from(old: TriagePolicyV0_44) -> Self {
         Self {
             id: old.id,
@@ -110,6 +194,47 @@ impl From<TriagePolicyV0_44> for crate::TriagePolicy {
     }
 }
 
+impl From<TriagePolicyV0_44> for crate::TriagePolicy {
+    fn from(old: TriagePolicyV0_44) -> Self {
+        TriagePolicyV0_46::from(old).into()
+    }
+}
+
+/// `TriagePolicy` structure up to version 0.46.x, scoring a flat list of
+/// packet attributes. From 0.47.0-alpha.3, the list became `rules`, an
+/// expression structure over the attributes.
+#[derive(Clone, Deserialize, Serialize)]
+pub(crate) struct TriagePolicyV0_46 {
+    pub(crate) id: u32,
+    pub(crate) name: String,
+    pub(crate) triage_exclusion_id: Vec<u32>,
+    pub(crate) packet_attr: Vec<PacketAttrV0_46>,
+    pub(crate) confidence: Vec<crate::Confidence>,
+    pub(crate) response: Vec<Response>,
+    pub(crate) creation_time: DateTime<Utc>,
+    pub(crate) customer_id: Option<u32>,
+}
+
+impl From<TriagePolicyV0_46> for crate::TriagePolicy {
+    fn from(old: TriagePolicyV0_46) -> Self {
+        Self {
+            id: old.id,
+            name: old.name,
+            triage_exclusion_id: old.triage_exclusion_id,
+            rules: TriageRules::V1(
+                old.packet_attr
+                    .into_iter()
+                    .map(PacketAttrV0_46::into_rule)
+                    .collect(),
+            ),
+            confidence: old.confidence,
+            response: old.response,
+            creation_time: old.creation_time,
+            customer_id: old.customer_id,
+        }
+    }
+}
+
 /// The stored `Agent` value up to database format 0.47.0-alpha.1, before the
 /// install-state fields were added.
 ///
@@ -207,6 +332,30 @@ impl From<ExternalServiceValueV0_47Alpha1> for ExternalServiceValueV0_47Alpha2 {
     }
 }
 
+/// `TriageExclusionReason` structure up to version 0.47.0-alpha.2, applying
+/// to the policies of all customers at all times. From 0.47.0-alpha.3, a
+/// reason can have an owning customer and a scope.
+#[derive(Deserialize, Serialize)]
+pub(crate) struct TriageExclusionReasonV0_46 {
+    pub(crate) id: u32,
+    pub(crate) name: String,
+    pub(crate) exclusion_reason: crate::ExclusionReason,
+    pub(crate) description: String,
+}
+
+impl From<TriageExclusionReasonV0_46> for crate::TriageExclusionReason {
+    fn from(old: TriageExclusionReasonV0_46) -> Self {
+        Self {
+            id: old.id,
+            name: old.name,
+            exclusion_reason: old.exclusion_reason,
+            description: old.description,
+            customer_id: None,
+            scope: crate::ExclusionScope::default(),
+        }
+    }
+}
+
 // ============================================================================
 // Historical persisted event schemas
 // ============================================================================
@@ -2,10 +2,12 @@ mod access_token;
 mod accounts;
 mod agent;
 mod allow_network;
+mod audit;
 mod backup_config;
 mod batch_info;
 mod block_network;
 mod category;
+mod change_log;
 mod cluster;
 mod column_stats;
 mod config;
@@ -26,14 +28,18 @@ mod node;
 mod operation_attempt;
 mod outlier_info;
 mod qualifier;
+mod response_action;
 mod retention_config;
 mod sampling_policy;
 mod scores;
 mod status;
+mod tag_index;
 mod template;
 mod time_series;
 mod tor_exit_node;
 mod traffic_filter;
+mod triage_analysis;
+mod triage_history;
 mod triage_policy;
 mod triage_response;
 mod trusted_domain;
@@ -41,15 +47,18 @@ mod trusted_user_agent;
 
 use std::path::{Path, PathBuf};
 
-use anyhow::{Result, anyhow};
+use anyhow::{Context, Result, anyhow};
 use rocksdb::Direction;
 use serde::{Deserialize, Serialize};
 
 pub use self::access_token::AccessToken;
 pub use self::agent::{Agent, AgentKind};
 pub use self::allow_network::{AllowNetwork, Update as AllowNetworkUpdate};
+pub use self::audit::{Actor, AuditAction, AuditEvent, AuditFilter, AuditIter, AuditLog};
 pub use self::backup_config::{BackupConfig, BackupConfigUpdate};
 pub use self::block_network::{BlockNetwork, Update as BlockNetworkUpdate};
+pub use self::change_log::{ChangeIter, ChangeLog, ChangeRecord};
+pub(crate) use self::change_log::{attribute as attribute_changes, record as record_change};
 pub use self::cluster::Cluster;
 pub use self::column_stats::{ColumnStats, TopColumnsOfCluster, TopMultimaps};
 pub use self::config::{
@@ -87,11 +96,16 @@ pub use self::operation_attempt::{
     RetentionBound as OperationRetentionBound, RetryPolicy as OperationRetryPolicy,
 };
 pub use self::outlier_info::{Key as OutlierInfoKey, OutlierInfo, Value as OutlierInfoValue};
+pub use self::response_action::{
+    ResponseAction, ResponseActionKind, ResponseActionState, ResponseActions, ResponseEffect,
+    ResponseTargets,
+};
 pub use self::retention_config::{RetentionConfig, RetentionConfigUpdate};
 pub use self::sampling_policy::{
     Interval as SamplingInterval, Kind as SamplingKind, Period as SamplingPeriod, SamplingPolicy,
     Update as SamplingPolicyUpdate,
 };
+pub(crate) use self::tag_index::{entries as tag_index_entries, record as record_tag_change};
 pub use self::template::{
     Structured, StructuredClusteringAlgorithm, Template, Unstructured,
     UnstructuredClusteringAlgorithm,
@@ -99,10 +113,16 @@ pub use self::template::{
 pub use self::time_series::{Cluster as ClusterTimeSeries, Column as ColumnTimeSeries, TimeSeries};
 pub use self::tor_exit_node::TorExitNode;
 pub use self::traffic_filter::{ProtocolPorts, TrafficFilter};
+pub use self::triage_analysis::PolicyFinding;
+pub use self::triage_history::{
+    RevisionAction, Revisioned, TriageHistory, TriagePolicyDiff, TriageRevision,
+};
+pub(crate) use self::triage_policy::AttrOperand;
 pub use self::triage_policy::{
-    AttrCmpKind, Confidence, ExclusionReason, NetworkFilter, PacketAttr, Response, ResponseKind,
-    TriageExclusion, TriageExclusionReason, TriageExclusionReasonUpdate, TriagePolicy,
-    TriagePolicyInput, Update as TriagePolicyUpdate, ValueKind,
+    AttrCmpKind, AttrValue, Confidence, ExclusionReason, ExclusionScope, NetworkFilter, PacketAttr,
+    Response, ResponseKind, TriageCondition, TriageExclusion, TriageExclusionReason,
+    TriageExclusionReasonUpdate, TriagePolicy, TriagePolicyInput, TriageRule, TriageRules,
+    Update as TriagePolicyUpdate, ValueKind,
 };
 pub use self::triage_response::{TriageResponse, Update as TriageResponseUpdate};
 pub use self::trusted_domain::TrustedDomain;
@@ -122,9 +142,11 @@ pub(super) const ACCESS_TOKENS: &str = "access_tokens";
 pub(super) const ACCOUNTS: &str = "accounts";
 pub(super) const AGENTS: &str = "agents";
 pub(super) const ALLOW_NETWORKS: &str = "allow networks";
+pub(super) const AUDIT: &str = "audit";
 pub(super) const BATCH_INFO: &str = "batch_info";
 pub(super) const BLOCK_NETWORKS: &str = "block networks";
 pub(super) const CATEGORY: &str = "category";
+pub(super) const CHANGE_LOG: &str = "change log";
 pub(super) const CLUSTER: &str = "cluster";
 pub(super) const COLUMN_STATS: &str = "column stats";
 pub(super) const CONFIGS: &str = "configs";
@@ -133,6 +155,12 @@ pub(super) const CSV_COLUMN_EXTRAS: &str = "csv column extras";
 pub(crate) const CUSTOMERS: &str = "customers";
 pub(super) const CUSTOMER_DELETION_JOBS: &str = "customer deletion jobs";
 pub(super) const DATA_SOURCES: &str = "data sources";
+pub(super) const EVENT_CONSUMER_OFFSETS: &str = "event consumer offsets";
+pub(super) const EVENT_ORIGINATOR_INDEX: &str = "event originator index";
+pub(super) const EVENT_RESPONDER_INDEX: &str = "event responder index";
+pub(super) const EVENT_ROLLUPS: &str = "event rollups";
+pub(super) const EVENT_SENSOR_INDEX: &str = "event sensor index";
+pub(super) const EVENT_TRIAGE_RESULTS: &str = "event triage results";
 pub(super) const FILTERS: &str = "filters";
 pub(super) const HOSTS: &str = "hosts";
 pub(super) const MODELS: &str = "models";
@@ -144,28 +172,34 @@ pub(super) const OPERATION_ATTEMPTS: &str = "operation attempts";
 pub(super) const OUTLIERS: &str = "outliers";
 pub(super) const QUALIFIERS: &str = "qualifiers";
 pub(super) const EXTERNAL_SERVICES: &str = "external services";
+pub(super) const RESPONSE_ACTIONS: &str = "response actions";
 pub(super) const SAMPLING_POLICY: &str = "sampling policy";
 pub(super) const SCORES: &str = "scores";
 pub(super) const STATUSES: &str = "statuses";
+pub(super) const TAGS: &str = "tags";
+pub(super) const TAG_INDEX: &str = "tag index";
 pub(super) const TEMPLATES: &str = "templates";
 pub(super) const LABEL_DB: &str = "label database";
 pub(super) const TIME_SERIES: &str = "time series";
 pub(super) const TOR_EXIT_NODES: &str = "Tor exit nodes";
 pub(super) const TRAFFIC_FILTER_RULES: &str = "traffic filter rules";
 pub(super) const TRIAGE_EXCLUSION_REASON: &str = "triage exclusion reason";
+pub(super) const TRIAGE_HISTORY: &str = "triage history";
 pub(super) const TRIAGE_POLICY: &str = "triage policy";
 pub(super) const TRIAGE_RESPONSE: &str = "triage response";
 pub(super) const TRUSTED_DNS_SERVERS: &str = "trusted DNS servers";
 pub(super) const TRUSTED_USER_AGENTS: &str = "trusted user agents";
 
-pub(crate) const MAP_NAMES: [&str; 39] = [
+pub(crate) const MAP_NAMES: [&str; 51] = [
     ACCESS_TOKENS,
     ACCOUNTS,
     AGENTS,
     ALLOW_NETWORKS,
+    AUDIT,
     BATCH_INFO,
     BLOCK_NETWORKS,
     CATEGORY,
+    CHANGE_LOG,
     CLUSTER,
     COLUMN_STATS,
     CONFIGS,
@@ -174,6 +208,12 @@ pub(crate) const MAP_NAMES: [&str; 39] = [
     CUSTOMERS,
     CUSTOMER_DELETION_JOBS,
     DATA_SOURCES,
+    EVENT_CONSUMER_OFFSETS,
+    EVENT_ORIGINATOR_INDEX,
+    EVENT_RESPONDER_INDEX,
+    EVENT_ROLLUPS,
+    EVENT_SENSOR_INDEX,
+    EVENT_TRIAGE_RESULTS,
     FILTERS,
     HOSTS,
     MODELS,
@@ -185,15 +225,19 @@ pub(crate) const MAP_NAMES: [&str; 39] = [
     OUTLIERS,
     QUALIFIERS,
     EXTERNAL_SERVICES,
+    RESPONSE_ACTIONS,
     SAMPLING_POLICY,
     SCORES,
     STATUSES,
+    TAGS,
+    TAG_INDEX,
     TEMPLATES,
     LABEL_DB,
     TIME_SERIES,
     TOR_EXIT_NODES,
     TRAFFIC_FILTER_RULES,
     TRIAGE_EXCLUSION_REASON,
+    TRIAGE_HISTORY,
     TRIAGE_POLICY,
     TRIAGE_RESPONSE,
     TRUSTED_DNS_SERVERS,
@@ -201,6 +245,11 @@ pub(crate) const MAP_NAMES: [&str; 39] = [
 ];
 
 // Keys for the meta map.
+pub(super) const EVENT_INDEXES: &[u8] = b"event indexes";
+pub(super) const EVENT_ROLLUP_STATE: &[u8] = b"event rollups";
+
+// Names of the sets in the tags map. Until 0.47.0-alpha.3, they were keys for
+// the meta map, each holding a whole set.
 pub(super) const EVENT_TAGS: &[u8] = b"event tags";
 pub(super) const NETWORK_TAGS: &[u8] = b"network tags";
 pub(super) const WORKFLOW_TAGS: &[u8] = b"workflow tags";
@@ -398,6 +447,30 @@ impl StateDb {
         IndexedTable::<TriagePolicy>::open(inner).expect("{TRIAGE_POLICY} table must be present")
     }
 
+    #[must_use]
+    pub(crate) fn triage_history<T: Revisioned>(&self) -> TriageHistory<'_, T> {
+        let inner = self.inner.as_ref().expect("database must be open");
+        TriageHistory::<T>::open(inner).expect("{TRIAGE_HISTORY} table must be present")
+    }
+
+    #[must_use]
+    pub(crate) fn audit_log(&self) -> AuditLog<'_> {
+        let inner = self.inner.as_ref().expect("database must be open");
+        AuditLog::open(inner).expect("{AUDIT} table must be present")
+    }
+
+    #[must_use]
+    pub(crate) fn change_log(&self) -> ChangeLog<'_> {
+        let inner = self.inner.as_ref().expect("database must be open");
+        ChangeLog::open(inner).expect("{CHANGE_LOG} table must be present")
+    }
+
+    #[must_use]
+    pub(crate) fn response_actions(&self) -> ResponseActions<'_> {
+        let inner = self.inner.as_ref().expect("database must be open");
+        ResponseActions::open(inner).expect("{RESPONSE_ACTIONS} table must be present")
+    }
+
     #[must_use]
     pub(crate) fn label_dbs(&self) -> Table<'_, LabelDb> {
         let inner = self.inner.as_ref().expect("database must be open");
@@ -473,7 +546,7 @@ impl StateDb {
     #[must_use]
     pub(super) fn indexed_set(&self, name: &'static [u8]) -> Option<IndexedSet<'_>> {
         let inner = self.inner.as_ref().expect("database must be open");
-        IndexedSet::new(inner, META, name).ok()
+        IndexedSet::new(inner, TAGS, name).ok()
     }
 
     pub(super) fn create_new_backup_flush(
@@ -519,6 +592,78 @@ impl StateDb {
         self.reboot()
     }
 
+    /// Restores the backup with `id`, or the latest backup if `id` is `None`,
+    /// keeping the entries the column families `kept` have before the
+    /// restore, so that those written after the backup was created survive.
+    ///
+    /// The entries are streamed into SST files before the restore and
+    /// ingested into the restored column families, so they are not held in
+    /// memory, and they are written neither in a transaction nor to the change
+    /// log.
+    pub fn restore_keeping(&mut self, id: Option<u32>, kept: &[&str]) -> Result<()> {
+        let dir =
+            std::env::temp_dir().join(format!("review-database-restore-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).context("cannot create directory for kept entries")?;
+        let result = self.restore_keeping_in(&dir, id, kept);
+        if let Err(e) = std::fs::remove_dir_all(&dir) {
+            tracing::warn!("cannot remove {}: {e}", dir.display());
+        }
+        result
+    }
+
+    fn restore_keeping_in(&mut self, dir: &Path, id: Option<u32>, kept: &[&str]) -> Result<()> {
+        let mut files = Vec::new();
+        for (i, name) in kept.iter().enumerate() {
+            let path = dir.join(format!("{i}.sst"));
+            if self.export(name, &path)? {
+                files.push((*name, path));
+            }
+        }
+        match id {
+            Some(id) => self.restore_from_backup(id)?,
+            None => self.restore_from_latest_backup()?,
+        }
+        let db = self
+            .inner
+            .as_ref()
+            .ok_or(anyhow!("unable to restore, database has closed"))?;
+        for (name, path) in files {
+            let cf = db
+                .cf_handle(name)
+                .with_context(|| format!("{name} column family not found"))?;
+            db.ingest_external_file_cf(cf, vec![path])
+                .with_context(|| format!("cannot write back {name}"))?;
+        }
+        Ok(())
+    }
+
+    /// Writes the entries of the column family `name` into an SST file at
+    /// `path`. Returns `false`, leaving the file unfinished, if there are no
+    /// entries to write.
+    fn export(&self, name: &str, path: &Path) -> Result<bool> {
+        let db = self
+            .inner
+            .as_ref()
+            .ok_or(anyhow!("unable to export, database has closed"))?;
+        let cf = db
+            .cf_handle(name)
+            .with_context(|| format!("{name} column family not found"))?;
+        let opts = rocksdb::Options::default();
+        let mut writer = rocksdb::SstFileWriter::create(&opts);
+        writer.open(path).context("cannot create SST file")?;
+        let mut empty = true;
+        for entry in db.iterator_cf(cf, rocksdb::IteratorMode::Start) {
+            let (key, value) = entry.with_context(|| format!("cannot read {name}"))?;
+            writer.put(key, value).context("cannot write SST file")?;
+            empty = false;
+        }
+        if empty {
+            return Ok(false);
+        }
+        writer.finish().context("cannot write SST file")?;
+        Ok(true)
+    }
+
     pub fn get_backup_info(&self) -> Result<Vec<rocksdb::backup::BackupEngineInfo>> {
         let engine = open_rocksdb_backup_engine(self.backup.as_path())?;
 
@@ -708,6 +853,17 @@ impl<'d, R> Table<'d, R> {
     pub(crate) fn transaction(&self) -> rocksdb::Transaction<'_, rocksdb::OptimisticTransactionDB> {
         self.map.db.transaction()
     }
+
+    /// Runs `write` in a transaction that also records in the audit trail the
+    /// event `event` makes out of the output of `write`, trying both again if
+    /// the transaction conflicts with another.
+    pub(crate) fn audited<T, F, E>(&self, write: F, event: E) -> Result<T>
+    where
+        F: FnMut(&rocksdb::Transaction<rocksdb::OptimisticTransactionDB>) -> Result<T>,
+        E: Fn(&T) -> AuditEvent,
+    {
+        audit::audited(self.map.db, write, event)
+    }
 }
 
 impl<R: UniqueKey + Value> Table<'_, R> {
@@ -876,15 +1032,28 @@ impl<'d, R> IndexedTable<'d, R> {
         self.indexed_map.count()
     }
 
+    /// Runs `write` in a transaction that also records in the audit trail the
+    /// event `event` makes out of the output of `write`, trying both again if
+    /// the transaction conflicts with another.
+    pub(crate) fn audited<T, F, E>(&self, write: F, event: E) -> Result<T>
+    where
+        F: FnMut(&rocksdb::Transaction<rocksdb::OptimisticTransactionDB>) -> Result<T>,
+        E: Fn(&T) -> AuditEvent,
+    {
+        audit::audited(self.indexed_map.db(), write, event)
+    }
+
     /// Stores a record with the given ID.
     ///
     /// # Errors
     ///
-    /// Returns an error if the database operation fails.
+    /// Returns an error if [`Indexable::validate`] rejects the record or the
+    /// database operation fails.
     pub fn put(&self, entry: R) -> Result<u32>
     where
         R: Indexable,
     {
+        entry.validate()?;
         self.indexed_map.insert(entry)
     }
 
@@ -892,7 +1061,8 @@ impl<'d, R> IndexedTable<'d, R> {
     ///
     /// # Errors
     ///
-    /// Returns an error if the database operation fails.
+    /// Returns an error if [`Indexable::validate`] rejects the record or the
+    /// database operation fails.
     pub fn put_with_transaction(
         &self,
         entry: R,
@@ -901,6 +1071,7 @@ impl<'d, R> IndexedTable<'d, R> {
     where
         R: Indexable,
     {
+        entry.validate()?;
         self.indexed_map.insert_with_transaction(entry, txn)
     }
 
@@ -8,7 +8,7 @@ use chrono::Utc;
 use rocksdb::OptimisticTransactionDB;
 
 use crate::{
-    EXCLUSIVE, Map, Role, Table,
+    Actor, AuditAction, AuditEvent, EXCLUSIVE, Map, Role, Table,
     types::{Account, FromKeyValue},
 };
 
@@ -143,8 +143,7 @@ impl<'d> Table<'d, Account> {
                 }
 
                 let value = bincode::DefaultOptions::new().serialize(&account)?;
-                txn.put_cf(self.map.cf, username, value)
-                    .context("failed to write new entry")?;
+                self.map.put_with_transaction(username, &value, &txn)?;
             } else {
                 bail!("no such entry");
             }
@@ -190,8 +189,8 @@ impl<'d> Table<'d, Account> {
                 }
 
                 let value = bincode::DefaultOptions::new().serialize(&account)?;
-                txn.put_cf(self.map.cf, username.as_bytes(), value)
-                    .context("failed to write updated entry")?;
+                self.map
+                    .put_with_transaction(username.as_bytes(), &value, &txn)?;
             } else {
                 bail!("no such entry");
             }
@@ -230,8 +229,8 @@ impl<'d> Table<'d, Account> {
                 account.locked_out_until = None;
 
                 let value = bincode::DefaultOptions::new().serialize(&account)?;
-                txn.put_cf(self.map.cf, username.as_bytes(), value)
-                    .context("failed to write updated entry")?;
+                self.map
+                    .put_with_transaction(username.as_bytes(), &value, &txn)?;
             } else {
                 bail!("no such entry");
             }
@@ -271,6 +270,26 @@ impl<'d> Table<'d, Account> {
         }
     }
 
+    /// Adds an account, and records its creation by `actor` in the audit
+    /// trail in the same transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an account with the same username exists or the
+    /// database operation fails.
+    pub fn insert_as(&self, account: &Account, actor: &Actor) -> Result<(), anyhow::Error> {
+        self.audited(
+            |txn| self.insert_with_transaction(account, txn),
+            |_| {
+                AuditEvent::new(
+                    actor.clone(),
+                    AuditAction::AccountCreated,
+                    &account.username,
+                )
+            },
+        )
+    }
+
     /// Suspends an account with the given username.
     ///
     /// # Errors
@@ -279,24 +298,7 @@ impl<'d> Table<'d, Account> {
     pub fn suspend_account(&self, username: &str) -> Result<(), anyhow::Error> {
         loop {
             let txn = self.map.db.transaction();
-            if let Some(old_value) = txn
-                .get_for_update_cf(self.map.cf, username.as_bytes(), EXCLUSIVE)
-                .context("cannot read old entry")?
-            {
-                let options = bincode::DefaultOptions::new();
-                let Ok(mut account) = options.deserialize::<Account>(old_value.as_ref()) else {
-                    return Err(anyhow::anyhow!("Failed to deserialize account data"));
-                };
-
-                account.is_suspended = true;
-
-                let value = bincode::DefaultOptions::new().serialize(&account)?;
-                txn.put_cf(self.map.cf, username.as_bytes(), value)
-                    .context("failed to write updated entry")?;
-            } else {
-                bail!("no such entry");
-            }
-
+            self.set_suspended(username, true, &txn)?;
             match txn.commit() {
                 Ok(()) => break,
                 Err(e) => {
@@ -309,6 +311,19 @@ impl<'d> Table<'d, Account> {
         Ok(())
     }
 
+    /// Suspends an account with the given username, and records it as done by
+    /// `actor` in the audit trail in the same transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the account does not exist or the database operation fails.
+    pub fn suspend_account_as(&self, username: &str, actor: &Actor) -> Result<(), anyhow::Error> {
+        self.audited(
+            |txn| self.set_suspended(username, true, txn),
+            |_| AuditEvent::new(actor.clone(), AuditAction::AccountSuspended, username),
+        )
+    }
+
     /// Unsuspends an account with the given username.
     ///
     /// # Errors
@@ -317,24 +332,7 @@ impl<'d> Table<'d, Account> {
     pub fn unsuspend_account(&self, username: &str) -> Result<(), anyhow::Error> {
         loop {
             let txn = self.map.db.transaction();
-            if let Some(old_value) = txn
-                .get_for_update_cf(self.map.cf, username.as_bytes(), EXCLUSIVE)
-                .context("cannot read old entry")?
-            {
-                let options = bincode::DefaultOptions::new();
-                let Ok(mut account) = options.deserialize::<Account>(old_value.as_ref()) else {
-                    return Err(anyhow::anyhow!("Failed to deserialize account data"));
-                };
-
-                account.is_suspended = false;
-
-                let value = bincode::DefaultOptions::new().serialize(&account)?;
-                txn.put_cf(self.map.cf, username.as_bytes(), value)
-                    .context("failed to write updated entry")?;
-            } else {
-                bail!("no such entry");
-            }
-
+            self.set_suspended(username, false, &txn)?;
             match txn.commit() {
                 Ok(()) => break,
                 Err(e) => {
@@ -347,6 +345,43 @@ impl<'d> Table<'d, Account> {
         Ok(())
     }
 
+    /// Unsuspends an account with the given username, and records it as done
+    /// by `actor` in the audit trail in the same transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the account does not exist or the database operation fails.
+    pub fn unsuspend_account_as(&self, username: &str, actor: &Actor) -> Result<(), anyhow::Error> {
+        self.audited(
+            |txn| self.set_suspended(username, false, txn),
+            |_| AuditEvent::new(actor.clone(), AuditAction::AccountUnsuspended, username),
+        )
+    }
+
+    fn set_suspended(
+        &self,
+        username: &str,
+        suspended: bool,
+        txn: &rocksdb::Transaction<OptimisticTransactionDB>,
+    ) -> Result<(), anyhow::Error> {
+        let Some(old_value) = txn
+            .get_for_update_cf(self.map.cf, username.as_bytes(), EXCLUSIVE)
+            .context("cannot read old entry")?
+        else {
+            bail!("no such entry");
+        };
+        let options = bincode::DefaultOptions::new();
+        let Ok(mut account) = options.deserialize::<Account>(old_value.as_ref()) else {
+            return Err(anyhow::anyhow!("Failed to deserialize account data"));
+        };
+
+        account.is_suspended = suspended;
+
+        let value = bincode::DefaultOptions::new().serialize(&account)?;
+        self.map
+            .put_with_transaction(username.as_bytes(), &value, txn)
+    }
+
     /// Returns all accounts with their security status information.
     /// This method is useful for administrative dashboards showing user security states.
     ///
@@ -621,6 +656,47 @@ mod tests {
         assert!(!unsuspended_account.is_suspended);
     }
 
+    #[test]
+    fn account_changes_are_logged() {
+        use bincode::Options;
+
+        let (_permit, store) = setup_store();
+        let table = store.account_map();
+        let account = Account::new(
+            "user1",
+            "password",
+            Role::SystemAdministrator,
+            "User 1".to_string(),
+            "Department 1".to_string(),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        table.put(&account).unwrap();
+
+        let log = store.change_log();
+        log.enable().unwrap();
+        table.suspend_account("user1").unwrap();
+        table.increment_failed_login("user1").unwrap();
+
+        let records = log.since(0).collect::<anyhow::Result<Vec<_>>>().unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(
+            records
+                .iter()
+                .all(|r| r.table == super::super::ACCOUNTS && r.key == b"user1")
+        );
+        assert!(records.iter().all(|r| r.old_value_hash.is_some()));
+        let logged: Account = bincode::DefaultOptions::new()
+            .deserialize(records[1].new_value.as_deref().unwrap())
+            .unwrap();
+        assert!(logged.is_suspended);
+        assert_eq!(logged.failed_login_attempts, 1);
+    }
+
     #[test]
     fn test_get_accounts_with_security_status() {
         let (_permit, store) = setup_store();
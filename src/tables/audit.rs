@@ -0,0 +1,415 @@
+//! The `audit` table.
+//!
+//! An [`AuditEvent`] records an administrative action and the [`Actor`] who
+//! took it. Unlike the change log, which records every write to the tables,
+//! the events say what was done rather than which entries changed. The
+//! functions taking an [`Actor`], such as `Table<Account>::insert_as`, record
+//! the event in the transaction of the action, so an event is recorded if and
+//! only if the action is committed.
+//!
+//! An event is keyed by its time in nanoseconds, big-endian, followed by a
+//! big-endian counter telling apart the events recorded at the same time, so
+//! the table is in the order the events happened.
+
+use std::net::IpAddr;
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use rocksdb::{Direction, IteratorMode, OptimisticTransactionDB, Transaction};
+use serde::{Deserialize, Serialize};
+
+use crate::{EXCLUSIVE, Map};
+
+const TIME_LEN: usize = std::mem::size_of::<i64>();
+
+/// Who took an audited action.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Actor {
+    pub username: String,
+    /// The address the request came from, or `None` if the action was not
+    /// requested over the network.
+    pub source_ip: Option<IpAddr>,
+}
+
+/// The kind of an audited action.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum AuditAction {
+    AccountCreated,
+    AccountSuspended,
+    AccountUnsuspended,
+    AccountPolicyChanged,
+    TriagePolicyInserted,
+    TriagePolicyUpdated,
+    TriagePolicyRemoved,
+    CustomerInserted,
+    CustomerUpdated,
+    CustomerRemoved,
+    NetworkInserted,
+    NetworkUpdated,
+    NetworkRemoved,
+    /// The drafts of a node's name and profile were applied.
+    NodeConfigApplied,
+    BackupCreated,
+    BackupRestored,
+}
+
+/// An administrative action, stored in the `audit` table.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct AuditEvent {
+    pub time: DateTime<Utc>,
+    pub actor: Actor,
+    pub action: AuditAction,
+    /// What the action was taken on, such as the username of an account or
+    /// the ID of a triage policy.
+    pub target: String,
+    pub details: Option<String>,
+}
+
+impl AuditEvent {
+    /// Creates an event for an action taken now.
+    #[must_use]
+    pub fn new(actor: Actor, action: AuditAction, target: impl Into<String>) -> Self {
+        Self {
+            time: Utc::now(),
+            actor,
+            action,
+            target: target.into(),
+            details: None,
+        }
+    }
+
+    /// Sets the details of the event.
+    #[must_use]
+    pub fn with_details(mut self, details: impl Into<String>) -> Self {
+        self.details = Some(details.into());
+        self
+    }
+}
+
+/// The conditions an audit event must meet to be returned by
+/// [`AuditLog::events`]. A condition left as `None` is met by every event.
+#[derive(Clone, Debug, Default)]
+pub struct AuditFilter {
+    pub username: Option<String>,
+    pub action: Option<AuditAction>,
+    /// The earliest time of the events, inclusive.
+    pub start: Option<DateTime<Utc>>,
+    /// The latest time of the events, exclusive.
+    pub end: Option<DateTime<Utc>>,
+}
+
+impl AuditFilter {
+    fn matches(&self, event: &AuditEvent) -> bool {
+        self.username
+            .as_ref()
+            .is_none_or(|username| *username == event.actor.username)
+            && self.action.is_none_or(|action| action == event.action)
+    }
+}
+
+/// The trail of administrative actions.
+pub struct AuditLog<'d> {
+    map: Map<'d>,
+}
+
+impl<'d> AuditLog<'d> {
+    pub(super) fn open(db: &'d OptimisticTransactionDB) -> Option<Self> {
+        Map::open(db, super::AUDIT).map(|map| Self { map })
+    }
+
+    /// Records an event.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the time of the event cannot be represented in
+    /// nanoseconds or the database operation fails.
+    pub fn record(&self, event: &AuditEvent) -> Result<()> {
+        loop {
+            let txn = self.map.db.transaction();
+            self.record_with_transaction(event, &txn)?;
+            match txn.commit() {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    if !e.as_ref().starts_with("Resource busy:") {
+                        return Err(e).context("failed to record audit event");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Records an event within a transaction, and attributes the changes made
+    /// in the transaction to the actor of the event.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the time of the event cannot be represented in
+    /// nanoseconds or the database operation fails.
+    pub fn record_with_transaction(
+        &self,
+        event: &AuditEvent,
+        txn: &Transaction<OptimisticTransactionDB>,
+    ) -> Result<()> {
+        let time = time_key(event.time)?;
+        let mut counter = 0_u32;
+        let key = loop {
+            let key = [time.as_slice(), &counter.to_be_bytes()].concat();
+            if txn
+                .get_for_update_cf(self.map.cf, &key, EXCLUSIVE)
+                .context("cannot read audit event")?
+                .is_none()
+            {
+                break key;
+            }
+            let Some(next) = counter.checked_add(1) else {
+                bail!("too many audit events at {}", event.time);
+            };
+            counter = next;
+        };
+        self.map
+            .put_with_transaction(&key, &super::serialize(event)?, txn)?;
+        super::attribute_changes(self.map.db, txn, &event.actor)
+    }
+
+    /// Returns an iterator over the events meeting `filter`, in the order they
+    /// happened. The events are read as the iterator advances, and only those
+    /// within the time range of `filter` are read.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a time in `filter` cannot be represented in
+    /// nanoseconds.
+    pub fn events(&self, filter: &AuditFilter) -> Result<AuditIter<'d>> {
+        let start = filter.start.map(time_key).transpose()?;
+        let end = filter.end.map(time_key).transpose()?;
+        let mode = match &start {
+            Some(start) => IteratorMode::From(start, Direction::Forward),
+            None => IteratorMode::Start,
+        };
+        Ok(AuditIter {
+            inner: self.map.db.iterator_cf(self.map.cf, mode),
+            filter: filter.clone(),
+            end,
+        })
+    }
+}
+
+/// An iterator over the events in the audit trail meeting an [`AuditFilter`].
+pub struct AuditIter<'d> {
+    inner: rocksdb::DBIteratorWithThreadMode<'d, OptimisticTransactionDB>,
+    filter: AuditFilter,
+    end: Option<[u8; TIME_LEN]>,
+}
+
+impl Iterator for AuditIter<'_> {
+    type Item = Result<AuditEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (key, value) = match self.inner.next()? {
+                Ok(entry) => entry,
+                Err(e) => return Some(Err(e).context("cannot read audit event")),
+            };
+            if self
+                .end
+                .as_ref()
+                .is_some_and(|end| key.get(..TIME_LEN) >= Some(end.as_slice()))
+            {
+                return None;
+            }
+            let event: AuditEvent = match super::deserialize(&value) {
+                Ok(event) => event,
+                Err(e) => return Some(Err(e).context("invalid audit event in database")),
+            };
+            if self.filter.matches(&event) {
+                return Some(Ok(event));
+            }
+        }
+    }
+}
+
+/// Runs `write` in a transaction that also records the event `event` makes
+/// out of the output of `write`, trying both again if the transaction
+/// conflicts with another.
+pub(super) fn audited<T, F, E>(db: &OptimisticTransactionDB, mut write: F, event: E) -> Result<T>
+where
+    F: FnMut(&Transaction<OptimisticTransactionDB>) -> Result<T>,
+    E: Fn(&T) -> AuditEvent,
+{
+    let log = AuditLog::open(db).context("cannot find audit table")?;
+    loop {
+        let txn = db.transaction();
+        let output = write(&txn)?;
+        log.record_with_transaction(&event(&output), &txn)?;
+        match txn.commit() {
+            Ok(()) => return Ok(output),
+            Err(e) => {
+                if !e.as_ref().starts_with("Resource busy:") {
+                    return Err(e).context("failed to commit audited action");
+                }
+            }
+        }
+    }
+}
+
+fn time_key(time: DateTime<Utc>) -> Result<[u8; TIME_LEN]> {
+    time.timestamp_nanos_opt()
+        .map(i64::to_be_bytes)
+        .context("time out of range")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        sync::Arc,
+    };
+
+    use anyhow::Result;
+    use chrono::{TimeZone, Utc};
+
+    use crate::test::{DbGuard, acquire_db_permit};
+    use crate::{
+        Actor, AuditAction, AuditEvent, AuditFilter, Customer, Role, Store, types::Account,
+    };
+
+    fn setup_store() -> (DbGuard<'static>, Arc<Store>) {
+        let permit = acquire_db_permit();
+        let db_dir = tempfile::tempdir().unwrap();
+        let backup_dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(Store::new(db_dir.path(), backup_dir.path(), None).unwrap());
+        (permit, store)
+    }
+
+    fn actor(username: &str) -> Actor {
+        Actor {
+            username: username.to_string(),
+            source_ip: Some(IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1))),
+        }
+    }
+
+    #[test]
+    fn query_events() {
+        let (_permit, store) = setup_store();
+        let log = store.audit_log();
+        let time = Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap();
+        let mut events = vec![
+            AuditEvent::new(actor("admin"), AuditAction::AccountCreated, "alice"),
+            AuditEvent::new(actor("admin"), AuditAction::AccountSuspended, "alice"),
+            AuditEvent::new(actor("alice"), AuditAction::TriagePolicyUpdated, "3")
+                .with_details("renamed"),
+            AuditEvent::new(actor("admin"), AuditAction::BackupCreated, "1"),
+        ];
+        for (event, minutes) in events.iter_mut().zip([0, 0, 1, 2]) {
+            event.time = time + chrono::Duration::minutes(minutes);
+        }
+        // Recorded out of order, with two events at the same time.
+        for event in events.iter().rev() {
+            log.record(event).unwrap();
+        }
+
+        // Events at the same time are in the order they were recorded.
+        let all = log
+            .events(&AuditFilter::default())
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            all,
+            [
+                events[1].clone(),
+                events[0].clone(),
+                events[2].clone(),
+                events[3].clone(),
+            ]
+        );
+
+        let by_admin = log
+            .events(&AuditFilter {
+                username: Some("admin".to_string()),
+                ..AuditFilter::default()
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(by_admin.len(), 3);
+        assert!(by_admin.iter().all(|e| e.actor.username == "admin"));
+
+        let suspensions = log
+            .events(&AuditFilter {
+                action: Some(AuditAction::AccountSuspended),
+                ..AuditFilter::default()
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(suspensions, [events[1].clone()]);
+
+        let in_range = log
+            .events(&AuditFilter {
+                start: Some(events[2].time),
+                end: Some(events[3].time),
+                ..AuditFilter::default()
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(in_range, [events[2].clone()]);
+        assert_eq!(in_range[0].details.as_deref(), Some("renamed"));
+    }
+
+    #[test]
+    fn audited_actions() {
+        let (_permit, store) = setup_store();
+        let admin = actor("admin");
+        let accounts = store.account_map();
+        let account = Account::new(
+            "alice",
+            "password",
+            Role::SecurityAdministrator,
+            "Alice".to_string(),
+            "Department".to_string(),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        accounts.insert_as(&account, &admin).unwrap();
+        accounts.suspend_account_as("alice", &admin).unwrap();
+        // An action that fails records nothing.
+        assert!(accounts.insert_as(&account, &admin).is_err());
+        assert!(accounts.suspend_account_as("bob", &admin).is_err());
+
+        let customers = store.customer_map();
+        let customer = Customer {
+            id: u32::MAX,
+            name: "customer".to_string(),
+            description: String::new(),
+            networks: Vec::new(),
+            creation_time: Utc::now(),
+        };
+        let id = customers.insert_as(customer, &admin).unwrap();
+
+        let events = store
+            .audit_log()
+            .events(&AuditFilter::default())
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            events
+                .iter()
+                .map(|e| (e.action, e.target.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (AuditAction::AccountCreated, "alice"),
+                (AuditAction::AccountSuspended, "alice"),
+                (AuditAction::CustomerInserted, id.to_string().as_str()),
+            ]
+        );
+        assert!(events.iter().all(|e| e.actor == admin));
+        assert!(accounts.get("alice").unwrap().unwrap().is_suspended);
+    }
+}
@@ -0,0 +1,474 @@
+//! The `change log` table.
+//!
+//! When enabled, every write made through a [`Map`], an [`IndexedMap`], or an
+//! [`IndexedSet`] appends a [`ChangeRecord`] to this table within the
+//! transaction of the write, so a change is logged if and only if it is
+//! committed. A write to an [`IndexedSet`], such as a tag set, is recorded as
+//! the writes to the RocksDB keys storing the set. The records
+//! are keyed by a sequence number, big-endian, and the empty key holds the
+//! sequence number of the next record. The log is enabled exactly while that
+//! key exists.
+//!
+//! Every write reads the next sequence number within its transaction, so a
+//! write made while the log is being enabled or disabled conflicts with it
+//! and is tried again. While the log is enabled, every write also updates the
+//! number, so concurrent writes conflict with one another, and the order of
+//! the sequence numbers is the order in which the writes were committed.
+//!
+//! The changes made in the transaction of an action recorded in the audit
+//! trail, such as those of `Table<Account>::insert_as`, are attributed to the
+//! [`Actor`] who took it.
+//!
+//! [`Map`]: crate::Map
+//! [`IndexedMap`]: crate::IndexedMap
+//! [`IndexedSet`]: crate::collections::IndexedSet
+
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use ring::digest;
+use rocksdb::{ColumnFamily, IteratorMode, OptimisticTransactionDB, Transaction};
+use serde::{Deserialize, Serialize};
+
+use crate::{Actor, EXCLUSIVE};
+
+const STATE_KEY: &[u8] = &[];
+
+/// A change made to an entry of a table.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct ChangeRecord {
+    pub sequence: u64,
+    /// The name of the column family of the table.
+    pub table: String,
+    pub key: Vec<u8>,
+    /// The SHA-256 digest of the value before the change, or `None` if there
+    /// was no entry with the key.
+    pub old_value_hash: Option<Vec<u8>>,
+    /// The value after the change, or `None` if the entry was deleted.
+    pub new_value: Option<Vec<u8>>,
+    /// Who made the change, or `None` if it was not made by an action recorded
+    /// in the audit trail.
+    pub actor: Option<Actor>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// The log of the changes made to the tables.
+pub struct ChangeLog<'d> {
+    db: &'d OptimisticTransactionDB,
+    cf: &'d ColumnFamily,
+}
+
+impl<'d> ChangeLog<'d> {
+    pub(super) fn open(db: &'d OptimisticTransactionDB) -> Option<Self> {
+        db.cf_handle(super::CHANGE_LOG).map(|cf| Self { db, cf })
+    }
+
+    /// Returns `true` if changes are being logged.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn is_enabled(&self) -> Result<bool> {
+        is_enabled(self.db, self.cf)
+    }
+
+    /// Starts logging changes. The sequence numbers continue from the last
+    /// record in the log, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn enable(&self) -> Result<()> {
+        loop {
+            let txn = self.db.transaction();
+            if txn
+                .get_for_update_cf(self.cf, STATE_KEY, EXCLUSIVE)
+                .context("cannot read change log state")?
+                .is_some()
+            {
+                return Ok(());
+            }
+            let next = self.last_sequence()?.map_or(1, |sequence| sequence + 1);
+            txn.put_cf(self.cf, STATE_KEY, next.to_be_bytes())
+                .context("failed to write change log state")?;
+            match txn.commit() {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    if !e.as_ref().starts_with("Resource busy:") {
+                        return Err(e).context("failed to enable change log");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Stops logging changes. The records already in the log are kept.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn disable(&self) -> Result<()> {
+        loop {
+            let txn = self.db.transaction();
+            if txn
+                .get_for_update_cf(self.cf, STATE_KEY, EXCLUSIVE)
+                .context("cannot read change log state")?
+                .is_none()
+            {
+                return Ok(());
+            }
+            txn.delete_cf(self.cf, STATE_KEY)
+                .context("failed to delete change log state")?;
+            match txn.commit() {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    if !e.as_ref().starts_with("Resource busy:") {
+                        return Err(e).context("failed to disable change log");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the sequence number of the last record in the log, or `None`
+    /// if the log is empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn last_sequence(&self) -> Result<Option<u64>> {
+        let Some(entry) = self.db.iterator_cf(self.cf, IteratorMode::End).next() else {
+            return Ok(None);
+        };
+        let (key, _) = entry.context("cannot read change log")?;
+        if key.is_empty() {
+            return Ok(None);
+        }
+        decode_sequence(&key).map(Some)
+    }
+
+    /// Returns the records with a sequence number greater than `sequence`, in
+    /// the order they were logged. Sequence numbers start from 1, so passing 0
+    /// returns every record in the log.
+    #[must_use]
+    pub fn since(&self, sequence: u64) -> ChangeIter<'d> {
+        ChangeIter {
+            inner: self.db.iterator_cf(
+                self.cf,
+                IteratorMode::From(&sequence.to_be_bytes(), rocksdb::Direction::Forward),
+            ),
+            after: sequence,
+        }
+    }
+}
+
+/// An iterator over the records in the change log.
+pub struct ChangeIter<'d> {
+    inner: rocksdb::DBIteratorWithThreadMode<'d, OptimisticTransactionDB>,
+    after: u64,
+}
+
+impl Iterator for ChangeIter<'_> {
+    type Item = Result<ChangeRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (key, value) = match self.inner.next()? {
+                Ok(entry) => entry,
+                Err(e) => return Some(Err(e).context("cannot read change log")),
+            };
+            match decode_sequence(&key) {
+                Ok(sequence) if sequence <= self.after => {}
+                Ok(_) => {
+                    return Some(
+                        super::deserialize(&value).context("invalid record in change log"),
+                    );
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Appends a record of writing `new_value` under `key` in `cf`, or deleting
+/// the entry if `new_value` is `None`, to the change log, unless it is
+/// disabled.
+///
+/// This must be called before the write is made in `txn`, so that the value
+/// read for `old_value_hash` is the one being replaced.
+pub(crate) fn record(
+    db: &OptimisticTransactionDB,
+    txn: &Transaction<OptimisticTransactionDB>,
+    cf: &ColumnFamily,
+    table: &str,
+    key: &[u8],
+    new_value: Option<&[u8]>,
+) -> Result<()> {
+    let Some(log) = db.cf_handle(super::CHANGE_LOG) else {
+        return Ok(());
+    };
+    let Some(state) = txn
+        .get_for_update_cf(log, STATE_KEY, EXCLUSIVE)
+        .context("cannot read change log state")?
+    else {
+        return Ok(());
+    };
+    let sequence = decode_sequence(&state)?;
+    let old_value_hash = txn
+        .get_pinned_cf(cf, key)
+        .context("cannot read old entry")?
+        .map(|value| digest::digest(&digest::SHA256, &value).as_ref().to_vec());
+    let record = ChangeRecord {
+        sequence,
+        table: table.to_string(),
+        key: key.to_vec(),
+        old_value_hash,
+        new_value: new_value.map(<[u8]>::to_vec),
+        actor: None,
+        timestamp: Utc::now(),
+    };
+    txn.put_cf(log, sequence.to_be_bytes(), super::serialize(&record)?)
+        .context("failed to write change record")?;
+    let next = sequence
+        .checked_add(1)
+        .ok_or_else(|| anyhow!("change log is full"))?;
+    txn.put_cf(log, STATE_KEY, next.to_be_bytes())
+        .context("failed to write change log state")
+}
+
+/// Attributes the records appended to the change log within `txn` to
+/// `actor`.
+///
+/// The records appended within `txn` are those from the next sequence number
+/// committed to the database up to the one `txn` reads. If another
+/// transaction appends records in the meantime, `txn` conflicts with it on
+/// the sequence number and is not committed.
+pub(crate) fn attribute(
+    db: &OptimisticTransactionDB,
+    txn: &Transaction<OptimisticTransactionDB>,
+    actor: &Actor,
+) -> Result<()> {
+    let Some(log) = db.cf_handle(super::CHANGE_LOG) else {
+        return Ok(());
+    };
+    let Some(end) = txn
+        .get_for_update_cf(log, STATE_KEY, EXCLUSIVE)
+        .context("cannot read change log state")?
+    else {
+        return Ok(());
+    };
+    let Some(start) = db
+        .get_pinned_cf(log, STATE_KEY)
+        .context("cannot read change log state")?
+    else {
+        return Ok(());
+    };
+    for sequence in decode_sequence(&start)?..decode_sequence(&end)? {
+        let key = sequence.to_be_bytes();
+        let Some(value) = txn.get_cf(log, key).context("cannot read change log")? else {
+            continue;
+        };
+        let mut record: ChangeRecord =
+            super::deserialize(&value).context("invalid record in change log")?;
+        record.actor = Some(actor.clone());
+        txn.put_cf(log, key, super::serialize(&record)?)
+            .context("failed to write change record")?;
+    }
+    Ok(())
+}
+
+fn is_enabled(db: &OptimisticTransactionDB, cf: &ColumnFamily) -> Result<bool> {
+    Ok(db
+        .get_pinned_cf(cf, STATE_KEY)
+        .context("cannot read change log state")?
+        .is_some())
+}
+
+fn decode_sequence(bytes: &[u8]) -> Result<u64> {
+    Ok(u64::from_be_bytes(
+        bytes.try_into().context("invalid sequence number")?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use anyhow::Result;
+    use chrono::Utc;
+
+    use crate::test::{DbGuard, acquire_db_permit};
+    use crate::{
+        Actor, AuditAction, AuditEvent, ChildTags, Customer, CustomerUpdate, Store,
+        TrustedUserAgent,
+    };
+
+    fn setup_store() -> (DbGuard<'static>, Arc<Store>) {
+        let permit = acquire_db_permit();
+        let db_dir = tempfile::tempdir().unwrap();
+        let backup_dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(Store::new(db_dir.path(), backup_dir.path(), None).unwrap());
+        (permit, store)
+    }
+
+    fn user_agent(name: &str) -> TrustedUserAgent {
+        TrustedUserAgent {
+            user_agent: name.to_string(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn records_changes_only_while_enabled() {
+        let (_permit, store) = setup_store();
+        let table = store.trusted_user_agent_map();
+        let log = store.change_log();
+        assert!(!log.is_enabled().unwrap());
+
+        table.put(&user_agent("ignored")).unwrap();
+        assert_eq!(log.since(0).count(), 0);
+
+        log.enable().unwrap();
+        assert!(log.is_enabled().unwrap());
+        let first = user_agent("agent");
+        table.put(&first).unwrap();
+        table.update("agent", &user_agent("agent")).unwrap();
+        table.remove("agent").unwrap();
+
+        let records = log.since(0).collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(
+            records.iter().map(|r| r.sequence).collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+        assert!(
+            records
+                .iter()
+                .all(|r| r.table == super::super::TRUSTED_USER_AGENTS && r.key == b"agent")
+        );
+        assert_eq!(records[0].old_value_hash, None);
+        assert_eq!(
+            records[0].new_value.as_deref(),
+            Some(first.updated_at.to_string().as_bytes())
+        );
+        assert_eq!(records[1].old_value_hash.as_ref().map(Vec::len), Some(32));
+        assert_eq!(records[2].new_value, None);
+        assert!(records.iter().all(|r| r.actor.is_none()));
+
+        let later = log.since(2).collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(later, records[2..]);
+
+        log.disable().unwrap();
+        table.put(&user_agent("ignored")).unwrap();
+        log.enable().unwrap();
+        table.put(&user_agent("ignored")).unwrap();
+        assert_eq!(log.last_sequence().unwrap(), Some(4));
+    }
+
+    fn customer(name: &str) -> Customer {
+        Customer {
+            id: u32::MAX,
+            name: name.to_string(),
+            description: String::new(),
+            networks: Vec::new(),
+            creation_time: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn records_indexed_changes() {
+        let (_permit, store) = setup_store();
+        store.change_log().enable().unwrap();
+        let mut table = store.customer_map();
+        let id = table.put(customer("a")).unwrap();
+        let old = CustomerUpdate {
+            name: Some("a".to_string()),
+            description: None,
+            networks: None,
+        };
+        let new = CustomerUpdate {
+            name: Some("b".to_string()),
+            description: None,
+            networks: None,
+        };
+        table.update(id, &old, &new).unwrap();
+        table.remove(id).unwrap();
+
+        let records = store
+            .change_log()
+            .since(0)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert!(records.iter().all(|r| r.table == super::super::CUSTOMERS));
+        assert_eq!(
+            records
+                .iter()
+                .map(|r| (r.key.as_slice(), r.new_value.is_some()))
+                .collect::<Vec<_>>(),
+            [
+                (b"a".as_slice(), true),
+                (b"a".as_slice(), false),
+                (b"b".as_slice(), true),
+                (b"b".as_slice(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn attributes_audited_changes() {
+        let (_permit, store) = setup_store();
+        let log = store.change_log();
+        log.enable().unwrap();
+        let admin = Actor {
+            username: "admin".to_string(),
+            source_ip: None,
+        };
+        let table = store.customer_map();
+        table.insert_as(customer("a"), &admin).unwrap();
+        table.put(customer("b")).unwrap();
+        store
+            .audit_log()
+            .record(&AuditEvent::new(
+                admin.clone(),
+                AuditAction::BackupCreated,
+                "1",
+            ))
+            .unwrap();
+
+        let records = log.since(0).collect::<Result<Vec<_>>>().unwrap();
+        let (audited, unaudited): (Vec<_>, Vec<_>) =
+            records.iter().partition(|r| r.actor.is_some());
+        assert!(audited.iter().all(|r| r.actor.as_ref() == Some(&admin)));
+        assert!(audited.iter().any(|r| r.key == b"a"));
+        assert_eq!(
+            audited
+                .iter()
+                .filter(|r| r.table == super::super::AUDIT)
+                .count(),
+            2
+        );
+        assert!(unaudited.iter().all(|r| r.table == super::super::CUSTOMERS));
+        assert!(unaudited.iter().any(|r| r.key == b"b"));
+    }
+
+    #[test]
+    fn records_tag_changes() {
+        let (_permit, store) = setup_store();
+        store.change_log().enable().unwrap();
+        let mut tags = store.workflow_tag_set().unwrap();
+        let id = tags.insert("a").unwrap();
+        tags.update(id, "a", "b").unwrap();
+        tags.remove_workflow_tag(id, ChildTags::Refuse).unwrap();
+
+        let records = store
+            .change_log()
+            .since(0)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert!(records.iter().all(|r| r.table == super::super::TAGS));
+        assert!(
+            records
+                .iter()
+                .any(|r| r.new_value.as_deref() == Some(b"b".as_slice()))
+        );
+        assert!(records.last().is_some_and(|r| r.new_value.is_none()));
+    }
+}
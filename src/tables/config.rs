@@ -101,6 +101,26 @@ impl<'d> Table<'d, String> {
         self.map.update_compare_multi(&updates)
     }
 
+    /// Updates multiple config values with compare-and-swap semantics within a
+    /// transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any old value does not match, any key does not exist,
+    /// or database operation fails.
+    pub fn update_compare_multi_with_transaction(
+        &self,
+        updates: &[(&str, &str, &str)],
+        txn: &rocksdb::Transaction<OptimisticTransactionDB>,
+    ) -> Result<()> {
+        let updates: Vec<_> = updates
+            .iter()
+            .map(|(k, o, n)| (k.as_bytes(), o.as_bytes(), n.as_bytes()))
+            .collect();
+        self.map
+            .update_compare_multi_with_transaction(&updates, txn)
+    }
+
     /// Deletes a config value by key.
     ///
     /// # Errors
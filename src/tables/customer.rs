@@ -9,8 +9,8 @@ use serde::{Deserialize, Serialize};
 
 use super::UniqueKey;
 use crate::{
-    HostNetworkGroup, Indexable, IndexedMap, IndexedMapUpdate, IndexedTable, collections::Indexed,
-    event::NetworkType, types::FromKeyValue,
+    Actor, AuditAction, AuditEvent, HostNetworkGroup, Indexable, IndexedMap, IndexedMapUpdate,
+    IndexedTable, collections::Indexed, event::NetworkType, types::FromKeyValue,
 };
 
 #[derive(Clone, Deserialize, Serialize)]
@@ -153,6 +153,46 @@ impl<'d> IndexedTable<'d, Customer> {
     pub fn update(&mut self, id: u32, old: &Update, new: &Update) -> Result<()> {
         self.indexed_map.update(id, old, new)
     }
+
+    /// Inserts a customer, and records its insertion by `actor` in the audit
+    /// trail in the same transaction. Returns the ID of the customer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a customer with the same name exists or the database
+    /// operation fails.
+    pub fn insert_as(&self, entry: Customer, actor: &Actor) -> Result<u32> {
+        self.audited(
+            |txn| self.put_with_transaction(entry.clone(), txn),
+            |id| AuditEvent::new(actor.clone(), AuditAction::CustomerInserted, id.to_string()),
+        )
+    }
+
+    /// Updates the `Customer` from `old` to `new`, given `id`, and records the
+    /// update by `actor` in the audit trail in the same transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `id` is invalid or the database operation fails.
+    pub fn update_as(&mut self, id: u32, old: &Update, new: &Update, actor: &Actor) -> Result<()> {
+        self.audited(
+            |txn| self.update_with_transaction(id, old, new, txn),
+            |_| AuditEvent::new(actor.clone(), AuditAction::CustomerUpdated, id.to_string()),
+        )
+    }
+
+    /// Removes the customer with `id`, and records its removal by `actor` in the
+    /// audit trail in the same transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `id` is invalid or the database operation fails.
+    pub fn remove_as(&self, id: u32, actor: &Actor) -> Result<()> {
+        self.audited(
+            |txn| self.remove_with_transaction(id, txn).map(drop),
+            |_| AuditEvent::new(actor.clone(), AuditAction::CustomerRemoved, id.to_string()),
+        )
+    }
 }
 
 #[cfg(test)]
@@ -166,8 +166,7 @@ impl<'d> Table<'d, CustomerDataDeletionJob> {
             service_results.push(result.clone());
 
             let value = super::serialize(&service_results)?;
-            txn.put_cf(self.map.cf, key, value)
-                .context("failed to write customer deletion job")?;
+            self.map.put_with_transaction(&key, &value, &txn)?;
             match txn.commit() {
                 Ok(()) => return Ok(()),
                 Err(error) if error.as_ref().starts_with(RESOURCE_BUSY_PREFIX) => {}
@@ -214,8 +213,7 @@ impl<'d> Table<'d, CustomerDataDeletionJob> {
             existing.error.clone_from(&result.error);
 
             let value = super::serialize(&service_results)?;
-            txn.put_cf(self.map.cf, key, value)
-                .context("failed to write customer deletion job")?;
+            self.map.put_with_transaction(&key, &value, &txn)?;
             match txn.commit() {
                 Ok(()) => return Ok(()),
                 Err(error) if error.as_ref().starts_with(RESOURCE_BUSY_PREFIX) => {}
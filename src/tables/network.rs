@@ -1,16 +1,19 @@
 //! The `network` table.
 
-use std::borrow::Cow;
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashMap},
+};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
-use rocksdb::{Direction, OptimisticTransactionDB};
+use rocksdb::OptimisticTransactionDB;
 use serde::{Deserialize, Serialize};
 
-use super::UniqueKey;
+use super::{UniqueKey, tag_index};
 use crate::{
-    HostNetworkGroup, Indexable, IndexedMap, IndexedMapUpdate, IndexedTable, Iterable,
-    collections::Indexed, types::FromKeyValue,
+    Actor, AuditAction, AuditEvent, HostNetworkGroup, Indexable, IndexedMap, IndexedMapUpdate,
+    IndexedTable, collections::Indexed, types::FromKeyValue,
 };
 
 #[derive(Clone, PartialEq, Debug)]
@@ -46,12 +49,6 @@ impl Network {
         &self.tag_ids
     }
 
-    fn contains_tag(&self, tag: u32) -> Result<usize> {
-        self.tag_ids
-            .binary_search(&tag)
-            .map_err(|idx| anyhow::anyhow!("{idx}"))
-    }
-
     fn clean_up(mut tag_ids: Vec<u32>) -> Vec<u32> {
         tag_ids.sort_unstable();
         tag_ids.dedup();
@@ -152,19 +149,78 @@ impl<'d> IndexedTable<'d, Network> {
     ///
     /// Returns an error if the database query fails.
     pub fn remove_tag(&self, tag_id: u32) -> Result<()> {
-        let iter = self.iter(Direction::Forward, None);
-        for entry in iter {
-            let mut network = entry?;
-            if let Ok(idx) = network.contains_tag(tag_id) {
-                network.tag_ids.remove(idx);
+        loop {
+            let txn = self.indexed_map.db().transaction();
+            self.remove_tags_with_transaction(&[tag_id], &txn)?;
+            match txn.commit() {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    if !e.as_ref().starts_with("Resource busy:") {
+                        return Err(e).context("failed to remove tag");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Removes the tags in `tag_ids` from all the networks within `txn`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub(crate) fn remove_tags_with_transaction(
+        &self,
+        tag_ids: &[u32],
+        txn: &rocksdb::Transaction<OptimisticTransactionDB>,
+    ) -> Result<()> {
+        let db = self.indexed_map.db();
+        let mut ids = BTreeSet::new();
+        for &tag_id in tag_ids {
+            ids.extend(tag_index::ids_with_tag_in(
+                db,
+                txn,
+                super::NETWORKS,
+                tag_id,
+            )?);
+        }
+        for id in ids {
+            let Some(network) = self.get_by_id_in_transaction(id, txn)? else {
+                continue;
+            };
+            let remaining: Vec<u32> = network
+                .tag_ids
+                .iter()
+                .copied()
+                .filter(|tag| !tag_ids.contains(tag))
+                .collect();
+            if remaining.len() < network.tag_ids.len() {
                 let old = Update::new(None, None, None, None);
-                let new = Update::new(None, None, None, Some(network.tag_ids));
-                self.indexed_map.update(network.id, &old, &new)?;
+                let new = Update::new(None, None, None, Some(remaining));
+                self.update_with_transaction(network.id, &old, &new, txn)?;
             }
         }
         Ok(())
     }
 
+    /// Returns the IDs of the networks with `tag_id`, in ascending order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn ids_with_tag(&self, tag_id: u32) -> Result<Vec<u32>> {
+        tag_index::ids_with_tag(self.indexed_map.db(), super::NETWORKS, tag_id)
+    }
+
+    /// Returns the number of networks with each tag, for every tag at least
+    /// one of them has.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn tag_counts(&self) -> Result<HashMap<u32, usize>> {
+        tag_index::tag_counts(self.indexed_map.db(), super::NETWORKS)
+    }
+
     /// Updates the `Network` from `old` to `new`, given `id`.
     ///
     /// # Errors
@@ -173,6 +229,46 @@ impl<'d> IndexedTable<'d, Network> {
     pub fn update(&mut self, id: u32, old: &Update, new: &Update) -> Result<()> {
         self.indexed_map.update(id, old, new)
     }
+
+    /// Inserts a network, and records its insertion by `actor` in the audit
+    /// trail in the same transaction. Returns the ID of the network.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a network with the same name exists or the database
+    /// operation fails.
+    pub fn insert_as(&self, entry: Network, actor: &Actor) -> Result<u32> {
+        self.audited(
+            |txn| self.put_with_transaction(entry.clone(), txn),
+            |id| AuditEvent::new(actor.clone(), AuditAction::NetworkInserted, id.to_string()),
+        )
+    }
+
+    /// Updates the `Network` from `old` to `new`, given `id`, and records the
+    /// update by `actor` in the audit trail in the same transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `id` is invalid or the database operation fails.
+    pub fn update_as(&mut self, id: u32, old: &Update, new: &Update, actor: &Actor) -> Result<()> {
+        self.audited(
+            |txn| self.update_with_transaction(id, old, new, txn),
+            |_| AuditEvent::new(actor.clone(), AuditAction::NetworkUpdated, id.to_string()),
+        )
+    }
+
+    /// Removes the network with `id`, and records its removal by `actor` in the
+    /// audit trail in the same transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `id` is invalid or the database operation fails.
+    pub fn remove_as(&self, id: u32, actor: &Actor) -> Result<()> {
+        self.audited(
+            |txn| self.remove_with_transaction(id, txn).map(drop),
+            |_| AuditEvent::new(actor.clone(), AuditAction::NetworkRemoved, id.to_string()),
+        )
+    }
 }
 
 pub struct Update {
@@ -413,6 +509,32 @@ mod test {
         );
     }
 
+    #[test]
+    fn tag_lookup() {
+        let (_permit, store) = setup_store();
+        let table = store.network_map();
+
+        let network1 = table
+            .insert(create_network("Network1", "Description1", vec![1, 2]))
+            .unwrap();
+        let network2 = table
+            .insert(create_network("Network2", "Description2", vec![2]))
+            .unwrap();
+        table
+            .insert(create_network("Network3", "Description3", vec![3]))
+            .unwrap();
+
+        assert_eq!(table.ids_with_tag(2).unwrap(), vec![network1, network2]);
+        assert_eq!(table.ids_with_tag(1).unwrap(), vec![network1]);
+        assert!(table.ids_with_tag(4).unwrap().is_empty());
+
+        let counts = table.tag_counts().unwrap();
+        assert_eq!(counts.len(), 3);
+        assert_eq!(counts[&1], 1);
+        assert_eq!(counts[&2], 2);
+        assert_eq!(counts[&3], 1);
+    }
+
     #[test]
     fn update() {
         let (_permit, store) = setup_store();
@@ -440,6 +562,8 @@ mod test {
 
         let retrieved_network = table.get_by_id(network.id).unwrap().unwrap();
         assert_eq!(retrieved_network, updated_network);
+        assert!(table.ids_with_tag(1).unwrap().is_empty());
+        assert_eq!(table.ids_with_tag(3).unwrap(), vec![network.id]);
 
         let iter = table.iter(Direction::Forward, None);
         assert_eq!(iter.count(), 1);
@@ -9,10 +9,11 @@ use rocksdb::{Direction, OptimisticTransactionDB};
 use serde::{Deserialize, Serialize};
 use strum_macros::EnumString;
 
-use super::TableIter as TI;
+use super::{AuditLog, TableIter as TI};
 use crate::{
-    Agent, ExternalService, Indexable, IndexedMap, IndexedMapUpdate, IndexedTable, Iterable, Map,
-    Table as CrateTable, UniqueKey, collections::Indexed, types::FromKeyValue,
+    Actor, Agent, AuditAction, AuditEvent, ExternalService, Indexable, IndexedMap,
+    IndexedMapUpdate, IndexedTable, Iterable, Map, Table as CrateTable, UniqueKey,
+    collections::Indexed, types::FromKeyValue,
 };
 
 #[derive(
@@ -455,8 +456,35 @@ impl<'d> Table<'d> {
     /// # Errors
     ///
     /// Returns an error if the `id` is invalid, the database operation fails, or if the hostname is already in use.
-    #[allow(clippy::too_many_lines)]
     pub fn update(&mut self, id: u32, old: &Update, new: &Update) -> Result<Node> {
+        self.update_audited(id, old, new, None)
+    }
+
+    /// Updates the `Node` from `old` to `new` as [`update`](Self::update)
+    /// does, for applying the drafts of its name and profile, and records
+    /// that `actor` applied them in the audit trail in the same transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `id` is invalid, the database operation fails,
+    /// or if the hostname is already in use.
+    pub fn apply_as(&mut self, id: u32, old: &Update, new: &Update, actor: &Actor) -> Result<Node> {
+        let event = AuditEvent::new(
+            actor.clone(),
+            AuditAction::NodeConfigApplied,
+            id.to_string(),
+        );
+        self.update_audited(id, old, new, Some(&event))
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn update_audited(
+        &mut self,
+        id: u32,
+        old: &Update,
+        new: &Update,
+        event: Option<&AuditEvent>,
+    ) -> Result<Node> {
         use crate::collections::Indexed;
 
         let old_inner = InnerUpdate {
@@ -639,6 +667,12 @@ impl<'d> Table<'d> {
                 }
             }
 
+            if let Some(event) = event {
+                AuditLog::open(self.node.raw().db())
+                    .context("cannot find audit table")?
+                    .record_with_transaction(event, &txn)?;
+            }
+
             // Commit all writes atomically
             match txn.commit() {
                 Ok(()) => {
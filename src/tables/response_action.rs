@@ -0,0 +1,795 @@
+//! The `response_action` table.
+//!
+//! A [`ResponseAction`] is what a [`Response`] of a triage policy calls for
+//! once the score of an event reaches its `minimum_score`: adding the
+//! originator of the event to a block or an allow network, or tagging the
+//! event through its triage response. Actions are proposed as pending and
+//! take effect only once approved. An applied action can be undone, which
+//! reverts only what applying it changed.
+//!
+//! An action is keyed by what it does rather than by the event that called for
+//! it, so proposing an action already in the table, for another event or by
+//! another policy, leaves the table as it is. The exception is an action that
+//! was undone: proposing it again makes it pending once more. A rejected
+//! action stays rejected.
+//!
+//! [`Response`]: super::Response
+
+use std::{borrow::Cow, net::IpAddr};
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use rocksdb::{Direction, OptimisticTransactionDB, Transaction};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    AllowNetwork, AllowNetworkUpdate, BlockNetwork, BlockNetworkUpdate, TriageResponse,
+    TriageResponseUpdate,
+};
+use crate::{
+    EXCLUSIVE, HostNetworkGroup, Indexable, IndexedMap, IndexedTable, Iterable,
+    collections::Indexed, types::FromKeyValue,
+};
+
+/// Where the actions proposed for the responses of triage policies go.
+#[derive(Clone, Debug, Default)]
+pub struct ResponseTargets {
+    /// The block network a `Blacklist` response adds the originators of an
+    /// event to, or `None` to propose no action for such a response.
+    pub block_network_id: Option<u32>,
+    /// The allow network a `Whitelist` response adds the originators of an
+    /// event to, or `None` to propose no action for such a response.
+    pub allow_network_id: Option<u32>,
+    /// The tags a `Manual` response tags an event with.
+    pub tag_ids: Vec<u32>,
+}
+
+/// What a response action does once applied.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum ResponseActionKind {
+    /// Adds `addr` to the hosts of the block network with `network_id`.
+    Block { network_id: u32, addr: IpAddr },
+    /// Adds `addr` to the hosts of the allow network with `network_id`.
+    Allow { network_id: u32, addr: IpAddr },
+    /// Adds `tag_ids` to the triage response for the event from `sensor` at
+    /// `time`, creating the response if there is none.
+    Tag {
+        sensor: String,
+        time: DateTime<Utc>,
+        tag_ids: Vec<u32>,
+    },
+}
+
+impl ResponseActionKind {
+    fn key(&self) -> Vec<u8> {
+        match self {
+            Self::Block { network_id, addr } => address_key(0, *network_id, *addr),
+            Self::Allow { network_id, addr } => address_key(1, *network_id, *addr),
+            Self::Tag {
+                sensor,
+                time,
+                tag_ids,
+            } => {
+                let mut key = vec![2];
+                key.extend_from_slice(sensor.as_bytes());
+                key.extend_from_slice(
+                    &time.timestamp_nanos_opt().unwrap_or_default().to_be_bytes(),
+                );
+                let mut tag_ids = tag_ids.clone();
+                tag_ids.sort_unstable();
+                tag_ids.dedup();
+                for tag_id in tag_ids {
+                    key.extend_from_slice(&tag_id.to_be_bytes());
+                }
+                key
+            }
+        }
+    }
+}
+
+fn address_key(kind: u8, network_id: u32, addr: IpAddr) -> Vec<u8> {
+    let mut key = vec![kind];
+    key.extend_from_slice(&network_id.to_be_bytes());
+    match addr {
+        IpAddr::V4(addr) => key.extend_from_slice(&addr.octets()),
+        IpAddr::V6(addr) => key.extend_from_slice(&addr.octets()),
+    }
+    key
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum ResponseActionState {
+    Pending,
+    Applied,
+    Rejected,
+    Undone,
+}
+
+/// What applying a response action changed.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum ResponseEffect {
+    /// Nothing, as the network already had the address or the triage response
+    /// already had the tags.
+    Unchanged,
+    /// The address was added to the hosts of the network.
+    AddedHost,
+    /// The tags were added to the triage response, which was created for the
+    /// action if `created` is `true`.
+    AddedTags { tag_ids: Vec<u32>, created: bool },
+}
+
+/// An action a triage policy's response called for, stored in the
+/// `response_action` table.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ResponseAction {
+    pub id: u32,
+    pub kind: ResponseActionKind,
+    /// The policy whose response called for the action.
+    pub policy_id: u32,
+    /// The key of the event whose score reached the response's threshold.
+    pub event_key: i128,
+    pub score: f64,
+    pub state: ResponseActionState,
+    pub creation_time: DateTime<Utc>,
+    /// Who last approved, rejected, or undid the action.
+    pub decided_by: Option<String>,
+    pub decision_time: Option<DateTime<Utc>>,
+    /// What applying the action changed, or `None` if it was never applied.
+    pub effect: Option<ResponseEffect>,
+}
+
+impl ResponseAction {
+    /// Creates a pending action.
+    #[must_use]
+    pub fn new(kind: ResponseActionKind, policy_id: u32, event_key: i128, score: f64) -> Self {
+        Self {
+            id: u32::MAX,
+            kind,
+            policy_id,
+            event_key,
+            score,
+            state: ResponseActionState::Pending,
+            creation_time: Utc::now(),
+            decided_by: None,
+            decision_time: None,
+            effect: None,
+        }
+    }
+}
+
+impl FromKeyValue for ResponseAction {
+    fn from_key_value(_key: &[u8], value: &[u8]) -> Result<Self> {
+        super::deserialize(value)
+    }
+}
+
+impl Indexable for ResponseAction {
+    fn key(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(self.kind.key())
+    }
+
+    fn index(&self) -> u32 {
+        self.id
+    }
+
+    fn make_indexed_key(key: Cow<[u8]>, _index: u32) -> Cow<[u8]> {
+        key
+    }
+
+    fn value(&self) -> Vec<u8> {
+        super::serialize(self).expect("serializable")
+    }
+
+    fn set_index(&mut self, index: u32) {
+        self.id = index;
+    }
+}
+
+/// The response actions, and the functions that decide on them and apply
+/// them to the tables they change.
+pub struct ResponseActions<'d> {
+    actions: IndexedTable<'d, ResponseAction>,
+    block_networks: IndexedTable<'d, BlockNetwork>,
+    allow_networks: IndexedTable<'d, AllowNetwork>,
+    triage_responses: IndexedTable<'d, TriageResponse>,
+}
+
+impl<'d> ResponseActions<'d> {
+    /// Opens the `response_action` table and the tables its actions change.
+    ///
+    /// Returns `None` if any of the tables does not exist.
+    pub(super) fn open(db: &'d OptimisticTransactionDB) -> Option<Self> {
+        Some(Self {
+            actions: IndexedMap::new(db, super::RESPONSE_ACTIONS)
+                .map(IndexedTable::new)
+                .ok()?,
+            block_networks: IndexedTable::<BlockNetwork>::open(db)?,
+            allow_networks: IndexedTable::<AllowNetwork>::open(db)?,
+            triage_responses: IndexedTable::<TriageResponse>::open(db)?,
+        })
+    }
+
+    /// Stores `action` as pending, unless an action doing the same is already
+    /// stored. Returns the ID of the stored action, or `None` if there was
+    /// one already.
+    ///
+    /// An undone action doing the same is replaced by `action`, which keeps
+    /// its ID. A rejected one is left as it is, so `None` is returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn propose(&self, action: &ResponseAction) -> Result<Option<u32>> {
+        let action = ResponseAction {
+            state: ResponseActionState::Pending,
+            decided_by: None,
+            decision_time: None,
+            effect: None,
+            ..action.clone()
+        };
+        loop {
+            let txn = self.actions.indexed_map.db().transaction();
+            let id = if let Some(value) = txn
+                .get_for_update_cf(self.actions.indexed_map.cf(), action.key(), EXCLUSIVE)
+                .context("cannot read response action")?
+            {
+                let stored: ResponseAction = super::deserialize(&value)?;
+                if stored.state != ResponseActionState::Undone {
+                    return Ok(None);
+                }
+                let action = ResponseAction {
+                    id: stored.id,
+                    ..action.clone()
+                };
+                self.overwrite(&action, &txn)?;
+                stored.id
+            } else {
+                self.actions.put_with_transaction(action.clone(), &txn)?
+            };
+            if commit(txn)? {
+                return Ok(Some(id));
+            }
+        }
+    }
+
+    /// Returns the action with `id`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn get(&self, id: u32) -> Result<Option<ResponseAction>> {
+        self.actions.get_by_id(id)
+    }
+
+    /// Returns the actions in `state`, in the order of their keys.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails or an action cannot
+    /// be decoded.
+    pub fn by_state(&self, state: ResponseActionState) -> Result<Vec<ResponseAction>> {
+        let mut actions = Vec::new();
+        for action in self.actions.iter(Direction::Forward, None) {
+            let action = action?;
+            if action.state == state {
+                actions.push(action);
+            }
+        }
+        Ok(actions)
+    }
+
+    /// Applies the pending action with `id`, approved by `actor`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the action does not exist or is not pending, the
+    /// network it adds an address to does not exist, or the database
+    /// operation fails.
+    pub fn approve(&self, id: u32, actor: &str) -> Result<()> {
+        self.decide(
+            id,
+            actor,
+            ResponseActionState::Pending,
+            ResponseActionState::Applied,
+        )
+    }
+
+    /// Rejects the pending action with `id` on behalf of `actor`. A rejected
+    /// action stays in the table, so it is not proposed again.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the action does not exist or is not pending, or
+    /// the database operation fails.
+    pub fn reject(&self, id: u32, actor: &str) -> Result<()> {
+        self.decide(
+            id,
+            actor,
+            ResponseActionState::Pending,
+            ResponseActionState::Rejected,
+        )
+    }
+
+    /// Reverts what applying the action with `id` changed, on behalf of
+    /// `actor`. An address or a tag removed since the action was applied, or
+    /// a network removed since, is left as it is.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the action does not exist or is not applied, or
+    /// the database operation fails.
+    pub fn undo(&self, id: u32, actor: &str) -> Result<()> {
+        self.decide(
+            id,
+            actor,
+            ResponseActionState::Applied,
+            ResponseActionState::Undone,
+        )
+    }
+
+    /// Moves the action with `id` from `from` to `to`, applying or reverting
+    /// it in the same transaction.
+    fn decide(
+        &self,
+        id: u32,
+        actor: &str,
+        from: ResponseActionState,
+        to: ResponseActionState,
+    ) -> Result<()> {
+        loop {
+            let txn = self.actions.indexed_map.db().transaction();
+            let Some(mut action) = self.actions.get_by_id_in_transaction(id, &txn)? else {
+                bail!("no such response action");
+            };
+            if action.state != from {
+                bail!("response action is {:?}, not {from:?}", action.state);
+            }
+            match to {
+                ResponseActionState::Applied => {
+                    action.effect = Some(self.apply(&action.kind, &txn)?);
+                }
+                ResponseActionState::Undone => {
+                    if let Some(effect) = &action.effect {
+                        self.revert(&action.kind, effect, &txn)?;
+                    }
+                }
+                ResponseActionState::Pending | ResponseActionState::Rejected => {}
+            }
+            action.state = to;
+            action.decided_by = Some(actor.to_string());
+            action.decision_time = Some(Utc::now());
+            self.overwrite(&action, &txn)?;
+            if commit(txn)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Replaces the stored action having the key of `action` within `txn`.
+    fn overwrite(
+        &self,
+        action: &ResponseAction,
+        txn: &Transaction<OptimisticTransactionDB>,
+    ) -> Result<()> {
+        let (key, value) = (action.key(), action.value());
+        self.actions
+            .indexed_map
+            .record_change(txn, &key, Some(&value))?;
+        txn.put_cf(self.actions.indexed_map.cf(), key, value)
+            .context("cannot write response action")
+    }
+
+    fn apply(
+        &self,
+        kind: &ResponseActionKind,
+        txn: &Transaction<OptimisticTransactionDB>,
+    ) -> Result<ResponseEffect> {
+        match kind {
+            ResponseActionKind::Block { network_id, addr } => {
+                let Some(network) = self
+                    .block_networks
+                    .get_by_id_in_transaction(*network_id, txn)?
+                else {
+                    bail!("no such block network");
+                };
+                let Some(networks) = with_host(&network.networks, *addr) else {
+                    return Ok(ResponseEffect::Unchanged);
+                };
+                self.block_networks.update_with_transaction(
+                    *network_id,
+                    &block_network_update(network.networks),
+                    &block_network_update(networks),
+                    txn,
+                )?;
+                Ok(ResponseEffect::AddedHost)
+            }
+            ResponseActionKind::Allow { network_id, addr } => {
+                let Some(network) = self
+                    .allow_networks
+                    .get_by_id_in_transaction(*network_id, txn)?
+                else {
+                    bail!("no such allow network");
+                };
+                let Some(networks) = with_host(&network.networks, *addr) else {
+                    return Ok(ResponseEffect::Unchanged);
+                };
+                self.allow_networks.update_with_transaction(
+                    *network_id,
+                    &allow_network_update(network.networks),
+                    &allow_network_update(networks),
+                    txn,
+                )?;
+                Ok(ResponseEffect::AddedHost)
+            }
+            ResponseActionKind::Tag {
+                sensor,
+                time,
+                tag_ids,
+            } => {
+                let response =
+                    TriageResponse::new(sensor.clone(), *time, tag_ids.clone(), String::new());
+                let Some(existing) = self.triage_response(&response.key(), txn)? else {
+                    let tag_ids = response.tag_ids().to_vec();
+                    self.triage_responses.put_with_transaction(response, txn)?;
+                    return Ok(ResponseEffect::AddedTags {
+                        tag_ids,
+                        created: true,
+                    });
+                };
+                let added: Vec<_> = response
+                    .tag_ids()
+                    .iter()
+                    .filter(|tag_id| !existing.tag_ids().contains(tag_id))
+                    .copied()
+                    .collect();
+                if added.is_empty() {
+                    return Ok(ResponseEffect::Unchanged);
+                }
+                let mut tag_ids = existing.tag_ids().to_vec();
+                tag_ids.extend_from_slice(&added);
+                self.set_tags(&existing, tag_ids, txn)?;
+                Ok(ResponseEffect::AddedTags {
+                    tag_ids: added,
+                    created: false,
+                })
+            }
+        }
+    }
+
+    fn revert(
+        &self,
+        kind: &ResponseActionKind,
+        effect: &ResponseEffect,
+        txn: &Transaction<OptimisticTransactionDB>,
+    ) -> Result<()> {
+        match (kind, effect) {
+            (ResponseActionKind::Block { network_id, addr }, ResponseEffect::AddedHost) => {
+                let Some(network) = self
+                    .block_networks
+                    .get_by_id_in_transaction(*network_id, txn)?
+                else {
+                    return Ok(());
+                };
+                let Some(networks) = without_host(&network.networks, *addr) else {
+                    return Ok(());
+                };
+                self.block_networks.update_with_transaction(
+                    *network_id,
+                    &block_network_update(network.networks),
+                    &block_network_update(networks),
+                    txn,
+                )
+            }
+            (ResponseActionKind::Allow { network_id, addr }, ResponseEffect::AddedHost) => {
+                let Some(network) = self
+                    .allow_networks
+                    .get_by_id_in_transaction(*network_id, txn)?
+                else {
+                    return Ok(());
+                };
+                let Some(networks) = without_host(&network.networks, *addr) else {
+                    return Ok(());
+                };
+                self.allow_networks.update_with_transaction(
+                    *network_id,
+                    &allow_network_update(network.networks),
+                    &allow_network_update(networks),
+                    txn,
+                )
+            }
+            (
+                ResponseActionKind::Tag { sensor, time, .. },
+                ResponseEffect::AddedTags { tag_ids, created },
+            ) => {
+                let key = TriageResponse::new(sensor.clone(), *time, Vec::new(), String::new())
+                    .key()
+                    .into_owned();
+                let Some(existing) = self.triage_response(&key, txn)? else {
+                    return Ok(());
+                };
+                let remaining: Vec<_> = existing
+                    .tag_ids()
+                    .iter()
+                    .filter(|tag_id| !tag_ids.contains(tag_id))
+                    .copied()
+                    .collect();
+                if *created && remaining.is_empty() && existing.remarks.is_empty() {
+                    self.triage_responses
+                        .remove_with_transaction(existing.id, txn)?;
+                } else if remaining.len() < existing.tag_ids().len() {
+                    self.set_tags(&existing, remaining, txn)?;
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Reads the triage response under `key` within `txn`.
+    fn triage_response(
+        &self,
+        key: &[u8],
+        txn: &Transaction<OptimisticTransactionDB>,
+    ) -> Result<Option<TriageResponse>> {
+        txn.get_for_update_cf(self.triage_responses.indexed_map.cf(), key, EXCLUSIVE)
+            .context("cannot read triage response")?
+            .map(|value| TriageResponse::from_key_value(key, &value))
+            .transpose()
+    }
+
+    fn set_tags(
+        &self,
+        response: &TriageResponse,
+        tag_ids: Vec<u32>,
+        txn: &Transaction<OptimisticTransactionDB>,
+    ) -> Result<()> {
+        let key = response.key().into_owned();
+        self.triage_responses.update_with_transaction(
+            response.id,
+            &TriageResponseUpdate::new(key.clone(), Some(response.tag_ids().to_vec()), None),
+            &TriageResponseUpdate::new(key, Some(tag_ids), None),
+            txn,
+        )
+    }
+}
+
+/// Returns `group` with `addr` added to its hosts, or `None` if `group`
+/// already contains `addr`.
+fn with_host(group: &HostNetworkGroup, addr: IpAddr) -> Option<HostNetworkGroup> {
+    if group.contains(addr) {
+        return None;
+    }
+    let mut hosts = group.hosts().to_vec();
+    hosts.push(addr);
+    Some(HostNetworkGroup::new(
+        hosts,
+        group.networks().to_vec(),
+        group.ip_ranges().to_vec(),
+    ))
+}
+
+/// Returns `group` with `addr` removed from its hosts, or `None` if it is not
+/// one of them.
+fn without_host(group: &HostNetworkGroup, addr: IpAddr) -> Option<HostNetworkGroup> {
+    if !group.contains_host(addr) {
+        return None;
+    }
+    let mut hosts = group.hosts().to_vec();
+    hosts.retain(|host| *host != addr);
+    Some(HostNetworkGroup::new(
+        hosts,
+        group.networks().to_vec(),
+        group.ip_ranges().to_vec(),
+    ))
+}
+
+fn block_network_update(networks: HostNetworkGroup) -> BlockNetworkUpdate {
+    BlockNetworkUpdate {
+        name: None,
+        networks: Some(networks),
+        description: None,
+        customer_id: None,
+    }
+}
+
+fn allow_network_update(networks: HostNetworkGroup) -> AllowNetworkUpdate {
+    AllowNetworkUpdate {
+        name: None,
+        networks: Some(networks),
+        description: None,
+        customer_id: None,
+    }
+}
+
+/// Commits `txn`, returning `false` if it conflicted with another
+/// transaction and should be retried.
+fn commit(txn: Transaction<OptimisticTransactionDB>) -> Result<bool> {
+    match txn.commit() {
+        Ok(()) => Ok(true),
+        Err(e) => {
+            if e.as_ref().starts_with("Resource busy:") {
+                Ok(false)
+            } else {
+                Err(e).context("failed to commit response action")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, sync::Arc};
+
+    use chrono::Utc;
+
+    use super::{ResponseAction, ResponseActionKind, ResponseActionState, ResponseEffect};
+    use crate::test::{DbGuard, acquire_db_permit};
+    use crate::{BlockNetwork, HostNetworkGroup, Store, TriageResponse};
+
+    fn setup_store() -> (DbGuard<'static>, Arc<Store>) {
+        let permit = acquire_db_permit();
+        let db_dir = tempfile::tempdir().unwrap();
+        let backup_dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(Store::new(db_dir.path(), backup_dir.path(), None).unwrap());
+        (permit, store)
+    }
+
+    #[test]
+    fn block_action_is_proposed_once_approved_and_undone() {
+        let (_permit, store) = setup_store();
+        let network_id = store
+            .block_network_map()
+            .put(BlockNetwork {
+                id: u32::MAX,
+                name: "blocked".to_string(),
+                networks: HostNetworkGroup::default(),
+                description: String::new(),
+                customer_id: 0,
+            })
+            .unwrap();
+        let addr: IpAddr = "192.168.0.1".parse().unwrap();
+        let kind = ResponseActionKind::Block { network_id, addr };
+        let actions = store.response_actions();
+
+        let id = actions
+            .propose(&ResponseAction::new(kind.clone(), 1, 10, 0.9))
+            .unwrap()
+            .unwrap();
+        assert!(
+            actions
+                .propose(&ResponseAction::new(kind, 2, 20, 0.8))
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            actions
+                .by_state(ResponseActionState::Pending)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(actions.undo(id, "alice").is_err());
+
+        actions.approve(id, "alice").unwrap();
+        let network = store.block_network_map().get_by_id(network_id).unwrap();
+        assert!(network.unwrap().networks.contains_host(addr));
+        let action = actions.get(id).unwrap().unwrap();
+        assert_eq!(action.state, ResponseActionState::Applied);
+        assert_eq!(action.decided_by.as_deref(), Some("alice"));
+        assert_eq!(action.effect, Some(ResponseEffect::AddedHost));
+        assert!(actions.approve(id, "alice").is_err());
+
+        actions.undo(id, "bob").unwrap();
+        let network = store.block_network_map().get_by_id(network_id).unwrap();
+        assert!(!network.unwrap().networks.contains_host(addr));
+        let action = actions.get(id).unwrap().unwrap();
+        assert_eq!(action.state, ResponseActionState::Undone);
+        assert_eq!(action.decided_by.as_deref(), Some("bob"));
+
+        // Proposing an undone action again makes it pending.
+        let again = ResponseAction::new(action.kind.clone(), 3, 30, 0.7);
+        assert_eq!(actions.propose(&again).unwrap(), Some(id));
+        let action = actions.get(id).unwrap().unwrap();
+        assert_eq!(action.state, ResponseActionState::Pending);
+        assert_eq!(action.policy_id, 3);
+        assert_eq!(action.decided_by, None);
+        assert_eq!(action.effect, None);
+    }
+
+    #[test]
+    fn tag_action_reverts_only_its_own_tags() {
+        let (_permit, store) = setup_store();
+        let time = Utc::now();
+        let later = time + chrono::Duration::seconds(1);
+        let actions = store.response_actions();
+        let tag = |time, tag_ids| {
+            ResponseAction::new(
+                ResponseActionKind::Tag {
+                    sensor: "sensor".to_string(),
+                    time,
+                    tag_ids,
+                },
+                1,
+                10,
+                0.9,
+            )
+        };
+        store
+            .triage_response_map()
+            .put(TriageResponse::new(
+                "sensor".to_string(),
+                time,
+                vec![2],
+                "seen".to_string(),
+            ))
+            .unwrap();
+
+        let id = actions.propose(&tag(time, vec![1, 2])).unwrap().unwrap();
+        // The same tags in another order do the same; other tags do not.
+        assert!(actions.propose(&tag(time, vec![2, 1])).unwrap().is_none());
+        let other = actions.propose(&tag(time, vec![4])).unwrap().unwrap();
+        assert_ne!(other, id);
+        actions.reject(other, "alice").unwrap();
+        actions.approve(id, "alice").unwrap();
+        let response = store.triage_response_map().get("sensor", &time).unwrap();
+        assert_eq!(response.unwrap().tag_ids(), &[1, 2]);
+        assert_eq!(
+            actions.get(id).unwrap().unwrap().effect,
+            Some(ResponseEffect::AddedTags {
+                tag_ids: vec![1],
+                created: false
+            })
+        );
+        actions.undo(id, "alice").unwrap();
+        let response = store.triage_response_map().get("sensor", &time).unwrap();
+        assert_eq!(response.unwrap().tag_ids(), &[2]);
+
+        // A response created for the action goes away with it.
+        let id = actions.propose(&tag(later, vec![3])).unwrap().unwrap();
+        actions.approve(id, "alice").unwrap();
+        assert!(
+            store
+                .triage_response_map()
+                .get("sensor", &later)
+                .unwrap()
+                .is_some()
+        );
+        actions.undo(id, "alice").unwrap();
+        assert!(
+            store
+                .triage_response_map()
+                .get("sensor", &later)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn rejected_action_is_not_applied_or_proposed_again() {
+        let (_permit, store) = setup_store();
+        let actions = store.response_actions();
+        let action = ResponseAction::new(
+            ResponseActionKind::Allow {
+                network_id: 0,
+                addr: "10.0.0.1".parse().unwrap(),
+            },
+            1,
+            10,
+            0.9,
+        );
+
+        let id = actions.propose(&action).unwrap().unwrap();
+        // The allow network does not exist, so the action cannot be applied.
+        assert!(actions.approve(id, "alice").is_err());
+        actions.reject(id, "alice").unwrap();
+        assert!(actions.approve(id, "alice").is_err());
+        assert!(actions.propose(&action).unwrap().is_none());
+        assert_eq!(
+            actions
+                .by_state(ResponseActionState::Rejected)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! The `tag index` table.
+//!
+//! The index has an entry for every tag of every network and triage response,
+//! so that the records with a tag are found, and the records with each tag
+//! counted, without reading the records. An entry's key is the name of the
+//! column family of the record, a NUL byte, and the tag ID and the record ID,
+//! both big-endian `u32`s, and its value is empty.
+//!
+//! [`Indexed::record_change`] updates the index for every write to those
+//! tables, in the transaction of the write.
+//!
+//! [`Indexed::record_change`]: crate::collections::Indexed::record_change
+
+use std::collections::{BTreeSet, HashMap};
+
+use anyhow::{Context, Result};
+use rocksdb::{ColumnFamily, Direction, IteratorMode, OptimisticTransactionDB, Transaction};
+
+use super::{NETWORKS, TAG_INDEX, TRIAGE_RESPONSE};
+use crate::{Network, TriageResponse, types::FromKeyValue};
+
+/// Returns the keys of the index entries of the record stored as `value`
+/// under `key` in the column family `table`, or nothing if the records of
+/// `table` have no tags.
+///
+/// # Errors
+///
+/// Returns an error if the record cannot be decoded.
+pub(crate) fn entries(table: &str, key: &[u8], value: &[u8]) -> Result<BTreeSet<Vec<u8>>> {
+    let (id, tag_ids) = match table {
+        NETWORKS => {
+            let network = Network::from_key_value(key, value)?;
+            (network.id, network.tag_ids().to_vec())
+        }
+        TRIAGE_RESPONSE => {
+            let response = TriageResponse::from_key_value(key, value)?;
+            (response.id, response.tag_ids().to_vec())
+        }
+        _ => return Ok(BTreeSet::new()),
+    };
+    Ok(tag_ids
+        .into_iter()
+        .map(|tag_id| entry_key(table, tag_id, id))
+        .collect())
+}
+
+/// Updates the index within `txn` for writing `new_value` under `key` in
+/// `cf`, the column family `table`, or deleting the entry if `new_value` is
+/// `None`. It must be called before the write, while `txn` still reads the old
+/// value.
+///
+/// # Errors
+///
+/// Returns an error if the old or new record cannot be decoded, or if the
+/// database operation fails.
+pub(crate) fn record(
+    db: &OptimisticTransactionDB,
+    txn: &Transaction<OptimisticTransactionDB>,
+    cf: &ColumnFamily,
+    table: &str,
+    key: &[u8],
+    new_value: Option<&[u8]>,
+) -> Result<()> {
+    if !matches!(table, NETWORKS | TRIAGE_RESPONSE) || key.is_empty() {
+        return Ok(());
+    }
+    let index = index_cf(db)?;
+    let old = match txn.get_cf(cf, key).context("cannot read entry")? {
+        Some(value) => entries(table, key, &value).context("invalid entry in database")?,
+        None => BTreeSet::new(),
+    };
+    let new = match new_value {
+        Some(value) => entries(table, key, value)?,
+        None => BTreeSet::new(),
+    };
+    for entry in old.difference(&new) {
+        txn.delete_cf(index, entry)
+            .context("failed to delete tag index entry")?;
+    }
+    for entry in new.difference(&old) {
+        txn.put_cf(index, entry, b"")
+            .context("failed to write tag index entry")?;
+    }
+    Ok(())
+}
+
+/// Returns the IDs of the records in `table` with `tag_id`, in ascending
+/// order.
+///
+/// # Errors
+///
+/// Returns an error if the database operation fails.
+pub(super) fn ids_with_tag(
+    db: &OptimisticTransactionDB,
+    table: &str,
+    tag_id: u32,
+) -> Result<Vec<u32>> {
+    let prefix = tag_prefix(table, tag_id);
+    let iter = db.iterator_cf(
+        index_cf(db)?,
+        IteratorMode::From(&prefix, Direction::Forward),
+    );
+    ids_in(iter, &prefix)
+}
+
+/// Returns the IDs of the records in `table` with `tag_id`, in ascending
+/// order, as `txn` reads them.
+///
+/// # Errors
+///
+/// Returns an error if the database operation fails.
+pub(super) fn ids_with_tag_in(
+    db: &OptimisticTransactionDB,
+    txn: &Transaction<OptimisticTransactionDB>,
+    table: &str,
+    tag_id: u32,
+) -> Result<Vec<u32>> {
+    let prefix = tag_prefix(table, tag_id);
+    let iter = txn.iterator_cf(
+        index_cf(db)?,
+        IteratorMode::From(&prefix, Direction::Forward),
+    );
+    ids_in(iter, &prefix)
+}
+
+/// Returns the number of records in `table` with each tag, for every tag at
+/// least one of them has.
+///
+/// # Errors
+///
+/// Returns an error if the database operation fails or an entry is invalid.
+pub(super) fn tag_counts(db: &OptimisticTransactionDB, table: &str) -> Result<HashMap<u32, usize>> {
+    let prefix = table_prefix(table);
+    let mut counts = HashMap::new();
+    for item in db.iterator_cf(
+        index_cf(db)?,
+        IteratorMode::From(&prefix, Direction::Forward),
+    ) {
+        let (key, _) = item.context("cannot read tag index")?;
+        let Some(rest) = key.strip_prefix(prefix.as_slice()) else {
+            break;
+        };
+        let tag_id = rest
+            .get(..4)
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(u32::from_be_bytes)
+            .context("invalid tag index entry")?;
+        *counts.entry(tag_id).or_default() += 1;
+    }
+    Ok(counts)
+}
+
+/// Reads the record IDs of the entries `iter` yields while their keys start
+/// with `prefix`.
+fn ids_in<I>(iter: I, prefix: &[u8]) -> Result<Vec<u32>>
+where
+    I: Iterator<Item = Result<(Box<[u8]>, Box<[u8]>), rocksdb::Error>>,
+{
+    let mut ids = Vec::new();
+    for item in iter {
+        let (key, _) = item.context("cannot read tag index")?;
+        let Some(rest) = key.strip_prefix(prefix) else {
+            break;
+        };
+        let id = rest
+            .try_into()
+            .map(u32::from_be_bytes)
+            .ok()
+            .context("invalid tag index entry")?;
+        ids.push(id);
+    }
+    Ok(ids)
+}
+
+fn index_cf(db: &OptimisticTransactionDB) -> Result<&ColumnFamily> {
+    db.cf_handle(TAG_INDEX)
+        .with_context(|| format!("{TAG_INDEX} column family not found"))
+}
+
+fn table_prefix(table: &str) -> Vec<u8> {
+    let mut prefix = Vec::with_capacity(table.len() + 1);
+    prefix.extend_from_slice(table.as_bytes());
+    prefix.push(0);
+    prefix
+}
+
+fn tag_prefix(table: &str, tag_id: u32) -> Vec<u8> {
+    let mut prefix = table_prefix(table);
+    prefix.extend_from_slice(&tag_id.to_be_bytes());
+    prefix
+}
+
+fn entry_key(table: &str, tag_id: u32, id: u32) -> Vec<u8> {
+    let mut key = tag_prefix(table, tag_id);
+    key.extend_from_slice(&id.to_be_bytes());
+    key
+}
@@ -0,0 +1,694 @@
+//! Conflicts and redundancy among the stored triage policies.
+//!
+//! The analysis looks only at the policies and the exclusion reasons they
+//! refer to, not at any event: it finds what no event can make a policy do,
+//! and what a policy does twice or does as another policy does.
+
+use std::{cmp::Ordering, collections::HashMap, net::IpAddr};
+
+use anyhow::Result;
+use rocksdb::Direction;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    AttrCmpKind, AttrOperand, ExclusionReason, NetworkFilter, PacketAttr, TriageCondition,
+    TriageExclusionReason, TriagePolicy, TriageRules, ValueKind,
+};
+use crate::{IndexedTable, Iterable};
+
+/// A conflict or redundancy found in a triage policy.
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub enum PolicyFinding {
+    /// The same comparison of an attribute is required more than once by the
+    /// rules, at the given positions, so an event matching it has the
+    /// weights of all of them added to its score. A position appears twice
+    /// if the rule requires the comparison twice.
+    DuplicateAttr {
+        policy_id: u32,
+        attr: PacketAttr,
+        rules: Vec<usize>,
+    },
+    /// The rule at the position requires two comparisons of the same
+    /// attribute that no value satisfies together, so it never holds.
+    ContradictoryAttrs {
+        policy_id: u32,
+        rule: usize,
+        attrs: (PacketAttr, PacketAttr),
+    },
+    /// The rule at the position requires the event to match an exclusion
+    /// reason the policy excludes by, so it never adds to a score.
+    ShadowedRule {
+        policy_id: u32,
+        rule: usize,
+        exclusion_id: u32,
+    },
+    /// The exclusion reason excludes every event with an address from the
+    /// policy.
+    ShadowedPolicy { policy_id: u32, exclusion_id: u32 },
+    /// The minimum score of the response at the position is higher than
+    /// any score the policy can give.
+    UnreachableResponse {
+        policy_id: u32,
+        response: usize,
+        minimum_score: f64,
+        maximum_score: f64,
+    },
+    /// The policy scores every event as the other policy does, and applies
+    /// to no customer the other does not.
+    DuplicatePolicy { policy_id: u32, duplicate_of: u32 },
+}
+
+impl PolicyFinding {
+    /// Returns the ID of the policy the finding is about.
+    #[must_use]
+    pub fn policy_id(&self) -> u32 {
+        match self {
+            Self::DuplicateAttr { policy_id, .. }
+            | Self::ContradictoryAttrs { policy_id, .. }
+            | Self::ShadowedRule { policy_id, .. }
+            | Self::ShadowedPolicy { policy_id, .. }
+            | Self::UnreachableResponse { policy_id, .. }
+            | Self::DuplicatePolicy { policy_id, .. } => *policy_id,
+        }
+    }
+}
+
+impl IndexedTable<'_, TriagePolicy> {
+    /// Analyzes the stored policies, looking up the exclusion reasons they
+    /// refer to in `reasons`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails or a stored entry
+    /// cannot be decoded.
+    pub fn analyze(
+        &self,
+        reasons: &IndexedTable<'_, TriageExclusionReason>,
+    ) -> Result<Vec<PolicyFinding>> {
+        let policies = self
+            .iter(Direction::Forward, None)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(analyze(&policies, &exclusion_reasons(reasons)?))
+    }
+
+    /// Analyzes `policy` before it is stored, together with the stored
+    /// policies other than the one with its ID, and returns the findings
+    /// about it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails or a stored entry
+    /// cannot be decoded.
+    pub fn analyze_candidate(
+        &self,
+        policy: &TriagePolicy,
+        reasons: &IndexedTable<'_, TriageExclusionReason>,
+    ) -> Result<Vec<PolicyFinding>> {
+        let mut policies = Vec::new();
+        for stored in self.iter(Direction::Forward, None) {
+            let stored = stored?;
+            if stored.id != policy.id {
+                policies.push(stored);
+            }
+        }
+        policies.push(policy.clone());
+        let mut findings = analyze(&policies, &exclusion_reasons(reasons)?);
+        findings.retain(|finding| match finding {
+            PolicyFinding::DuplicatePolicy {
+                policy_id,
+                duplicate_of,
+            } => *policy_id == policy.id || *duplicate_of == policy.id,
+            _ => finding.policy_id() == policy.id,
+        });
+        Ok(findings)
+    }
+}
+
+fn exclusion_reasons(
+    table: &IndexedTable<'_, TriageExclusionReason>,
+) -> Result<HashMap<u32, TriageExclusionReason>> {
+    table
+        .iter(Direction::Forward, None)
+        .map(|reason| reason.map(|reason| (reason.id, reason)))
+        .collect()
+}
+
+/// Analyzes `policies`, in order, looking up the exclusion reasons they refer
+/// to in `reasons`.
+fn analyze(
+    policies: &[TriagePolicy],
+    reasons: &HashMap<u32, TriageExclusionReason>,
+) -> Vec<PolicyFinding> {
+    let mut findings = Vec::new();
+    for policy in policies {
+        analyze_policy(policy, reasons, &mut findings);
+    }
+    for (i, policy) in policies.iter().enumerate() {
+        let duplicate_of = policies[..i].iter().find_map(|other| {
+            if !scores_alike(policy, other) {
+                return None;
+            }
+            if other.customer_id.is_none() || other.customer_id == policy.customer_id {
+                Some((policy.id, other.id))
+            } else if policy.customer_id.is_none() {
+                Some((other.id, policy.id))
+            } else {
+                None
+            }
+        });
+        if let Some((policy_id, duplicate_of)) = duplicate_of {
+            findings.push(PolicyFinding::DuplicatePolicy {
+                policy_id,
+                duplicate_of,
+            });
+        }
+    }
+    findings
+}
+
+fn analyze_policy(
+    policy: &TriagePolicy,
+    reasons: &HashMap<u32, TriageExclusionReason>,
+    findings: &mut Vec<PolicyFinding>,
+) {
+    let policy_id = policy.id;
+    let TriageRules::V1(rules) = &policy.rules;
+    let required: Vec<_> = rules
+        .iter()
+        .map(|rule| {
+            let mut required = Required::default();
+            required.collect(&rule.condition);
+            required
+        })
+        .collect();
+
+    let mut duplicates: Vec<(&PacketAttr, Vec<usize>)> = Vec::new();
+    for (i, required) in required.iter().enumerate() {
+        for attr in &required.attrs {
+            match duplicates
+                .iter_mut()
+                .find(|(seen, _)| same_comparison(seen, attr))
+            {
+                Some((_, positions)) => positions.push(i),
+                None => duplicates.push((*attr, vec![i])),
+            }
+        }
+    }
+    for (attr, rules) in duplicates {
+        if rules.len() > 1 {
+            findings.push(PolicyFinding::DuplicateAttr {
+                policy_id,
+                attr: attr.clone(),
+                rules,
+            });
+        }
+    }
+
+    let mut maximum_score = 0.0;
+    for (i, (rule, required)) in rules.iter().zip(&required).enumerate() {
+        let mut holds = true;
+        for (j, a) in required.attrs.iter().enumerate() {
+            for b in &required.attrs[j + 1..] {
+                if contradicts(a, b) {
+                    holds = false;
+                    findings.push(PolicyFinding::ContradictoryAttrs {
+                        policy_id,
+                        rule: i,
+                        attrs: ((*a).clone(), (*b).clone()),
+                    });
+                }
+            }
+        }
+        if let Some(&exclusion_id) = required
+            .exclusions
+            .iter()
+            .find(|id| policy.triage_exclusion_id.contains(id))
+        {
+            holds = false;
+            findings.push(PolicyFinding::ShadowedRule {
+                policy_id,
+                rule: i,
+                exclusion_id,
+            });
+        }
+        if holds && rule.weight > 0.0 {
+            maximum_score += rule.weight;
+        }
+    }
+    maximum_score = (maximum_score * 100.0).trunc() / 100.0;
+    maximum_score += policy
+        .confidence
+        .iter()
+        .map(|confidence| confidence.weight.unwrap_or(1.0))
+        .filter(|weight| *weight > 0.0)
+        .sum::<f64>();
+
+    for &exclusion_id in &policy.triage_exclusion_id {
+        if let Some(reason) = reasons.get(&exclusion_id)
+            && reason.is_available_to(policy.customer_id)
+            && reason.scope.is_unrestricted()
+            && excludes_every_address(&reason.exclusion_reason)
+        {
+            findings.push(PolicyFinding::ShadowedPolicy {
+                policy_id,
+                exclusion_id,
+            });
+        }
+    }
+
+    for (i, response) in policy.response.iter().enumerate() {
+        if response.minimum_score > maximum_score {
+            findings.push(PolicyFinding::UnreachableResponse {
+                policy_id,
+                response: i,
+                minimum_score: response.minimum_score,
+                maximum_score,
+            });
+        }
+    }
+}
+
+/// The attributes and exclusion reasons a condition holds only if all of
+/// them match.
+#[derive(Default)]
+struct Required<'a> {
+    attrs: Vec<&'a PacketAttr>,
+    exclusions: Vec<u32>,
+}
+
+impl<'a> Required<'a> {
+    fn collect(&mut self, condition: &'a TriageCondition) {
+        match condition {
+            TriageCondition::Attr(attr) => self.attrs.push(attr),
+            TriageCondition::Exclusion(id) => self.exclusions.push(*id),
+            TriageCondition::All(conditions) => {
+                for condition in conditions {
+                    self.collect(condition);
+                }
+            }
+            TriageCondition::Any(conditions) => {
+                if let [condition] = conditions.as_slice() {
+                    self.collect(condition);
+                }
+            }
+            TriageCondition::Not(_) => {}
+        }
+    }
+}
+
+/// Returns whether `a` and `b` compare the same attribute in the same way,
+/// regardless of their weights.
+fn same_comparison(a: &PacketAttr, b: &PacketAttr) -> bool {
+    same_attr(a, b) && a.cmp_kind == b.cmp_kind && a.value == b.value
+}
+
+fn same_attr(a: &PacketAttr, b: &PacketAttr) -> bool {
+    a.raw_event_kind == b.raw_event_kind
+        && a.attr_name == b.attr_name
+        && a.value_kind() == b.value_kind()
+}
+
+/// Returns whether policies `a` and `b` give every event the same score and
+/// fire the same responses.
+fn scores_alike(a: &TriagePolicy, b: &TriagePolicy) -> bool {
+    let sorted = |ids: &[u32]| {
+        let mut ids = ids.to_vec();
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    };
+    a.rules == b.rules
+        && sorted(&a.triage_exclusion_id) == sorted(&b.triage_exclusion_id)
+        && a.confidence == b.confidence
+        && a.response == b.response
+}
+
+/// Returns whether no value of the attribute satisfies both `a` and `b`.
+/// Comparisons that are not a single range of values are never found
+/// contradictory.
+fn contradicts(a: &PacketAttr, b: &PacketAttr) -> bool {
+    if !same_attr(a, b) {
+        return false;
+    }
+    match a.value_kind() {
+        ValueKind::Integer => disjoint::<i64>(a, b),
+        ValueKind::UInteger => disjoint::<u64>(a, b),
+        ValueKind::Float => disjoint::<f64>(a, b),
+        ValueKind::IpAddr => disjoint::<IpAddr>(a, b),
+        ValueKind::Bool => disjoint::<bool>(a, b),
+        ValueKind::String | ValueKind::Vector => false,
+    }
+}
+
+fn disjoint<T: AttrOperand + Clone + PartialOrd>(a: &PacketAttr, b: &PacketAttr) -> bool {
+    let (Some(a), Some(b)) = (Range::<T>::new(a), Range::<T>::new(b)) else {
+        return false;
+    };
+    [&a.lower, &b.lower].into_iter().flatten().any(|lower| {
+        [&a.upper, &b.upper]
+            .into_iter()
+            .flatten()
+            .any(|upper| lower.excludes(upper))
+    })
+}
+
+/// The values a comparison is satisfied by.
+struct Range<T> {
+    lower: Option<Bound<T>>,
+    upper: Option<Bound<T>>,
+}
+
+struct Bound<T> {
+    value: T,
+    inclusive: bool,
+}
+
+impl<T: PartialOrd> Bound<T> {
+    /// Returns whether no value is both above this lower bound and below
+    /// `upper`.
+    fn excludes(&self, upper: &Self) -> bool {
+        match self.value.partial_cmp(&upper.value) {
+            Some(Ordering::Greater) => true,
+            Some(Ordering::Equal) => !(self.inclusive && upper.inclusive),
+            _ => false,
+        }
+    }
+}
+
+impl<T: AttrOperand + Clone> Range<T> {
+    /// Returns the values `attr` is satisfied by, or `None` if they are not a
+    /// single range or its value is not of a kind `T` can hold.
+    fn new(attr: &PacketAttr) -> Option<Self> {
+        let bound = |value, inclusive| Some(Bound { value, inclusive });
+        let (first, second) = attr.value.operands::<T>()?;
+        let (lower, upper) = match attr.cmp_kind {
+            AttrCmpKind::Less => (None, bound(first, false)),
+            AttrCmpKind::LessOrEqual => (None, bound(first, true)),
+            AttrCmpKind::Greater => (bound(first, false), None),
+            AttrCmpKind::GreaterOrEqual => (bound(first, true), None),
+            AttrCmpKind::Equal => (bound(first.clone(), true), bound(first, true)),
+            AttrCmpKind::OpenRange => (bound(first, false), bound(second?, false)),
+            AttrCmpKind::CloseRange => (bound(first, true), bound(second?, true)),
+            AttrCmpKind::LeftOpenRange => (bound(first, false), bound(second?, true)),
+            AttrCmpKind::RightOpenRange => (bound(first, true), bound(second?, false)),
+            AttrCmpKind::Contain
+            | AttrCmpKind::NotEqual
+            | AttrCmpKind::NotContain
+            | AttrCmpKind::NotOpenRange
+            | AttrCmpKind::NotCloseRange
+            | AttrCmpKind::NotLeftOpenRange
+            | AttrCmpKind::NotRightOpenRange => return None,
+        };
+        Some(Self { lower, upper })
+    }
+}
+
+/// Returns whether `reason` matches every IPv4 and IPv6 address.
+fn excludes_every_address(reason: &ExclusionReason) -> bool {
+    let ExclusionReason::IpAddress(group) = reason else {
+        return false;
+    };
+    let Ok(filter) = NetworkFilter::new(&mut group.clone()) else {
+        return false;
+    };
+    let mut v4 = Vec::new();
+    let mut v6 = Vec::new();
+    for (start, end) in filter.ranges() {
+        match (start, end) {
+            (IpAddr::V4(start), IpAddr::V4(end)) => {
+                v4.push((u128::from(u32::from(start)), u128::from(u32::from(end))));
+            }
+            (IpAddr::V6(start), IpAddr::V6(end)) => v6.push((u128::from(start), u128::from(end))),
+            _ => {}
+        }
+    }
+    covers(v4, u128::from(u32::MAX)) && covers(v6, u128::MAX)
+}
+
+/// Returns whether `ranges` cover every number from zero to `max`.
+fn covers(mut ranges: Vec<(u128, u128)>, max: u128) -> bool {
+    ranges.sort_unstable();
+    let mut uncovered = 0;
+    for (start, end) in ranges {
+        if start > uncovered {
+            return false;
+        }
+        if end >= max {
+            return true;
+        }
+        uncovered = uncovered.max(end + 1);
+    }
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, sync::Arc};
+
+    use attrievent::attribute::{ConnAttr, RawEventKind};
+    use chrono::Utc;
+    use ipnet::IpNet;
+
+    use super::{PolicyFinding, analyze};
+    use crate::test::{DbGuard, acquire_db_permit};
+    use crate::{
+        AttrCmpKind, AttrValue, ExclusionReason, ExclusionScope, HostNetworkGroup, PacketAttr,
+        Response, ResponseKind, Store, TriageCondition, TriageExclusionReason, TriagePolicy,
+        TriageRule, TriageRules,
+    };
+
+    fn port(cmp_kind: AttrCmpKind, first: i64, second: Option<i64>) -> PacketAttr {
+        PacketAttr {
+            raw_event_kind: RawEventKind::Conn,
+            attr_name: ConnAttr::DstPort.to_string(),
+            cmp_kind,
+            value: second.map_or(AttrValue::Integer(first), |second| {
+                AttrValue::IntegerRange(first, second)
+            }),
+            weight: None,
+        }
+    }
+
+    fn rule(attrs: Vec<PacketAttr>, weight: f64) -> TriageRule {
+        TriageRule {
+            condition: TriageCondition::All(attrs.into_iter().map(TriageCondition::Attr).collect()),
+            weight,
+        }
+    }
+
+    fn policy(id: u32, rules: Vec<TriageRule>, customer_id: Option<u32>) -> TriagePolicy {
+        TriagePolicy {
+            id,
+            name: format!("policy {id}"),
+            triage_exclusion_id: vec![],
+            rules: TriageRules::V1(rules),
+            confidence: vec![],
+            response: vec![],
+            creation_time: Utc::now(),
+            customer_id,
+        }
+    }
+
+    fn reason(id: u32, exclusion_reason: ExclusionReason) -> TriageExclusionReason {
+        TriageExclusionReason {
+            id,
+            name: format!("reason {id}"),
+            exclusion_reason,
+            description: String::new(),
+            customer_id: None,
+            scope: ExclusionScope::default(),
+        }
+    }
+
+    #[test]
+    fn contradictory_ranges() {
+        let rules = vec![
+            rule(
+                vec![
+                    port(AttrCmpKind::Less, 10, None),
+                    port(AttrCmpKind::Greater, 20, None),
+                ],
+                1.0,
+            ),
+            rule(
+                vec![
+                    port(AttrCmpKind::RightOpenRange, 10, Some(20)),
+                    port(AttrCmpKind::GreaterOrEqual, 20, None),
+                ],
+                1.0,
+            ),
+            rule(
+                vec![
+                    port(AttrCmpKind::CloseRange, 10, Some(20)),
+                    port(AttrCmpKind::Equal, 20, None),
+                ],
+                1.0,
+            ),
+        ];
+        let findings = analyze(&[policy(1, rules, None)], &HashMap::new());
+
+        let contradictory: Vec<_> = findings
+            .iter()
+            .filter_map(|finding| match finding {
+                PolicyFinding::ContradictoryAttrs { rule, .. } => Some(*rule),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(contradictory, [0, 1]);
+    }
+
+    #[test]
+    fn duplicate_attrs_and_policies() {
+        let mut weighted = port(AttrCmpKind::Equal, 443, None);
+        weighted.weight = Some(0.5);
+        let rules = vec![
+            rule(vec![port(AttrCmpKind::Equal, 443, None)], 1.0),
+            rule(vec![weighted], 2.0),
+            rule(vec![port(AttrCmpKind::Equal, 80, None)], 1.0),
+        ];
+        let policies = [
+            policy(1, rules.clone(), Some(1)),
+            policy(2, rules.clone(), None),
+            policy(3, rules, Some(2)),
+        ];
+        let findings = analyze(&policies, &HashMap::new());
+
+        assert!(findings.iter().any(|finding| matches!(
+            finding,
+            PolicyFinding::DuplicateAttr { policy_id: 1, rules, .. } if *rules == [0, 1]
+        )));
+        assert!(!findings.iter().any(|finding| matches!(
+            finding,
+            PolicyFinding::DuplicateAttr { rules, .. } if rules.contains(&2)
+        )));
+        let duplicates: Vec<_> = findings
+            .iter()
+            .filter_map(|finding| match finding {
+                PolicyFinding::DuplicatePolicy {
+                    policy_id,
+                    duplicate_of,
+                } => Some((*policy_id, *duplicate_of)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(duplicates, [(1, 2), (3, 2)]);
+    }
+
+    #[test]
+    fn shadowed_rules_and_unreachable_responses() {
+        let rules = vec![
+            rule(vec![port(AttrCmpKind::Equal, 22, None)], 1.5),
+            TriageRule {
+                condition: TriageCondition::Exclusion(7),
+                weight: 3.0,
+            },
+        ];
+        let mut shadowed = policy(1, rules, None);
+        shadowed.triage_exclusion_id = vec![7, 8];
+        shadowed.response = vec![
+            Response {
+                minimum_score: 1.0,
+                kind: ResponseKind::Manual,
+            },
+            Response {
+                minimum_score: 4.0,
+                kind: ResponseKind::Manual,
+            },
+        ];
+        let everything: Vec<IpNet> = vec!["0.0.0.0/0".parse().unwrap(), "::/0".parse().unwrap()];
+        let mut reasons = HashMap::new();
+        reasons.insert(
+            7,
+            reason(7, ExclusionReason::Domain(vec!["example.com".to_string()])),
+        );
+        reasons.insert(
+            8,
+            reason(
+                8,
+                ExclusionReason::IpAddress(HostNetworkGroup::new(vec![], everything, vec![])),
+            ),
+        );
+
+        let findings = analyze(&[shadowed.clone()], &reasons);
+        assert!(findings.iter().any(|finding| matches!(
+            finding,
+            PolicyFinding::ShadowedRule {
+                policy_id: 1,
+                rule: 1,
+                exclusion_id: 7
+            }
+        )));
+        assert!(findings.iter().any(|finding| matches!(
+            finding,
+            PolicyFinding::ShadowedPolicy {
+                policy_id: 1,
+                exclusion_id: 8
+            }
+        )));
+        assert!(findings.iter().any(|finding| matches!(
+            finding,
+            PolicyFinding::UnreachableResponse { policy_id: 1, response: 1, maximum_score, .. }
+                if (*maximum_score - 1.5).abs() < f64::EPSILON
+        )));
+        assert!(!findings.iter().any(|finding| matches!(
+            finding,
+            PolicyFinding::UnreachableResponse { response: 0, .. }
+        )));
+
+        let half: Vec<IpNet> = vec!["0.0.0.0/1".parse().unwrap(), "::/0".parse().unwrap()];
+        reasons.insert(
+            8,
+            reason(
+                8,
+                ExclusionReason::IpAddress(HostNetworkGroup::new(vec![], half, vec![])),
+            ),
+        );
+        let findings = analyze(&[shadowed], &reasons);
+        assert!(
+            !findings
+                .iter()
+                .any(|finding| matches!(finding, PolicyFinding::ShadowedPolicy { .. }))
+        );
+    }
+
+    #[test]
+    fn analyze_candidate_before_save() {
+        let (_permit, store) = setup_store();
+        let table = store.triage_policy_map();
+        let reasons = store.triage_exclusion_reason_map();
+        let rules = vec![rule(vec![port(AttrCmpKind::Equal, 443, None)], 1.0)];
+        let mut stored = policy(u32::MAX, rules.clone(), None);
+        stored.name = "stored".to_string();
+        let stored_id = table.put(stored).unwrap();
+
+        let mut candidate = policy(u32::MAX, rules.clone(), Some(1));
+        candidate.name = "candidate".to_string();
+        let findings = table.analyze_candidate(&candidate, &reasons).unwrap();
+        assert!(findings.iter().any(|finding| matches!(
+            finding,
+            PolicyFinding::DuplicatePolicy { policy_id, duplicate_of }
+                if *policy_id == u32::MAX && *duplicate_of == stored_id
+        )));
+
+        let mut edited = policy(stored_id, rules, None);
+        edited.response = vec![Response {
+            minimum_score: 2.0,
+            kind: ResponseKind::Manual,
+        }];
+        let findings = table.analyze_candidate(&edited, &reasons).unwrap();
+        assert_eq!(findings.len(), 1);
+        assert!(matches!(
+            findings[0],
+            PolicyFinding::UnreachableResponse { response: 0, .. }
+        ));
+        assert!(table.analyze(&reasons).unwrap().is_empty());
+    }
+
+    fn setup_store() -> (DbGuard<'static>, Arc<Store>) {
+        let permit = acquire_db_permit();
+        let db_dir = tempfile::tempdir().unwrap();
+        let backup_dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(Store::new(db_dir.path(), backup_dir.path(), None).unwrap());
+        (permit, store)
+    }
+}
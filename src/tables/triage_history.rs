@@ -0,0 +1,786 @@
+//! The `triage_history` table.
+//!
+//! Every change made to a triage policy or a triage exclusion reason through
+//! [`TriageHistory`] is appended to the table as a [`TriageRevision`] holding
+//! the entry as it was after the change, in the same transaction as the
+//! change itself. Revisions are never modified or removed, so the table
+//! answers what an entry looked like at any point since its history began.
+//!
+//! A revision is keyed by the kind of entry (one byte), the ID of the entry
+//! and the revision number, both big-endian, so the revisions of an entry are
+//! contiguous and in order. Changes made through [`IndexedTable`] directly are
+//! not recorded.
+
+use std::borrow::Cow;
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use rocksdb::{IteratorMode, OptimisticTransactionDB, ReadOptions, Transaction};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use super::{Confidence, Response, TriageExclusionReason, TriagePolicy, TriageRule, TriageRules};
+use crate::{
+    Actor, AuditAction, AuditEvent, EXCLUSIVE, Indexable, IndexedMap, IndexedMapUpdate,
+    IndexedTable, Map, types::FromKeyValue,
+};
+
+/// The length of a revision key: the kind, the ID, and the revision number.
+const REVISION_KEY_LEN: usize = 9;
+
+/// An entry whose changes [`TriageHistory`] records.
+pub trait Revisioned: Indexable + FromKeyValue + Clone + Serialize + DeserializeOwned {
+    /// The first byte of the keys of the revisions of this kind of entry.
+    const KIND: u8;
+    /// The name of the column family holding the entries.
+    const TABLE: &'static str;
+}
+
+impl Revisioned for TriagePolicy {
+    const KIND: u8 = 0;
+    const TABLE: &'static str = super::TRIAGE_POLICY;
+}
+
+impl Revisioned for TriageExclusionReason {
+    const KIND: u8 = 1;
+    const TABLE: &'static str = super::TRIAGE_EXCLUSION_REASON;
+}
+
+/// What a revision did to an entry.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum RevisionAction {
+    Created,
+    Updated,
+    Removed,
+    /// The entry was restored to the revision with the given number.
+    RolledBack(u32),
+}
+
+/// A change made to a triage policy or a triage exclusion reason.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct TriageRevision<T> {
+    /// The ID of the entry.
+    pub id: u32,
+    /// The number of the revision, starting from 1 for the first change
+    /// recorded for the entry.
+    pub revision: u32,
+    pub time: DateTime<Utc>,
+    /// Who made the change.
+    pub actor: String,
+    pub action: RevisionAction,
+    /// The entry after the change, or `None` if it was removed.
+    pub entry: Option<T>,
+}
+
+/// The revisions of the entries of a triage table, and the functions that
+/// change the entries while recording the changes.
+pub struct TriageHistory<'d, T> {
+    entries: IndexedTable<'d, T>,
+    revisions: Map<'d>,
+}
+
+impl<'d, T: Revisioned> TriageHistory<'d, T> {
+    /// Opens the history of the entries of type `T` in the database.
+    ///
+    /// Returns `None` if the table of the entries or of the history does not
+    /// exist.
+    pub(super) fn open(db: &'d OptimisticTransactionDB) -> Option<Self> {
+        let entries = IndexedMap::new(db, T::TABLE).map(IndexedTable::new).ok()?;
+        let revisions = Map::open(db, super::TRIAGE_HISTORY)?;
+        Some(Self { entries, revisions })
+    }
+
+    /// Stores `entry` and records its creation by `actor`. Returns the ID of
+    /// the stored entry.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the entry is invalid, an entry with the same key
+    /// exists, or the database operation fails.
+    pub fn insert(&self, entry: &T, actor: &str) -> Result<u32> {
+        entry.validate()?;
+        loop {
+            let txn = self.revisions.db.transaction();
+            let id = self.insert_with_transaction(&txn, entry, actor)?;
+            if commit(txn)? {
+                return Ok(id);
+            }
+        }
+    }
+
+    /// Updates the entry with `id` from `old` to `new`, as
+    /// [`IndexedTable::update_with_transaction`] does, and records the change
+    /// by `actor`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `id` is invalid, the entry does not match
+    /// `old`, or the database operation fails.
+    pub fn update<U>(&self, id: u32, old: &U, new: &U, actor: &str) -> Result<()>
+    where
+        U: IndexedMapUpdate<Entry = T>,
+    {
+        loop {
+            let txn = self.revisions.db.transaction();
+            self.update_with_transaction(&txn, id, old, new, actor)?;
+            if commit(txn)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Removes the entry with `id` and records its removal by `actor`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `id` is invalid or the database operation
+    /// fails.
+    pub fn remove(&self, id: u32, actor: &str) -> Result<()> {
+        loop {
+            let txn = self.revisions.db.transaction();
+            self.remove_with_transaction(&txn, id, actor)?;
+            if commit(txn)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Restores the entry with `id` to what it was after `revision`, and
+    /// records the change by `actor`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the entry does not exist, the revision does not
+    /// exist or removed the entry, another entry has the key of the revision,
+    /// or the database operation fails.
+    pub fn rollback(&self, id: u32, revision: u32, actor: &str) -> Result<()> {
+        let Some(target) = self.revision(id, revision)? else {
+            bail!("no such revision");
+        };
+        let Some(target) = target.entry else {
+            bail!("revision {revision} removed the entry");
+        };
+        loop {
+            let txn = self.revisions.db.transaction();
+            let Some(current) = self.entries.get_by_id_in_transaction(id, &txn)? else {
+                bail!("no such entry");
+            };
+            let restore = Restore {
+                current: &current,
+                target: &target,
+            };
+            self.entries
+                .update_with_transaction(id, &restore, &restore, &txn)?;
+            let stored = self.entries.get_by_id_in_transaction(id, &txn)?;
+            self.append(
+                &txn,
+                id,
+                actor,
+                RevisionAction::RolledBack(revision),
+                stored,
+            )?;
+            if commit(txn)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Returns the revisions of the entry with `id`, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails or a revision cannot
+    /// be decoded.
+    pub fn revisions(&self, id: u32) -> Result<Vec<TriageRevision<T>>> {
+        let prefix = revision_prefix::<T>(id);
+        let mut readopts = ReadOptions::default();
+        readopts.set_iterate_range(rocksdb::PrefixRange(prefix.as_slice()));
+        self.revisions
+            .db
+            .iterator_cf_opt(self.revisions.cf, readopts, IteratorMode::Start)
+            .map(|item| {
+                let (_, value) = item.context("cannot read triage history")?;
+                super::deserialize(&value).context("invalid triage revision")
+            })
+            .collect()
+    }
+
+    /// Returns the revision numbered `revision` of the entry with `id`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails or the revision cannot
+    /// be decoded.
+    pub fn revision(&self, id: u32, revision: u32) -> Result<Option<TriageRevision<T>>> {
+        self.revisions
+            .get(&revision_key::<T>(id, revision))?
+            .map(|value| super::deserialize(value.as_ref()).context("invalid triage revision"))
+            .transpose()
+    }
+
+    /// Returns the entry with `id` as it was at `time`, or `None` if it did
+    /// not exist then, was removed by then, or has no revision recorded by
+    /// then.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails or a revision cannot
+    /// be decoded.
+    pub fn as_of(&self, id: u32, time: DateTime<Utc>) -> Result<Option<T>> {
+        Ok(self
+            .revisions(id)?
+            .into_iter()
+            .take_while(|revision| revision.time <= time)
+            .last()
+            .and_then(|revision| revision.entry))
+    }
+
+    /// Stores `entry` and records its creation by `actor` within `txn`.
+    fn insert_with_transaction(
+        &self,
+        txn: &Transaction<OptimisticTransactionDB>,
+        entry: &T,
+        actor: &str,
+    ) -> Result<u32> {
+        let id = self.entries.put_with_transaction(entry.clone(), txn)?;
+        let mut stored = entry.clone();
+        stored.set_index(id);
+        self.append(txn, id, actor, RevisionAction::Created, Some(stored))?;
+        Ok(id)
+    }
+
+    /// Updates the entry with `id` from `old` to `new` and records the change
+    /// by `actor` within `txn`.
+    fn update_with_transaction<U>(
+        &self,
+        txn: &Transaction<OptimisticTransactionDB>,
+        id: u32,
+        old: &U,
+        new: &U,
+        actor: &str,
+    ) -> Result<()>
+    where
+        U: IndexedMapUpdate<Entry = T>,
+    {
+        self.entries.update_with_transaction(id, old, new, txn)?;
+        let stored = self.entries.get_by_id_in_transaction(id, txn)?;
+        self.append(txn, id, actor, RevisionAction::Updated, stored)
+    }
+
+    /// Removes the entry with `id` and records its removal by `actor` within
+    /// `txn`.
+    fn remove_with_transaction(
+        &self,
+        txn: &Transaction<OptimisticTransactionDB>,
+        id: u32,
+        actor: &str,
+    ) -> Result<()> {
+        self.entries.remove_with_transaction(id, txn)?;
+        self.append(txn, id, actor, RevisionAction::Removed, None)
+    }
+
+    /// Appends a revision of the entry with `id` within `txn`, numbered one
+    /// past the last revision of the entry.
+    fn append(
+        &self,
+        txn: &Transaction<OptimisticTransactionDB>,
+        id: u32,
+        actor: &str,
+        action: RevisionAction,
+        entry: Option<T>,
+    ) -> Result<()> {
+        let prefix = revision_prefix::<T>(id);
+        let mut readopts = ReadOptions::default();
+        readopts.set_iterate_range(rocksdb::PrefixRange(prefix.as_slice()));
+        let last = txn
+            .iterator_cf_opt(self.revisions.cf, readopts, IteratorMode::End)
+            .next()
+            .transpose()
+            .context("cannot read triage history")?;
+        let revision = match last {
+            Some((key, _)) => revision_number(&key)?
+                .checked_add(1)
+                .context("too many revisions")?,
+            None => 1,
+        };
+
+        let key = revision_key::<T>(id, revision);
+        if txn
+            .get_for_update_cf(self.revisions.cf, &key, EXCLUSIVE)
+            .context("cannot read triage history")?
+            .is_some()
+        {
+            bail!("revision {revision} already exists");
+        }
+        let record = TriageRevision {
+            id,
+            revision,
+            time: Utc::now(),
+            actor: actor.to_string(),
+            action,
+            entry,
+        };
+        self.revisions
+            .put_with_transaction(&key, &super::serialize(&record)?, txn)
+    }
+}
+
+impl TriageHistory<'_, TriagePolicy> {
+    /// Stores `policy` as [`insert`](Self::insert) does, and records its
+    /// insertion by `actor` in the audit trail in the same transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the policy is invalid, one with the same name
+    /// exists, or the database operation fails.
+    pub fn insert_as(&self, policy: &TriagePolicy, actor: &Actor) -> Result<u32> {
+        policy.validate()?;
+        self.entries.audited(
+            |txn| self.insert_with_transaction(txn, policy, &actor.username),
+            |id| {
+                AuditEvent::new(
+                    actor.clone(),
+                    AuditAction::TriagePolicyInserted,
+                    id.to_string(),
+                )
+            },
+        )
+    }
+
+    /// Updates the policy with `id` as [`update`](Self::update) does, and
+    /// records the update by `actor` in the audit trail in the same
+    /// transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `id` is invalid, the policy does not match
+    /// `old`, or the database operation fails.
+    pub fn update_as<U>(&self, id: u32, old: &U, new: &U, actor: &Actor) -> Result<()>
+    where
+        U: IndexedMapUpdate<Entry = TriagePolicy>,
+    {
+        self.entries.audited(
+            |txn| self.update_with_transaction(txn, id, old, new, &actor.username),
+            |_| {
+                AuditEvent::new(
+                    actor.clone(),
+                    AuditAction::TriagePolicyUpdated,
+                    id.to_string(),
+                )
+            },
+        )
+    }
+
+    /// Removes the policy with `id` as [`remove`](Self::remove) does, and
+    /// records the removal by `actor` in the audit trail in the same
+    /// transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `id` is invalid or the database operation
+    /// fails.
+    pub fn remove_as(&self, id: u32, actor: &Actor) -> Result<()> {
+        self.entries.audited(
+            |txn| self.remove_with_transaction(txn, id, &actor.username),
+            |_| {
+                AuditEvent::new(
+                    actor.clone(),
+                    AuditAction::TriagePolicyRemoved,
+                    id.to_string(),
+                )
+            },
+        )
+    }
+
+    /// Returns what `revision` of the policy with `id` changed from the
+    /// revision before it, or `None` if the revision does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails or a revision cannot
+    /// be decoded.
+    pub fn diff(&self, id: u32, revision: u32) -> Result<Option<TriagePolicyDiff>> {
+        let Some(new) = self.revision(id, revision)? else {
+            return Ok(None);
+        };
+        let old = if revision > 1 {
+            self.revision(id, revision - 1)?
+        } else {
+            None
+        };
+        Ok(Some(TriagePolicyDiff::new(
+            old.as_ref().and_then(|old| old.entry.as_ref()),
+            new.entry.as_ref(),
+        )))
+    }
+}
+
+impl TriageHistory<'_, TriageExclusionReason> {
+    /// Removes the exclusion reasons that no longer apply at `now`, records
+    /// each removal by `actor`, and returns the IDs of the removed reasons.
+    ///
+    /// The policies referring to a removed reason are left as they are, and
+    /// no longer exclude by it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails or a stored reason
+    /// cannot be decoded.
+    pub fn remove_expired(&self, now: DateTime<Utc>, actor: &str) -> Result<Vec<u32>> {
+        let mut removed = Vec::new();
+        for reason in self.entries.expired(now)? {
+            loop {
+                let txn = self.revisions.db.transaction();
+                let Some(current) = self.entries.get_by_id_in_transaction(reason.id, &txn)? else {
+                    break;
+                };
+                if !current.scope.is_expired(now) {
+                    break;
+                }
+                self.entries.remove_with_transaction(reason.id, &txn)?;
+                self.append(&txn, reason.id, actor, RevisionAction::Removed, None)?;
+                if commit(txn)? {
+                    removed.push(reason.id);
+                    break;
+                }
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// What changed in a triage policy, as the entries added to and removed from
+/// each of its lists.
+#[derive(Clone, Default)]
+pub struct TriagePolicyDiff {
+    pub triage_exclusion_id_added: Vec<u32>,
+    pub triage_exclusion_id_removed: Vec<u32>,
+    pub rules_added: Vec<TriageRule>,
+    pub rules_removed: Vec<TriageRule>,
+    pub confidence_added: Vec<Confidence>,
+    pub confidence_removed: Vec<Confidence>,
+    pub response_added: Vec<Response>,
+    pub response_removed: Vec<Response>,
+}
+
+impl TriagePolicyDiff {
+    /// Compares `old` with `new`, where `None` is a policy that does not
+    /// exist and so has empty lists.
+    #[must_use]
+    pub fn new(old: Option<&TriagePolicy>, new: Option<&TriagePolicy>) -> Self {
+        let rules = |policy: Option<&TriagePolicy>| match policy.map(|policy| &policy.rules) {
+            Some(TriageRules::V1(rules)) => rules.as_slice(),
+            None => &[],
+        };
+        let exclusion_ids = |policy: Option<&TriagePolicy>| {
+            policy.map_or(&[][..], |policy| policy.triage_exclusion_id.as_slice())
+        };
+        let confidence = |policy: Option<&TriagePolicy>| {
+            policy.map_or(&[][..], |policy| policy.confidence.as_slice())
+        };
+        let response = |policy: Option<&TriagePolicy>| {
+            policy.map_or(&[][..], |policy| policy.response.as_slice())
+        };
+        Self {
+            triage_exclusion_id_added: missing_from(exclusion_ids(old), exclusion_ids(new)),
+            triage_exclusion_id_removed: missing_from(exclusion_ids(new), exclusion_ids(old)),
+            rules_added: missing_from(rules(old), rules(new)),
+            rules_removed: missing_from(rules(new), rules(old)),
+            confidence_added: missing_from(confidence(old), confidence(new)),
+            confidence_removed: missing_from(confidence(new), confidence(old)),
+            response_added: missing_from(response(old), response(new)),
+            response_removed: missing_from(response(new), response(old)),
+        }
+    }
+
+    /// Returns whether nothing was added or removed.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.triage_exclusion_id_added.is_empty()
+            && self.triage_exclusion_id_removed.is_empty()
+            && self.rules_added.is_empty()
+            && self.rules_removed.is_empty()
+            && self.confidence_added.is_empty()
+            && self.confidence_removed.is_empty()
+            && self.response_added.is_empty()
+            && self.response_removed.is_empty()
+    }
+}
+
+/// Returns the items of `items` that are not in `base`.
+fn missing_from<I: Clone + PartialEq>(base: &[I], items: &[I]) -> Vec<I> {
+    items
+        .iter()
+        .filter(|item| !base.contains(item))
+        .cloned()
+        .collect()
+}
+
+/// Replaces an entry with the one a revision holds.
+struct Restore<'a, T> {
+    current: &'a T,
+    target: &'a T,
+}
+
+impl<T: Revisioned> IndexedMapUpdate for Restore<'_, T> {
+    type Entry = T;
+
+    fn key(&self) -> Option<Cow<'_, [u8]>> {
+        Some(self.target.key())
+    }
+
+    fn apply(&self, value: Self::Entry) -> Result<Self::Entry> {
+        let mut entry = self.target.clone();
+        entry.set_index(value.index());
+        Ok(entry)
+    }
+
+    fn verify(&self, value: &Self::Entry) -> bool {
+        value.value() == self.current.value()
+    }
+}
+
+fn revision_prefix<T: Revisioned>(id: u32) -> Vec<u8> {
+    let mut prefix = Vec::with_capacity(REVISION_KEY_LEN);
+    prefix.push(T::KIND);
+    prefix.extend_from_slice(&id.to_be_bytes());
+    prefix
+}
+
+fn revision_key<T: Revisioned>(id: u32, revision: u32) -> Vec<u8> {
+    let mut key = revision_prefix::<T>(id);
+    key.extend_from_slice(&revision.to_be_bytes());
+    key
+}
+
+fn revision_number(key: &[u8]) -> Result<u32> {
+    key.get(REVISION_KEY_LEN - 4..)
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(u32::from_be_bytes)
+        .context("invalid triage revision key")
+}
+
+/// Commits `txn`, returning `false` if it conflicted with another
+/// transaction and should be retried.
+fn commit(txn: Transaction<OptimisticTransactionDB>) -> Result<bool> {
+    match txn.commit() {
+        Ok(()) => Ok(true),
+        Err(e) => {
+            if e.as_ref().starts_with("Resource busy:") {
+                Ok(false)
+            } else {
+                Err(e).context("failed to record triage revision")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{Duration, Utc};
+
+    use crate::test::{DbGuard, acquire_db_permit};
+    use crate::{
+        Confidence, ExclusionReason, ExclusionScope, RevisionAction, Store, TriageExclusionReason,
+        TriageExclusionReasonUpdate, TriagePolicy, TriagePolicyUpdate, TriageRules,
+    };
+
+    fn setup_store() -> (DbGuard<'static>, Arc<Store>) {
+        let permit = acquire_db_permit();
+        let db_dir = tempfile::tempdir().unwrap();
+        let backup_dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(Store::new(db_dir.path(), backup_dir.path(), None).unwrap());
+        (permit, store)
+    }
+
+    fn confidence(threat_kind: &str) -> Confidence {
+        Confidence {
+            threat_category: None,
+            threat_kind: threat_kind.to_string(),
+            confidence: 0.5,
+            weight: None,
+        }
+    }
+
+    fn update(name: &str, confidence: Vec<Confidence>) -> TriagePolicyUpdate {
+        TriagePolicyUpdate {
+            name: name.to_string(),
+            triage_exclusion_id: vec![],
+            rules: TriageRules::default(),
+            confidence,
+            response: vec![],
+            customer_id: None,
+        }
+    }
+
+    #[test]
+    fn policy_revisions_read_as_of_and_roll_back() {
+        let (_permit, store) = setup_store();
+        let history = store.triage_policy_history();
+
+        let id = history
+            .insert(
+                &TriagePolicy {
+                    id: u32::MAX,
+                    name: "a".to_string(),
+                    triage_exclusion_id: vec![],
+                    rules: TriageRules::default(),
+                    confidence: vec![confidence("port scan")],
+                    response: vec![],
+                    creation_time: Utc::now(),
+                    customer_id: None,
+                },
+                "alice",
+            )
+            .unwrap();
+        let created = Utc::now();
+        history
+            .update(
+                id,
+                &update("a", vec![confidence("port scan")]),
+                &update("b", vec![confidence("dns covert channel")]),
+                "bob",
+            )
+            .unwrap();
+
+        let as_of_creation = history.as_of(id, created).unwrap().unwrap();
+        assert_eq!(as_of_creation.name, "a");
+        let current = store.triage_policy_map().get_by_id(id).unwrap().unwrap();
+        assert_eq!(current.name, "b");
+
+        let diff = history.diff(id, 2).unwrap().unwrap();
+        assert_eq!(
+            diff.confidence_added,
+            vec![confidence("dns covert channel")]
+        );
+        assert_eq!(diff.confidence_removed, vec![confidence("port scan")]);
+        assert!(diff.rules_added.is_empty());
+        assert!(history.diff(id, 1).unwrap().is_some_and(|diff| {
+            diff.confidence_added == vec![confidence("port scan")]
+                && diff.confidence_removed.is_empty()
+        }));
+        assert!(history.diff(id, 3).unwrap().is_none());
+
+        history.rollback(id, 1, "carol").unwrap();
+        let current = store.triage_policy_map().get_by_id(id).unwrap().unwrap();
+        assert_eq!(current.name, "a");
+        assert_eq!(current.confidence, vec![confidence("port scan")]);
+
+        history.remove(id, "dave").unwrap();
+        assert!(store.triage_policy_map().get_by_id(id).unwrap().is_none());
+        assert!(history.as_of(id, Utc::now()).unwrap().is_none());
+        assert!(history.rollback(id, 1, "dave").is_err());
+        assert!(history.rollback(id, 4, "dave").is_err());
+
+        let revisions = history.revisions(id).unwrap();
+        assert_eq!(
+            revisions
+                .iter()
+                .map(|r| (r.revision, r.actor.as_str(), r.action))
+                .collect::<Vec<_>>(),
+            vec![
+                (1, "alice", RevisionAction::Created),
+                (2, "bob", RevisionAction::Updated),
+                (3, "carol", RevisionAction::RolledBack(1)),
+                (4, "dave", RevisionAction::Removed),
+            ]
+        );
+        assert!(revisions[3].entry.is_none());
+    }
+
+    #[test]
+    fn exclusion_reason_revisions_are_kept_apart_from_policies() {
+        let (_permit, store) = setup_store();
+        let history = store.triage_exclusion_reason_history();
+        let reason = |description: &str| TriageExclusionReasonUpdate {
+            name: "trusted".to_string(),
+            exclusion_reason: ExclusionReason::Domain(vec!["example.com".to_string()]),
+            description: description.to_string(),
+            customer_id: None,
+            scope: ExclusionScope::default(),
+        };
+
+        let id = history
+            .insert(
+                &TriageExclusionReason {
+                    id: u32::MAX,
+                    name: "trusted".to_string(),
+                    exclusion_reason: ExclusionReason::Domain(vec!["example.com".to_string()]),
+                    description: "first".to_string(),
+                    customer_id: None,
+                    scope: ExclusionScope::default(),
+                },
+                "alice",
+            )
+            .unwrap();
+        history
+            .update(id, &reason("first"), &reason("second"), "bob")
+            .unwrap();
+        assert!(
+            history
+                .update(id, &reason("first"), &reason("third"), "bob")
+                .is_err()
+        );
+
+        let revisions = history.revisions(id).unwrap();
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(
+            revisions[1].entry.as_ref().map(|e| e.description.as_str()),
+            Some("second")
+        );
+        assert!(
+            store
+                .triage_policy_history()
+                .revisions(id)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn expired_exclusion_reasons_are_swept() {
+        let (_permit, store) = setup_store();
+        let history = store.triage_exclusion_reason_history();
+        let now = Utc::now();
+        let reason = |name: &str, valid_until| TriageExclusionReason {
+            id: u32::MAX,
+            name: name.to_string(),
+            exclusion_reason: ExclusionReason::Domain(vec!["example.com".to_string()]),
+            description: String::new(),
+            customer_id: None,
+            scope: ExclusionScope {
+                valid_until,
+                ..ExclusionScope::default()
+            },
+        };
+        let expired = history
+            .insert(&reason("expired", Some(now - Duration::days(1))), "alice")
+            .unwrap();
+        let current = history
+            .insert(&reason("current", Some(now + Duration::days(1))), "alice")
+            .unwrap();
+        let permanent = history.insert(&reason("permanent", None), "alice").unwrap();
+
+        let table = store.triage_exclusion_reason_map();
+        let listed: Vec<_> = table
+            .expired(now)
+            .unwrap()
+            .into_iter()
+            .map(|reason| reason.id)
+            .collect();
+        assert_eq!(listed, vec![expired]);
+
+        assert_eq!(
+            history.remove_expired(now, "sweeper").unwrap(),
+            vec![expired]
+        );
+        assert!(table.get_by_id(expired).unwrap().is_none());
+        assert!(table.get_by_id(current).unwrap().is_some());
+        assert!(table.get_by_id(permanent).unwrap().is_some());
+        let revisions = history.revisions(expired).unwrap();
+        assert_eq!(revisions[1].actor, "sweeper");
+        assert_eq!(revisions[1].action, RevisionAction::Removed);
+        assert!(history.remove_expired(now, "sweeper").unwrap().is_empty());
+    }
+}
@@ -8,17 +8,18 @@ use std::{
     ops::{BitAnd, RangeInclusive},
 };
 
-use anyhow::{Result, anyhow};
-use attrievent::attribute::RawEventKind;
+use anyhow::{Context, Result, anyhow, bail};
+use attrievent::attribute::{RawEventAttrKind, RawEventKind};
 use chrono::{DateTime, Utc};
 use ipnet::{IpNet, Ipv4Net, Ipv6Net};
-use rocksdb::OptimisticTransactionDB;
+use rocksdb::{Direction, OptimisticTransactionDB};
 use serde::{Deserialize, Serialize};
 use tracing::warn;
 
 use super::UniqueKey;
 use crate::{
-    Indexable, IndexedMap, IndexedMapUpdate, IndexedTable,
+    Actor, AuditAction, AuditEvent, EventKind, Indexable, IndexedMap, IndexedMapUpdate,
+    IndexedTable, Iterable,
     collections::Indexed,
     types::{EventCategory, FromKeyValue, HostNetworkGroup},
 };
@@ -31,7 +32,7 @@ pub struct TriagePolicy {
     pub id: u32,
     pub name: String,
     pub triage_exclusion_id: Vec<u32>,
-    pub packet_attr: Vec<PacketAttr>,
+    pub rules: TriageRules,
     pub confidence: Vec<Confidence>,
     pub response: Vec<Response>,
     pub creation_time: DateTime<Utc>,
@@ -82,29 +83,189 @@ impl Indexable for TriagePolicy {
     fn set_index(&mut self, index: u32) {
         self.id = index;
     }
+
+    fn validate(&self) -> Result<()> {
+        TriagePolicy::validate(self)
+    }
 }
 
 impl TriagePolicy {
+    /// Checks that every attribute the rules compare exists and is compared
+    /// in a way its value allows.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an attribute of the rules is invalid, as
+    /// [`PacketAttr::validate`] finds.
+    pub fn validate(&self) -> Result<()> {
+        self.rules.validate()
+    }
+
+    /// Returns the IDs of the exclusion reasons referenced by the conditions
+    /// of the rules, sorted and without duplicates.
+    #[must_use]
+    pub fn rule_exclusion_ids(&self) -> Vec<u32> {
+        let mut ids = Vec::new();
+        match &self.rules {
+            TriageRules::V1(rules) => {
+                for rule in rules {
+                    rule.condition.collect_exclusion_ids(&mut ids);
+                }
+            }
+        }
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
     /// Converts `TriagePolicy` into `TriagePolicyInput` with the given exclusion reasons.
     ///
     /// Applications using `review-database` must fetch `ExclusionReason` values from the
     /// `triage_exclusion_map` using the `triage_exclusion_id`s stored in `TriagePolicy`
-    /// and pass them to this method.
+    /// and pass them to this method, together with the reasons for the IDs returned by
+    /// [`rule_exclusion_ids`](Self::rule_exclusion_ids). A condition referring to an
+    /// exclusion reason missing from `rule_exclusion_reason` never holds.
     #[must_use]
     pub fn into_input_with_exclusion_reason(
         self,
         exclusion_reason: Vec<ExclusionReason>,
+        rule_exclusion_reason: HashMap<u32, ExclusionReason>,
     ) -> TriagePolicyInput {
         TriagePolicyInput {
             id: self.id,
             name: self.name,
             creation_time: self.creation_time,
             triage_exclusion: exclusion_reason.into_iter().map(Into::into).collect(),
-            packet_attr: self.packet_attr,
+            rules: self.rules,
+            rule_exclusions: rule_exclusion_reason
+                .into_iter()
+                .map(|(id, reason)| (id, reason.into()))
+                .collect(),
             confidence: self.confidence,
             response: self.response,
         }
     }
+
+    /// Converts `TriagePolicy` into `TriagePolicyInput` with the given exclusion reasons,
+    /// keeping the scope of each.
+    ///
+    /// Works as [`into_input_with_exclusion_reason`](Self::into_input_with_exclusion_reason)
+    /// does, except that a reason owned by a customer other than the one of the policy is
+    /// left out, as if it did not exist. A policy for all customers can use only the reasons
+    /// no customer owns.
+    #[must_use]
+    pub fn into_input_with_scoped_exclusion_reason(
+        self,
+        exclusion_reason: Vec<TriageExclusionReason>,
+        rule_exclusion_reason: HashMap<u32, TriageExclusionReason>,
+    ) -> TriagePolicyInput {
+        let customer_id = self.customer_id;
+        TriagePolicyInput {
+            id: self.id,
+            name: self.name,
+            creation_time: self.creation_time,
+            triage_exclusion: exclusion_reason
+                .into_iter()
+                .filter(|reason| reason.is_available_to(customer_id))
+                .map(Into::into)
+                .collect(),
+            rules: self.rules,
+            rule_exclusions: rule_exclusion_reason
+                .into_iter()
+                .filter(|(_, reason)| reason.is_available_to(customer_id))
+                .map(|(id, reason)| (id, reason.into()))
+                .collect(),
+            confidence: self.confidence,
+            response: self.response,
+        }
+    }
+}
+
+/// The rules of a triage policy, in a versioned format.
+///
+/// The score of an event is the sum of the weights of the rules whose
+/// conditions hold for it.
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub enum TriageRules {
+    V1(Vec<TriageRule>),
+}
+
+impl Default for TriageRules {
+    fn default() -> Self {
+        Self::V1(Vec::new())
+    }
+}
+
+impl TriageRules {
+    fn validate(&self) -> Result<()> {
+        match self {
+            Self::V1(rules) => rules.iter().try_for_each(|rule| rule.condition.validate()),
+        }
+    }
+}
+
+/// Wraps a flat list of attributes, each scoring its own weight, as it did
+/// before rules were introduced.
+impl From<Vec<PacketAttr>> for TriageRules {
+    fn from(packet_attr: Vec<PacketAttr>) -> Self {
+        Self::V1(
+            packet_attr
+                .into_iter()
+                .map(|attr| TriageRule {
+                    weight: attr.weight.unwrap_or_default(),
+                    condition: TriageCondition::Attr(attr),
+                })
+                .collect(),
+        )
+    }
+}
+
+/// A condition and the weight it adds to the score of an event it holds for.
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct TriageRule {
+    pub condition: TriageCondition,
+    pub weight: f64,
+}
+
+/// A boolean expression over the attributes of an event.
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub enum TriageCondition {
+    /// Holds if the attribute of the event matches. The weight of the
+    /// attribute is not used.
+    Attr(PacketAttr),
+    /// Holds if the event matches the exclusion reason with the given ID.
+    Exclusion(u32),
+    /// Holds if every condition holds, including when there are none.
+    All(Vec<TriageCondition>),
+    /// Holds if at least one condition holds.
+    Any(Vec<TriageCondition>),
+    Not(Box<TriageCondition>),
+}
+
+impl TriageCondition {
+    fn validate(&self) -> Result<()> {
+        match self {
+            Self::Attr(attr) => attr.validate(),
+            Self::Exclusion(_) => Ok(()),
+            Self::All(conditions) | Self::Any(conditions) => {
+                conditions.iter().try_for_each(Self::validate)
+            }
+            Self::Not(condition) => condition.validate(),
+        }
+    }
+
+    fn collect_exclusion_ids(&self, ids: &mut Vec<u32>) {
+        match self {
+            Self::Attr(_) => {}
+            Self::Exclusion(id) => ids.push(*id),
+            Self::All(conditions) | Self::Any(conditions) => {
+                for condition in conditions {
+                    condition.collect_exclusion_ids(ids);
+                }
+            }
+            Self::Not(condition) => condition.collect_exclusion_ids(ids),
+        }
+    }
 }
 
 #[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Deserialize, Serialize)]
@@ -118,6 +279,188 @@ pub enum ValueKind {
     Bool,
 }
 
+/// The value a packet attribute is compared with.
+///
+/// A range is the value of a comparison with a range, such as
+/// [`AttrCmpKind::OpenRange`], and holds its lower and upper bounds.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum AttrValue {
+    String(String),
+    Integer(i64),
+    UInteger(u64),
+    Float(f64),
+    IpAddr(IpAddr),
+    Bool(bool),
+    Vector(Vec<u8>),
+    IntegerRange(i64, i64),
+    UIntegerRange(u64, u64),
+    FloatRange(f64, f64),
+    IpAddrRange(IpAddr, IpAddr),
+}
+
+impl AttrValue {
+    /// Returns the kind of the value, or of the bounds of the range.
+    #[must_use]
+    pub fn kind(&self) -> ValueKind {
+        match self {
+            Self::String(_) => ValueKind::String,
+            Self::Integer(_) | Self::IntegerRange(..) => ValueKind::Integer,
+            Self::UInteger(_) | Self::UIntegerRange(..) => ValueKind::UInteger,
+            Self::Float(_) | Self::FloatRange(..) => ValueKind::Float,
+            Self::IpAddr(_) | Self::IpAddrRange(..) => ValueKind::IpAddr,
+            Self::Bool(_) => ValueKind::Bool,
+            Self::Vector(_) => ValueKind::Vector,
+        }
+    }
+
+    /// Returns whether the value is a range.
+    #[must_use]
+    pub fn is_range(&self) -> bool {
+        matches!(
+            self,
+            Self::IntegerRange(..)
+                | Self::UIntegerRange(..)
+                | Self::FloatRange(..)
+                | Self::IpAddrRange(..)
+        )
+    }
+
+    /// Returns the value, or the bounds of the range, as `T`, or `None` if
+    /// the value is not of a kind `T` can hold.
+    pub(crate) fn operands<T: AttrOperand>(&self) -> Option<(T, Option<T>)> {
+        T::operands(self)
+    }
+
+    /// Returns the string the value is.
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the bytes a raw attribute is searched for: those of a string
+    /// or a vector.
+    pub(crate) fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Self::String(value) => Some(value.as_bytes()),
+            Self::Vector(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the position of the variant in the order of values.
+    fn rank(&self) -> u8 {
+        match self {
+            Self::String(_) => 0,
+            Self::Integer(_) => 1,
+            Self::UInteger(_) => 2,
+            Self::Float(_) => 3,
+            Self::IpAddr(_) => 4,
+            Self::Bool(_) => 5,
+            Self::Vector(_) => 6,
+            Self::IntegerRange(..) => 7,
+            Self::UIntegerRange(..) => 8,
+            Self::FloatRange(..) => 9,
+            Self::IpAddrRange(..) => 10,
+        }
+    }
+}
+
+impl Eq for AttrValue {}
+
+impl PartialOrd for AttrValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for AttrValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::String(a), Self::String(b)) => a.cmp(b),
+            (Self::Integer(a), Self::Integer(b)) => a.cmp(b),
+            (Self::UInteger(a), Self::UInteger(b)) => a.cmp(b),
+            (Self::Float(a), Self::Float(b)) => a.total_cmp(b),
+            (Self::IpAddr(a), Self::IpAddr(b)) => a.cmp(b),
+            (Self::Bool(a), Self::Bool(b)) => a.cmp(b),
+            (Self::Vector(a), Self::Vector(b)) => a.cmp(b),
+            (Self::IntegerRange(a, b), Self::IntegerRange(c, d)) => (a, b).cmp(&(c, d)),
+            (Self::UIntegerRange(a, b), Self::UIntegerRange(c, d)) => (a, b).cmp(&(c, d)),
+            (Self::FloatRange(a, b), Self::FloatRange(c, d)) => {
+                a.total_cmp(c).then_with(|| b.total_cmp(d))
+            }
+            (Self::IpAddrRange(a, b), Self::IpAddrRange(c, d)) => (a, b).cmp(&(c, d)),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+/// A type of the attributes of events that the values of packet attributes
+/// are compared with.
+///
+/// An integer converts to the other integer type if it fits in it.
+pub(crate) trait AttrOperand: Sized {
+    fn operands(value: &AttrValue) -> Option<(Self, Option<Self>)>;
+}
+
+impl AttrOperand for i64 {
+    fn operands(value: &AttrValue) -> Option<(Self, Option<Self>)> {
+        match *value {
+            AttrValue::Integer(v) => Some((v, None)),
+            AttrValue::UInteger(v) => Some((v.try_into().ok()?, None)),
+            AttrValue::IntegerRange(first, second) => Some((first, Some(second))),
+            AttrValue::UIntegerRange(first, second) => {
+                Some((first.try_into().ok()?, Some(second.try_into().ok()?)))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl AttrOperand for u64 {
+    fn operands(value: &AttrValue) -> Option<(Self, Option<Self>)> {
+        match *value {
+            AttrValue::UInteger(v) => Some((v, None)),
+            AttrValue::Integer(v) => Some((v.try_into().ok()?, None)),
+            AttrValue::UIntegerRange(first, second) => Some((first, Some(second))),
+            AttrValue::IntegerRange(first, second) => {
+                Some((first.try_into().ok()?, Some(second.try_into().ok()?)))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl AttrOperand for f64 {
+    fn operands(value: &AttrValue) -> Option<(Self, Option<Self>)> {
+        match *value {
+            AttrValue::Float(v) => Some((v, None)),
+            AttrValue::FloatRange(first, second) => Some((first, Some(second))),
+            _ => None,
+        }
+    }
+}
+
+impl AttrOperand for IpAddr {
+    fn operands(value: &AttrValue) -> Option<(Self, Option<Self>)> {
+        match *value {
+            AttrValue::IpAddr(v) => Some((v, None)),
+            AttrValue::IpAddrRange(first, second) => Some((first, Some(second))),
+            _ => None,
+        }
+    }
+}
+
+impl AttrOperand for bool {
+    fn operands(value: &AttrValue) -> Option<(Self, Option<Self>)> {
+        match *value {
+            AttrValue::Bool(v) => Some((v, None)),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Deserialize, Serialize)]
 pub enum AttrCmpKind {
     Less,
@@ -138,6 +481,24 @@ pub enum AttrCmpKind {
     NotRightOpenRange,
 }
 
+impl AttrCmpKind {
+    /// Returns whether the comparison is with a range.
+    #[must_use]
+    pub fn is_range(self) -> bool {
+        matches!(
+            self,
+            Self::OpenRange
+                | Self::CloseRange
+                | Self::LeftOpenRange
+                | Self::RightOpenRange
+                | Self::NotOpenRange
+                | Self::NotCloseRange
+                | Self::NotLeftOpenRange
+                | Self::NotRightOpenRange
+        )
+    }
+}
+
 #[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Deserialize, Serialize)]
 pub enum ResponseKind {
     Manual,
@@ -148,13 +509,143 @@ pub enum ResponseKind {
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub enum ExclusionReason {
     IpAddress(HostNetworkGroup),
+    /// Domains, each matching itself and its subdomains. A `*` matches one or
+    /// more characters within a label, so `*.example.com` matches the
+    /// subdomains of `example.com` but not `example.com` itself.
     Domain(Vec<String>),
     Hostname(Vec<String>),
     Uri(Vec<String>),
+    /// Regular expressions, any of which matches a hostname when it matches
+    /// any part of it.
+    HostnamePattern(Vec<String>),
+    /// Regular expressions, any of which matches a URI when it matches any
+    /// part of it.
+    UriPattern(Vec<String>),
+    /// Regular expressions, any of which matches a user agent when it matches
+    /// any part of it.
+    UserAgent(Vec<String>),
+    /// JA3 fingerprints, as hexadecimal MD5 digests compared regardless of
+    /// case.
+    Ja3(Vec<String>),
+}
+
+impl ExclusionReason {
+    /// Checks that the addresses, domains, patterns, and fingerprints of the
+    /// reason can be matched.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an IP range cannot be matched, a domain has an
+    /// empty label, a regular expression is invalid, or a JA3 fingerprint is
+    /// not 32 hexadecimal digits.
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            ExclusionReason::IpAddress(group) => {
+                NetworkFilter::new(&mut group.clone())?;
+            }
+            ExclusionReason::Domain(domains) => {
+                for domain in domains {
+                    if domain.split('.').any(str::is_empty) {
+                        bail!("invalid domain {domain:?}: empty label");
+                    }
+                }
+            }
+            ExclusionReason::Hostname(_) | ExclusionReason::Uri(_) => {}
+            ExclusionReason::HostnamePattern(patterns)
+            | ExclusionReason::UriPattern(patterns)
+            | ExclusionReason::UserAgent(patterns) => {
+                for pattern in patterns {
+                    regex::Regex::new(pattern)
+                        .with_context(|| format!("invalid pattern {pattern:?}"))?;
+                }
+            }
+            ExclusionReason::Ja3(fingerprints) => {
+                for fingerprint in fingerprints {
+                    if fingerprint.len() != 32
+                        || !fingerprint.bytes().all(|b| b.is_ascii_hexdigit())
+                    {
+                        bail!("invalid JA3 fingerprint {fingerprint:?}");
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the position of the kind of the reason in the order of reasons.
+    fn rank(&self) -> u8 {
+        match self {
+            ExclusionReason::IpAddress(_) => 0,
+            ExclusionReason::Domain(_) => 1,
+            ExclusionReason::Hostname(_) => 2,
+            ExclusionReason::Uri(_) => 3,
+            ExclusionReason::HostnamePattern(_) => 4,
+            ExclusionReason::UriPattern(_) => 5,
+            ExclusionReason::UserAgent(_) => 6,
+            ExclusionReason::Ja3(_) => 7,
+        }
+    }
 }
 
 impl Eq for ExclusionReason {}
 
+/// When and to which events a triage exclusion reason applies.
+///
+/// A reason excludes an event only if the event happened while the reason was
+/// valid, and only if the event is of the kind and from the sensor the scope
+/// names, if any.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+pub struct ExclusionScope {
+    /// The time the reason starts to apply, or `None` if it always has.
+    pub valid_from: Option<DateTime<Utc>>,
+    /// The time the reason stops applying, or `None` if it never expires.
+    pub valid_until: Option<DateTime<Utc>>,
+    pub event_kind: Option<EventKind>,
+    pub sensor: Option<String>,
+}
+
+impl ExclusionScope {
+    /// Returns whether the scope applies to all events at all times.
+    #[must_use]
+    pub fn is_unrestricted(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns whether the reason no longer applies at `now`.
+    #[must_use]
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.valid_until.is_some_and(|until| until <= now)
+    }
+
+    /// Returns whether the reason applies at `now`.
+    #[must_use]
+    pub fn is_valid(&self, now: DateTime<Utc>) -> bool {
+        self.valid_from.is_none_or(|from| from <= now) && !self.is_expired(now)
+    }
+
+    /// Returns whether the reason applies to an event of `kind` from `sensor`
+    /// at `time`.
+    pub(crate) fn applies(&self, kind: EventKind, sensor: &str, time: DateTime<Utc>) -> bool {
+        self.is_valid(time)
+            && self.event_kind.is_none_or(|event_kind| event_kind == kind)
+            && self.sensor.as_deref().is_none_or(|name| name == sensor)
+    }
+
+    /// Checks that the validity period is not empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `valid_until` is not after `valid_from`.
+    pub fn validate(&self) -> Result<()> {
+        if let (Some(from), Some(until)) = (self.valid_from, self.valid_until)
+            && until <= from
+        {
+            bail!("invalid validity period: {until} is not after {from}");
+        }
+        Ok(())
+    }
+}
+
 /// A triage exclusion reason stored in the `triage_exclusion_map`.
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct TriageExclusionReason {
@@ -162,6 +653,31 @@ pub struct TriageExclusionReason {
     pub name: String,
     pub exclusion_reason: ExclusionReason,
     pub description: String,
+    /// The customer owning the reason, or `None` if the policies of all
+    /// customers can use it.
+    pub customer_id: Option<u32>,
+    pub scope: ExclusionScope,
+}
+
+impl TriageExclusionReason {
+    /// Checks that the reason and its scope can be matched.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if [`ExclusionReason::validate`] or
+    /// [`ExclusionScope::validate`] does.
+    pub fn validate(&self) -> Result<()> {
+        self.exclusion_reason.validate()?;
+        self.scope.validate()
+    }
+
+    /// Returns whether a policy of `customer_id`, or of all customers if
+    /// `None`, can use the reason.
+    #[must_use]
+    pub fn is_available_to(&self, customer_id: Option<u32>) -> bool {
+        self.customer_id
+            .is_none_or(|owner| Some(owner) == customer_id)
+    }
 }
 
 impl Eq for TriageExclusionReason {}
@@ -197,6 +713,10 @@ impl Indexable for TriageExclusionReason {
     fn set_index(&mut self, index: u32) {
         self.id = index;
     }
+
+    fn validate(&self) -> Result<()> {
+        TriageExclusionReason::validate(self)
+    }
 }
 
 impl PartialOrd for ExclusionReason {
@@ -205,23 +725,18 @@ impl PartialOrd for ExclusionReason {
     }
 }
 
-#[allow(clippy::match_same_arms)]
 impl Ord for ExclusionReason {
     fn cmp(&self, other: &Self) -> Ordering {
         match (self, other) {
             (ExclusionReason::IpAddress(a), ExclusionReason::IpAddress(b)) => a.cmp(b),
-            (ExclusionReason::Domain(a), ExclusionReason::Domain(b)) => a.cmp(b),
-            (ExclusionReason::Hostname(a), ExclusionReason::Hostname(b)) => a.cmp(b),
-            (ExclusionReason::Uri(a), ExclusionReason::Uri(b)) => a.cmp(b),
-            (ExclusionReason::IpAddress(_), _) => Ordering::Less,
-            (ExclusionReason::Domain(_), ExclusionReason::IpAddress(_)) => Ordering::Greater,
-            (ExclusionReason::Domain(_), _) => Ordering::Less,
-            (
-                ExclusionReason::Hostname(_),
-                ExclusionReason::IpAddress(_) | ExclusionReason::Domain(_),
-            ) => Ordering::Greater,
-            (ExclusionReason::Hostname(_), _) => Ordering::Less,
-            (ExclusionReason::Uri(_), _) => Ordering::Greater,
+            (ExclusionReason::Domain(a), ExclusionReason::Domain(b))
+            | (ExclusionReason::Hostname(a), ExclusionReason::Hostname(b))
+            | (ExclusionReason::Uri(a), ExclusionReason::Uri(b))
+            | (ExclusionReason::HostnamePattern(a), ExclusionReason::HostnamePattern(b))
+            | (ExclusionReason::UriPattern(a), ExclusionReason::UriPattern(b))
+            | (ExclusionReason::UserAgent(a), ExclusionReason::UserAgent(b))
+            | (ExclusionReason::Ja3(a), ExclusionReason::Ja3(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
         }
     }
 }
@@ -247,6 +762,8 @@ pub struct TriageExclusionReasonUpdate {
     pub name: String,
     pub exclusion_reason: ExclusionReason,
     pub description: String,
+    pub customer_id: Option<u32>,
+    pub scope: ExclusionScope,
 }
 
 impl IndexedMapUpdate for TriageExclusionReasonUpdate {
@@ -257,11 +774,15 @@ impl IndexedMapUpdate for TriageExclusionReasonUpdate {
     }
 
     fn apply(&self, mut value: Self::Entry) -> Result<Self::Entry, anyhow::Error> {
+        self.exclusion_reason.validate()?;
+        self.scope.validate()?;
         value.name.clear();
         value.name.push_str(&self.name);
         value.exclusion_reason = self.exclusion_reason.clone();
         value.description.clear();
         value.description.push_str(&self.description);
+        value.customer_id = self.customer_id;
+        value.scope = self.scope.clone();
         Ok(value)
     }
 
@@ -269,6 +790,8 @@ impl IndexedMapUpdate for TriageExclusionReasonUpdate {
         self.name == value.name
             && self.exclusion_reason == value.exclusion_reason
             && self.description == value.description
+            && self.customer_id == value.customer_id
+            && self.scope == value.scope
     }
 }
 
@@ -345,6 +868,18 @@ impl NetworkFilter {
         };
         networks.iter().any(|net| net.detect(ip))
     }
+
+    /// Returns the first and last addresses of each network and range the
+    /// filter matches.
+    pub(crate) fn ranges(&self) -> impl Iterator<Item = (IpAddr, IpAddr)> + '_ {
+        self.tree
+            .values()
+            .flatten()
+            .map(|compare_ip| match compare_ip {
+                CompareIp::Network(net) => (net.network(), net.broadcast()),
+                CompareIp::Iprange(range) => (*range.start(), *range.end()),
+            })
+    }
 }
 
 #[derive(Clone)]
@@ -353,6 +888,24 @@ pub enum TriageExclusion {
     Domain(regex::RegexSet),
     Hostname(Vec<String>),
     Uri(Vec<String>),
+    HostnamePattern(regex::RegexSet),
+    UriPattern(regex::RegexSet),
+    UserAgent(regex::RegexSet),
+    /// The JA3 fingerprints in lowercase.
+    Ja3(Vec<String>),
+    /// An exclusion that applies only within the scope.
+    Scoped(ExclusionScope, Box<TriageExclusion>),
+}
+
+impl From<TriageExclusionReason> for TriageExclusion {
+    fn from(reason: TriageExclusionReason) -> Self {
+        let exclusion = reason.exclusion_reason.into();
+        if reason.scope.is_unrestricted() {
+            exclusion
+        } else {
+            TriageExclusion::Scoped(reason.scope, Box::new(exclusion))
+        }
+    }
 }
 
 impl From<ExclusionReason> for TriageExclusion {
@@ -376,8 +929,13 @@ impl From<ExclusionReason> for TriageExclusion {
                     domains
                         .iter()
                         .map(|domain| {
-                            // Escape special regex characters in domain
-                            let escaped = regex::escape(domain);
+                            // Escape special regex characters in domain, and
+                            // let a wildcard match within a label
+                            let escaped = domain
+                                .split('*')
+                                .map(regex::escape)
+                                .collect::<Vec<_>>()
+                                .join("[^.]+");
                             // Pattern to match exact domain or subdomain
                             format!(r"(^{escaped}$|\.{escaped}$)")
                         })
@@ -389,17 +947,43 @@ impl From<ExclusionReason> for TriageExclusion {
             }
             ExclusionReason::Hostname(hostnames) => TriageExclusion::Hostname(hostnames),
             ExclusionReason::Uri(uris) => TriageExclusion::Uri(uris),
+            ExclusionReason::HostnamePattern(patterns) => {
+                TriageExclusion::HostnamePattern(pattern_set(&patterns))
+            }
+            ExclusionReason::UriPattern(patterns) => {
+                TriageExclusion::UriPattern(pattern_set(&patterns))
+            }
+            ExclusionReason::UserAgent(patterns) => {
+                TriageExclusion::UserAgent(pattern_set(&patterns))
+            }
+            ExclusionReason::Ja3(fingerprints) => TriageExclusion::Ja3(
+                fingerprints
+                    .iter()
+                    .map(|fingerprint| fingerprint.to_ascii_lowercase())
+                    .collect(),
+            ),
         }
     }
 }
 
+/// Builds the set of `patterns`, or a set matching nothing if any of them is
+/// invalid, which only a reason stored before it was validated can have.
+fn pattern_set(patterns: &[String]) -> regex::RegexSet {
+    regex::RegexSet::new(patterns).unwrap_or_else(|error| {
+        warn!("Failed to build triage exclusion patterns: {error}");
+        regex::RegexSet::empty()
+    })
+}
+
 #[derive(Clone)]
 pub struct TriagePolicyInput {
     pub id: u32,
     pub name: String,
     pub creation_time: DateTime<Utc>,
     pub triage_exclusion: Vec<TriageExclusion>,
-    pub packet_attr: Vec<PacketAttr>,
+    pub rules: TriageRules,
+    /// The exclusions referenced by the conditions of `rules`, by ID.
+    pub rule_exclusions: HashMap<u32, TriageExclusion>,
     pub confidence: Vec<Confidence>,
     pub response: Vec<Response>,
 }
@@ -408,13 +992,82 @@ pub struct TriagePolicyInput {
 pub struct PacketAttr {
     pub raw_event_kind: RawEventKind,
     pub attr_name: String,
-    pub value_kind: ValueKind,
     pub cmp_kind: AttrCmpKind,
-    pub first_value: Vec<u8>,
-    pub second_value: Option<Vec<u8>>,
+    pub value: AttrValue,
     pub weight: Option<f64>,
 }
 
+impl PacketAttr {
+    /// Returns the kind of the value the attribute is compared with.
+    #[must_use]
+    pub fn value_kind(&self) -> ValueKind {
+        self.value.kind()
+    }
+
+    /// Checks that the attribute exists for its raw event kind and that its
+    /// value can be compared in the way `cmp_kind` names.
+    ///
+    /// A string or a vector is compared by `Contain` and `NotContain`, a
+    /// boolean by `Equal` and `NotEqual`, a number or an address by the other
+    /// comparisons that are not ranges, and a range by the range
+    /// comparisons.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the attribute does not exist, `cmp_kind` cannot
+    /// compare the value, a float is NaN, or the lower bound of a range is
+    /// greater than the upper one.
+    pub fn validate(&self) -> Result<()> {
+        RawEventAttrKind::from_kind_and_attr_name(&self.raw_event_kind, &self.attr_name)
+            .map_err(|_| anyhow!("unknown attribute {:?}", self.attr_name))?;
+        let legal = match &self.value {
+            AttrValue::String(_) | AttrValue::Vector(_) => {
+                matches!(
+                    self.cmp_kind,
+                    AttrCmpKind::Contain | AttrCmpKind::NotContain
+                )
+            }
+            AttrValue::Bool(_) => {
+                matches!(self.cmp_kind, AttrCmpKind::Equal | AttrCmpKind::NotEqual)
+            }
+            AttrValue::Integer(_)
+            | AttrValue::UInteger(_)
+            | AttrValue::Float(_)
+            | AttrValue::IpAddr(_) => matches!(
+                self.cmp_kind,
+                AttrCmpKind::Less
+                    | AttrCmpKind::LessOrEqual
+                    | AttrCmpKind::Equal
+                    | AttrCmpKind::NotEqual
+                    | AttrCmpKind::Greater
+                    | AttrCmpKind::GreaterOrEqual
+            ),
+            AttrValue::IntegerRange(..)
+            | AttrValue::UIntegerRange(..)
+            | AttrValue::FloatRange(..)
+            | AttrValue::IpAddrRange(..) => self.cmp_kind.is_range(),
+        };
+        if !legal {
+            bail!(
+                "attribute {:?} cannot compare a value of this kind that way",
+                self.attr_name
+            );
+        }
+        let ordered = match self.value {
+            AttrValue::Float(v) => !v.is_nan(),
+            AttrValue::IntegerRange(first, second) => first <= second,
+            AttrValue::UIntegerRange(first, second) => first <= second,
+            AttrValue::FloatRange(first, second) => first <= second,
+            AttrValue::IpAddrRange(first, second) => first <= second,
+            _ => true,
+        };
+        if !ordered {
+            bail!("attribute {:?} has an empty range or NaN", self.attr_name);
+        }
+        Ok(())
+    }
+}
+
 impl Eq for PacketAttr {}
 
 impl PartialOrd for PacketAttr {
@@ -429,7 +1082,7 @@ impl Ord for PacketAttr {
         if first != Ordering::Equal {
             return first;
         }
-        let second = self.value_kind.cmp(&other.value_kind);
+        let second = self.value_kind().cmp(&other.value_kind());
         if second != Ordering::Equal {
             return second;
         }
@@ -437,14 +1090,10 @@ impl Ord for PacketAttr {
         if third != Ordering::Equal {
             return third;
         }
-        let fourth = self.first_value.cmp(&other.first_value);
+        let fourth = self.value.cmp(&other.value);
         if fourth != Ordering::Equal {
             return fourth;
         }
-        let fifth = self.second_value.cmp(&other.second_value);
-        if fifth != Ordering::Equal {
-            return fifth;
-        }
         match (self.weight, other.weight) {
             (None, None) => Ordering::Equal,
             (None, Some(_)) => Ordering::Less,
@@ -528,14 +1177,85 @@ impl<'d> IndexedTable<'d, TriagePolicy> {
             .ok()
     }
 
+    /// Stores `policy` once [`TriagePolicy::validate`] accepts it, and
+    /// returns its ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the policy is invalid, one with the same name
+    /// exists, or the database operation fails.
+    pub fn insert(&self, policy: TriagePolicy) -> Result<u32> {
+        self.put(policy)
+    }
+
     /// Updates the `TriagePolicy` from `old` to `new`, given `id`.
     ///
     /// # Errors
     ///
-    /// Returns an error if the `id` is invalid or the database operation fails.
+    /// Returns an error if the `id` is invalid, an attribute of the rules of
+    /// `new` is invalid, or the database operation fails.
     pub fn update(&mut self, id: u32, old: &Update, new: &Update) -> Result<()> {
         self.indexed_map.update(id, old, new)
     }
+
+    /// Stores `policy` as [`insert`](Self::insert) does, and records its
+    /// insertion by `actor` in the audit trail in the same transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the policy is invalid, one with the same name
+    /// exists, or the database operation fails.
+    pub fn insert_as(&self, policy: TriagePolicy, actor: &Actor) -> Result<u32> {
+        self.audited(
+            |txn| self.put_with_transaction(policy.clone(), txn),
+            |id| {
+                AuditEvent::new(
+                    actor.clone(),
+                    AuditAction::TriagePolicyInserted,
+                    id.to_string(),
+                )
+            },
+        )
+    }
+
+    /// Updates the `TriagePolicy` from `old` to `new`, given `id`, and records
+    /// the update by `actor` in the audit trail in the same transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `id` is invalid, an attribute of the rules of
+    /// `new` is invalid, or the database operation fails.
+    pub fn update_as(&mut self, id: u32, old: &Update, new: &Update, actor: &Actor) -> Result<()> {
+        self.audited(
+            |txn| self.update_with_transaction(id, old, new, txn),
+            |_| {
+                AuditEvent::new(
+                    actor.clone(),
+                    AuditAction::TriagePolicyUpdated,
+                    id.to_string(),
+                )
+            },
+        )
+    }
+
+    /// Removes the policy with `id`, and records its removal by `actor` in the
+    /// audit trail in the same transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `id` is invalid or the database operation fails.
+    pub fn remove_as(&self, id: u32, actor: &Actor) -> Result<()> {
+        self.audited(
+            |txn| self.remove_with_transaction(id, txn).map(drop),
+            |_| {
+                AuditEvent::new(
+                    actor.clone(),
+                    AuditAction::TriagePolicyRemoved,
+                    id.to_string(),
+                )
+            },
+        )
+    }
 }
 
 /// Functions for the `triage_exclusion_reason` indexed map.
@@ -549,11 +1269,40 @@ impl<'d> IndexedTable<'d, TriageExclusionReason> {
             .ok()
     }
 
+    /// Stores `reason` once [`TriageExclusionReason::validate`] accepts it,
+    /// and returns its ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the reason is invalid, one with the same name
+    /// exists, or the database operation fails.
+    pub fn insert(&self, reason: TriageExclusionReason) -> Result<u32> {
+        self.put(reason)
+    }
+
+    /// Returns the reasons that no longer apply at `now`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails or a stored reason
+    /// cannot be decoded.
+    pub fn expired(&self, now: DateTime<Utc>) -> Result<Vec<TriageExclusionReason>> {
+        let mut expired = Vec::new();
+        for reason in self.iter(Direction::Forward, None) {
+            let reason = reason?;
+            if reason.scope.is_expired(now) {
+                expired.push(reason);
+            }
+        }
+        Ok(expired)
+    }
+
     /// Updates the `TriageExclusionReason` from `old` to `new`, given `id`.
     ///
     /// # Errors
     ///
-    /// Returns an error if the `id` is invalid or the database operation fails.
+    /// Returns an error if the `id` is invalid, the exclusion reason or the
+    /// scope of `new` is invalid, or the database operation fails.
     pub fn update(
         &mut self,
         id: u32,
@@ -568,7 +1317,7 @@ impl<'d> IndexedTable<'d, TriageExclusionReason> {
 pub struct Update {
     pub name: String,
     pub triage_exclusion_id: Vec<u32>,
-    pub packet_attr: Vec<PacketAttr>,
+    pub rules: TriageRules,
     pub confidence: Vec<Confidence>,
     pub response: Vec<Response>,
     pub customer_id: Option<u32>,
@@ -582,15 +1331,14 @@ impl IndexedMapUpdate for Update {
     }
 
     fn apply(&self, mut value: Self::Entry) -> Result<Self::Entry, anyhow::Error> {
+        self.rules.validate()?;
         value.name.clear();
         value.name.push_str(&self.name);
         let mut triage_exclusion_id = self.triage_exclusion_id.clone();
         triage_exclusion_id.sort_unstable();
         value.triage_exclusion_id = triage_exclusion_id;
 
-        let mut packet_attr: Vec<PacketAttr> = self.packet_attr.clone();
-        packet_attr.sort_unstable();
-        value.packet_attr = packet_attr;
+        value.rules = self.rules.clone();
 
         let mut confidence = self.confidence.clone();
         confidence.sort_unstable();
@@ -614,9 +1362,7 @@ impl IndexedMapUpdate for Update {
         if triage_exclusion_id != value.triage_exclusion_id {
             return false;
         }
-        let mut packet_attr = self.packet_attr.clone();
-        packet_attr.sort_unstable();
-        if packet_attr != value.packet_attr {
+        if self.rules != value.rules {
             return false;
         }
 
@@ -744,9 +1490,9 @@ mod test {
 
     use crate::test::{DbGuard, acquire_db_permit};
     use crate::{
-        AttrCmpKind, ExclusionReason, PacketAttr, Response, ResponseKind, Store,
-        TriageExclusionReason, TriageExclusionReasonUpdate, TriagePolicy, TriagePolicyUpdate,
-        ValueKind,
+        AttrCmpKind, AttrValue, EventKind, ExclusionReason, ExclusionScope, PacketAttr, Response,
+        ResponseKind, Store, TriageCondition, TriageExclusion, TriageExclusionReason,
+        TriageExclusionReasonUpdate, TriagePolicy, TriagePolicyUpdate, TriageRule, TriageRules,
     };
 
     #[test]
@@ -767,6 +1513,111 @@ mod test {
         assert_eq!(entry.map(|e| e.name), Some("b".to_string()));
     }
 
+    #[test]
+    fn update_rules() {
+        let (_permit, store) = setup_store();
+        let mut table = store.triage_policy_map();
+
+        let id = table.put(create_entry("a", None)).unwrap();
+        let method = PacketAttr {
+            raw_event_kind: attrievent::attribute::RawEventKind::Http,
+            attr_name: attrievent::attribute::HttpAttr::Method.to_string(),
+            cmp_kind: AttrCmpKind::Contain,
+            value: AttrValue::String("POST".to_string()),
+            weight: None,
+        };
+        let rules = TriageRules::V1(vec![TriageRule {
+            condition: TriageCondition::All(vec![
+                TriageCondition::Attr(method),
+                TriageCondition::Not(Box::new(TriageCondition::Any(vec![
+                    TriageCondition::Exclusion(7),
+                    TriageCondition::Exclusion(3),
+                ]))),
+                TriageCondition::Exclusion(7),
+            ]),
+            weight: 0.5,
+        }]);
+        let old = create_update("a", None);
+        let mut new = create_update("a", None);
+        new.rules = rules.clone();
+        table.update(id, &old, &new).unwrap();
+
+        let entry = table.get_by_id(id).unwrap().unwrap();
+        assert!(entry.rules == rules);
+        assert_eq!(entry.rule_exclusion_ids(), vec![3, 7]);
+        assert!(table.update(id, &old, &new).is_err());
+    }
+
+    #[test]
+    fn invalid_attrs_are_rejected() {
+        use attrievent::attribute::{ConnAttr, RawEventKind};
+
+        let (_permit, store) = setup_store();
+        let mut table = store.triage_policy_map();
+        let port = |cmp_kind, value| PacketAttr {
+            raw_event_kind: RawEventKind::Conn,
+            attr_name: ConnAttr::DstPort.to_string(),
+            cmp_kind,
+            value,
+            weight: None,
+        };
+        let with_attr = |name: &str, attr: PacketAttr| {
+            let mut policy = create_entry(name, None);
+            policy.rules = TriageRules::from(vec![attr]);
+            policy
+        };
+
+        let valid = [
+            port(AttrCmpKind::Less, AttrValue::UInteger(1024)),
+            port(AttrCmpKind::CloseRange, AttrValue::UIntegerRange(80, 80)),
+            port(AttrCmpKind::NotOpenRange, AttrValue::IntegerRange(0, 1024)),
+        ];
+        for (i, attr) in valid.into_iter().enumerate() {
+            assert!(table.insert(with_attr(&format!("valid {i}"), attr)).is_ok());
+        }
+
+        let mut unknown = port(AttrCmpKind::Equal, AttrValue::UInteger(80));
+        unknown.attr_name = "no such attribute".to_string();
+        let invalid = [
+            unknown,
+            port(AttrCmpKind::Contain, AttrValue::UInteger(80)),
+            port(AttrCmpKind::OpenRange, AttrValue::UInteger(80)),
+            port(AttrCmpKind::Equal, AttrValue::UIntegerRange(80, 443)),
+            port(AttrCmpKind::Equal, AttrValue::String("80".to_string())),
+            port(AttrCmpKind::Contain, AttrValue::Bool(true)),
+            port(AttrCmpKind::Less, AttrValue::Float(f64::NAN)),
+            port(AttrCmpKind::CloseRange, AttrValue::UIntegerRange(443, 80)),
+        ];
+        for (i, attr) in invalid.into_iter().enumerate() {
+            let policy = with_attr(&format!("invalid {i}"), attr);
+            assert!(policy.validate().is_err());
+            assert!(table.insert(policy).is_err());
+        }
+        assert_eq!(table.count().unwrap(), 3);
+
+        // An invalid attribute nested in a condition is rejected on update.
+        let id = table.insert(create_entry("a", None)).unwrap();
+        let old = create_update("a", None);
+        let mut new = create_update("a", None);
+        new.rules = TriageRules::V1(vec![TriageRule {
+            condition: TriageCondition::Not(Box::new(TriageCondition::Attr(port(
+                AttrCmpKind::Greater,
+                AttrValue::Bool(false),
+            )))),
+            weight: 1.0,
+        }]);
+        assert!(table.update(id, &old, &new).is_err());
+        assert!(
+            store
+                .triage_policy_history()
+                .insert(
+                    &with_attr("b", port(AttrCmpKind::Contain, AttrValue::Integer(1))),
+                    "admin"
+                )
+                .is_err()
+        );
+    }
+
     #[test]
     fn same_name_different_customer() {
         let (_permit, store) = setup_store();
@@ -819,6 +1670,100 @@ mod test {
         assert_eq!(entry.description, "new description");
     }
 
+    #[test]
+    fn invalid_exclusion_reasons_are_rejected() {
+        let (_permit, store) = setup_store();
+        let mut table = store.triage_exclusion_reason_map();
+
+        let invalid = [
+            ExclusionReason::HostnamePattern(vec!["(".to_string()]),
+            ExclusionReason::UriPattern(vec!["[a-".to_string()]),
+            ExclusionReason::UserAgent(vec!["*curl".to_string()]),
+            ExclusionReason::Ja3(vec!["not a fingerprint".to_string()]),
+            ExclusionReason::Domain(vec!["example..com".to_string()]),
+            ExclusionReason::Domain(vec!["*.example.com.".to_string()]),
+        ];
+        for reason in invalid {
+            let mut entry = create_exclusion_reason_entry("invalid");
+            entry.exclusion_reason = reason;
+            assert!(table.put(entry.clone()).is_err());
+            assert!(table.insert(entry).is_err());
+        }
+        assert_eq!(table.count().unwrap(), 0);
+
+        let mut entry = create_exclusion_reason_entry("a");
+        entry.exclusion_reason =
+            ExclusionReason::Ja3(vec!["E7D705A3286E19EA42F587B344EE6865".to_string()]);
+        let id = table.insert(entry).unwrap();
+
+        let old = create_exclusion_reason_update("a", "test description");
+        let mut new = create_exclusion_reason_update("a", "test description");
+        new.exclusion_reason = ExclusionReason::Ja3(vec!["e7d705a3".to_string()]);
+        assert!(table.update(id, &old, &new).is_err());
+    }
+
+    #[test]
+    fn scoped_exclusion_reasons() {
+        let (_permit, store) = setup_store();
+        let table = store.triage_exclusion_reason_map();
+        let now = Utc::now();
+
+        let mut entry = create_exclusion_reason_entry("empty period");
+        entry.scope.valid_from = Some(now);
+        entry.scope.valid_until = Some(now);
+        assert!(table.insert(entry).is_err());
+
+        let unscoped = create_exclusion_reason_entry("unscoped");
+        assert!(matches!(
+            TriageExclusion::from(unscoped.clone()),
+            TriageExclusion::Domain(_)
+        ));
+        let mut scoped = create_exclusion_reason_entry("scoped");
+        scoped.scope.event_kind = Some(EventKind::DnsCovertChannel);
+        assert!(matches!(
+            TriageExclusion::from(scoped.clone()),
+            TriageExclusion::Scoped(_, _)
+        ));
+
+        let mut owned = create_exclusion_reason_entry("owned");
+        owned.customer_id = Some(1);
+        assert!(owned.is_available_to(Some(1)));
+        assert!(!owned.is_available_to(Some(2)));
+        assert!(!owned.is_available_to(None));
+        assert!(unscoped.is_available_to(Some(2)));
+
+        let input = |customer_id| {
+            let mut policy = create_entry("policy", customer_id);
+            policy.rules = TriageRules::V1(vec![TriageRule {
+                condition: TriageCondition::Exclusion(7),
+                weight: 1.0,
+            }]);
+            policy.into_input_with_scoped_exclusion_reason(
+                vec![unscoped.clone(), owned.clone(), scoped.clone()],
+                [(7, owned.clone())].into_iter().collect(),
+            )
+        };
+        let for_owner = input(Some(1));
+        assert_eq!(for_owner.triage_exclusion.len(), 3);
+        assert!(for_owner.rule_exclusions.contains_key(&7));
+        let for_all = input(None);
+        assert_eq!(for_all.triage_exclusion.len(), 2);
+        assert!(for_all.rule_exclusions.is_empty());
+    }
+
+    #[test]
+    fn wildcard_domain_matches_subdomains() {
+        let exclusion =
+            TriageExclusion::from(ExclusionReason::Domain(vec!["*.example.com".to_string()]));
+        let TriageExclusion::Domain(set) = exclusion else {
+            panic!("domain exclusion expected");
+        };
+        assert!(set.is_match("www.example.com"));
+        assert!(set.is_match("a.b.example.com"));
+        assert!(!set.is_match("example.com"));
+        assert!(!set.is_match("www.example.community"));
+    }
+
     fn setup_store() -> (DbGuard<'static>, Arc<Store>) {
         let permit = acquire_db_permit();
         let db_dir = tempfile::tempdir().unwrap();
@@ -832,7 +1777,7 @@ mod test {
             id: u32::MAX,
             name: name.to_string(),
             triage_exclusion_id: vec![],
-            packet_attr: vec![],
+            rules: TriageRules::default(),
             response: vec![],
             confidence: vec![],
             creation_time: Utc::now(),
@@ -844,7 +1789,7 @@ mod test {
         TriagePolicyUpdate {
             name: name.to_string(),
             triage_exclusion_id: vec![],
-            packet_attr: vec![],
+            rules: TriageRules::default(),
             confidence: vec![],
             response: vec![],
             customer_id,
@@ -857,6 +1802,8 @@ mod test {
             name: name.to_string(),
             exclusion_reason: ExclusionReason::Domain(vec!["example.com".to_string()]),
             description: "test description".to_string(),
+            customer_id: None,
+            scope: ExclusionScope::default(),
         }
     }
 
@@ -868,6 +1815,8 @@ mod test {
             name: name.to_string(),
             exclusion_reason: ExclusionReason::Domain(vec!["example.com".to_string()]),
             description: description.to_string(),
+            customer_id: None,
+            scope: ExclusionScope::default(),
         }
     }
 
@@ -988,15 +1937,13 @@ mod test {
             id: 42,
             name: "fixture-policy".to_string(),
             triage_exclusion_id: vec![1, 2],
-            packet_attr: vec![PacketAttr {
+            rules: TriageRules::from(vec![PacketAttr {
                 raw_event_kind: RawEventKind::Http,
                 attr_name: "host".to_string(),
-                value_kind: ValueKind::String,
                 cmp_kind: AttrCmpKind::Contain,
-                first_value: b"example.com".to_vec(),
-                second_value: None,
+                value: AttrValue::String("example.com".to_string()),
                 weight: Some(1.5),
-            }],
+            }]),
             confidence: vec![
                 Confidence {
                     threat_category: Some(EventCategory::Reconnaissance),
@@ -1034,7 +1981,7 @@ mod test {
         assert_eq!(decoded.id, expected.id);
         assert_eq!(decoded.name, expected.name);
         assert_eq!(decoded.triage_exclusion_id, expected.triage_exclusion_id);
-        assert!(decoded.packet_attr == expected.packet_attr);
+        assert!(decoded.rules == expected.rules);
         assert_eq!(decoded.confidence, expected.confidence);
         assert!(decoded.response == expected.response);
         assert_eq!(decoded.creation_time, expected.creation_time);
@@ -1,15 +1,18 @@
 //! The `triage_policy` table.
 
-use std::borrow::Cow;
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashMap},
+};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
-use rocksdb::{Direction, OptimisticTransactionDB};
+use rocksdb::OptimisticTransactionDB;
 use serde::{Deserialize, Serialize};
 
-use super::UniqueKey;
+use super::{UniqueKey, tag_index};
 use crate::{
-    Indexable, IndexedMap, IndexedMapUpdate, IndexedTable, Iterable, collections::Indexed,
+    Indexable, IndexedMap, IndexedMapUpdate, IndexedTable, collections::Indexed,
     types::FromKeyValue,
 };
 
@@ -50,12 +53,6 @@ impl TriageResponse {
         &self.tag_ids
     }
 
-    fn contains_tag(&self, tag: u32) -> Result<usize> {
-        self.tag_ids
-            .binary_search(&tag)
-            .map_err(|idx| anyhow::anyhow!("{idx}"))
-    }
-
     fn create_key(sensor: &str, time: &DateTime<Utc>) -> Vec<u8> {
         let mut key = sensor.as_bytes().to_vec();
         key.extend_from_slice(&time.timestamp_nanos_opt().unwrap_or_default().to_be_bytes());
@@ -135,11 +132,51 @@ impl<'d> IndexedTable<'d, TriageResponse> {
     ///
     /// Returns an error if the database query fails.
     pub fn remove_tag(&self, tag_id: u32) -> Result<()> {
-        let iter = self.iter(Direction::Forward, None);
-        for entry in iter {
-            let mut response = entry?;
-            if let Ok(idx) = response.contains_tag(tag_id) {
-                response.tag_ids.remove(idx);
+        loop {
+            let txn = self.indexed_map.db().transaction();
+            self.remove_tags_with_transaction(&[tag_id], &txn)?;
+            match txn.commit() {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    if !e.as_ref().starts_with("Resource busy:") {
+                        return Err(e).context("failed to remove tag");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Removes the tags in `tag_ids` from all the triage responses within `txn`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub(crate) fn remove_tags_with_transaction(
+        &self,
+        tag_ids: &[u32],
+        txn: &rocksdb::Transaction<OptimisticTransactionDB>,
+    ) -> Result<()> {
+        let db = self.indexed_map.db();
+        let mut ids = BTreeSet::new();
+        for &tag_id in tag_ids {
+            ids.extend(tag_index::ids_with_tag_in(
+                db,
+                txn,
+                super::TRIAGE_RESPONSE,
+                tag_id,
+            )?);
+        }
+        for id in ids {
+            let Some(response) = self.get_by_id_in_transaction(id, txn)? else {
+                continue;
+            };
+            let remaining: Vec<u32> = response
+                .tag_ids
+                .iter()
+                .copied()
+                .filter(|tag| !tag_ids.contains(tag))
+                .collect();
+            if remaining.len() < response.tag_ids.len() {
                 let old = Update {
                     key: response.key.clone(),
                     tag_ids: None,
@@ -147,15 +184,34 @@ impl<'d> IndexedTable<'d, TriageResponse> {
                 };
                 let new = Update {
                     key: response.key,
-                    tag_ids: Some(response.tag_ids),
+                    tag_ids: Some(remaining),
                     remarks: None,
                 };
-                self.indexed_map.update(response.id, &old, &new)?;
+                self.update_with_transaction(response.id, &old, &new, txn)?;
             }
         }
         Ok(())
     }
 
+    /// Returns the IDs of the triage responses with `tag_id`, in ascending order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn ids_with_tag(&self, tag_id: u32) -> Result<Vec<u32>> {
+        tag_index::ids_with_tag(self.indexed_map.db(), super::TRIAGE_RESPONSE, tag_id)
+    }
+
+    /// Returns the number of triage responses with each tag, for every tag at least
+    /// one of them has.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database query fails.
+    pub fn tag_counts(&self) -> Result<HashMap<u32, usize>> {
+        tag_index::tag_counts(self.indexed_map.db(), super::TRIAGE_RESPONSE)
+    }
+
     /// Updates the `TriageResponse` from `old` to `new`, given `id`.
     ///
     /// # Errors
@@ -446,4 +502,38 @@ mod test {
         let iter = table.iter(rocksdb::Direction::Reverse, None);
         assert_eq!(iter.count(), 0);
     }
+
+    #[test]
+    fn tag_lookup() {
+        let (_permit, store) = setup_store();
+        let table = store.triage_response_map();
+
+        let time = Utc::now();
+        let id1 = table
+            .put(TriageResponse::new(
+                "sensor1".to_string(),
+                time,
+                vec![1, 2],
+                String::new(),
+            ))
+            .unwrap();
+        let id2 = table
+            .put(TriageResponse::new(
+                "sensor2".to_string(),
+                time,
+                vec![2],
+                String::new(),
+            ))
+            .unwrap();
+
+        assert_eq!(table.ids_with_tag(2).unwrap(), vec![id1, id2]);
+        assert_eq!(table.ids_with_tag(1).unwrap(), vec![id1]);
+        assert!(table.ids_with_tag(3).unwrap().is_empty());
+
+        table.remove_tag(2).unwrap();
+        assert!(table.ids_with_tag(2).unwrap().is_empty());
+        let counts = table.tag_counts().unwrap();
+        assert_eq!(counts.len(), 1);
+        assert_eq!(counts[&1], 1);
+    }
 }
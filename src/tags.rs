@@ -1,4 +1,14 @@
-use crate::{IndexedTable, Network, TriageResponse, collections::IndexedSet};
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{Context, bail};
+use bincode::Options;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    IndexedTable, Network, TriageResponse,
+    collections::{IndexedSet, SetView},
+};
 
 // Kinds of tag IDs. They are used to define the behavior of tag sets.
 
@@ -11,32 +21,88 @@ pub struct NetworkTagId;
 /// A compile-time tag indicating that tag IDs are for workflow tags.
 pub struct WorkflowTagId;
 
-#[derive(Default)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct Tag {
     pub id: u32,
     pub name: String,
+    pub details: TagDetails,
+    /// When the tag was created, or `None` for a tag created before tags
+    /// recorded it.
+    pub creation_time: Option<DateTime<Utc>>,
+    /// The user who created the tag, if known.
+    pub creator: Option<String>,
+}
+
+/// The attributes of a tag that can be changed after it is created, other
+/// than its name.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TagDetails {
+    /// The ID of the parent tag, in the same set, or `None` for a top-level
+    /// tag.
+    pub parent: Option<u32>,
+    pub description: Option<String>,
+    /// The color to display the tag in, as `#` followed by six hexadecimal
+    /// digits.
+    pub color: Option<String>,
+}
+
+impl TagDetails {
+    /// Checks that the color, if any, is `#` followed by six hexadecimal
+    /// digits.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the color is malformed.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Some(color) = &self.color {
+            let valid = color
+                .strip_prefix('#')
+                .is_some_and(|hex| hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()));
+            if !valid {
+                bail!("invalid color {color:?}");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What removing a tag with child tags does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChildTags {
+    /// Refuses to remove the tag.
+    Refuse,
+    /// Removes the tag and all its descendants.
+    Cascade,
+}
+
+/// A tag with the tags below it.
+pub struct TagNode<'a> {
+    pub tag: &'a Tag,
+    pub children: Vec<TagNode<'a>>,
+}
+
+/// What is stored for a tag besides its name.
+#[derive(Deserialize, Serialize)]
+struct TagRecord {
+    details: TagDetails,
+    creation_time: Option<DateTime<Utc>>,
+    creator: Option<String>,
 }
 
 /// A set of tags. `T` represents the removal behavior. When a tag is removed,
 /// `TagSet<T>::remove` removes all the references to the tag in the database.
+///
+/// Tag names are unique within a set, and the ID of a removed tag is never
+/// assigned to another.
 pub struct TagSet<'a, IdKind> {
-    set: IndexedSet<'a>, // will be needed when we implement write operations
+    set: IndexedSet<'a>,
     tags: Vec<Tag>,
     _phantom: std::marker::PhantomData<IdKind>,
 }
 
 impl<'a, IdKind> TagSet<'a, IdKind> {
     pub(crate) fn new(set: IndexedSet<'a>) -> anyhow::Result<Self> {
-        use anyhow::Context;
-
-        let index = set.index()?;
-        let mut tags = Vec::new();
-        for (id, name) in index.iter() {
-            tags.push(Tag {
-                id,
-                name: String::from_utf8(name.to_vec()).context("invalid data")?,
-            });
-        }
+        let tags = load(&set, "")?;
         Ok(Self {
             set,
             tags,
@@ -44,28 +110,92 @@ impl<'a, IdKind> TagSet<'a, IdKind> {
         })
     }
 
-    /// Inserts a new tag into the set, returning its ID.
+    /// Inserts a new top-level tag into the set, returning its ID.
     ///
     /// # Errors
     ///
-    /// Returns an error if any database operation fails.
+    /// Returns an error if `name` is empty, the set already has a tag named
+    /// `name`, or any database operation fails.
     pub fn insert(&mut self, name: &str) -> anyhow::Result<u32> {
-        // TODO: Reject a duplicate name. Not implemented yet, because it
-        // requires searching the name in the set. We need to convert the format
-        // so that keys are stored as actual RocksDB keys.
-        self.set.insert(name.as_bytes())
+        self.insert_with_details(name, &TagDetails::default(), None)
+    }
+
+    /// Inserts a new tag with `details` into the set, recording `creator` and
+    /// the current time as its creation, and returns its ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `name` is empty, the set already has a tag named
+    /// `name`, the parent is not in the set, the details are invalid, or any
+    /// database operation fails.
+    pub fn insert_with_details(
+        &mut self,
+        name: &str,
+        details: &TagDetails,
+        creator: Option<&str>,
+    ) -> anyhow::Result<u32> {
+        let id = insert(&self.set, "", name, details, creator)?;
+        self.tags = load(&self.set, "")?;
+        Ok(id)
+    }
+
+    /// Returns the ID of the tag named `name`, or `None` if there is no such
+    /// tag.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any database operation fails.
+    pub fn id_by_name(&self, name: &str) -> anyhow::Result<Option<u32>> {
+        self.set.id(name.as_bytes())
     }
 
     /// Updates an old tag name to a new one for the given ID.
     ///
     /// It returns `true` if the name was updated successfully, and `false` if
-    /// the old name was different from what was stored or not found.
+    /// the old name was different from what was stored or not found. The tag
+    /// is renamed in a single transaction, so no reader sees both or neither
+    /// of the names.
     ///
     /// # Errors
     ///
-    /// Returns an error if `id` is invalid or any database operation fails.
+    /// Returns an error if `new` is empty, another tag is named `new`, or any
+    /// database operation fails.
     pub fn update(&mut self, id: u32, old: &str, new: &str) -> anyhow::Result<bool> {
-        self.set.update(id, old.as_bytes(), new.as_bytes())
+        if new.is_empty() {
+            bail!("tag name shouldn't be empty");
+        }
+        let updated = self.set.update(id, old.as_bytes(), new.as_bytes())?;
+        self.tags = load(&self.set, "")?;
+        Ok(updated)
+    }
+
+    /// Updates the details of the tag with the given ID from `old` to `new`.
+    ///
+    /// It returns `true` if the details were updated, and `false` if the
+    /// stored details were different from `old` or there is no such tag.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the new parent is not in the set or is the tag
+    /// itself or one of its descendants, the new details are invalid, or any
+    /// database operation fails.
+    pub fn update_details(
+        &mut self,
+        id: u32,
+        old: &TagDetails,
+        new: &TagDetails,
+    ) -> anyhow::Result<bool> {
+        let updated = update_details(&self.set, "", id, old, new)?;
+        self.tags = load(&self.set, "")?;
+        Ok(updated)
+    }
+
+    /// Returns the names of the tag with the given ID and its ancestors, from
+    /// the top-level one down, joined by `/`, or `None` if there is no such
+    /// tag.
+    #[must_use]
+    pub fn path(&self, id: u32) -> Option<String> {
+        path(&self.tags, id)
     }
 
     /// Returns an iterator over the tags in the set.
@@ -79,35 +209,64 @@ impl<'a, IdKind> TagSet<'a, IdKind> {
 }
 
 impl TagSet<'_, EventTagId> {
-    /// Removes a tag from the event tag set, returning its name.
+    /// Returns the number of triage responses with each tag in the set, in
+    /// the order of the tags' IDs, including the tags no response has.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any database operation fails.
+    pub fn usage_counts(
+        &self,
+        triage_responses: &IndexedTable<TriageResponse>,
+    ) -> anyhow::Result<Vec<(u32, usize)>> {
+        Ok(usage_counts(&self.tags, &triage_responses.tag_counts()?))
+    }
+
+    /// Removes a tag from the event tag set, returning the removed tags.
+    ///
+    /// If the tag has child tags, `children` decides whether they are removed
+    /// with it or the tag is not removed. The removed tags are the tag itself
+    /// followed by its descendants, and the triage responses no longer refer
+    /// to any of them. The tags and the references to them are removed in one
+    /// transaction.
     ///
     /// # Errors
     ///
-    /// Returns an error if `id` is invalid or any database operation fails.
+    /// Returns an error if `id` is invalid, the tag has child tags and
+    /// `children` is [`ChildTags::Refuse`], or any database operation fails.
     pub fn remove_event_tag(
         &mut self,
         id: u32,
+        children: ChildTags,
         triage_responses: &IndexedTable<TriageResponse>,
-    ) -> anyhow::Result<String> {
-        let key = self.set.deactivate(id)?;
-        triage_responses.remove_tag(id)?;
-        self.set.clear_inactive()?;
-
-        let name = String::from_utf8(key)?;
-        Ok(name)
+    ) -> anyhow::Result<Vec<Tag>> {
+        let removed = remove(&self.set, "", id, children, |txn, ids| {
+            triage_responses.remove_tags_with_transaction(ids, txn)
+        })?;
+        self.tags = load(&self.set, "")?;
+        Ok(removed)
     }
 }
 
 impl TagSet<'_, WorkflowTagId> {
-    /// Removes a tag from the workflow tag set, returning its name.
+    /// Removes a tag from the workflow tag set, returning the removed tags.
+    ///
+    /// If the tag has child tags, `children` decides whether they are removed
+    /// with it or the tag is not removed. The removed tags are the tag itself
+    /// followed by its descendants.
     ///
     /// # Errors
     ///
-    /// Returns an error if `id` is invalid or any database operation fails.
-    pub fn remove_workflow_tag(&mut self, id: u32) -> anyhow::Result<String> {
-        let key = self.set.remove(id)?;
-        let name = String::from_utf8(key)?;
-        Ok(name)
+    /// Returns an error if `id` is invalid, the tag has child tags and
+    /// `children` is [`ChildTags::Refuse`], or any database operation fails.
+    pub fn remove_workflow_tag(
+        &mut self,
+        id: u32,
+        children: ChildTags,
+    ) -> anyhow::Result<Vec<Tag>> {
+        let removed = remove(&self.set, "", id, children, |_, _| Ok(()))?;
+        self.tags = load(&self.set, "")?;
+        Ok(removed)
     }
 }
 
@@ -117,6 +276,42 @@ pub struct Tags<'a> {
     index: usize,
 }
 
+impl<'a> Tags<'a> {
+    /// Returns the tags of the set as a forest of top-level tags, each with
+    /// its descendants, in the order of their IDs at every level.
+    ///
+    /// It covers all the tags of the set, however far the iterator has
+    /// advanced.
+    #[must_use]
+    pub fn tree(&self) -> Vec<TagNode<'a>> {
+        let ids: HashSet<u32> = self.tags.iter().map(|tag| tag.id).collect();
+        let mut children: HashMap<u32, Vec<&Tag>> = HashMap::new();
+        let mut roots = Vec::new();
+        for tag in self.tags {
+            match tag.details.parent {
+                Some(parent) if ids.contains(&parent) => {
+                    children.entry(parent).or_default().push(tag);
+                }
+                _ => roots.push(tag),
+            }
+        }
+
+        let mut visited = HashSet::new();
+        let mut forest: Vec<_> = roots
+            .into_iter()
+            .map(|tag| node(tag, &children, &mut visited))
+            .collect();
+        // A tag in a cycle of parents, which concurrent updates can leave, has
+        // no top-level ancestor; it is listed at the top level instead.
+        for tag in self.tags {
+            if !visited.contains(&tag.id) {
+                forest.push(node(tag, &children, &mut visited));
+            }
+        }
+        forest
+    }
+}
+
 impl<'a> Iterator for Tags<'a> {
     type Item = &'a Tag;
 
@@ -135,7 +330,8 @@ impl<'a> Iterator for Tags<'a> {
 ///
 /// Tags are stored with a key format of `{customer_id}\0{tag_name}` to ensure
 /// uniqueness within each customer's scope. The `customer_id` is encoded as
-/// ASCII decimal followed by a null byte separator.
+/// ASCII decimal followed by a null byte separator. The parent of a tag is
+/// one of the same customer.
 pub struct CustomerTagSet<'a, IdKind> {
     set: IndexedSet<'a>,
     customer_id: u32,
@@ -150,20 +346,7 @@ impl<'a, IdKind> CustomerTagSet<'a, IdKind> {
     ///
     /// Returns an error if the database operation fails or the data is invalid.
     pub(crate) fn new(set: IndexedSet<'a>, customer_id: u32) -> anyhow::Result<Self> {
-        use anyhow::Context;
-
-        let prefix = Self::make_prefix(customer_id);
-        let index = set.index()?;
-        let mut tags = Vec::new();
-
-        for (id, key) in index.iter() {
-            // Only include tags that belong to this customer
-            if let Some(name_bytes) = key.strip_prefix(prefix.as_bytes()) {
-                let name = String::from_utf8(name_bytes.to_vec()).context("invalid data")?;
-                tags.push(Tag { id, name });
-            }
-        }
-
+        let tags = load(&set, &Self::make_prefix(customer_id))?;
         Ok(Self {
             set,
             customer_id,
@@ -178,29 +361,99 @@ impl<'a, IdKind> CustomerTagSet<'a, IdKind> {
         self.customer_id
     }
 
-    /// Inserts a new tag into the set, returning its ID.
+    /// Inserts a new top-level tag into the set, returning its ID.
     ///
     /// # Errors
     ///
-    /// Returns an error if any database operation fails.
+    /// Returns an error if `name` is empty, the customer already has a tag
+    /// named `name`, or any database operation fails.
     pub fn insert(&mut self, name: &str) -> anyhow::Result<u32> {
-        let prefixed_key = self.make_prefixed_key(name);
-        self.set.insert(prefixed_key.as_bytes())
+        self.insert_with_details(name, &TagDetails::default(), None)
+    }
+
+    /// Inserts a new tag with `details` into the set, recording `creator` and
+    /// the current time as its creation, and returns its ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `name` is empty, the customer already has a tag
+    /// named `name`, the parent is not one of the customer's tags, the details
+    /// are invalid, or any database operation fails.
+    pub fn insert_with_details(
+        &mut self,
+        name: &str,
+        details: &TagDetails,
+        creator: Option<&str>,
+    ) -> anyhow::Result<u32> {
+        let prefix = Self::make_prefix(self.customer_id);
+        let id = insert(&self.set, &prefix, name, details, creator)?;
+        self.tags = load(&self.set, &prefix)?;
+        Ok(id)
+    }
+
+    /// Returns the ID of the customer's tag named `name`, or `None` if there
+    /// is no such tag.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any database operation fails.
+    pub fn id_by_name(&self, name: &str) -> anyhow::Result<Option<u32>> {
+        self.set.id(self.make_prefixed_key(name).as_bytes())
     }
 
     /// Updates an old tag name to a new one for the given ID.
     ///
     /// Returns `true` if the name was updated successfully, and `false` if
-    /// the old name was different from what was stored or not found.
+    /// the old name was different from what was stored or not found. The tag
+    /// is renamed in a single transaction.
     ///
     /// # Errors
     ///
-    /// Returns an error if `id` is invalid or any database operation fails.
+    /// Returns an error if `new` is empty, the customer has another tag named
+    /// `new`, or any database operation fails.
     pub fn update(&mut self, id: u32, old: &str, new: &str) -> anyhow::Result<bool> {
+        if new.is_empty() {
+            bail!("tag name shouldn't be empty");
+        }
         let old_prefixed = self.make_prefixed_key(old);
         let new_prefixed = self.make_prefixed_key(new);
-        self.set
-            .update(id, old_prefixed.as_bytes(), new_prefixed.as_bytes())
+        let updated = self
+            .set
+            .update(id, old_prefixed.as_bytes(), new_prefixed.as_bytes())?;
+        self.tags = load(&self.set, &Self::make_prefix(self.customer_id))?;
+        Ok(updated)
+    }
+
+    /// Updates the details of the customer's tag with the given ID from `old`
+    /// to `new`.
+    ///
+    /// It returns `true` if the details were updated, and `false` if the
+    /// stored details were different from `old` or the customer has no such
+    /// tag.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the new parent is not one of the customer's tags or
+    /// is the tag itself or one of its descendants, the new details are
+    /// invalid, or any database operation fails.
+    pub fn update_details(
+        &mut self,
+        id: u32,
+        old: &TagDetails,
+        new: &TagDetails,
+    ) -> anyhow::Result<bool> {
+        let prefix = Self::make_prefix(self.customer_id);
+        let updated = update_details(&self.set, &prefix, id, old, new)?;
+        self.tags = load(&self.set, &prefix)?;
+        Ok(updated)
+    }
+
+    /// Returns the names of the tag with the given ID and its ancestors, from
+    /// the top-level one down, joined by `/`, or `None` if the customer has no
+    /// such tag.
+    #[must_use]
+    pub fn path(&self, id: u32) -> Option<String> {
+        path(&self.tags, id)
     }
 
     /// Returns an iterator over the tags in the set.
@@ -212,30 +465,44 @@ impl<'a, IdKind> CustomerTagSet<'a, IdKind> {
         }
     }
 
-    /// Removes a tag from the network tag set, returning its name.
+    /// Returns the number of networks with each of the customer's tags, in the
+    /// order of the tags' IDs, including the tags no network has.
     ///
     /// # Errors
     ///
-    /// Returns an error if `id` is invalid or any database operation fails.
+    /// Returns an error if any database operation fails.
+    pub fn usage_counts(
+        &self,
+        networks: &IndexedTable<Network>,
+    ) -> anyhow::Result<Vec<(u32, usize)>> {
+        Ok(usage_counts(&self.tags, &networks.tag_counts()?))
+    }
+
+    /// Removes a tag from the network tag set, returning the removed tags.
+    ///
+    /// If the tag has child tags, `children` decides whether they are removed
+    /// with it or the tag is not removed. The removed tags are the tag itself
+    /// followed by its descendants, and the networks no longer refer to any of
+    /// them. The tags and the references to them are removed in one
+    /// transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the customer has no tag with `id`, the tag has
+    /// child tags and `children` is [`ChildTags::Refuse`], or any database
+    /// operation fails.
     pub fn remove_network_tag(
         &mut self,
         id: u32,
+        children: ChildTags,
         networks: &IndexedTable<Network>,
-    ) -> anyhow::Result<String> {
-        let key = self.set.deactivate(id)?;
-        networks.remove_tag(id)?;
-        self.set.clear_inactive()?;
-
-        // Strip the customer prefix to get the actual tag name
+    ) -> anyhow::Result<Vec<Tag>> {
         let prefix = Self::make_prefix(self.customer_id);
-        let name = if let Some(name_bytes) = key.strip_prefix(prefix.as_bytes()) {
-            String::from_utf8(name_bytes.to_vec())?
-        } else {
-            // Fallback: return the full key if prefix doesn't match
-            String::from_utf8(key)?
-        };
-
-        Ok(name)
+        let removed = remove(&self.set, &prefix, id, children, |txn, ids| {
+            networks.remove_tags_with_transaction(ids, txn)
+        })?;
+        self.tags = load(&self.set, &prefix)?;
+        Ok(removed)
     }
 
     /// Creates the prefix for a customer ID: `{customer_id}\0`
@@ -249,9 +516,248 @@ impl<'a, IdKind> CustomerTagSet<'a, IdKind> {
     }
 }
 
+/// Reads the tags whose keys start with `prefix`, in the order of their IDs,
+/// with the prefix stripped from their names.
+fn load(set: &IndexedSet, prefix: &str) -> anyhow::Result<Vec<Tag>> {
+    tags_from(
+        set.entries_starting_with(prefix.as_bytes())?,
+        set.values()?,
+        prefix,
+    )
+}
+
+/// Reads the tags whose keys start with `prefix` as `view` sees them, in the
+/// order of their IDs, with the prefix stripped from their names.
+fn load_in(view: &SetView, prefix: &str) -> anyhow::Result<Vec<Tag>> {
+    tags_from(
+        view.entries_starting_with(prefix.as_bytes())?,
+        view.values()?,
+        prefix,
+    )
+}
+
+/// Makes the tags out of the `entries` of a set whose keys start with
+/// `prefix`, with the details in `values`.
+fn tags_from(
+    entries: Vec<(u32, Vec<u8>)>,
+    values: Vec<(u32, Vec<u8>)>,
+    prefix: &str,
+) -> anyhow::Result<Vec<Tag>> {
+    let mut records: HashMap<u32, Vec<u8>> = values.into_iter().collect();
+    let mut tags = Vec::new();
+    for (id, key) in entries {
+        let name_bytes = key.strip_prefix(prefix.as_bytes()).unwrap_or(&key);
+        let name = String::from_utf8(name_bytes.to_vec()).context("invalid data")?;
+        let mut tag = Tag {
+            id,
+            name,
+            ..Tag::default()
+        };
+        if let Some(record) = records.remove(&id) {
+            let record: TagRecord = bincode::DefaultOptions::new()
+                .deserialize(&record)
+                .context("invalid data")?;
+            tag.details = record.details;
+            tag.creation_time = record.creation_time;
+            tag.creator = record.creator;
+        }
+        tags.push(tag);
+    }
+    tags.sort_unstable_by_key(|tag| tag.id);
+    Ok(tags)
+}
+
+/// Returns whether `view` has a tag with `id` whose key starts with `prefix`.
+fn has_tag(view: &SetView, prefix: &str, id: u32) -> anyhow::Result<bool> {
+    Ok(view
+        .get(id)?
+        .is_some_and(|key| key.starts_with(prefix.as_bytes())))
+}
+
+/// Inserts a tag, checking that its parent exists in the same transaction, so
+/// the parent cannot be removed in the meantime.
+fn insert(
+    set: &IndexedSet,
+    prefix: &str,
+    name: &str,
+    details: &TagDetails,
+    creator: Option<&str>,
+) -> anyhow::Result<u32> {
+    if name.is_empty() {
+        bail!("tag name shouldn't be empty");
+    }
+    details.validate()?;
+    let key = format!("{prefix}{name}");
+    if set.id(key.as_bytes())?.is_some() {
+        bail!("tag {name:?} already exists");
+    }
+    let record = TagRecord {
+        details: details.clone(),
+        creation_time: Some(Utc::now()),
+        creator: creator.map(ToString::to_string),
+    };
+    let value = bincode::DefaultOptions::new().serialize(&record)?;
+    set.insert_with_value(key.as_bytes(), &value, |view| {
+        if let Some(parent) = details.parent
+            && !has_tag(view, prefix, parent)?
+        {
+            bail!("no parent tag with ID {parent}");
+        }
+        Ok(())
+    })
+}
+
+/// Updates the details of a tag, checking the new parent against the tags as
+/// the update reads them.
+fn update_details(
+    set: &IndexedSet,
+    prefix: &str,
+    id: u32,
+    old: &TagDetails,
+    new: &TagDetails,
+) -> anyhow::Result<bool> {
+    new.validate()?;
+    if !set
+        .get(id)?
+        .is_some_and(|key| key.starts_with(prefix.as_bytes()))
+    {
+        return Ok(false);
+    }
+
+    let current = set.value(id)?;
+    let record = match &current {
+        Some(value) => bincode::DefaultOptions::new()
+            .deserialize::<TagRecord>(value)
+            .context("invalid data")?,
+        None => TagRecord {
+            details: TagDetails::default(),
+            creation_time: None,
+            creator: None,
+        },
+    };
+    if record.details != *old {
+        return Ok(false);
+    }
+    let value = bincode::DefaultOptions::new().serialize(&TagRecord {
+        details: new.clone(),
+        ..record
+    })?;
+    set.update_value(id, current.as_deref(), &value, |view| {
+        let Some(parent) = new.parent else {
+            return Ok(());
+        };
+        if !has_tag(view, prefix, parent)? {
+            bail!("no parent tag with ID {parent}");
+        }
+        if ancestors(&load_in(view, prefix)?, parent).any(|ancestor| ancestor == id) {
+            bail!("tag {id} cannot be below itself");
+        }
+        Ok(())
+    })
+}
+
+/// Removes a tag and, unless `children` refuses, its descendants, as the
+/// removal reads them, so a child another handle adds is not left behind.
+///
+/// `unlink` removes the references to the removed tags in the transaction of
+/// the removal, so that no reference outlives its tag.
+fn remove<F>(
+    set: &IndexedSet,
+    prefix: &str,
+    id: u32,
+    children: ChildTags,
+    unlink: F,
+) -> anyhow::Result<Vec<Tag>>
+where
+    F: FnMut(&rocksdb::Transaction<rocksdb::OptimisticTransactionDB>, &[u32]) -> anyhow::Result<()>,
+{
+    let mut removed = Vec::new();
+    let keys = set.remove_all_with(
+        |view| {
+            let tags = load_in(view, prefix)?;
+            removed = with_descendants(&tags, id)?;
+            if removed.len() > 1 && children == ChildTags::Refuse {
+                bail!("tag {:?} has child tags", removed[0].name);
+            }
+            Ok(removed.iter().map(|tag| tag.id).collect())
+        },
+        unlink,
+    )?;
+    // The keys removed are the ones stored, which a concurrent rename may
+    // have changed since the tags were read.
+    for (tag, key) in removed.iter_mut().zip(keys) {
+        let name_bytes = key.strip_prefix(prefix.as_bytes()).unwrap_or(&key);
+        tag.name = String::from_utf8(name_bytes.to_vec())?;
+    }
+    Ok(removed)
+}
+
+/// Returns the tag with `id` followed by its descendants in `tags`.
+fn with_descendants(tags: &[Tag], id: u32) -> anyhow::Result<Vec<Tag>> {
+    let Some(tag) = tags.iter().find(|tag| tag.id == id) else {
+        bail!("no such ID");
+    };
+    let mut found = vec![tag.clone()];
+    let mut seen = HashSet::from([id]);
+    let mut i = 0;
+    while let Some(parent) = found.get(i).map(|tag| tag.id) {
+        for child in tags.iter().filter(|tag| tag.details.parent == Some(parent)) {
+            if seen.insert(child.id) {
+                found.push(child.clone());
+            }
+        }
+        i += 1;
+    }
+    Ok(found)
+}
+
+fn usage_counts(tags: &[Tag], counts: &HashMap<u32, usize>) -> Vec<(u32, usize)> {
+    tags.iter()
+        .map(|tag| (tag.id, counts.get(&tag.id).copied().unwrap_or_default()))
+        .collect()
+}
+
+/// Returns the IDs of `id` and its ancestors, from `id` up.
+fn ancestors(tags: &[Tag], id: u32) -> impl Iterator<Item = u32> + '_ {
+    let parents: HashMap<u32, Option<u32>> = tags
+        .iter()
+        .map(|tag| (tag.id, tag.details.parent))
+        .collect();
+    std::iter::successors(Some(id), move |id| parents.get(id).copied().flatten())
+        .take(tags.len().max(1))
+}
+
+fn path(tags: &[Tag], id: u32) -> Option<String> {
+    let names: HashMap<u32, &str> = tags.iter().map(|tag| (tag.id, tag.name.as_str())).collect();
+    names.get(&id)?;
+    let mut path: Vec<&str> = ancestors(tags, id)
+        .map_while(|id| names.get(&id).copied())
+        .collect();
+    path.reverse();
+    Some(path.join("/"))
+}
+
+fn node<'a>(
+    tag: &'a Tag,
+    children: &HashMap<u32, Vec<&'a Tag>>,
+    visited: &mut HashSet<u32>,
+) -> TagNode<'a> {
+    visited.insert(tag.id);
+    let mut nodes = Vec::new();
+    for &child in children.get(&tag.id).into_iter().flatten() {
+        if !visited.contains(&child.id) {
+            nodes.push(node(child, children, visited));
+        }
+    }
+    TagNode {
+        tag,
+        children: nodes,
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::TagSet;
+    use super::{ChildTags, TagDetails, TagSet};
     use crate::{
         tags::{NetworkTagId, WorkflowTagId},
         test,
@@ -270,10 +776,11 @@ mod tests {
         let id = tag_set.insert("tag3").unwrap();
         assert_eq!(id, 2);
 
-        assert!(tag_set.remove_workflow_tag(5).is_err());
-        let removed_name = tag_set.remove_workflow_tag(1).unwrap();
-        assert_eq!(removed_name, "tag2");
-        assert!(tag_set.remove_workflow_tag(1).is_err());
+        assert!(tag_set.remove_workflow_tag(5, ChildTags::Refuse).is_err());
+        let removed = tag_set.remove_workflow_tag(1, ChildTags::Refuse).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].name, "tag2");
+        assert!(tag_set.remove_workflow_tag(1, ChildTags::Refuse).is_err());
 
         let updated = tag_set.update(2, "tag3", "tag3.1").unwrap();
         assert!(updated);
@@ -283,6 +790,253 @@ mod tests {
         assert!(!updated);
     }
 
+    #[test]
+    fn tag_set_rejects_duplicate_names() {
+        let db = test::Store::new();
+        let set = db.indexed_set();
+        let mut tag_set = TagSet::<WorkflowTagId>::new(set).unwrap();
+
+        let id1 = tag_set.insert("tag1").unwrap();
+        let id2 = tag_set.insert("tag2").unwrap();
+        assert!(tag_set.insert("tag1").is_err());
+        assert!(tag_set.insert("").is_err());
+        assert_eq!(tag_set.id_by_name("tag1").unwrap(), Some(id1));
+        assert_eq!(tag_set.id_by_name("tag3").unwrap(), None);
+
+        // Renaming to a name in use leaves both tags as they were.
+        assert!(tag_set.update(id2, "tag2", "tag1").is_err());
+        assert_eq!(tag_set.id_by_name("tag2").unwrap(), Some(id2));
+
+        assert!(tag_set.update(id2, "tag2", "tag3").unwrap());
+        assert_eq!(tag_set.id_by_name("tag2").unwrap(), None);
+        assert_eq!(tag_set.id_by_name("tag3").unwrap(), Some(id2));
+        let id = tag_set.insert("tag2").unwrap();
+        assert_eq!(id, 2);
+    }
+
+    #[test]
+    fn tag_hierarchy() {
+        let db = test::Store::new();
+        let mut tag_set = TagSet::<WorkflowTagId>::new(db.indexed_set()).unwrap();
+
+        let child_of = |parent| TagDetails {
+            parent: Some(parent),
+            ..TagDetails::default()
+        };
+        let malware = tag_set
+            .insert_with_details(
+                "malware",
+                &TagDetails {
+                    parent: None,
+                    description: Some("Malicious software".to_string()),
+                    color: Some("#c0392B".to_string()),
+                },
+                Some("admin"),
+            )
+            .unwrap();
+        let ransomware = tag_set
+            .insert_with_details("ransomware", &child_of(malware), None)
+            .unwrap();
+        let locky = tag_set
+            .insert_with_details("locky", &child_of(ransomware), None)
+            .unwrap();
+        let phishing = tag_set.insert("phishing").unwrap();
+
+        assert!(
+            tag_set
+                .insert_with_details("orphan", &child_of(100), None)
+                .is_err()
+        );
+        let bad_color = TagDetails {
+            color: Some("red".to_string()),
+            ..TagDetails::default()
+        };
+        assert!(
+            tag_set
+                .insert_with_details("red", &bad_color, None)
+                .is_err()
+        );
+
+        assert_eq!(
+            tag_set.path(locky).as_deref(),
+            Some("malware/ransomware/locky")
+        );
+        let tag = tag_set.tags().find(|tag| tag.id == malware).unwrap();
+        assert_eq!(tag.creator.as_deref(), Some("admin"));
+        assert!(tag.creation_time.is_some());
+        let malware_details = tag.details.clone();
+
+        let tree = tag_set.tags().tree();
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree[0].tag.id, malware);
+        assert_eq!(tree[0].children.len(), 1);
+        assert_eq!(tree[0].children[0].tag.id, ransomware);
+        assert_eq!(tree[0].children[0].children[0].tag.id, locky);
+        assert_eq!(tree[1].tag.id, phishing);
+        assert!(tree[1].children.is_empty());
+
+        // A tag cannot move below itself.
+        assert!(
+            tag_set
+                .update_details(malware, &malware_details, &child_of(locky))
+                .is_err()
+        );
+        assert!(
+            !tag_set
+                .update_details(locky, &TagDetails::default(), &child_of(phishing))
+                .unwrap()
+        );
+        assert!(
+            tag_set
+                .update_details(locky, &child_of(ransomware), &child_of(phishing))
+                .unwrap()
+        );
+        assert_eq!(tag_set.path(locky).as_deref(), Some("phishing/locky"));
+
+        assert!(
+            tag_set
+                .remove_workflow_tag(phishing, ChildTags::Refuse)
+                .is_err()
+        );
+        assert_eq!(tag_set.tags().count(), 4);
+        let removed = tag_set
+            .remove_workflow_tag(phishing, ChildTags::Cascade)
+            .unwrap();
+        let removed: Vec<_> = removed.iter().map(|tag| tag.id).collect();
+        assert_eq!(removed, [phishing, locky]);
+        assert_eq!(tag_set.id_by_name("locky").unwrap(), None);
+        assert_eq!(tag_set.tags().count(), 2);
+    }
+
+    #[test]
+    fn tag_hierarchy_across_handles() {
+        let db = test::Store::new();
+        let mut first = TagSet::<WorkflowTagId>::new(db.indexed_set()).unwrap();
+        let mut second = TagSet::<WorkflowTagId>::new(db.indexed_set()).unwrap();
+
+        let child_of = |parent| TagDetails {
+            parent: Some(parent),
+            ..TagDetails::default()
+        };
+        let malware = first.insert("malware").unwrap();
+        let phishing = first.insert("phishing").unwrap();
+
+        // `first` hasn't seen the child `second` adds, but still refuses.
+        let ransomware = second
+            .insert_with_details("ransomware", &child_of(malware), None)
+            .unwrap();
+        assert!(
+            first
+                .remove_workflow_tag(malware, ChildTags::Refuse)
+                .is_err()
+        );
+        let removed = first
+            .remove_workflow_tag(malware, ChildTags::Cascade)
+            .unwrap();
+        let removed: Vec<_> = removed.iter().map(|tag| tag.id).collect();
+        assert_eq!(removed, [malware, ransomware]);
+
+        // `second` hasn't seen the removal, but cannot use the removed parent.
+        let other = second.insert("other").unwrap();
+        assert!(
+            second
+                .insert_with_details("locky", &child_of(malware), None)
+                .is_err()
+        );
+        assert!(
+            second
+                .update_details(other, &TagDetails::default(), &child_of(malware))
+                .is_err()
+        );
+        assert!(
+            second
+                .update_details(other, &TagDetails::default(), &child_of(phishing))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn event_tag_usage_counts() {
+        let _permit = test::acquire_db_permit();
+        let db_dir = tempfile::tempdir().unwrap();
+        let backup_dir = tempfile::tempdir().unwrap();
+        let store = crate::Store::new(db_dir.path(), backup_dir.path(), None).unwrap();
+        let responses = store.triage_response_map();
+        let mut tag_set = store.event_tag_set().unwrap();
+
+        let used = tag_set.insert("used").unwrap();
+        let unused = tag_set.insert("unused").unwrap();
+        let response = crate::TriageResponse::new(
+            "sensor".to_string(),
+            chrono::Utc::now(),
+            vec![used],
+            String::new(),
+        );
+        let response = responses.put(response).unwrap();
+
+        assert_eq!(responses.ids_with_tag(used).unwrap(), vec![response]);
+        assert_eq!(
+            tag_set.usage_counts(&responses).unwrap(),
+            vec![(used, 1), (unused, 0)]
+        );
+
+        tag_set
+            .remove_event_tag(used, ChildTags::Refuse, &responses)
+            .unwrap();
+        assert!(responses.ids_with_tag(used).unwrap().is_empty());
+        assert_eq!(tag_set.usage_counts(&responses).unwrap(), vec![(unused, 0)]);
+    }
+
+    #[test]
+    fn network_tag_removal_unlinks_descendants() {
+        let _permit = test::acquire_db_permit();
+        let db_dir = tempfile::tempdir().unwrap();
+        let backup_dir = tempfile::tempdir().unwrap();
+        let store = crate::Store::new(db_dir.path(), backup_dir.path(), None).unwrap();
+        let networks = store.network_map();
+        let mut tag_set = store.network_tag_set(1).unwrap();
+
+        let parent = tag_set.insert("parent").unwrap();
+        let child = tag_set
+            .insert_with_details(
+                "child",
+                &TagDetails {
+                    parent: Some(parent),
+                    ..TagDetails::default()
+                },
+                None,
+            )
+            .unwrap();
+        let other = tag_set.insert("other").unwrap();
+        let network = networks
+            .put(crate::Network::new(
+                "network".to_string(),
+                String::new(),
+                crate::HostNetworkGroup::default(),
+                vec![parent, child, other],
+            ))
+            .unwrap();
+
+        assert!(
+            tag_set
+                .remove_network_tag(parent, ChildTags::Refuse, &networks)
+                .is_err()
+        );
+        assert_eq!(
+            networks.get_by_id(network).unwrap().unwrap().tag_ids(),
+            [parent, child, other]
+        );
+
+        let removed = tag_set
+            .remove_network_tag(parent, ChildTags::Cascade, &networks)
+            .unwrap();
+        assert_eq!(removed.len(), 2);
+        assert_eq!(
+            networks.get_by_id(network).unwrap().unwrap().tag_ids(),
+            [other]
+        );
+    }
+
     #[test]
     fn customer_tag_set_insert_and_list() {
         use super::CustomerTagSet;
@@ -358,6 +1112,28 @@ mod tests {
         assert!(!names_2.contains(&"tag-a"));
     }
 
+    #[test]
+    fn customer_tag_set_rejects_duplicate_names() {
+        use super::CustomerTagSet;
+        let store = test::Store::new();
+
+        let mut customer_1_tags =
+            CustomerTagSet::<NetworkTagId>::new(store.indexed_set(), 4).unwrap();
+        let id = customer_1_tags.insert("tag").unwrap();
+        assert!(customer_1_tags.insert("tag").is_err());
+
+        let mut customer_2_tags =
+            CustomerTagSet::<NetworkTagId>::new(store.indexed_set(), 42).unwrap();
+        assert_eq!(customer_2_tags.id_by_name("tag").unwrap(), None);
+        let other_id = customer_2_tags.insert("tag").unwrap();
+        assert_ne!(id, other_id);
+        assert_eq!(customer_1_tags.id_by_name("tag").unwrap(), Some(id));
+        assert_eq!(customer_2_tags.id_by_name("tag").unwrap(), Some(other_id));
+
+        let customer_1_tags = CustomerTagSet::<NetworkTagId>::new(store.indexed_set(), 4).unwrap();
+        assert_eq!(customer_1_tags.tags().count(), 1);
+    }
+
     #[test]
     fn customer_tag_set_update() {
         use super::CustomerTagSet;